@@ -22,7 +22,7 @@
 #![allow(dead_code)]  //removes some warnings for the user
 //use std::error::Error; //Might add in future but is useless for now
 
-use crate::i2c::I2c;
+use crate::i2c::{I2c, I2cBus};
 //Dump of all the addresses and giving them the same name
 //as is in the documentation for the ADXL345
 const ADXL_ADD: u16 = 0x53;
@@ -59,8 +59,8 @@ const DATAZ1: u8 =55;
 const FIFO_CTL: u8 =56;
 const FIFO_STATUS: u8 =57;
 
-pub struct Adxl {
-    adxl: I2c,          //this is the I2c channel from the RPPAL lib
+pub struct Adxl<B: I2cBus = I2c> {
+    adxl: B,            //this is the I2c channel, either I2c or SoftI2c
                         //Not made public for good reasons
     pub id: u8,         //The ID from the accel, not needed but good to see the conection
     pub power_status: u8, //powerstatus, 0 is sleep and 8 is go, might upgrade to a enum
@@ -92,32 +92,8 @@ impl Adxl {
     /// # Example
     /// let mut adxl = Adxl::new();
     pub fn new()-> Self{
-        let mut _adxl = I2c::new().expect("I2c init failed");   //Starts a new i2c communication
-        _adxl.set_slave_address(ADXL_ADD).expect("SETTING SLAVE FAILED"); //Sets the addres ass ADXL_ADD
-        let mut adxl = Self{
-            //Null values for all except for the i2c channel
-            adxl: _adxl,
-            id: 0,
-            power_status: 0,
-            offsets: [0u8;3],
-            raw_data: [0u8;6],
-            data: [0i16;3],
-
-            free_fall: false,
-            tap: false,
-            dtap: false,
-            act: false,
-            inact: false,
-            data_ready: false,
-            overrun: false,
-            watermark: false,
-
-            pitch: 0.0,
-            roll: 0.0,
-
-        };
-        adxl.start();
-        adxl
+        let i2c = I2c::new().expect("I2c init failed");   //Starts a new i2c communication
+        Adxl::with_bus(i2c, ADXL_ADD) //Sets the addres ass ADXL_ADD
     }
     /// Creates a empty struct to allow usage and starts the i2c channel
     /// Sets it to a adress of your choise
@@ -125,11 +101,21 @@ impl Adxl {
     /// let mut adxl = Adxl::new_alt_adress(0x21);
 
     pub fn new_alt_adress(address:u16)-> Self{
-        let mut _adxl = I2c::new().expect("I2c init failed");   //Starts a new i2c communication
-        _adxl.set_slave_address(address).expect("SETTING SLAVE FAILED"); //Sets the addres address
+        let i2c = I2c::new().expect("I2c init failed");   //Starts a new i2c communication
+        Adxl::with_bus(i2c, address) //Sets the addres address
+    }
+}
+
+impl<B: I2cBus> Adxl<B> {
+    /// Creates a empty struct on an existing bus, such as a bit-banged SoftI2c,
+    /// and sets it to the address of your choise
+    /// # Example
+    /// let mut adxl = Adxl::with_bus(SoftI2c::new(sda, scl), 0x53);
+    pub fn with_bus(mut bus: B, address: u16)-> Self{
+        bus.set_slave_address(address).expect("SETTING SLAVE FAILED"); //Sets the addres address
         let mut adxl = Self{
             //Null values for all except for the i2c channel
-            adxl: _adxl,
+            adxl: bus,
             id: 0,
             power_status: 0,
             offsets: [0u8;3],
@@ -239,7 +225,7 @@ impl Adxl {
 }
 
 // THis function has all the interupt thingies
-impl Adxl{
+impl<B: I2cBus> Adxl<B> {
     ///UNTESTED should set the tap threshold as the datasheet specifies
     pub fn set_tap_threshold(&self,cmd:f32){
         let mut out_big:f32 = cmd/0.0625;
@@ -0,0 +1,631 @@
+//! Interface for the general-purpose clock (GPCLK) outputs.
+//!
+//! The BCM283x SoC contains three general-purpose clock generators, which output a
+//! programmable clock signal on a GPIO pin without any CPU involvement. They're
+//! commonly used as a reference clock for camera sensors, audio codecs and
+//! microcontrollers.
+//!
+//! Each generator divides one of several clock sources by an integer or fractional
+//! divider. A fractional divider is implemented through a MASH noise-shaping filter,
+//! which alternates between neighbouring integer dividers so the average output
+//! frequency matches the requested frequency, at the cost of some jitter. [`Divider`]
+//! calculates the divider for a requested frequency, and reports the frequency that's
+//! actually achieved.
+//!
+//! ## Clock channels
+//!
+//! Only pins set to the correct alternate function output the clock signal. Use
+//! [`Gpio`] to configure the pin's mode before enabling the clock.
+//!
+//! * GPCLK0: BCM GPIO 4 (physical pin 7), BCM GPIO 32 and 34 (Alt0), BCM GPIO 20 (Alt5)
+//! * GPCLK1: BCM GPIO 5 (physical pin 29), BCM GPIO 42 and 44 (Alt0), BCM GPIO 21 (Alt5)
+//! * GPCLK2: BCM GPIO 6 (physical pin 31), BCM GPIO 43 (Alt0)
+//!
+//! GPCLK1 may be in use by the firmware on some models. Changing its configuration
+//! can cause the Raspberry Pi to become unstable.
+//!
+//! The clock manager registers are accessed through `/dev/mem`, which usually requires
+//! superuser privileges.
+//!
+//! ## Example
+//!
+//! ```no_run
+//! use std::error::Error;
+//!
+//! use rpi_embedded::clock::{Channel, Clock, Mash, Source};
+//! use rpi_embedded::gpio::{Gpio, Mode};
+//!
+//! # fn main() -> Result<(), Box<dyn Error>> {
+//! // Route GPCLK0 to BCM GPIO 4
+//! let _pin = Gpio::new()?.get(4)?.into_io(Mode::Alt0);
+//!
+//! let clock = Clock::with_frequency(Channel::Gpclk0, Source::PllD, 12_000_000.0, Mash::Stage1, true)?;
+//! println!("GPCLK0: {:?} Hz", clock.frequency());
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! [`Divider`]: struct.Divider.html
+//! [`Gpio`]: ../gpio/struct.Gpio.html
+
+use std::error;
+use std::fmt;
+use std::io;
+use std::result;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use crate::system::{self, DeviceInfo, SoC};
+
+mod mem;
+
+// The divider registers hold a 12-bit integer and a 12-bit fractional part
+const DIVI_MAX: u32 = 0xfff;
+const DIVF_SCALE: f64 = 4096.0;
+
+/// Errors that can occur when accessing the clock manager.
+#[derive(Debug)]
+pub enum Error {
+    /// Unknown model.
+    ///
+    /// The Raspberry Pi model or SoC can't be identified, so the location of the
+    /// clock manager registers and the clock source frequencies are unknown.
+    UnknownModel,
+    /// Permission denied when opening `/dev/mem` for read/write access.
+    PermissionDenied(String),
+    /// I/O error.
+    Io(io::Error),
+    /// Invalid frequency.
+    ///
+    /// The frequency can't be generated from the selected clock source with the
+    /// selected MASH filter, because the required divider is out of range.
+    InvalidFrequency(f64),
+    /// Invalid divider.
+    ///
+    /// The integer part of the divider is out of range for the selected MASH filter,
+    /// or the fractional part exceeds 4095.
+    InvalidDivider(u16, u16),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Error::UnknownModel => write!(f, "Unknown Raspberry Pi model"),
+            Error::PermissionDenied(ref path) => write!(f, "Permission denied: {}", path),
+            Error::Io(ref err) => write!(f, "I/O error: {}", err),
+            Error::InvalidFrequency(frequency) => {
+                write!(f, "Frequency {} Hz can't be generated", frequency)
+            }
+            Error::InvalidDivider(integer, fraction) => {
+                write!(f, "Invalid divider: {} + {}/4096", integer, fraction)
+            }
+        }
+    }
+}
+
+impl error::Error for Error {}
+
+impl From<io::Error> for Error {
+    fn from(err: io::Error) -> Error {
+        Error::Io(err)
+    }
+}
+
+impl From<system::Error> for Error {
+    fn from(_err: system::Error) -> Error {
+        Error::UnknownModel
+    }
+}
+
+/// Result type returned from methods that can have `clock::Error`s.
+pub type Result<T> = result::Result<T, Error>;
+
+/// General-purpose clock channels.
+///
+/// More information on the pins each channel can be routed to can be found [here].
+///
+/// [here]: index.html#clock-channels
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub enum Channel {
+    Gpclk0 = 0,
+    Gpclk1 = 1,
+    Gpclk2 = 2,
+}
+
+impl fmt::Display for Channel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Channel::Gpclk0 => write!(f, "Gpclk0"),
+            Channel::Gpclk1 => write!(f, "Gpclk1"),
+            Channel::Gpclk2 => write!(f, "Gpclk2"),
+        }
+    }
+}
+
+/// Clock sources.
+///
+/// Only sources with a fixed frequency are supported. The frequency of PLLA and PLLC
+/// changes with the ARM and core clock settings.
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub enum Source {
+    /// Crystal oscillator. 19.2 MHz, or 54 MHz on the BCM2711.
+    Oscillator = 1,
+    /// PLLD. 500 MHz, or 750 MHz on the BCM2711.
+    PllD = 6,
+    /// HDMI auxiliary clock. 216 MHz.
+    HdmiAux = 7,
+}
+
+impl Source {
+    /// Returns the source frequency in hertz (Hz) for the specified SoC.
+    pub fn frequency(self, soc: SoC) -> u32 {
+        match (self, soc) {
+            (Source::Oscillator, SoC::Bcm2711) => 54_000_000,
+            (Source::Oscillator, _) => 19_200_000,
+            (Source::PllD, SoC::Bcm2711) => 750_000_000,
+            (Source::PllD, _) => 500_000_000,
+            (Source::HdmiAux, _) => 216_000_000,
+        }
+    }
+
+    fn from_register(value: u32) -> Option<Source> {
+        match value {
+            1 => Some(Source::Oscillator),
+            6 => Some(Source::PllD),
+            7 => Some(Source::HdmiAux),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Source {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Source::Oscillator => write!(f, "Oscillator"),
+            Source::PllD => write!(f, "PllD"),
+            Source::HdmiAux => write!(f, "HdmiAux"),
+        }
+    }
+}
+
+/// MASH noise-shaping filters.
+///
+/// [`Integer`] divides the source frequency by an integer, which results in a clean
+/// output signal. The higher MASH stages support fractional dividers, with increasing
+/// amounts of jitter and a higher minimum integer divider.
+///
+/// [`Integer`]: #variant.Integer
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub enum Mash {
+    /// Integer division. The fractional part is ignored.
+    Integer = 0,
+    /// 1-stage MASH filter. Requires an integer divider of at least 2.
+    Stage1 = 1,
+    /// 2-stage MASH filter. Requires an integer divider of at least 3.
+    Stage2 = 2,
+    /// 3-stage MASH filter. Requires an integer divider of at least 5.
+    Stage3 = 3,
+}
+
+impl Mash {
+    // Minimum integer divider (datasheet @ 6.3)
+    fn min_integer(self) -> u32 {
+        match self {
+            Mash::Integer => 1,
+            Mash::Stage1 => 2,
+            Mash::Stage2 => 3,
+            Mash::Stage3 => 5,
+        }
+    }
+
+    fn from_register(value: u32) -> Mash {
+        match value {
+            1 => Mash::Stage1,
+            2 => Mash::Stage2,
+            3 => Mash::Stage3,
+            _ => Mash::Integer,
+        }
+    }
+}
+
+impl fmt::Display for Mash {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Mash::Integer => write!(f, "Integer"),
+            Mash::Stage1 => write!(f, "Stage1"),
+            Mash::Stage2 => write!(f, "Stage2"),
+            Mash::Stage3 => write!(f, "Stage3"),
+        }
+    }
+}
+
+/// A clock divider.
+///
+/// The source frequency is divided by `integer + fraction / 4096`. The fractional part
+/// is only used when a MASH filter is selected.
+///
+/// ## Example
+///
+/// ```
+/// use rpi_embedded::clock::{Divider, Mash, Source};
+/// use rpi_embedded::system::SoC;
+///
+/// # fn main() -> rpi_embedded::clock::Result<()> {
+/// let source = Source::Oscillator.frequency(SoC::Bcm2711);
+///
+/// let divider = Divider::with_frequency(source, 12_000_000.0, Mash::Stage1)?;
+/// assert_eq!((divider.integer(), divider.fraction()), (4, 2048));
+/// assert_eq!(divider.frequency(source), 12_000_000.0);
+///
+/// // Without a MASH filter, 4.5 is rounded to the nearest integer divider
+/// let divider = Divider::with_frequency(source, 12_000_000.0, Mash::Integer)?;
+/// assert_eq!(divider.frequency(source), 10_800_000.0);
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub struct Divider {
+    integer: u16,
+    fraction: u16,
+    mash: Mash,
+}
+
+impl Divider {
+    /// Constructs a new `Divider`.
+    ///
+    /// Returns `Err(`[`Error::InvalidDivider`]`)` if `integer` is lower than the
+    /// minimum for the selected MASH filter or higher than 4095, or if `fraction`
+    /// is higher than 4095.
+    ///
+    /// [`Error::InvalidDivider`]: enum.Error.html#variant.InvalidDivider
+    pub fn new(integer: u16, fraction: u16, mash: Mash) -> Result<Divider> {
+        if u32::from(integer) < mash.min_integer()
+            || u32::from(integer) > DIVI_MAX
+            || u32::from(fraction) > DIVI_MAX
+        {
+            return Err(Error::InvalidDivider(integer, fraction));
+        }
+
+        let fraction = if mash == Mash::Integer { 0 } else { fraction };
+
+        Ok(Divider {
+            integer,
+            fraction,
+            mash,
+        })
+    }
+
+    /// Calculates the divider that gets closest to `frequency` for the specified
+    /// source frequency.
+    ///
+    /// Both `source_frequency` and `frequency` are specified in hertz (Hz). Returns
+    /// `Err(`[`Error::InvalidFrequency`]`)` if the required divider is out of range.
+    ///
+    /// [`Error::InvalidFrequency`]: enum.Error.html#variant.InvalidFrequency
+    pub fn with_frequency(source_frequency: u32, frequency: f64, mash: Mash) -> Result<Divider> {
+        if !frequency.is_finite() || frequency <= 0.0 {
+            return Err(Error::InvalidFrequency(frequency));
+        }
+
+        let divider = f64::from(source_frequency) / frequency;
+
+        let (integer, fraction) = if mash == Mash::Integer {
+            (divider.round(), 0.0)
+        } else {
+            let integer = divider.floor();
+            let fraction = ((divider - integer) * DIVF_SCALE).round();
+
+            // Rounding the fraction up can carry over into the integer part
+            if fraction >= DIVF_SCALE {
+                (integer + 1.0, 0.0)
+            } else {
+                (integer, fraction)
+            }
+        };
+
+        if integer < f64::from(mash.min_integer()) || integer > f64::from(DIVI_MAX) {
+            return Err(Error::InvalidFrequency(frequency));
+        }
+
+        Ok(Divider {
+            integer: integer as u16,
+            fraction: fraction as u16,
+            mash,
+        })
+    }
+
+    /// Returns the integer part.
+    pub fn integer(&self) -> u16 {
+        self.integer
+    }
+
+    /// Returns the fractional part, in units of 1/4096.
+    pub fn fraction(&self) -> u16 {
+        self.fraction
+    }
+
+    /// Returns the MASH filter.
+    pub fn mash(&self) -> Mash {
+        self.mash
+    }
+
+    /// Returns the average output frequency in hertz (Hz) for the specified
+    /// source frequency.
+    pub fn frequency(&self, source_frequency: u32) -> f64 {
+        f64::from(source_frequency)
+            / (f64::from(self.integer) + f64::from(self.fraction) / DIVF_SCALE)
+    }
+}
+
+/// Provides access to the Raspberry Pi's general-purpose clock generators.
+///
+/// The selected channel's output pin needs to be set to the appropriate alternate
+/// function mode separately. More information can be found [here].
+///
+/// [here]: index.html#clock-channels
+#[derive(Debug)]
+pub struct Clock {
+    channel: Channel,
+    soc: SoC,
+    mem: mem::ClockMem,
+    reset_on_drop: bool,
+    // Set once this instance changes the channel's settings or enables it
+    configured: AtomicBool,
+}
+
+impl Clock {
+    /// Constructs a new `Clock`.
+    ///
+    /// `new` doesn't change the channel's source, divider or state.
+    pub fn new(channel: Channel) -> Result<Clock> {
+        let device_info = DeviceInfo::new()?;
+
+        Ok(Clock {
+            channel,
+            soc: device_info.soc(),
+            mem: mem::ClockMem::open(&device_info)?,
+            reset_on_drop: true,
+            configured: AtomicBool::new(false),
+        })
+    }
+
+    /// Constructs a new `Clock` using the specified settings.
+    ///
+    /// `frequency` is specified in hertz (Hz), and is converted to a [`Divider`] for
+    /// the selected `source` and `mash` filter through [`Divider::with_frequency`].
+    ///
+    /// `enabled` enables the clock output. If `enabled` is set to `false`, the
+    /// clock will remain disabled until [`enable`] is called.
+    ///
+    /// [`Divider`]: struct.Divider.html
+    /// [`Divider::with_frequency`]: struct.Divider.html#method.with_frequency
+    /// [`enable`]: #method.enable
+    pub fn with_frequency(
+        channel: Channel,
+        source: Source,
+        frequency: f64,
+        mash: Mash,
+        enabled: bool,
+    ) -> Result<Clock> {
+        let clock = Clock::new(channel)?;
+        clock.configure(source, frequency, mash, enabled)?;
+
+        Ok(clock)
+    }
+
+    /// Returns the channel.
+    pub fn channel(&self) -> Channel {
+        self.channel
+    }
+
+    /// Returns the clock source, or `None` if the channel is set to a source
+    /// that isn't supported.
+    pub fn source(&self) -> Option<Source> {
+        Source::from_register(self.mem.registers(self.channel).source)
+    }
+
+    /// Returns the divider, or `None` if the divider registers contain an
+    /// invalid value.
+    pub fn divider(&self) -> Option<Divider> {
+        let registers = self.mem.registers(self.channel);
+
+        Divider::new(
+            registers.integer as u16,
+            registers.fraction as u16,
+            Mash::from_register(registers.mash),
+        )
+        .ok()
+    }
+
+    /// Returns the average output frequency in hertz (Hz) achieved with the
+    /// current source and divider.
+    ///
+    /// The returned frequency doesn't depend on whether the clock is enabled.
+    /// Returns `None` if the source or divider isn't supported.
+    pub fn frequency(&self) -> Option<f64> {
+        let source = self.source()?;
+
+        Some(self.divider()?.frequency(source.frequency(self.soc)))
+    }
+
+    /// Sets the clock source and frequency.
+    ///
+    /// `frequency` is specified in hertz (Hz), and is converted to a [`Divider`] for
+    /// the selected `source` and `mash` filter through [`Divider::with_frequency`].
+    /// The achieved frequency is returned by [`frequency`].
+    ///
+    /// If the clock is enabled, it's briefly stopped while the new settings are applied.
+    ///
+    /// [`Divider`]: struct.Divider.html
+    /// [`Divider::with_frequency`]: struct.Divider.html#method.with_frequency
+    /// [`frequency`]: #method.frequency
+    pub fn set_frequency(&self, source: Source, frequency: f64, mash: Mash) -> Result<()> {
+        self.configure(source, frequency, mash, self.is_enabled())
+    }
+
+    /// Sets the clock source and divider.
+    ///
+    /// If the clock is enabled, it's briefly stopped while the new settings are applied.
+    pub fn set_divider(&self, source: Source, divider: Divider) {
+        self.apply(source, divider, self.is_enabled());
+    }
+
+    /// Enables the clock output.
+    pub fn enable(&self) {
+        self.set_enabled(true);
+    }
+
+    /// Disables the clock output.
+    pub fn disable(&self) {
+        self.set_enabled(false);
+    }
+
+    /// Returns `true` if the clock output is enabled.
+    pub fn is_enabled(&self) -> bool {
+        self.mem.registers(self.channel).enabled
+    }
+
+    /// Returns the value of `reset_on_drop`.
+    pub fn reset_on_drop(&self) -> bool {
+        self.reset_on_drop
+    }
+
+    /// When enabled, disables the clock output when the `Clock` instance goes out of
+    /// scope, if the instance changed the clock's settings or enabled it. A `Clock`
+    /// that's only used to read the current settings leaves the channel untouched.
+    /// By default, this is set to `true`.
+    pub fn set_reset_on_drop(&mut self, reset_on_drop: bool) {
+        self.reset_on_drop = reset_on_drop;
+    }
+
+    fn configure(&self, source: Source, frequency: f64, mash: Mash, enabled: bool) -> Result<()> {
+        let divider = Divider::with_frequency(source.frequency(self.soc), frequency, mash)?;
+        self.apply(source, divider, enabled);
+
+        Ok(())
+    }
+
+    fn apply(&self, source: Source, divider: Divider, enabled: bool) {
+        self.configured.store(true, Ordering::SeqCst);
+        self.mem.set_registers(
+            self.channel,
+            mem::ClockRegisters {
+                source: source as u32,
+                mash: divider.mash as u32,
+                integer: u32::from(divider.integer),
+                fraction: u32::from(divider.fraction),
+                enabled,
+            },
+        );
+    }
+
+    fn set_enabled(&self, enabled: bool) {
+        if enabled {
+            self.configured.store(true, Ordering::SeqCst);
+        }
+
+        let registers = self.mem.registers(self.channel);
+        if registers.enabled != enabled {
+            self.mem.set_registers(
+                self.channel,
+                mem::ClockRegisters {
+                    enabled,
+                    ..registers
+                },
+            );
+        }
+    }
+}
+
+impl Drop for Clock {
+    fn drop(&mut self) {
+        if self.reset_on_drop && self.configured.load(Ordering::SeqCst) {
+            self.disable();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn source_frequency() {
+        for &soc in [SoC::Bcm2835, SoC::Bcm2836, SoC::Bcm2837A1, SoC::Bcm2837B0].iter() {
+            assert_eq!(Source::Oscillator.frequency(soc), 19_200_000);
+            assert_eq!(Source::PllD.frequency(soc), 500_000_000);
+            assert_eq!(Source::HdmiAux.frequency(soc), 216_000_000);
+        }
+
+        assert_eq!(Source::Oscillator.frequency(SoC::Bcm2711), 54_000_000);
+        assert_eq!(Source::PllD.frequency(SoC::Bcm2711), 750_000_000);
+    }
+
+    #[test]
+    fn integer_divider() {
+        let osc = Source::Oscillator.frequency(SoC::Bcm2835);
+        let divider = Divider::with_frequency(osc, 1_000_000.0, Mash::Integer).unwrap();
+        assert_eq!((divider.integer(), divider.fraction()), (19, 0));
+        assert!((divider.frequency(osc) - 1_010_526.3).abs() < 0.1);
+
+        let plld = Source::PllD.frequency(SoC::Bcm2835);
+        let divider = Divider::with_frequency(plld, 25_000_000.0, Mash::Integer).unwrap();
+        assert_eq!((divider.integer(), divider.fraction()), (20, 0));
+        assert_eq!(divider.frequency(plld), 25_000_000.0);
+
+        let plld = Source::PllD.frequency(SoC::Bcm2711);
+        let divider = Divider::with_frequency(plld, 25_000_000.0, Mash::Integer).unwrap();
+        assert_eq!((divider.integer(), divider.fraction()), (30, 0));
+        assert_eq!(divider.frequency(plld), 25_000_000.0);
+    }
+
+    #[test]
+    fn fractional_divider() {
+        let osc = Source::Oscillator.frequency(SoC::Bcm2835);
+        let divider = Divider::with_frequency(osc, 1_000_000.0, Mash::Stage1).unwrap();
+        assert_eq!((divider.integer(), divider.fraction()), (19, 819));
+        assert!((divider.frequency(osc) - 1_000_000.0).abs() < 10.0);
+
+        let osc = Source::Oscillator.frequency(SoC::Bcm2711);
+        let divider = Divider::with_frequency(osc, 12_000_000.0, Mash::Stage1).unwrap();
+        assert_eq!((divider.integer(), divider.fraction()), (4, 2048));
+
+        // 19.2 MHz / 4.99995 rounds the fraction up to 4096
+        let divider = Divider::with_frequency(19_200_000, 3_840_030.72, Mash::Stage1).unwrap();
+        assert_eq!((divider.integer(), divider.fraction()), (5, 0));
+    }
+
+    #[test]
+    fn divider_range() {
+        let osc = Source::Oscillator.frequency(SoC::Bcm2835);
+
+        // Lowest frequency is 19.2 MHz / 4095
+        assert!(Divider::with_frequency(osc, 4_689.0, Mash::Integer).is_ok());
+        assert!(Divider::with_frequency(osc, 4_680.0, Mash::Integer).is_err());
+
+        // MASH filters require a minimum integer divider
+        assert!(Divider::with_frequency(osc, 19_200_000.0, Mash::Integer).is_ok());
+        assert!(Divider::with_frequency(osc, 19_200_000.0, Mash::Stage1).is_err());
+        assert!(Divider::with_frequency(osc, 6_400_000.0, Mash::Stage2).is_ok());
+        assert!(Divider::with_frequency(osc, 4_800_000.0, Mash::Stage3).is_err());
+        assert!(Divider::with_frequency(osc, 3_840_000.0, Mash::Stage3).is_ok());
+
+        assert!(Divider::with_frequency(osc, 0.0, Mash::Integer).is_err());
+        assert!(Divider::with_frequency(osc, -1.0, Mash::Integer).is_err());
+        assert!(Divider::with_frequency(osc, f64::NAN, Mash::Integer).is_err());
+    }
+
+    #[test]
+    fn manual_divider() {
+        assert!(Divider::new(0, 0, Mash::Integer).is_err());
+        assert!(Divider::new(4, 0, Mash::Stage3).is_err());
+        assert!(Divider::new(4096, 0, Mash::Integer).is_err());
+        assert!(Divider::new(10, 4096, Mash::Stage1).is_err());
+        assert_eq!(Divider::new(10, 100, Mash::Integer).unwrap().fraction(), 0);
+        assert_eq!(
+            Divider::new(10, 2048, Mash::Stage1)
+                .unwrap()
+                .frequency(Source::HdmiAux.frequency(SoC::Bcm2837B0)),
+            216_000_000.0 / 10.5
+        );
+    }
+}
@@ -0,0 +1,180 @@
+use std::fmt;
+use std::fs::OpenOptions;
+use std::io;
+use std::os::unix::fs::OpenOptionsExt;
+use std::os::unix::io::AsRawFd;
+use std::ptr;
+use std::thread;
+use std::time::Duration;
+
+use libc::{self, c_void, off_t, size_t, MAP_FAILED, MAP_SHARED, O_SYNC, PROT_READ, PROT_WRITE};
+
+use crate::clock::{Channel, Error, Result};
+use crate::system::DeviceInfo;
+
+const PATH_DEV_MEM: &str = "/dev/mem";
+
+// The clock manager lives at a fixed offset from the peripheral base address
+const CLOCK_OFFSET: u32 = 0x10_1000;
+const CLOCK_MEM_SIZE: usize = 4096;
+
+// Each GPCLK has a control register followed by a divider register (word offsets)
+const CM_GP0CTL: usize = 0x70 / std::mem::size_of::<u32>();
+const CM_GP0DIV: usize = 0x74 / std::mem::size_of::<u32>();
+const CM_GP_STRIDE: usize = 0x08 / std::mem::size_of::<u32>();
+
+const CM_PASSWD: u32 = 0x5a << 24;
+const CM_MASH_SHIFT: u32 = 9;
+const CM_MASH_MASK: u32 = 0b11 << CM_MASH_SHIFT;
+const CM_BUSY: u32 = 1 << 7;
+const CM_KILL: u32 = 1 << 5;
+const CM_ENAB: u32 = 1 << 4;
+const CM_SRC_MASK: u32 = 0b1111;
+const CM_DIVI_SHIFT: u32 = 12;
+const CM_DIVI_MASK: u32 = 0xfff << CM_DIVI_SHIFT;
+const CM_DIVF_MASK: u32 = 0xfff;
+
+// How long to wait for a clock generator to finish its current cycle
+const BUSY_TIMEOUT: Duration = Duration::from_millis(10);
+const BUSY_POLL: Duration = Duration::from_micros(10);
+
+// Raw register contents for a single clock generator
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub(crate) struct ClockRegisters {
+    pub(crate) source: u32,
+    pub(crate) mash: u32,
+    pub(crate) integer: u32,
+    pub(crate) fraction: u32,
+    pub(crate) enabled: bool,
+}
+
+pub(crate) struct ClockMem {
+    mem_ptr: *mut u32,
+}
+
+impl fmt::Debug for ClockMem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ClockMem")
+            .field("mem_ptr", &self.mem_ptr)
+            .finish()
+    }
+}
+
+impl ClockMem {
+    pub(crate) fn open(device_info: &DeviceInfo) -> Result<ClockMem> {
+        // /dev/gpiomem only covers the GPIO registers, so the clock manager
+        // can only be accessed through /dev/mem
+        let mem_file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .custom_flags(O_SYNC)
+            .open(PATH_DEV_MEM)
+            .map_err(|e| match e.kind() {
+                io::ErrorKind::PermissionDenied => {
+                    Error::PermissionDenied(String::from(PATH_DEV_MEM))
+                }
+                _ => Error::Io(e),
+            })?;
+
+        let mem_ptr = unsafe {
+            libc::mmap(
+                ptr::null_mut(),
+                CLOCK_MEM_SIZE,
+                PROT_READ | PROT_WRITE,
+                MAP_SHARED,
+                mem_file.as_raw_fd(),
+                (device_info.peripheral_base() + CLOCK_OFFSET) as off_t,
+            )
+        };
+
+        if mem_ptr == MAP_FAILED {
+            return Err(Error::Io(io::Error::last_os_error()));
+        }
+
+        Ok(ClockMem {
+            mem_ptr: mem_ptr as *mut u32,
+        })
+    }
+
+    #[inline(always)]
+    fn read(&self, offset: usize) -> u32 {
+        unsafe { ptr::read_volatile(self.mem_ptr.add(offset)) }
+    }
+
+    #[inline(always)]
+    fn write(&self, offset: usize, value: u32) {
+        unsafe {
+            ptr::write_volatile(self.mem_ptr.add(offset), value);
+        }
+    }
+
+    pub(crate) fn registers(&self, channel: Channel) -> ClockRegisters {
+        let ctl = self.read(CM_GP0CTL + channel as usize * CM_GP_STRIDE);
+        let div = self.read(CM_GP0DIV + channel as usize * CM_GP_STRIDE);
+
+        ClockRegisters {
+            source: ctl & CM_SRC_MASK,
+            mash: (ctl & CM_MASH_MASK) >> CM_MASH_SHIFT,
+            integer: (div & CM_DIVI_MASK) >> CM_DIVI_SHIFT,
+            fraction: div & CM_DIVF_MASK,
+            enabled: ctl & CM_ENAB != 0,
+        }
+    }
+
+    // Stops the clock generator, and waits until it's no longer busy. Changing
+    // the source or MASH while the generator is running can cause glitches or
+    // lock it up.
+    fn stop(&self, ctl_offset: usize) {
+        let ctl = self.read(ctl_offset) & (CM_MASH_MASK | CM_SRC_MASK);
+        self.write(ctl_offset, CM_PASSWD | ctl);
+
+        let mut elapsed = Duration::from_secs(0);
+        while self.read(ctl_offset) & CM_BUSY != 0 {
+            if elapsed >= BUSY_TIMEOUT {
+                // The generator doesn't stop if its source isn't running
+                self.write(ctl_offset, CM_PASSWD | ctl | CM_KILL);
+                thread::sleep(BUSY_POLL);
+                self.write(ctl_offset, CM_PASSWD | ctl);
+                break;
+            }
+
+            thread::sleep(BUSY_POLL);
+            elapsed += BUSY_POLL;
+        }
+    }
+
+    pub(crate) fn set_registers(&self, channel: Channel, registers: ClockRegisters) {
+        let ctl_offset = CM_GP0CTL + channel as usize * CM_GP_STRIDE;
+        let div_offset = CM_GP0DIV + channel as usize * CM_GP_STRIDE;
+
+        self.stop(ctl_offset);
+
+        self.write(
+            div_offset,
+            CM_PASSWD
+                | ((registers.integer << CM_DIVI_SHIFT) & CM_DIVI_MASK)
+                | (registers.fraction & CM_DIVF_MASK),
+        );
+
+        let ctl =
+            ((registers.mash << CM_MASH_SHIFT) & CM_MASH_MASK) | (registers.source & CM_SRC_MASK);
+        self.write(ctl_offset, CM_PASSWD | ctl);
+
+        if registers.enabled {
+            self.write(ctl_offset, CM_PASSWD | ctl | CM_ENAB);
+        }
+    }
+}
+
+impl Drop for ClockMem {
+    fn drop(&mut self) {
+        unsafe {
+            libc::munmap(self.mem_ptr as *mut c_void, CLOCK_MEM_SIZE as size_t);
+        }
+    }
+}
+
+// Required because of the raw pointer to our memory-mapped file
+unsafe impl Send for ClockMem {}
+
+unsafe impl Sync for ClockMem {}
@@ -0,0 +1,477 @@
+//! Driver for DHT11, DHT22 and AM2302 temperature and humidity sensors.
+//!
+//! DHT sensors send their measurements over a proprietary single-wire protocol,
+//! where the value of each bit is encoded in the length of a pulse that lasts
+//! either 26-28 µs or 70 µs. [`Dht`] captures these pulses on a dedicated thread
+//! that's set to a real-time scheduling policy, and busy-waits while polling the
+//! pin, similar to software-based PWM.
+//!
+//! The real-time scheduling policy can only be set when running as root. Without it,
+//! the capture is more easily disrupted by other processes, which shows up as failed
+//! checksums or incomplete frames. [`Dht::read`] automatically retries those reads.
+//!
+//! ## Wiring
+//!
+//! Connect the sensor's data pin to any available GPIO pin, and add a 4.7-10 kΩ pull-up
+//! resistor to 3.3 V. Sensors on breakout boards usually include a pull-up resistor.
+//!
+//! [`Dht`]: struct.Dht.html
+//! [`Dht::read`]: struct.Dht.html#method.read
+
+use std::error;
+use std::fmt;
+use std::result;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::gpio::soft_pwm::{get_time_ns, set_realtime_priority};
+use crate::gpio::{IoPin, Level, Mode, PullUpDown};
+
+// The sensor's response takes 160 µs, followed by 40 bits of up to 120 µs each
+const CAPTURE_TIMEOUT_NS: i64 = 10_000_000;
+// Falling edge and rising edge of the response, 2 edges per bit, and a final falling edge
+const FRAME_EDGES: usize = 3 + 40 * 2;
+const DEFAULT_RETRIES: u32 = 3;
+
+/// Errors that can occur when reading a DHT sensor.
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub enum Error {
+    /// The sensor didn't respond to the start signal.
+    NoResponse,
+    /// The sensor stopped sending data before the frame was complete.
+    ///
+    /// Contains the number of bits that were received.
+    Incomplete(usize),
+    /// The checksum didn't match the received data.
+    ///
+    /// Contains the expected and the received checksum.
+    Checksum(u8, u8),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Error::NoResponse => write!(f, "No response from sensor"),
+            Error::Incomplete(bits) => write!(f, "Incomplete frame: {} of 40 bits", bits),
+            Error::Checksum(expected, received) => write!(
+                f,
+                "Checksum mismatch: expected {:#04x}, received {:#04x}",
+                expected, received
+            ),
+        }
+    }
+}
+
+impl error::Error for Error {}
+
+/// Result type returned from methods that can have `dht::Error`s.
+pub type Result<T> = result::Result<T, Error>;
+
+/// Supported sensor models.
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub enum Model {
+    /// DHT11. 1 % relative humidity and 1 °C resolution on most sensors.
+    Dht11,
+    /// DHT22 and AM2302. 0.1 % relative humidity and 0.1 °C resolution.
+    Dht22,
+}
+
+impl Model {
+    /// Returns the minimum time between two reads.
+    ///
+    /// Reading the sensor more often returns the previous measurement, or no
+    /// response at all.
+    pub fn min_interval(self) -> Duration {
+        match self {
+            Model::Dht11 => Duration::from_secs(1),
+            Model::Dht22 => Duration::from_secs(2),
+        }
+    }
+
+    // The host pulls the line low for at least this long to request a measurement
+    fn start_signal(self) -> Duration {
+        match self {
+            Model::Dht11 => Duration::from_millis(20),
+            Model::Dht22 => Duration::from_millis(2),
+        }
+    }
+
+    fn decode(self, data: [u8; 5]) -> Result<Reading> {
+        let checksum = data[..4]
+            .iter()
+            .fold(0u8, |sum, &byte| sum.wrapping_add(byte));
+        if checksum != data[4] {
+            return Err(Error::Checksum(checksum, data[4]));
+        }
+
+        Ok(match self {
+            // Integral and decimal parts. Bit 7 of the temperature's decimal part
+            // indicates a negative value.
+            Model::Dht11 => {
+                let humidity = f64::from(data[0]) + f64::from(data[1]) / 10.0;
+                let temperature = f64::from(data[2]) + f64::from(data[3] & 0x7f) / 10.0;
+
+                Reading {
+                    temperature: if data[3] & 0x80 != 0 {
+                        -temperature
+                    } else {
+                        temperature
+                    },
+                    humidity,
+                }
+            }
+            // 16-bit values in tenths. The temperature uses a sign bit rather than
+            // two's complement.
+            Model::Dht22 => {
+                let humidity = f64::from(u16::from_be_bytes([data[0], data[1]])) / 10.0;
+                let temperature = f64::from(u16::from_be_bytes([data[2] & 0x7f, data[3]])) / 10.0;
+
+                Reading {
+                    temperature: if data[2] & 0x80 != 0 {
+                        -temperature
+                    } else {
+                        temperature
+                    },
+                    humidity,
+                }
+            }
+        })
+    }
+}
+
+impl fmt::Display for Model {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Model::Dht11 => write!(f, "DHT11"),
+            Model::Dht22 => write!(f, "DHT22"),
+        }
+    }
+}
+
+/// A single measurement.
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub struct Reading {
+    /// Temperature in degrees Celsius (°C).
+    pub temperature: f64,
+    /// Relative humidity in percent (%).
+    pub humidity: f64,
+}
+
+/// DHT11, DHT22 or AM2302 temperature and humidity sensor.
+///
+/// ## Example
+///
+/// ```no_run
+/// use std::error::Error;
+///
+/// use rpi_embedded::dht::{Dht, Model};
+/// use rpi_embedded::gpio::{Gpio, Mode};
+///
+/// # fn main() -> Result<(), Box<dyn Error>> {
+/// let pin = Gpio::new()?.get(4)?.into_io(Mode::Input);
+/// let mut dht = Dht::new(pin, Model::Dht22);
+///
+/// let reading = dht.read()?;
+/// println!("{:.1} °C, {:.1} %", reading.temperature, reading.humidity);
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct Dht {
+    // Only taken while the capture thread is running
+    pin: Option<IoPin>,
+    model: Model,
+    retries: u32,
+    last_read: Option<Instant>,
+}
+
+impl Dht {
+    /// Constructs a new `Dht` for a sensor of the specified model connected to `pin`.
+    ///
+    /// The pin's built-in pull-up resistor is enabled.
+    pub fn new(mut pin: IoPin, model: Model) -> Dht {
+        pin.set_mode(Mode::Input);
+        pin.set_pullupdown(PullUpDown::PullUp);
+
+        Dht {
+            pin: Some(pin),
+            model,
+            retries: DEFAULT_RETRIES,
+            last_read: None,
+        }
+    }
+
+    /// Returns the sensor model.
+    pub fn model(&self) -> Model {
+        self.model
+    }
+
+    /// Returns the number of times a failed read is retried.
+    pub fn retries(&self) -> u32 {
+        self.retries
+    }
+
+    /// Sets the number of times [`read`] retries a failed read.
+    ///
+    /// By default, `retries` is set to 3.
+    ///
+    /// [`read`]: #method.read
+    pub fn set_retries(&mut self, retries: u32) {
+        self.retries = retries;
+    }
+
+    /// Reads the temperature and humidity, retrying failed reads.
+    ///
+    /// The sensor can't be read more often than [`Model::min_interval`]. `read` blocks
+    /// until that interval has passed since the previous read. After a failed read, it
+    /// waits for twice as long as before the previous attempt, starting at
+    /// [`Model::min_interval`]. Returns the error of the final attempt if all retries fail.
+    ///
+    /// [`Model::min_interval`]: enum.Model.html#method.min_interval
+    pub fn read(&mut self) -> Result<Reading> {
+        let mut backoff = self.model.min_interval();
+        let mut attempt = 0;
+
+        loop {
+            match self.read_once() {
+                Ok(reading) => return Ok(reading),
+                Err(e) if attempt >= self.retries => return Err(e),
+                Err(_) => {
+                    // read_once already waits for the minimum interval
+                    thread::sleep(backoff - self.model.min_interval());
+                    backoff *= 2;
+                    attempt += 1;
+                }
+            }
+        }
+    }
+
+    /// Reads the temperature and humidity once, without retrying.
+    ///
+    /// `read_once` blocks until [`Model::min_interval`] has passed since the previous read.
+    ///
+    /// [`Model::min_interval`]: enum.Model.html#method.min_interval
+    pub fn read_once(&mut self) -> Result<Reading> {
+        if let Some(last_read) = self.last_read {
+            let elapsed = last_read.elapsed();
+            if elapsed < self.model.min_interval() {
+                thread::sleep(self.model.min_interval() - elapsed);
+            }
+        }
+
+        let mut pin = self.pin.take().expect("pin is only taken during a read");
+        let start_signal = self.model.start_signal();
+
+        // Capture on a separate thread, so the caller's scheduling policy is left unchanged
+        let (pin, edges) = thread::spawn(move || {
+            set_realtime_priority();
+            let edges = capture(&mut pin, start_signal);
+
+            (pin, edges)
+        })
+        .join()
+        .expect("capture thread panicked");
+
+        self.pin = Some(pin);
+        self.last_read = Some(Instant::now());
+
+        self.model.decode(decode_edges(&edges)?)
+    }
+
+    /// Returns the pin the sensor is connected to.
+    pub fn into_pin(mut self) -> IoPin {
+        self.pin.take().expect("pin is only taken during a read")
+    }
+}
+
+// Sends the start signal, and returns the timestamps of every level change
+// until the frame is complete, or the capture times out
+fn capture(pin: &mut IoPin, start_signal: Duration) -> Vec<i64> {
+    let mut edges = Vec::with_capacity(FRAME_EDGES);
+
+    pin.set_low();
+    pin.set_mode(Mode::Output);
+    thread::sleep(start_signal);
+    pin.set_mode(Mode::Input);
+
+    let deadline = get_time_ns() + CAPTURE_TIMEOUT_NS;
+
+    // The pull-up resistor needs some time to raise the line after it's released.
+    // Reading it as low before then would be recorded as the sensor's response.
+    while pin.read() == Level::Low {
+        if get_time_ns() > deadline {
+            return edges;
+        }
+    }
+
+    let mut level = Level::High;
+    while edges.len() < FRAME_EDGES {
+        let now = get_time_ns();
+        if now > deadline {
+            break;
+        }
+
+        let current = pin.read();
+        if current != level {
+            edges.push(now);
+            level = current;
+        }
+    }
+
+    edges
+}
+
+// Each bit starts with a ~50 µs low pulse, followed by a high pulse that
+// lasts 26-28 µs for a 0, or 70 µs for a 1. Comparing the high pulse to the
+// low pulse that precedes it compensates for timing inaccuracies.
+fn decode_edges(edges: &[i64]) -> Result<[u8; 5]> {
+    if edges.len() < 3 {
+        return Err(Error::NoResponse);
+    }
+
+    if edges.len() < FRAME_EDGES {
+        return Err(Error::Incomplete((edges.len() - 3) / 2));
+    }
+
+    let mut data = [0u8; 5];
+    for bit in 0..40 {
+        let low = edges[3 + bit * 2] - edges[2 + bit * 2];
+        let high = edges[4 + bit * 2] - edges[3 + bit * 2];
+
+        if high > low {
+            data[bit / 8] |= 0x80 >> (bit % 8);
+        }
+    }
+
+    Ok(data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Returns the edge timestamps for a frame containing data
+    fn frame(data: [u8; 5]) -> Vec<i64> {
+        // Response: 80 µs low, 80 µs high
+        let mut edges = vec![1_000, 81_000, 161_000];
+        for bit in 0..40 {
+            let high = if data[bit / 8] & (0x80 >> (bit % 8)) != 0 {
+                70_000
+            } else {
+                27_000
+            };
+
+            let falling = *edges.last().unwrap();
+            edges.push(falling + 50_000);
+            edges.push(falling + 50_000 + high);
+        }
+
+        edges
+    }
+
+    fn with_checksum(data: [u8; 4]) -> [u8; 5] {
+        let checksum = data.iter().fold(0u8, |sum, &byte| sum.wrapping_add(byte));
+
+        [data[0], data[1], data[2], data[3], checksum]
+    }
+
+    #[test]
+    fn decode_edges_frame() {
+        let data = [0x02, 0x8c, 0x01, 0x5f, 0xee];
+        let edges = frame(data);
+
+        assert_eq!(edges.len(), FRAME_EDGES);
+        assert_eq!(decode_edges(&edges), Ok(data));
+        assert_eq!(decode_edges(&frame([0; 5])), Ok([0; 5]));
+        assert_eq!(decode_edges(&frame([0xff; 5])), Ok([0xff; 5]));
+    }
+
+    #[test]
+    fn decode_edges_relative_timing() {
+        // Scaling all pulses, as happens when the busy-wait loop is slowed down,
+        // doesn't change the decoded bits
+        let data = [0x35, 0x00, 0x18, 0x03, 0x50];
+        let edges: Vec<i64> = frame(data).iter().map(|edge| edge * 3 / 2).collect();
+
+        assert_eq!(decode_edges(&edges), Ok(data));
+    }
+
+    #[test]
+    fn decode_edges_incomplete() {
+        let edges = frame([0x02, 0x8c, 0x01, 0x5f, 0xee]);
+
+        assert_eq!(decode_edges(&[]), Err(Error::NoResponse));
+        assert_eq!(decode_edges(&edges[..2]), Err(Error::NoResponse));
+        assert_eq!(decode_edges(&edges[..3]), Err(Error::Incomplete(0)));
+        assert_eq!(decode_edges(&edges[..24]), Err(Error::Incomplete(10)));
+        assert_eq!(
+            decode_edges(&edges[..FRAME_EDGES - 1]),
+            Err(Error::Incomplete(39))
+        );
+    }
+
+    #[test]
+    fn decode_dht11() {
+        let reading = Model::Dht11.decode(with_checksum([45, 0, 23, 4])).unwrap();
+        assert_eq!(reading.humidity, 45.0);
+        assert_eq!(reading.temperature, 23.4);
+
+        let reading = Model::Dht11
+            .decode(with_checksum([60, 2, 1, 0x85]))
+            .unwrap();
+        assert_eq!(reading.humidity, 60.2);
+        assert_eq!(reading.temperature, -1.5);
+    }
+
+    #[test]
+    fn decode_dht22() {
+        // 65.2 %, 35.1 °C
+        let reading = Model::Dht22
+            .decode(with_checksum([0x02, 0x8c, 0x01, 0x5f]))
+            .unwrap();
+        assert_eq!(reading.humidity, 65.2);
+        assert_eq!(reading.temperature, 35.1);
+
+        // 100.0 %, -10.1 °C
+        let reading = Model::Dht22
+            .decode(with_checksum([0x03, 0xe8, 0x80, 0x65]))
+            .unwrap();
+        assert_eq!(reading.humidity, 100.0);
+        assert_eq!(reading.temperature, -10.1);
+    }
+
+    #[test]
+    fn decode_models_differ() {
+        let data = with_checksum([0x02, 0x8c, 0x01, 0x5f]);
+
+        assert_eq!(
+            Model::Dht11.decode(data),
+            Ok(Reading {
+                temperature: 1.0 + f64::from(0x5f) / 10.0,
+                humidity: 2.0 + f64::from(0x8c) / 10.0,
+            })
+        );
+        assert_eq!(
+            Model::Dht22.decode(data),
+            Ok(Reading {
+                temperature: 35.1,
+                humidity: 65.2,
+            })
+        );
+    }
+
+    #[test]
+    fn decode_checksum() {
+        assert_eq!(
+            Model::Dht22.decode([0x02, 0x8c, 0x01, 0x5f, 0xef]),
+            Err(Error::Checksum(0xee, 0xef))
+        );
+        assert_eq!(
+            Model::Dht11.decode([0, 0, 0, 0, 1]),
+            Err(Error::Checksum(0, 1))
+        );
+
+        // The checksum only includes the lowest 8 bits of the sum
+        assert!(Model::Dht22.decode([0xff, 0xff, 0x00, 0x02, 0x00]).is_ok());
+    }
+}
@@ -0,0 +1,941 @@
+//! Interface for MCP23017, MCP23S17 and PCF8574 GPIO port expanders.
+//!
+//! Port expanders add GPIO pins through an I2C or SPI bus. An [`Expander`] provides
+//! its own [`Gpio`] instance, so expander pins are retrieved and used like native pins,
+//! as [`InputPin`], [`OutputPin`] and [`IoPin`], and can be combined with anything that
+//! works with those types, such as [`Debouncer`] or [`Keypad`].
+//!
+//! ## Pin numbers
+//!
+//! The MCP23017 and MCP23S17 have 16 pins. Pins 0-7 refer to GPA0-GPA7, and pins 8-15
+//! refer to GPB0-GPB7. The PCF8574 has 8 pins, P0-P7, numbered 0-7.
+//!
+//! ## Register caching
+//!
+//! An [`Expander`] keeps a copy of the device's configuration and output registers.
+//! Changing a pin's mode, pull-up, polarity or output level only results in a bus
+//! transaction if the cached register value changes, and reading the level of an
+//! output pin returns the cached value. Only input levels are read from the device.
+//! If the device is reset or loses power, [`Expander::restore`] writes the cached
+//! registers back.
+//!
+//! Methods on the regular pin types can't return bus errors. Those errors are
+//! stored instead, and can be retrieved with [`Expander::take_error`].
+//!
+//! ## Interrupts
+//!
+//! Interrupt triggers configured on expander pins through [`InputPin::set_interrupt`],
+//! [`InputPin::set_async_interrupt`] or [`Gpio::poll_interrupts`] enable the device's
+//! interrupt-on-change feature for those pins. When the expander's interrupt output
+//! is connected to a native GPIO pin, [`Expander::set_interrupt_pin`] configures an
+//! asynchronous interrupt on that pin, which reads the changed pins from the device,
+//! and reports the rising and falling edges to the expander pins' interrupt triggers.
+//! Without an interrupt pin, changes are only detected when [`Expander::poll`] is called.
+//!
+//! Event timestamps are taken when the changes are read from the device, rather
+//! than when they occurred.
+//!
+//! ## Unsupported features
+//!
+//! Expander pins don't support alternate function modes, pad control settings,
+//! pull-down resistors or DMA-based PWM. Requests to select an alternate function
+//! mode or enable a pull-down resistor are ignored. Software-based PWM works, but
+//! every level change results in a bus transaction.
+//!
+//! [`Expander`]: struct.Expander.html
+//! [`Expander::restore`]: struct.Expander.html#method.restore
+//! [`Expander::take_error`]: struct.Expander.html#method.take_error
+//! [`Expander::set_interrupt_pin`]: struct.Expander.html#method.set_interrupt_pin
+//! [`Expander::poll`]: struct.Expander.html#method.poll
+//! [`Gpio`]: ../gpio/struct.Gpio.html
+//! [`Gpio::poll_interrupts`]: ../gpio/struct.Gpio.html#method.poll_interrupts
+//! [`InputPin`]: ../gpio/struct.InputPin.html
+//! [`InputPin::set_interrupt`]: ../gpio/struct.InputPin.html#method.set_interrupt
+//! [`InputPin::set_async_interrupt`]: ../gpio/struct.InputPin.html#method.set_async_interrupt
+//! [`OutputPin`]: ../gpio/struct.OutputPin.html
+//! [`IoPin`]: ../gpio/struct.IoPin.html
+//! [`Debouncer`]: ../gpio/struct.Debouncer.html
+//! [`Keypad`]: ../gpio/struct.Keypad.html
+
+use std::error;
+use std::fmt;
+use std::io;
+use std::result;
+use std::sync::{Arc, Mutex, Weak};
+
+use crate::gpio::backend::Backend;
+use crate::gpio::ioctl;
+use crate::gpio::sim::{self, Listener};
+use crate::gpio::{self, Gpio, InputPin, Level, Mode, Pin, PullUpDown, Trigger};
+use crate::i2c::{self, I2cBus};
+use crate::spi::{self, Spi};
+
+mod mcp23x17;
+mod pcf8574;
+
+use self::mcp23x17::{I2cRegisters, Mcp23x17, SpiRegisters};
+use self::pcf8574::Pcf8574;
+
+/// Errors that can occur when accessing a port expander.
+#[derive(Debug)]
+pub enum Error {
+    /// GPIO error.
+    Gpio(gpio::Error),
+    /// I2C error.
+    I2c(i2c::Error),
+    /// SPI error.
+    Spi(spi::Error),
+    /// The expander doesn't have a pin with the specified number.
+    InvalidPin(u8),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Error::Gpio(ref err) => write!(f, "GPIO error: {}", err),
+            Error::I2c(ref err) => write!(f, "I2C error: {}", err),
+            Error::Spi(ref err) => write!(f, "SPI error: {}", err),
+            Error::InvalidPin(pin) => write!(f, "Invalid pin number: {}", pin),
+        }
+    }
+}
+
+impl error::Error for Error {}
+
+impl From<gpio::Error> for Error {
+    fn from(err: gpio::Error) -> Error {
+        Error::Gpio(err)
+    }
+}
+
+impl From<i2c::Error> for Error {
+    fn from(err: i2c::Error) -> Error {
+        Error::I2c(err)
+    }
+}
+
+impl From<spi::Error> for Error {
+    fn from(err: spi::Error) -> Error {
+        Error::Spi(err)
+    }
+}
+
+/// Result type returned from methods that can have `expander::Error`s.
+pub type Result<T> = result::Result<T, Error>;
+
+/// Supported port expanders.
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub enum Chip {
+    /// Microchip MCP23017. 16 pins, I2C.
+    Mcp23017,
+    /// Microchip MCP23S17. 16 pins, SPI.
+    Mcp23s17,
+    /// NXP/TI PCF8574 or PCF8574A. 8 quasi-bidirectional pins, I2C.
+    Pcf8574,
+}
+
+impl Chip {
+    /// Returns the number of pins.
+    pub fn pin_count(self) -> u8 {
+        match self {
+            Chip::Mcp23017 | Chip::Mcp23s17 => 16,
+            Chip::Pcf8574 => 8,
+        }
+    }
+}
+
+impl fmt::Display for Chip {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Chip::Mcp23017 => write!(f, "MCP23017"),
+            Chip::Mcp23s17 => write!(f, "MCP23S17"),
+            Chip::Pcf8574 => write!(f, "PCF8574"),
+        }
+    }
+}
+
+// Pin configuration as seen by the pin types, with bit n representing pin n
+#[derive(Debug, Default, PartialEq, Eq, Copy, Clone)]
+struct State {
+    output: u16,
+    latch: u16,
+    pullup: u16,
+    inverted: u16,
+    interrupt: u16,
+}
+
+// Register access for a specific device
+trait Device: fmt::Debug + Send {
+    // Reads or initializes the device's configuration
+    fn init(&mut self) -> Result<State>;
+
+    // Writes the registers that differ between old and new. If old is None, all
+    // registers are written, including any device-level configuration.
+    fn configure(&mut self, old: Option<&State>, new: &State) -> Result<()>;
+
+    // Returns the levels of all input pins, with polarity inversion applied
+    fn read_levels(&mut self, state: &State) -> Result<u16>;
+
+    // Returns the pins that triggered an interrupt, the levels captured when the
+    // interrupt triggered, and the current levels, and clears the interrupt
+    fn read_interrupt(&mut self, state: &State) -> Result<(u16, u16, u16)>;
+
+    // Returns false if the pins are always pulled up
+    fn has_pullups(&self) -> bool {
+        true
+    }
+}
+
+#[derive(Debug)]
+struct Inner {
+    device: Box<dyn Device>,
+    state: State,
+    // Most recently read input levels
+    levels: u16,
+    // Input levels as of the most recent interrupt check, used for edge detection
+    reported: u16,
+}
+
+#[derive(Debug)]
+struct ExpanderBackend {
+    chip: Chip,
+    inner: Mutex<Inner>,
+    listeners: Mutex<Vec<Vec<Listener>>>,
+    error: Mutex<Option<Error>>,
+}
+
+impl ExpanderBackend {
+    fn new(chip: Chip, mut device: Box<dyn Device>) -> Result<ExpanderBackend> {
+        let state = device.init()?;
+        let levels = device.read_levels(&state)?;
+
+        Ok(ExpanderBackend {
+            chip,
+            inner: Mutex::new(Inner {
+                device,
+                state,
+                levels,
+                reported: levels,
+            }),
+            listeners: Mutex::new((0..chip.pin_count()).map(|_| Vec::new()).collect()),
+            error: Mutex::new(None),
+        })
+    }
+
+    // Pins that don't exist map to an empty mask, which turns any changes into no-ops
+    fn mask(&self, pin: u8) -> u16 {
+        if pin < self.chip.pin_count() {
+            1 << pin
+        } else {
+            0
+        }
+    }
+
+    fn store_error(&self, err: Error) {
+        *self.error.lock().unwrap() = Some(err);
+    }
+
+    // Applies a change to the cached configuration, and only writes the modified registers
+    fn update<F>(&self, f: F) -> Result<()>
+    where
+        F: FnOnce(&mut State),
+    {
+        let mut inner = self.inner.lock().unwrap();
+
+        let mut state = inner.state;
+        f(&mut state);
+        if state == inner.state {
+            return Ok(());
+        }
+
+        let old = inner.state;
+        inner.device.configure(Some(&old), &state)?;
+        inner.state = state;
+
+        Ok(())
+    }
+
+    // Used by the pin types, which can't return errors
+    fn update_or_store<F>(&self, f: F)
+    where
+        F: FnOnce(&mut State),
+    {
+        if let Err(err) = self.update(f) {
+            self.store_error(err);
+        }
+    }
+
+    fn read_levels(&self, mask: u16) -> u16 {
+        let mut inner = self.inner.lock().unwrap();
+        let state = inner.state;
+
+        // Output levels are cached, so the bus is only accessed for inputs
+        if mask & !state.output != 0 {
+            match inner.device.read_levels(&state) {
+                Ok(levels) => inner.levels = levels,
+                Err(err) => self.store_error(err),
+            }
+        }
+
+        ((inner.levels & !state.output) | (state.latch & state.output)) & mask
+    }
+
+    fn poll(&self) -> Result<()> {
+        let mut inner = self.inner.lock().unwrap();
+        let state = inner.state;
+        if state.interrupt == 0 {
+            return Ok(());
+        }
+
+        let (flags, captured, levels) = inner.device.read_interrupt(&state)?;
+        let previous = inner.reported;
+        inner.levels = levels;
+        inner.reported = levels;
+
+        let mut listeners = self.listeners.lock().unwrap();
+        let mut interrupt = state.interrupt;
+        for (pin, pin_listeners) in listeners.iter_mut().enumerate() {
+            let mask = 1 << pin;
+            if state.interrupt & mask == 0 {
+                continue;
+            }
+
+            // Report the captured level first, in case the pin changed again before
+            // the interrupt was handled
+            let mut level = previous & mask;
+            if flags & mask != 0 {
+                notify(pin_listeners, level, captured & mask);
+                level = captured & mask;
+            }
+            notify(pin_listeners, level, levels & mask);
+
+            if pin_listeners.is_empty() {
+                interrupt &= !mask;
+            }
+        }
+
+        // Disable interrupt-on-change for pins without any remaining triggers
+        if interrupt != state.interrupt {
+            let new = State { interrupt, ..state };
+            inner.device.configure(Some(&state), &new)?;
+            inner.state = new;
+        }
+
+        Ok(())
+    }
+}
+
+fn notify(listeners: &mut Vec<Listener>, previous: u16, current: u16) {
+    match (previous != 0, current != 0) {
+        (false, true) => sim::notify(listeners, Trigger::RisingEdge),
+        (true, false) => sim::notify(listeners, Trigger::FallingEdge),
+        _ => (),
+    }
+}
+
+impl Backend for ExpanderBackend {
+    fn set_high(&self, pin: u8) {
+        let mask = self.mask(pin);
+        self.update_or_store(|state| state.latch |= mask);
+    }
+
+    fn set_low(&self, pin: u8) {
+        let mask = self.mask(pin);
+        self.update_or_store(|state| state.latch &= !mask);
+    }
+
+    fn level(&self, pin: u8) -> Level {
+        if self.read_levels(self.mask(pin)) != 0 {
+            Level::High
+        } else {
+            Level::Low
+        }
+    }
+
+    fn mode(&self, pin: u8) -> Mode {
+        if self.inner.lock().unwrap().state.output & self.mask(pin) != 0 {
+            Mode::Output
+        } else {
+            Mode::Input
+        }
+    }
+
+    fn set_mode(&self, pin: u8, mode: Mode) {
+        let mask = self.mask(pin);
+        match mode {
+            Mode::Input => self.update_or_store(|state| state.output &= !mask),
+            Mode::Output => self.update_or_store(|state| state.output |= mask),
+            _ => (),
+        }
+    }
+
+    fn set_pullupdown(&self, pin: u8, pud: PullUpDown) {
+        let mask = self.mask(pin);
+        match pud {
+            PullUpDown::PullUp => self.update_or_store(|state| state.pullup |= mask),
+            PullUpDown::Off => self.update_or_store(|state| state.pullup &= !mask),
+            _ => (),
+        }
+    }
+
+    fn pullupdown(&self, pin: u8) -> Option<PullUpDown> {
+        let inner = self.inner.lock().unwrap();
+        if !inner.device.has_pullups() || inner.state.pullup & self.mask(pin) != 0 {
+            Some(PullUpDown::PullUp)
+        } else {
+            Some(PullUpDown::Off)
+        }
+    }
+
+    fn pin_count(&self) -> usize {
+        self.chip.pin_count() as usize
+    }
+
+    fn write_levels(&self, set_mask: u64, clear_mask: u64) {
+        let (set_mask, clear_mask) = (set_mask as u16, clear_mask as u16);
+        self.update_or_store(|state| state.latch = (state.latch | set_mask) & !clear_mask);
+    }
+
+    fn levels(&self, mask: u64) -> u64 {
+        u64::from(self.read_levels(mask as u16))
+    }
+
+    fn event_request(
+        &self,
+        pin: u8,
+        config: &ioctl::EventConfig,
+    ) -> gpio::Result<ioctl::EventHandle> {
+        let mask = self.mask(pin);
+        if mask == 0 {
+            return Err(gpio::Error::PinNotAvailable(pin, None));
+        }
+
+        let (listener, event_handle) = Listener::new(pin, config.trigger)?;
+
+        let mut inner = self.inner.lock().unwrap();
+        let state = inner.state;
+        if state.interrupt & mask == 0 {
+            // Start detecting edges from the pin's current level
+            let levels = inner.device.read_levels(&state).map_err(into_gpio_error)?;
+            inner.reported = (inner.reported & !mask) | (levels & mask);
+            inner.levels = levels;
+
+            let new = State {
+                interrupt: state.interrupt | mask,
+                ..state
+            };
+            inner
+                .device
+                .configure(Some(&state), &new)
+                .map_err(into_gpio_error)?;
+            inner.state = new;
+        }
+
+        self.listeners.lock().unwrap()[pin as usize].push(listener);
+
+        Ok(event_handle)
+    }
+
+    fn release(&self, pin: u8) {
+        let mask = self.mask(pin);
+        if mask == 0 {
+            return;
+        }
+
+        self.listeners.lock().unwrap()[pin as usize].clear();
+        self.update_or_store(|state| state.interrupt &= !mask);
+    }
+}
+
+fn into_gpio_error(err: Error) -> gpio::Error {
+    match err {
+        Error::Gpio(err) => err,
+        Error::I2c(i2c::Error::Io(err)) | Error::Spi(spi::Error::Io(err)) => gpio::Error::Io(err),
+        Error::InvalidPin(pin) => gpio::Error::PinNotAvailable(pin, None),
+        // Configuration errors, such as an invalid slave address
+        err => gpio::Error::Io(io::Error::new(io::ErrorKind::InvalidInput, err.to_string())),
+    }
+}
+
+/// Provides access to the pins of an MCP23017, MCP23S17 or PCF8574 port expander.
+///
+/// Pins are retrieved through [`get`], or through the [`Gpio`] instance returned by
+/// [`gpio`], and can then be used like native GPIO pins. More information on
+/// register caching and interrupts can be found [here].
+///
+/// `Expander` is thread-safe, and can be shared between threads through an [`Arc`].
+/// Pins retrieved from the same `Expander` can be moved to different threads.
+///
+/// ## Example
+///
+/// ```no_run
+/// use std::error::Error;
+///
+/// use rpi_embedded::expander::Expander;
+/// use rpi_embedded::gpio::{Gpio, Trigger};
+/// use rpi_embedded::i2c::I2c;
+///
+/// # fn main() -> Result<(), Box<dyn Error>> {
+/// let expander = Expander::mcp23017(I2c::new()?, 0x20)?;
+///
+/// // The expander's INTA output is connected to BCM GPIO 17
+/// expander.set_interrupt_pin(Gpio::new()?.get(17)?.into_input_pullup())?;
+///
+/// let mut led = expander.get(0)?.into_output();
+/// let mut button = expander.get(8)?.into_input_pullup();
+/// expander.set_inverted(8, true)?;
+///
+/// button.set_interrupt(Trigger::RisingEdge)?;
+/// while button.poll_interrupt(true, None)?.is_some() {
+///     led.toggle();
+/// }
+/// # Ok(())
+/// # }
+/// ```
+///
+/// [`get`]: #method.get
+/// [`gpio`]: #method.gpio
+/// [`Gpio`]: ../gpio/struct.Gpio.html
+/// [`Arc`]: https://doc.rust-lang.org/std/sync/struct.Arc.html
+/// [here]: index.html
+#[derive(Debug)]
+pub struct Expander {
+    backend: Arc<ExpanderBackend>,
+    gpio: Gpio,
+    interrupt_pin: Mutex<Option<InputPin>>,
+}
+
+impl Expander {
+    /// Constructs a new `Expander` for an MCP23017 at the specified I2C slave address.
+    ///
+    /// The MCP23017's address is 0x20-0x27, depending on the A0-A2 pins. The current
+    /// pin configuration is read from the device, and its `IOCON` register is set to
+    /// mirror the INTA and INTB outputs and configure them as open-drain, so either one
+    /// can be used as the interrupt output, and multiple expanders can share the same
+    /// interrupt pin. `IOCON.BANK` is expected to be 0, which is the power-on default.
+    pub fn mcp23017<B>(mut bus: B, address: u16) -> Result<Expander>
+    where
+        B: I2cBus + Send + 'static,
+    {
+        bus.set_slave_address(address)?;
+
+        Expander::new(
+            Chip::Mcp23017,
+            Box::new(Mcp23x17::new(I2cRegisters::new(bus))),
+        )
+    }
+
+    /// Constructs a new `Expander` for an MCP23S17 with the specified hardware address.
+    ///
+    /// The MCP23S17's hardware address is 0-7, depending on the A0-A2 pins. `spi`
+    /// should be configured for mode 0 with a clock speed of up to 10 MHz. Hardware
+    /// addressing is enabled, so multiple devices can share the same Slave Select pin.
+    /// See [`mcp23017`] for the `IOCON` configuration.
+    ///
+    /// [`mcp23017`]: #method.mcp23017
+    pub fn mcp23s17(spi: Spi, address: u8) -> Result<Expander> {
+        Expander::new(
+            Chip::Mcp23s17,
+            Box::new(Mcp23x17::new(SpiRegisters::new(spi, address))),
+        )
+    }
+
+    /// Constructs a new `Expander` for a PCF8574 at the specified I2C slave address.
+    ///
+    /// The PCF8574's address is 0x20-0x27, and the PCF8574A's address is 0x38-0x3f,
+    /// depending on the A0-A2 pins.
+    ///
+    /// The PCF8574 doesn't have any configuration registers. Its quasi-bidirectional pins
+    /// are either driven low, or weakly pulled up, which allows them to be used as inputs.
+    /// All pins are set to [`Input`] mode during construction. Polarity inversion is
+    /// applied in software, and the pull-up can't be disabled.
+    ///
+    /// [`Input`]: ../gpio/enum.Mode.html#variant.Input
+    pub fn pcf8574<B>(mut bus: B, address: u16) -> Result<Expander>
+    where
+        B: I2cBus + Send + 'static,
+    {
+        bus.set_slave_address(address)?;
+
+        Expander::new(Chip::Pcf8574, Box::new(Pcf8574::new(bus)))
+    }
+
+    fn new(chip: Chip, device: Box<dyn Device>) -> Result<Expander> {
+        let backend = Arc::new(ExpanderBackend::new(chip, device)?);
+        let gpio = Gpio::with_backend(backend.clone())?;
+
+        Ok(Expander {
+            backend,
+            gpio,
+            interrupt_pin: Mutex::new(None),
+        })
+    }
+
+    /// Returns the type of port expander.
+    pub fn chip(&self) -> Chip {
+        self.backend.chip
+    }
+
+    /// Returns the number of pins.
+    pub fn pin_count(&self) -> u8 {
+        self.backend.chip.pin_count()
+    }
+
+    /// Returns a [`Gpio`] instance that provides access to the expander's pins.
+    ///
+    /// Pin numbers passed to the returned `Gpio` refer to expander pins rather than
+    /// BCM GPIO pins.
+    ///
+    /// [`Gpio`]: ../gpio/struct.Gpio.html
+    pub fn gpio(&self) -> Gpio {
+        self.gpio.clone()
+    }
+
+    /// Returns a [`Pin`] for the specified expander pin.
+    ///
+    /// See [`Gpio::get`] for more information.
+    ///
+    /// [`Pin`]: ../gpio/struct.Pin.html
+    /// [`Gpio::get`]: ../gpio/struct.Gpio.html#method.get
+    pub fn get(&self, pin: u8) -> gpio::Result<Pin> {
+        self.gpio.get(pin)
+    }
+
+    /// Returns `true` if polarity inversion is enabled for the specified pin.
+    pub fn is_inverted(&self, pin: u8) -> bool {
+        self.backend.inner.lock().unwrap().state.inverted & self.backend.mask(pin) != 0
+    }
+
+    /// Enables or disables polarity inversion for the specified pin.
+    ///
+    /// When polarity inversion is enabled, the logic level read from an input pin is
+    /// inverted, including the level used to detect rising and falling edges for
+    /// interrupt triggers. Output levels aren't affected.
+    pub fn set_inverted(&self, pin: u8, inverted: bool) -> Result<()> {
+        let mask = self.backend.mask(pin);
+        if mask == 0 {
+            return Err(Error::InvalidPin(pin));
+        }
+
+        self.backend.update(|state| {
+            if inverted {
+                state.inverted |= mask;
+            } else {
+                state.inverted &= !mask;
+            }
+        })
+    }
+
+    /// Configures the native GPIO pin that's connected to the expander's interrupt output.
+    ///
+    /// An asynchronous falling-edge interrupt is configured on `pin`, which calls [`poll`]
+    /// whenever the expander signals a change. The interrupt output is open-drain, so
+    /// `pin` should have its pull-up resistor enabled, for instance by retrieving it with
+    /// [`Pin::into_input_pullup`]. Bus errors that occur while handling an interrupt are
+    /// available through [`take_error`].
+    ///
+    /// Any previously configured interrupt pin is released.
+    ///
+    /// [`poll`]: #method.poll
+    /// [`Pin::into_input_pullup`]: ../gpio/struct.Pin.html#method.into_input_pullup
+    /// [`take_error`]: #method.take_error
+    pub fn set_interrupt_pin(&self, mut pin: InputPin) -> Result<()> {
+        // A weak reference avoids a reference cycle through the pin's interrupt thread
+        let backend: Weak<ExpanderBackend> = Arc::downgrade(&self.backend);
+        pin.set_async_interrupt(Trigger::FallingEdge, move |_| {
+            if let Some(backend) = backend.upgrade() {
+                if let Err(err) = backend.poll() {
+                    backend.store_error(err);
+                }
+            }
+        })?;
+
+        *self.interrupt_pin.lock().unwrap() = Some(pin);
+
+        // Changes that occurred earlier keep the interrupt output low until they're read
+        self.poll()
+    }
+
+    /// Removes the interrupt pin configured by [`set_interrupt_pin`], and returns it.
+    ///
+    /// [`set_interrupt_pin`]: #method.set_interrupt_pin
+    pub fn clear_interrupt_pin(&self) -> Result<Option<InputPin>> {
+        let pin = self.interrupt_pin.lock().unwrap().take();
+
+        match pin {
+            Some(mut pin) => {
+                pin.clear_async_interrupt()?;
+                Ok(Some(pin))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Reads the pins that changed since the previous call from the device, and reports
+    /// their rising and falling edges to any interrupt triggers configured on those pins.
+    ///
+    /// `poll` is called automatically when an interrupt pin has been configured with
+    /// [`set_interrupt_pin`]. Without an interrupt pin, call `poll` periodically.
+    /// Changes that don't persist until `poll` is called may be missed, unless the
+    /// device captured them. The MCP23017 and MCP23S17 capture the levels at the time
+    /// of the first change.
+    ///
+    /// [`set_interrupt_pin`]: #method.set_interrupt_pin
+    pub fn poll(&self) -> Result<()> {
+        self.backend.poll()
+    }
+
+    /// Writes the cached configuration and output levels to the device.
+    ///
+    /// Use `restore` after the device has been reset or lost power.
+    pub fn restore(&self) -> Result<()> {
+        let mut inner = self.backend.inner.lock().unwrap();
+        let state = inner.state;
+
+        inner.device.configure(None, &state)
+    }
+
+    /// Returns and clears the most recent bus error that occurred while a pin was
+    /// accessed through one of the regular pin types, or while handling an interrupt.
+    pub fn take_error(&self) -> Option<Error> {
+        self.backend.error.lock().unwrap().take()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::time::Duration;
+
+    use self::mcp23x17::Registers;
+
+    // MCP23x17 register addresses for port A
+    const IODIR_A: u8 = 0x00;
+    const IPOL_A: u8 = 0x02;
+    const OLAT_A: u8 = 0x14;
+
+    #[derive(Debug, Default)]
+    struct FakeState {
+        configured: Vec<(Option<State>, State)>,
+        levels: u16,
+        // Interrupt flags and captured levels returned by the next read_interrupt
+        flags: u16,
+        captured: u16,
+    }
+
+    #[derive(Debug, Clone, Default)]
+    struct FakeDevice(Arc<Mutex<FakeState>>);
+
+    impl FakeDevice {
+        fn set_levels(&self, levels: u16) {
+            self.0.lock().unwrap().levels = levels;
+        }
+
+        fn set_interrupt(&self, flags: u16, captured: u16, levels: u16) {
+            let mut state = self.0.lock().unwrap();
+            state.flags = flags;
+            state.captured = captured;
+            state.levels = levels;
+        }
+
+        fn take_configured(&self) -> Vec<(Option<State>, State)> {
+            self.0.lock().unwrap().configured.drain(..).collect()
+        }
+    }
+
+    impl Device for FakeDevice {
+        fn init(&mut self) -> Result<State> {
+            Ok(State::default())
+        }
+
+        fn configure(&mut self, old: Option<&State>, new: &State) -> Result<()> {
+            self.0.lock().unwrap().configured.push((old.copied(), *new));
+
+            Ok(())
+        }
+
+        fn read_levels(&mut self, _state: &State) -> Result<u16> {
+            Ok(self.0.lock().unwrap().levels)
+        }
+
+        fn read_interrupt(&mut self, _state: &State) -> Result<(u16, u16, u16)> {
+            let mut state = self.0.lock().unwrap();
+            let flags = state.flags;
+            state.flags = 0;
+
+            Ok((flags, state.captured, state.levels))
+        }
+    }
+
+    // Register file that records every write
+    #[derive(Default)]
+    struct RegisterFile {
+        registers: [u8; 0x16],
+        writes: Vec<(u8, Vec<u8>)>,
+    }
+
+    #[derive(Clone, Default)]
+    struct FakeRegisters(Arc<Mutex<RegisterFile>>);
+
+    impl FakeRegisters {
+        fn take_writes(&self) -> Vec<(u8, Vec<u8>)> {
+            self.0.lock().unwrap().writes.drain(..).collect()
+        }
+    }
+
+    impl Registers for FakeRegisters {
+        fn read(&mut self, register: u8, buffer: &mut [u8]) -> Result<()> {
+            let registers = &self.0.lock().unwrap().registers;
+            let start = register as usize;
+            buffer.copy_from_slice(&registers[start..start + buffer.len()]);
+
+            Ok(())
+        }
+
+        fn write(&mut self, register: u8, buffer: &[u8]) -> Result<()> {
+            let mut file = self.0.lock().unwrap();
+            let start = register as usize;
+            file.registers[start..start + buffer.len()].copy_from_slice(buffer);
+            file.writes.push((register, buffer.to_vec()));
+
+            Ok(())
+        }
+    }
+
+    fn expander(device: &FakeDevice) -> Expander {
+        Expander::new(Chip::Mcp23017, Box::new(device.clone())).unwrap()
+    }
+
+    #[test]
+    fn unchanged_state_isnt_written() {
+        let device = FakeDevice::default();
+        let expander = expander(&device);
+
+        let mut pin = expander.get(3).unwrap().into_output();
+        assert_eq!(
+            device.take_configured(),
+            [(
+                Some(State::default()),
+                State {
+                    output: 0x08,
+                    ..State::default()
+                }
+            )]
+        );
+
+        pin.set_low();
+        assert_eq!(device.take_configured(), []);
+
+        pin.set_high();
+        pin.set_high();
+        assert_eq!(
+            device.take_configured(),
+            [(
+                Some(State {
+                    output: 0x08,
+                    ..State::default()
+                }),
+                State {
+                    output: 0x08,
+                    latch: 0x08,
+                    ..State::default()
+                }
+            )]
+        );
+
+        expander.set_inverted(5, false).unwrap();
+        assert_eq!(device.take_configured(), []);
+
+        // restore writes the full configuration
+        expander.restore().unwrap();
+        assert_eq!(device.take_configured().len(), 1);
+    }
+
+    #[test]
+    fn mcp23x17_changed_registers() {
+        let registers = FakeRegisters::default();
+        // All pins are inputs after a power-on reset
+        registers.clone().write(IODIR_A, &[0xff, 0xff]).unwrap();
+
+        let expander =
+            Expander::new(Chip::Mcp23017, Box::new(Mcp23x17::new(registers.clone()))).unwrap();
+        registers.take_writes();
+
+        let mut pin = expander.get(9).unwrap().into_output();
+        assert_eq!(registers.take_writes(), [(IODIR_A, vec![0xff, 0xfd])]);
+
+        pin.set_high();
+        assert_eq!(registers.take_writes(), [(OLAT_A, vec![0x00, 0x02])]);
+
+        pin.set_high();
+        assert_eq!(registers.take_writes(), []);
+
+        pin.set_low();
+        assert_eq!(registers.take_writes(), [(OLAT_A, vec![0x00, 0x00])]);
+
+        expander.set_inverted(0, true).unwrap();
+        assert_eq!(registers.take_writes(), [(IPOL_A, vec![0x01, 0x00])]);
+    }
+
+    #[test]
+    fn poll_edges() {
+        let device = FakeDevice::default();
+        let expander = expander(&device);
+
+        let mut pin = expander.get(2).unwrap().into_input();
+        let mut rising = expander.get(4).unwrap().into_input();
+        let _other = expander.get(6).unwrap().into_input();
+        pin.set_interrupt(Trigger::Both).unwrap();
+        rising.set_interrupt(Trigger::RisingEdge).unwrap();
+
+        let configured = device.take_configured();
+        assert_eq!(configured.last().unwrap().1.interrupt, 0x14);
+
+        let timeout = Some(Duration::from_millis(10));
+        let poll_level = |pin: &mut InputPin| {
+            pin.poll_interrupt(false, timeout)
+                .unwrap()
+                .map(|event| event.level)
+        };
+
+        // Nothing changed
+        expander.poll().unwrap();
+        assert_eq!(poll_level(&mut pin), None);
+
+        // Pin 2 went high, and pin 6 changed without an interrupt trigger
+        device.set_interrupt(0x44, 0x04, 0x44);
+        expander.poll().unwrap();
+        assert_eq!(poll_level(&mut pin), Some(Level::High));
+        assert_eq!(poll_level(&mut pin), None);
+
+        // Pin 2 went low and high again before the interrupt was handled. The
+        // captured level is reported first.
+        device.set_interrupt(0x04, 0x00, 0x04);
+        expander.poll().unwrap();
+        assert_eq!(poll_level(&mut pin), Some(Level::Low));
+        assert_eq!(poll_level(&mut pin), Some(Level::High));
+        assert_eq!(poll_level(&mut pin), None);
+
+        // Pin 2 went low after the interrupt, pin 4 went high and low again
+        device.set_interrupt(0x10, 0x14, 0x00);
+        expander.poll().unwrap();
+        assert_eq!(poll_level(&mut pin), Some(Level::Low));
+        assert_eq!(poll_level(&mut pin), None);
+        assert_eq!(poll_level(&mut rising), Some(Level::High));
+        assert_eq!(poll_level(&mut rising), None);
+
+        // Changes without interrupt flags are detected by comparing the levels
+        device.set_levels(0x14);
+        expander.poll().unwrap();
+        assert_eq!(poll_level(&mut pin), Some(Level::High));
+        assert_eq!(poll_level(&mut rising), Some(Level::High));
+
+        // Interrupt-on-change is disabled for the pin once an edge can't be
+        // delivered after clearing its trigger
+        pin.clear_interrupt().unwrap();
+        device.set_levels(0x10);
+        expander.poll().unwrap();
+        assert_eq!(device.take_configured().last().unwrap().1.interrupt, 0x10);
+    }
+}
@@ -0,0 +1,203 @@
+use std::fmt;
+
+use super::{Device, Result, State};
+use crate::i2c::I2cBus;
+use crate::spi::Spi;
+
+// Register addresses with IOCON.BANK = 0, where each port B register directly
+// follows its port A counterpart
+const IODIR: u8 = 0x00;
+const IPOL: u8 = 0x02;
+const GPINTEN: u8 = 0x04;
+const INTCON: u8 = 0x08;
+const IOCON: u8 = 0x0a;
+const GPPU: u8 = 0x0c;
+const INTF: u8 = 0x0e;
+const GPIO: u8 = 0x12;
+const OLAT: u8 = 0x14;
+
+const IOCON_MIRROR: u8 = 0x40;
+const IOCON_HAEN: u8 = 0x08;
+const IOCON_ODR: u8 = 0x04;
+
+const SPI_OPCODE: u8 = 0x40;
+const SPI_READ: u8 = 0x01;
+
+// Sequential register access over I2C or SPI
+pub(super) trait Registers: Send {
+    fn read(&mut self, register: u8, buffer: &mut [u8]) -> Result<()>;
+    fn write(&mut self, register: u8, buffer: &[u8]) -> Result<()>;
+}
+
+pub(super) struct I2cRegisters<B> {
+    bus: B,
+}
+
+impl<B: I2cBus> I2cRegisters<B> {
+    pub(super) fn new(bus: B) -> I2cRegisters<B> {
+        I2cRegisters { bus }
+    }
+}
+
+impl<B: I2cBus + Send> Registers for I2cRegisters<B> {
+    fn read(&mut self, register: u8, buffer: &mut [u8]) -> Result<()> {
+        Ok(self.bus.write_read(&[register], buffer)?)
+    }
+
+    fn write(&mut self, register: u8, buffer: &[u8]) -> Result<()> {
+        let mut data = Vec::with_capacity(buffer.len() + 1);
+        data.push(register);
+        data.extend_from_slice(buffer);
+
+        self.bus.write(&data)?;
+
+        Ok(())
+    }
+}
+
+pub(super) struct SpiRegisters {
+    spi: Spi,
+    opcode: u8,
+}
+
+impl SpiRegisters {
+    pub(super) fn new(spi: Spi, address: u8) -> SpiRegisters {
+        SpiRegisters {
+            spi,
+            opcode: SPI_OPCODE | ((address & 0x07) << 1),
+        }
+    }
+}
+
+impl Registers for SpiRegisters {
+    fn read(&mut self, register: u8, buffer: &mut [u8]) -> Result<()> {
+        let mut write_buffer = vec![0u8; buffer.len() + 2];
+        write_buffer[0] = self.opcode | SPI_READ;
+        write_buffer[1] = register;
+        let mut read_buffer = vec![0u8; write_buffer.len()];
+
+        self.spi.transfer(&mut read_buffer, &write_buffer)?;
+        buffer.copy_from_slice(&read_buffer[2..]);
+
+        Ok(())
+    }
+
+    fn write(&mut self, register: u8, buffer: &[u8]) -> Result<()> {
+        let mut data = Vec::with_capacity(buffer.len() + 2);
+        data.push(self.opcode);
+        data.push(register);
+        data.extend_from_slice(buffer);
+
+        self.spi.write(&data)?;
+
+        Ok(())
+    }
+}
+
+// MCP23017 and MCP23S17, which share the same register layout
+pub(super) struct Mcp23x17<R> {
+    registers: R,
+}
+
+impl<R: Registers> Mcp23x17<R> {
+    pub(super) fn new(registers: R) -> Mcp23x17<R> {
+        Mcp23x17 { registers }
+    }
+
+    fn read_u16(&mut self, register: u8) -> Result<u16> {
+        let mut buffer = [0u8; 2];
+        self.registers.read(register, &mut buffer)?;
+
+        Ok(u16::from_le_bytes(buffer))
+    }
+
+    fn write_u16(&mut self, register: u8, value: u16) -> Result<()> {
+        self.registers.write(register, &value.to_le_bytes())
+    }
+
+    // INTA and INTB are combined and open-drain, so either one can be wired to a
+    // shared interrupt pin. HAEN enables the hardware address on the MCP23S17.
+    fn configure_iocon(&mut self) -> Result<()> {
+        let iocon = IOCON_MIRROR | IOCON_HAEN | IOCON_ODR;
+
+        self.registers.write(IOCON, &[iocon, iocon])
+    }
+}
+
+impl<R> fmt::Debug for Mcp23x17<R> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Mcp23x17").finish()
+    }
+}
+
+impl<R: Registers> Device for Mcp23x17<R> {
+    fn init(&mut self) -> Result<State> {
+        self.configure_iocon()?;
+
+        // Keep the existing pin configuration, but start without any interrupts
+        let state = State {
+            output: !self.read_u16(IODIR)?,
+            latch: self.read_u16(OLAT)?,
+            pullup: self.read_u16(GPPU)?,
+            inverted: self.read_u16(IPOL)?,
+            interrupt: 0,
+        };
+
+        // Interrupt-on-change compares against the previous value rather than DEFVAL
+        self.write_u16(GPINTEN, 0)?;
+        self.write_u16(INTCON, 0)?;
+
+        Ok(state)
+    }
+
+    fn configure(&mut self, old: Option<&State>, new: &State) -> Result<()> {
+        if old.is_none() {
+            self.configure_iocon()?;
+            self.write_u16(INTCON, 0)?;
+        }
+
+        let changed = |f: fn(&State) -> u16| match old {
+            Some(old) => f(old) != f(new),
+            None => true,
+        };
+
+        // Set the output latch before switching pins to output mode, to avoid glitches
+        if changed(|state| state.latch) {
+            self.write_u16(OLAT, new.latch)?;
+        }
+
+        if changed(|state| state.pullup) {
+            self.write_u16(GPPU, new.pullup)?;
+        }
+
+        if changed(|state| state.inverted) {
+            self.write_u16(IPOL, new.inverted)?;
+        }
+
+        if changed(|state| state.output) {
+            self.write_u16(IODIR, !new.output)?;
+        }
+
+        if changed(|state| state.interrupt) {
+            self.write_u16(GPINTEN, new.interrupt)?;
+        }
+
+        Ok(())
+    }
+
+    fn read_levels(&mut self, _state: &State) -> Result<u16> {
+        self.read_u16(GPIO)
+    }
+
+    fn read_interrupt(&mut self, _state: &State) -> Result<(u16, u16, u16)> {
+        // INTF, INTCAP and GPIO are adjacent. Reading INTCAP or GPIO clears the interrupt.
+        let mut buffer = [0u8; 6];
+        self.registers.read(INTF, &mut buffer)?;
+
+        Ok((
+            u16::from_le_bytes([buffer[0], buffer[1]]),
+            u16::from_le_bytes([buffer[2], buffer[3]]),
+            u16::from_le_bytes([buffer[4], buffer[5]]),
+        ))
+    }
+}
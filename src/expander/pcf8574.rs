@@ -0,0 +1,68 @@
+use std::fmt;
+
+use super::{Device, Result, State};
+use crate::i2c::I2cBus;
+
+// PCF8574 and PCF8574A. Writing a 1 to a pin's bit enables a weak pull-up, which
+// lets the pin be used as an input. Writing a 0 drives the pin low.
+pub(super) struct Pcf8574<B> {
+    bus: B,
+}
+
+impl<B: I2cBus> Pcf8574<B> {
+    pub(super) fn new(bus: B) -> Pcf8574<B> {
+        Pcf8574 { bus }
+    }
+
+    fn write_port(&mut self, state: &State) -> Result<()> {
+        self.bus.write(&[port(state)])?;
+
+        Ok(())
+    }
+}
+
+// Inputs are released high, outputs reflect their latch
+fn port(state: &State) -> u8 {
+    (!state.output | state.latch) as u8
+}
+
+impl<B> fmt::Debug for Pcf8574<B> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Pcf8574").finish()
+    }
+}
+
+impl<B: I2cBus + Send> Device for Pcf8574<B> {
+    fn init(&mut self) -> Result<State> {
+        // The port can't be read back, so start with all pins set to input
+        let state = State::default();
+        self.write_port(&state)?;
+
+        Ok(state)
+    }
+
+    fn configure(&mut self, old: Option<&State>, new: &State) -> Result<()> {
+        // Pull-ups, polarity inversion and interrupts don't involve the device
+        if !matches!(old, Some(old) if port(old) == port(new)) {
+            self.write_port(new)?;
+        }
+
+        Ok(())
+    }
+
+    fn read_levels(&mut self, state: &State) -> Result<u16> {
+        let mut buffer = [0u8; 1];
+        self.bus.read(&mut buffer)?;
+
+        Ok((u16::from(buffer[0]) ^ state.inverted) & 0xff)
+    }
+
+    // The PCF8574 doesn't capture levels. Reading the port clears the interrupt.
+    fn read_interrupt(&mut self, state: &State) -> Result<(u16, u16, u16)> {
+        Ok((0, 0, self.read_levels(state)?))
+    }
+
+    fn has_pullups(&self) -> bool {
+        false
+    }
+}
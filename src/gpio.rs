@@ -24,6 +24,14 @@
 //! accessing the registers through either `/dev/gpiomem` or `/dev/mem`. GPIO interrupts
 //! are configured using the `gpiochip` character device.
 //!
+//! On Linux 5.10 or later, the `gpiochip` character device uAPI v2 is used for interrupts,
+//! which adds support for kernel-side debouncing through [`InputPin::set_kernel_debounce`]
+//! and a selectable timestamp clock through [`InputPin::set_event_clock`]. If the registers
+//! can't be mapped, [`Gpio::new`] falls back to configuring pins exclusively through the
+//! uAPI v2. In that case, pins can still be used as inputs (including the built-in
+//! pull-up/pull-down resistors and interrupts) or outputs, but alternate functions aren't
+//! available, and software-based PWM is limited by the higher overhead of each `ioctl` call.
+//!
 //! ## Pins
 //!
 //! GPIO pins are retrieved from a [`Gpio`] instance by their BCM GPIO pin number by calling
@@ -33,10 +41,21 @@
 //! appropriate mode, and provides access to additional methods relevant to the selected pin mode.
 //!
 //! Retrieving a GPIO pin with [`Gpio::get`] grants access to the pin through an owned [`Pin`]
-//! instance. If the pin is already in use, or the GPIO peripheral doesn't expose a pin with
-//! the specified number, [`Gpio::get`] returns `Err(`[`Error::PinNotAvailable`]`)`. After a [`Pin`]
-//! (or a derived [`InputPin`], [`OutputPin`] or [`IoPin`]) goes out of scope, it can be
-//! retrieved again through another [`Gpio::get`] call.
+//! instance. If the pin is already in use within the same process, or the GPIO peripheral
+//! doesn't expose a pin with the specified number, [`Gpio::get`] returns
+//! `Err(`[`Error::PinNotAvailable`]`)`. After a [`Pin`] (or a derived [`InputPin`],
+//! [`OutputPin`] or [`IoPin`]) goes out of scope, it can be retrieved again through another
+//! [`Gpio::get`] call.
+//!
+//! Pins are only protected against multiple use within the same process. Multiple processes
+//! can coordinate through advisory lock files by enabling [`Gpio::set_pin_locks`], in which
+//! case [`Gpio::get`] returns `Err(`[`Error::PinNotAvailable`]`)` with the process ID or the
+//! kernel's consumer label when another process or a kernel driver is using the pin.
+//!
+//! [`Gpio::get_by_id`] retrieves a pin through a [`PinId`] instead, which identifies the pin by
+//! its physical position on the GPIO header, its WiringPi number or a name such as `GPIO18` or
+//! `PWM0`. These are resolved based on the Raspberry Pi model, with an error describing whether
+//! the requested header pin is a power or ground pin, or isn't available on the board revision.
 //!
 //! By default, pins are reset to their original state when they go out of scope.
 //! Use [`InputPin::set_reset_on_drop(false)`], [`OutputPin::set_reset_on_drop(false)`]
@@ -57,6 +76,84 @@
 //! Asynchronous interrupt triggers are configured using [`InputPin::set_async_interrupt`]. The
 //! specified callback function will be executed on a separate thread when a trigger event occurs.
 //!
+//! Both interrupt handlers receive an [`InterruptEvent`], which contains the kernel timestamp,
+//! the detected edge, the resulting logic level and a sequence number. Timestamps are taken when
+//! the kernel handles the interrupt, so they can be used to accurately measure pulse widths even
+//! when the user thread is scheduled late.
+//!
+//! Bouncing switches can be filtered through [`InputPin::set_debounce`], which discards any
+//! events that occur within the debounce window after a reported event, and provides a
+//! debounced [`InputPin::read_debounced`].
+//!
+//! With the optional `async` feature enabled, interrupt trigger events can also be awaited
+//! from a tokio runtime. [`InputPin::wait_for_edge`] resolves on the next trigger event, and
+//! [`InputPin::interrupt_stream`] returns an [`InterruptStream`] that implements `Stream`.
+//! Both are driven by the tokio reactor instead of a separate thread.
+//!
+//! ## Pulse measurement
+//!
+//! [`InputPin::measure_pulse`] measures the width of a single pulse, such as the echo pulse of
+//! an ultrasonic distance sensor. [`InputPin::measure_signal`] captures a [`PulseMeter`] with
+//! the pulse widths, period, frequency and duty cycle of a repeating signal, and
+//! [`InputPin::count_edges`] counts the edges during a fixed period. All measurements are
+//! based on the kernel timestamps of both edges, and support a timeout. [`PulseStats`] reports
+//! the minimum, maximum, average and jitter of each series. [`PulseMeter`] and [`EdgeCounter`]
+//! can also be fed from an asynchronous interrupt callback for continuous measurements.
+//!
+//! ## Rotary encoders
+//!
+//! [`Encoder`] decodes the quadrature signal of a rotary encoder on two [`InputPin`]s, with
+//! an optional index pin or push button. It supports [`Counting::X1`], [`Counting::X2`] and
+//! [`Counting::X4`] counting, tracks the position and velocity, and sends an [`EncoderEvent`]
+//! to every subscribed channel when the position changes.
+//!
+//! ## Keypads
+//!
+//! [`Keypad`] scans a matrix keypad or button array on a separate thread, with its rows
+//! connected to [`OutputPin`]s and its columns connected to [`InputPin`]s with pull-up
+//! resistors. Every key is debounced individually, ghost keys on matrices without diodes are
+//! detected, and presses, releases, long presses and repeats are sent as [`KeypadEvent`]s.
+//!
+//! ## Buses
+//!
+//! [`Gpio::output_bus`] and [`Gpio::input_bus`] group multiple pins into an [`OutputBus`] or
+//! [`InputBus`], which write or read all pins at once through the `GPSETn`, `GPCLRn` and
+//! `GPLEVn` registers. This avoids the intermediate states that occur when the pins of a
+//! parallel bus are changed one at a time.
+//!
+//! ## Waveforms
+//!
+//! A [`Waveform`] describes a sequence of timed [`Pulse`]s, each of which sets and clears a
+//! group of pins at once. Waveforms are sent through a [`WaveformEngine`], which is returned by
+//! [`Gpio::waveform_engine`] and owns the pins it drives. The engine queues and loops waveforms
+//! on a high-priority thread, which makes it suitable for stepper step/direction trains, IR
+//! remote control carriers and custom bit-banged protocols. Timing is subject to the same
+//! limitations as software-based PWM.
+//!
+//! ## Alternate functions and pad control
+//!
+//! [`IoPin::set_config`] applies a [`PinConfig`], which selects the pin's mode or alternate
+//! function, and the drive strength, slew rate and hysteresis of the pin's bank. Unlike
+//! [`IoPin::set_mode`], it rejects alternate functions that aren't available on the pin, or
+//! whose peripheral signal is already routed to a different pin. [`alt_function`] looks up the
+//! signal name for any pin and mode, and [`Gpio::pin_function`] returns the signal that's
+//! currently routed to a pin.
+//!
+//! ## Snapshots
+//!
+//! [`Gpio::snapshot`] captures the mode, logic level, pull-up/pull-down state and kernel
+//! consumer label of every pin in a [`Snapshot`], without changing any of the pins.
+//! [`Snapshot::diff`] lists the pins that changed between two snapshots. Enabling the optional
+//! `serde` feature makes snapshots serializable, so they can be stored and compared later.
+//!
+//! ## Logic analyzer
+//!
+//! [`Gpio::logic_analyzer`] returns a [`LogicAnalyzer`] that samples a group of pins through the
+//! `GPLEVn` registers, and [`LogicAnalyzer::new`] records the timestamped edge events of a group
+//! of [`InputPin`]s instead. Captures start on a [`CaptureTrigger`], include a configurable
+//! pre-trigger period, and report the achieved sample rate. [`Capture::save_vcd`] stores the
+//! result as a VCD file for waveform viewers such as GTKWave or PulseView.
+//!
 //! ## Software-based PWM
 //!
 //! [`OutputPin`] and [`IoPin`] feature a software-based PWM implementation. The PWM signal is
@@ -64,24 +161,48 @@
 //! busy-waiting.
 //!
 //! Software-based PWM is inherently inaccurate on a multi-threaded OS due to scheduling/preemption.
-//! If an accurate or faster PWM signal is required, use the hardware [`Pwm`] peripheral instead.
+//! If an accurate or faster PWM signal is required, use the hardware [`Pwm`] peripheral instead,
+//! or switch to the DMA-based engine.
 //!
 //! PWM threads may occasionally sleep longer than needed. If the active or inactive part of the
 //! signal is shorter than 250 µs, only busy-waiting is used, which will increase CPU usage. Due to
 //! function call overhead, typical jitter is expected to be up to 10 µs on debug builds, and up to
 //! 2 µs on release builds.
 //!
+//! Calling [`OutputPin::set_pwm_engine`] or [`IoPin::set_pwm_engine`] with [`PwmEngine::Dma`]
+//! moves a pin to a DMA-based engine, similar to pigpio and ServoBlaster. A single DMA channel
+//! writes precomputed masks to the `GPSETn` and `GPCLRn` registers for every time step, with
+//! each step paced by the PWM peripheral's FIFO. Any number of pins can be driven without CPU
+//! involvement or jitter caused by the scheduler. The waveform for each combination of pins is
+//! described by a [`PwmSchedule`], which can be inspected without any hardware access.
+//!
+//! The DMA-based engine requires access to `/dev/mem` and `/dev/vcio`, which usually means
+//! running as root. It reconfigures the PWM peripheral and its clock, so it can't be used at
+//! the same time as [`Pwm`] or analog audio output. The DMA channel and time step are configured
+//! through [`Gpio::set_dma_pwm_config`].
+//!
+//! ## Simulation
+//!
+//! [`Gpio::simulated`] returns a [`Gpio`] instance backed by an in-memory register file,
+//! together with a [`Simulator`] that controls it. Pins retrieved from a simulated [`Gpio`]
+//! behave like regular pins, which allows code built on [`InputPin`], [`OutputPin`] and
+//! [`IoPin`] to be tested on machines without GPIO hardware.
+//!
+//! The [`Simulator`] drives input levels, injects interrupt trigger events for both
+//! synchronous and asynchronous interrupt handlers, and inspects output levels, modes and
+//! pull-up/pull-down settings.
+//!
 //! ## Examples
 //!
 //! Basic example:
 //!
-//! ```
+//! ```no_run
 //! use std::thread;
 //! use std::time::Duration;
 //!
-//! use rppal::gpio::Gpio;
+//! use rpi_embedded::gpio::Gpio;
 //!
-//! # fn main() -> rppal::gpio::Result<()> {
+//! # fn main() -> rpi_embedded::gpio::Result<()> {
 //! let gpio = Gpio::new()?;
 //! let mut pin = gpio.get(23)?.into_output();
 //!
@@ -119,7 +240,55 @@
 //! [raspberrypi/linux#2289]: https://github.com/raspberrypi/linux/issues/2289
 //! [`Gpio`]: struct.Gpio.html
 //! [`Gpio::get`]: struct.Gpio.html#method.get
+//! [`Gpio::set_pin_locks`]: struct.Gpio.html#method.set_pin_locks
+//! [`Gpio::get_by_id`]: struct.Gpio.html#method.get_by_id
+//! [`PinId`]: enum.PinId.html
 //! [`Gpio::poll_interrupts`]: struct.Gpio.html#method.poll_interrupts
+//! [`InterruptEvent`]: struct.InterruptEvent.html
+//! [`InputPin::measure_pulse`]: struct.InputPin.html#method.measure_pulse
+//! [`InputPin::measure_signal`]: struct.InputPin.html#method.measure_signal
+//! [`InputPin::count_edges`]: struct.InputPin.html#method.count_edges
+//! [`PulseMeter`]: struct.PulseMeter.html
+//! [`PulseStats`]: struct.PulseStats.html
+//! [`EdgeCounter`]: struct.EdgeCounter.html
+//! [`Encoder`]: struct.Encoder.html
+//! [`Counting::X1`]: enum.Counting.html#variant.X1
+//! [`Counting::X2`]: enum.Counting.html#variant.X2
+//! [`Counting::X4`]: enum.Counting.html#variant.X4
+//! [`EncoderEvent`]: enum.EncoderEvent.html
+//! [`Keypad`]: struct.Keypad.html
+//! [`KeypadEvent`]: enum.KeypadEvent.html
+//! [`InputPin::set_debounce`]: struct.InputPin.html#method.set_debounce
+//! [`InputPin::read_debounced`]: struct.InputPin.html#method.read_debounced
+//! [`InputPin::wait_for_edge`]: struct.InputPin.html#method.wait_for_edge
+//! [`InputPin::interrupt_stream`]: struct.InputPin.html#method.interrupt_stream
+//! [`InterruptStream`]: struct.InterruptStream.html
+//! [`Gpio::new`]: struct.Gpio.html#method.new
+//! [`InputPin::set_kernel_debounce`]: struct.InputPin.html#method.set_kernel_debounce
+//! [`InputPin::set_event_clock`]: struct.InputPin.html#method.set_event_clock
+//! [`Gpio::simulated`]: struct.Gpio.html#method.simulated
+//! [`Gpio::output_bus`]: struct.Gpio.html#method.output_bus
+//! [`Gpio::input_bus`]: struct.Gpio.html#method.input_bus
+//! [`OutputBus`]: struct.OutputBus.html
+//! [`Waveform`]: struct.Waveform.html
+//! [`IoPin::set_config`]: struct.IoPin.html#method.set_config
+//! [`IoPin::set_mode`]: struct.IoPin.html#method.set_mode
+//! [`PinConfig`]: struct.PinConfig.html
+//! [`alt_function`]: fn.alt_function.html
+//! [`Gpio::pin_function`]: struct.Gpio.html#method.pin_function
+//! [`Gpio::snapshot`]: struct.Gpio.html#method.snapshot
+//! [`Snapshot`]: struct.Snapshot.html
+//! [`Snapshot::diff`]: struct.Snapshot.html#method.diff
+//! [`Gpio::logic_analyzer`]: struct.Gpio.html#method.logic_analyzer
+//! [`LogicAnalyzer`]: struct.LogicAnalyzer.html
+//! [`LogicAnalyzer::new`]: struct.LogicAnalyzer.html#method.new
+//! [`CaptureTrigger`]: enum.CaptureTrigger.html
+//! [`Capture::save_vcd`]: struct.Capture.html#method.save_vcd
+//! [`Pulse`]: struct.Pulse.html
+//! [`WaveformEngine`]: struct.WaveformEngine.html
+//! [`Gpio::waveform_engine`]: struct.Gpio.html#method.waveform_engine
+//! [`InputBus`]: struct.InputBus.html
+//! [`Simulator`]: struct.Simulator.html
 //! [`Pin`]: struct.Pin.html
 //! [`InputPin`]: struct.InputPin.html
 //! [`InputPin::set_reset_on_drop(false)`]: struct.InputPin.html#method.set_reset_on_drop
@@ -131,12 +300,17 @@
 //! [`IoPin`]: struct.IoPin.html
 //! [`IoPin::set_reset_on_drop(false)`]: struct.IoPin.html#method.set_reset_on_drop
 //! [`Pwm`]: ../pwm/struct.Pwm.html
+//! [`OutputPin::set_pwm_engine`]: struct.OutputPin.html#method.set_pwm_engine
+//! [`IoPin::set_pwm_engine`]: struct.IoPin.html#method.set_pwm_engine
+//! [`PwmEngine::Dma`]: enum.PwmEngine.html#variant.Dma
+//! [`PwmSchedule`]: struct.PwmSchedule.html
+//! [`Gpio::set_dma_pwm_config`]: struct.Gpio.html#method.set_dma_pwm_config
 
 use std::error;
 use std::fmt;
 use std::io;
 use std::ops::Not;
-use std::os::unix::io::AsRawFd;
+use std::path::{Path, PathBuf};
 use std::result;
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::{Arc, Mutex, Weak};
@@ -144,20 +318,50 @@ use std::time::Duration;
 
 use lazy_static::lazy_static;
 
+pub(crate) mod backend;
+mod bus;
+mod capture;
+mod config;
+mod debounce;
+mod dma;
+mod dma_pwm;
+mod encoder;
 mod epoll;
+mod header;
 #[cfg(feature = "hal")]
 mod hal;
 #[cfg(feature = "hal-unproven")]
 mod hal_unproven;
 mod interrupt;
-mod ioctl;
+pub(crate) mod ioctl;
+mod keypad;
+mod lock;
+mod measure;
 mod mem;
 mod pin;
-mod soft_pwm;
+pub(crate) mod sim;
+mod snapshot;
+pub(crate) mod soft_pwm;
+mod waveform;
 
-use crate::system;
+use crate::system::{self, DeviceInfo, SoC};
 
+pub use self::bus::{InputBus, OutputBus};
+pub use self::capture::{Capture, CaptureTrigger, LevelChange, LogicAnalyzer};
+pub use self::config::{alt_function, DriveStrength, PinConfig, SlewRate};
+pub use self::debounce::Debouncer;
+pub use self::dma_pwm::PwmSchedule;
+pub use self::encoder::{Counting, Direction, Encoder, EncoderEvent};
+pub use self::header::{header, HeaderPin, PinId};
+#[cfg(feature = "async")]
+pub use self::interrupt::InterruptStream;
+pub use self::keypad::{Keypad, KeypadEvent};
+pub use self::lock::{PinOwner, PIN_LOCK_DIR};
+pub use self::measure::{EdgeCounter, PulseMeter, PulseStats};
 pub use self::pin::{InputPin, IoPin, OutputPin, Pin};
+pub use self::sim::Simulator;
+pub use self::snapshot::{PinChange, PinState, Snapshot};
+pub use self::waveform::{Pulse, Waveform, WaveformEngine};
 
 /// Errors that can occur when accessing the GPIO peripheral.
 #[derive(Debug)]
@@ -180,11 +384,18 @@ pub enum Error {
     /// can retrieve it again after the [`Pin`] (or a derived [`InputPin`], [`OutputPin`] or
     /// [`IoPin`]) instance goes out of scope.
     ///
+    /// When cross-process pin reservation has been enabled through [`Gpio::set_pin_locks`],
+    /// the pin may also be in use by another process or a kernel driver, in which case the
+    /// [`PinOwner`] identifies who's using it. The owner is `None` for pins that are in
+    /// use within the current process, or that don't exist.
+    ///
     /// [`Pin`]: struct.Pin.html
     /// [`InputPin`]: struct.InputPin.html
     /// [`OutputPin`]: struct.OutputPin.html
     /// [`IoPin`]: struct.IoPin.html
-    PinNotAvailable(u8),
+    /// [`Gpio::set_pin_locks`]: struct.Gpio.html#method.set_pin_locks
+    /// [`PinOwner`]: enum.PinOwner.html
+    PinNotAvailable(u8, Option<PinOwner>),
     /// Permission denied when opening `/dev/gpiomem`, `/dev/mem` or `/dev/gpiochipN` for
     /// read/write access.
     ///
@@ -196,16 +407,91 @@ pub enum Error {
     Io(io::Error),
     /// Thread panicked.
     ThreadPanic,
+    /// Invalid PWM period.
+    ///
+    /// The period is shorter than the DMA-based PWM engine's time step, or combining it
+    /// with the periods of the other pins in the DMA schedule results in a cycle that's
+    /// too long.
+    InvalidPwmPeriod(Duration),
+    /// DMA channel is not available.
+    ///
+    /// The DMA channel doesn't exist, or is in use by the firmware. Select a different
+    /// channel through [`Gpio::set_dma_pwm_config`].
+    ///
+    /// [`Gpio::set_dma_pwm_config`]: struct.Gpio.html#method.set_dma_pwm_config
+    DmaChannelNotAvailable(u8),
+    /// Alternate function is not available.
+    ///
+    /// The pin doesn't have a peripheral signal assigned to the requested alternate
+    /// function mode.
+    FunctionNotAvailable(u8, Mode),
+    /// Alternate function conflict.
+    ///
+    /// The peripheral signal selected for the first pin is already routed to the second
+    /// pin. Set the second pin to a different mode first.
+    ///
+    /// Pins that aren't exposed on the GPIO header of most models (BCM GPIO 28-53) are
+    /// routed by the firmware, and are only checked while they're in use.
+    FunctionConflict(u8, &'static str, u8),
+    /// Pad control conflict.
+    ///
+    /// The first pin shares its pad control register with the second pin, which is still
+    /// in use and was configured with different pad settings through [`IoPin::set_config`].
+    ///
+    /// [`IoPin::set_config`]: struct.IoPin.html#method.set_config
+    PadConflict(u8, u8),
+    /// Header pin isn't a GPIO pin.
+    ///
+    /// The physical pin on the GPIO header is a power or ground pin.
+    NotGpioPin(u8, HeaderPin),
+    /// Pin identifier is not available.
+    ///
+    /// The [`PinId`] doesn't refer to a GPIO pin on the model's board revision. The
+    /// physical pin number is larger than the number of pins on the GPIO header, the
+    /// model doesn't have a GPIO header, the WiringPi pin number isn't mapped to a pin, or
+    /// none of the pins on the GPIO header support the named signal.
+    ///
+    /// [`PinId`]: enum.PinId.html
+    PinIdNotAvailable(PinId, system::Model),
 }
 
 impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match *self {
             Error::UnknownModel => write!(f, "Unknown Raspberry Pi model"),
-            Error::PinNotAvailable(pin) => write!(f, "Pin {} is not available", pin),
+            Error::PinNotAvailable(pin, None) => write!(f, "Pin {} is not available", pin),
+            Error::PinNotAvailable(pin, Some(ref owner)) => {
+                write!(f, "Pin {} is not available (in use by {})", pin, owner)
+            }
             Error::PermissionDenied(ref path) => write!(f, "Permission denied: {}", path),
             Error::Io(ref err) => write!(f, "I/O error: {}", err),
             Error::ThreadPanic => write!(f, "Thread panicked"),
+            Error::InvalidPwmPeriod(period) => write!(f, "Invalid PWM period: {:?}", period),
+            Error::DmaChannelNotAvailable(channel) => {
+                write!(f, "DMA channel {} is not available", channel)
+            }
+            Error::FunctionNotAvailable(pin, mode) => {
+                write!(f, "Pin {} has no {} function", pin, mode)
+            }
+            Error::FunctionConflict(pin, signal, other) => write!(
+                f,
+                "Pin {} can't be set to {}, which is already in use by pin {}",
+                pin, signal, other
+            ),
+            Error::PadConflict(pin, other) => write!(
+                f,
+                "Pad settings for pin {} conflict with pin {}, which shares the same pad control register",
+                pin, other
+            ),
+            Error::NotGpioPin(pin, header_pin) => match header_pin {
+                HeaderPin::Gpio(gpio) => write!(f, "Header pin {} is GPIO{}", pin, gpio),
+                HeaderPin::Power3v3 => write!(f, "Header pin {} is a 3.3 V power pin", pin),
+                HeaderPin::Power5v => write!(f, "Header pin {} is a 5 V power pin", pin),
+                HeaderPin::Ground => write!(f, "Header pin {} is a ground pin", pin),
+            },
+            Error::PinIdNotAvailable(ref id, model) => {
+                write!(f, "{} is not available on the {}", id, model)
+            }
         }
     }
 }
@@ -229,6 +515,7 @@ pub type Result<T> = result::Result<T, Error>;
 
 /// Pin modes.
 #[derive(Debug, PartialEq, Eq, Copy, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[repr(u8)]
 pub enum Mode {
     Input = 0b000,
@@ -258,6 +545,7 @@ impl fmt::Display for Mode {
 
 /// Pin logic levels.
 #[derive(Debug, PartialEq, Eq, Copy, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[repr(u8)]
 pub enum Level {
     Low = 0,
@@ -286,6 +574,7 @@ impl Not for Level {
 
 /// Built-in pull-up/pull-down resistor states.
 #[derive(Debug, PartialEq, Eq, Copy, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum PullUpDown {
     Off = 0b00,
     PullDown = 0b01,
@@ -322,26 +611,138 @@ impl fmt::Display for Trigger {
     }
 }
 
+/// PWM signal generators.
+///
+/// The engine used by [`OutputPin::set_pwm`] and [`IoPin::set_pwm`] is selected
+/// through [`OutputPin::set_pwm_engine`] or [`IoPin::set_pwm_engine`]. More
+/// information can be found [here].
+///
+/// [`OutputPin::set_pwm`]: struct.OutputPin.html#method.set_pwm
+/// [`IoPin::set_pwm`]: struct.IoPin.html#method.set_pwm
+/// [`OutputPin::set_pwm_engine`]: struct.OutputPin.html#method.set_pwm_engine
+/// [`IoPin::set_pwm_engine`]: struct.IoPin.html#method.set_pwm_engine
+/// [here]: index.html#software-based-pwm
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub enum PwmEngine {
+    /// Toggles the pin on a separate thread.
+    Software,
+    /// Toggles the pin through DMA transfers paced by the PWM peripheral.
+    Dma,
+}
+
+impl fmt::Display for PwmEngine {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            PwmEngine::Software => write!(f, "Software"),
+            PwmEngine::Dma => write!(f, "Dma"),
+        }
+    }
+}
+
+/// Interrupt trigger event.
+///
+/// `InterruptEvent`s are returned by [`InputPin::poll_interrupt`] and [`Gpio::poll_interrupts`],
+/// and passed to the callback configured through [`InputPin::set_async_interrupt`].
+///
+/// [`InputPin::poll_interrupt`]: struct.InputPin.html#method.poll_interrupt
+/// [`Gpio::poll_interrupts`]: struct.Gpio.html#method.poll_interrupts
+/// [`InputPin::set_async_interrupt`]: struct.InputPin.html#method.set_async_interrupt
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub struct InterruptEvent {
+    /// Time at which the kernel detected the edge.
+    ///
+    /// The timestamp is based on the clock selected through [`InputPin::set_event_clock`],
+    /// and is only meaningful when compared to other timestamps using the same clock.
+    ///
+    /// [`InputPin::set_event_clock`]: struct.InputPin.html#method.set_event_clock
+    pub timestamp: Duration,
+    /// Detected edge, either [`RisingEdge`] or [`FallingEdge`].
+    ///
+    /// [`RisingEdge`]: enum.Trigger.html#variant.RisingEdge
+    /// [`FallingEdge`]: enum.Trigger.html#variant.FallingEdge
+    pub trigger: Trigger,
+    /// Logic level after the edge.
+    pub level: Level,
+    /// Sequence number of the event.
+    ///
+    /// Numbering starts at 1, and restarts whenever the interrupt trigger is reconfigured.
+    /// On Linux 5.10 or later, the kernel assigns sequence numbers, so a gap between
+    /// consecutive events indicates events were dropped because the kernel's event
+    /// buffer overflowed.
+    pub seqno: u32,
+}
+
+/// Clock used for interrupt event timestamps.
+///
+/// Selecting a clock requires the GPIO character device uAPI v2 (Linux 5.10 or later).
+/// On older kernels, timestamps are always based on the kernel's default clock.
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub enum EventClock {
+    /// `CLOCK_MONOTONIC`. Unaffected by changes to the system time.
+    Monotonic,
+    /// `CLOCK_REALTIME`. Wall-clock time since the Unix epoch.
+    Realtime,
+}
+
+impl fmt::Display for EventClock {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            EventClock::Monotonic => write!(f, "Monotonic"),
+            EventClock::Realtime => write!(f, "Realtime"),
+        }
+    }
+}
+
 // Store Gpio's state separately, so we can conveniently share it through
 // a cloned Arc.
 pub(crate) struct GpioState {
-    gpio_mem: mem::GpioMem,
-    cdev: std::fs::File,
+    backend: Arc<dyn backend::Backend>,
     sync_interrupts: Mutex<interrupt::EventLoop>,
+    dma_pwm: Mutex<dma_pwm::DmaPwm>,
+    soc: Option<SoC>,
+    model: Option<system::Model>,
+    // Pin that last changed the pad settings for each bank through IoPin::set_config()
+    pad_owners: Mutex<[Option<u8>; 3]>,
     pins_taken: [AtomicBool; pin::MAX],
+    // Lock file directory, if cross-process pin reservation is enabled
+    pin_locks: Mutex<(bool, PathBuf)>,
 }
 
 impl fmt::Debug for GpioState {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        f.debug_struct("EventLoop")
-            .field("gpio_mem", &self.gpio_mem)
-            .field("cdev", &self.cdev)
+        f.debug_struct("GpioState")
+            .field("backend", &self.backend)
             .field("sync_interrupts", &self.sync_interrupts)
+            .field("dma_pwm", &self.dma_pwm)
+            .field("soc", &self.soc)
+            .field("model", &self.model)
+            .field("pad_owners", &self.pad_owners)
             .field("pins_taken", &format_args!("{{ .. }}"))
+            .field("pin_locks", &self.pin_locks)
             .finish()
     }
 }
 
+impl GpioState {
+    fn new(
+        backend: Arc<dyn backend::Backend>,
+        device_info: Option<DeviceInfo>,
+    ) -> Result<GpioState> {
+        let soc = device_info.map(|device_info| device_info.soc());
+
+        Ok(GpioState {
+            sync_interrupts: Mutex::new(interrupt::EventLoop::new(backend.clone(), pin::MAX)?),
+            dma_pwm: Mutex::new(dma_pwm::DmaPwm::new(soc)),
+            soc,
+            model: device_info.map(|device_info| device_info.model()),
+            pad_owners: Mutex::new([None; 3]),
+            pins_taken: init_array!(AtomicBool::new(false), pin::MAX),
+            pin_locks: Mutex::new((false, PathBuf::from(PIN_LOCK_DIR))),
+            backend,
+        })
+    }
+}
+
 // Share state between Gpio and Pin instances. GpioState is dropped after
 // all Gpio and Pin instances go out of scope, guaranteeing we won't have
 // any pins simultaneously using different EventLoop or GpioMem instances.
@@ -367,18 +768,16 @@ impl Gpio {
                 inner: state.clone(),
             })
         } else {
-            let gpio_mem = mem::GpioMem::open()?;
-            let cdev = ioctl::find_gpiochip()?;
-            let sync_interrupts =
-                Mutex::new(interrupt::EventLoop::new(cdev.as_raw_fd(), pin::MAX)?);
-            let pins_taken = init_array!(AtomicBool::new(false), pin::MAX);
-
-            let gpio_state = Arc::new(GpioState {
-                gpio_mem,
-                cdev,
-                sync_interrupts,
-                pins_taken,
-            });
+            // Fall back to the GPIO character device if the registers can't be mapped
+            let backend: Arc<dyn backend::Backend> = match backend::HardwareBackend::open() {
+                Ok(hardware) => Arc::new(hardware),
+                Err(err) => match backend::CdevBackend::open() {
+                    Ok(cdev) => Arc::new(cdev),
+                    Err(_) => return Err(err),
+                },
+            };
+
+            let gpio_state = Arc::new(GpioState::new(backend, DeviceInfo::new().ok())?);
 
             // Store a weak reference to our state. This gets dropped when
             // all Gpio and Pin instances go out of scope.
@@ -387,6 +786,36 @@ impl Gpio {
             Ok(Gpio { inner: gpio_state })
         }
     }
+
+    /// Constructs a new `Gpio` backed by a simulated GPIO peripheral.
+    ///
+    /// The returned [`Simulator`] drives input levels, injects interrupt trigger events,
+    /// and inspects the state of any pins retrieved from the returned `Gpio`. No hardware
+    /// is accessed, so this works on any Linux machine.
+    ///
+    /// Unlike [`new`], every call to `simulated` creates an independent peripheral. Simulated
+    /// pins don't share state with pins retrieved through [`new`]. Alternate functions and
+    /// [`PinId`]s are resolved as they would be on a Raspberry Pi Zero W, which uses the
+    /// BCM2835 and a 40-pin GPIO header.
+    ///
+    /// [`Simulator`]: struct.Simulator.html
+    /// [`new`]: #method.new
+    /// [`PinId`]: enum.PinId.html
+    pub fn simulated() -> Result<(Gpio, Simulator)> {
+        let sim_backend = Arc::new(sim::SimBackend::new());
+        let device_info = DeviceInfo::from_model(system::Model::RaspberryPiZeroW);
+        let gpio_state = Arc::new(GpioState::new(sim_backend.clone(), Some(device_info))?);
+
+        Ok((Gpio { inner: gpio_state }, Simulator::new(sim_backend)))
+    }
+
+    // Constructs a new `Gpio` for pins that aren't part of the SoC's GPIO peripheral
+    pub(crate) fn with_backend(backend: Arc<dyn backend::Backend>) -> Result<Gpio> {
+        Ok(Gpio {
+            inner: Arc::new(GpioState::new(backend, None)?),
+        })
+    }
+
     pub fn output(pin : u8) -> Result<pin::OutputPin>{
             let gp = Gpio::new()?.get(pin)?.into_output();
             Ok(gp)
@@ -412,31 +841,333 @@ impl Gpio {
     /// Returns a [`Pin`] for the specified BCM GPIO pin number.
     ///
     /// Retrieving a GPIO pin grants access to the pin through an owned [`Pin`] instance.
-    /// If the pin is already in use, or the GPIO peripheral doesn't expose a pin with the
-    /// specified number, `get` returns `Err(`[`Error::PinNotAvailable`]`)`. After a [`Pin`]
-    /// (or a derived [`InputPin`], [`OutputPin`] or [`IoPin`]) goes out of scope, it
-    /// can be retrieved again through another `get` call.
+    /// If the pin is already in use within the current process, or the GPIO peripheral
+    /// doesn't expose a pin with the specified number, `get` returns
+    /// `Err(`[`Error::PinNotAvailable`]`)`. After a [`Pin`] (or a derived [`InputPin`],
+    /// [`OutputPin`] or [`IoPin`]) goes out of scope, it can be retrieved again through
+    /// another `get` call.
+    ///
+    /// When cross-process pin reservation is enabled through [`set_pin_locks`], `get`
+    /// also returns `Err(`[`Error::PinNotAvailable`]`)` if the pin is in use by another
+    /// process, and includes its owner.
     ///
     /// [`Pin`]: struct.Pin.html
     /// [`InputPin`]: struct.InputPin.html
     /// [`OutputPin`]: struct.OutputPin.html
     /// [`IoPin`]: struct.IoPin.html
     /// [`Error::PinNotAvailable`]: enum.Error.html#variant.PinNotAvailable
+    /// [`set_pin_locks`]: #method.set_pin_locks
     pub fn get(&self, pin: u8) -> Result<Pin> {
-        if pin as usize >= pin::MAX {
-            return Err(Error::PinNotAvailable(pin));
+        if pin as usize >= self.inner.backend.pin_count() {
+            return Err(Error::PinNotAvailable(pin, None));
         }
 
         // Returns true if the pin is already taken, otherwise atomically sets it to true here
         if self.inner.pins_taken[pin as usize].compare_and_swap(false, true, Ordering::SeqCst) {
             // Pin is taken
-            Err(Error::PinNotAvailable(pin))
-        } else {
+            return Err(Error::PinNotAvailable(pin, None));
+        }
+
+        match self.lock_pin(pin) {
             // Return an owned Pin
-            Ok(Pin::new(pin, self.inner.clone()))
+            Ok(lock) => Ok(Pin::new(pin, self.inner.clone(), lock)),
+            Err(err) => {
+                self.inner.pins_taken[pin as usize].store(false, Ordering::SeqCst);
+                Err(err)
+            }
         }
     }
 
+    // Reserves the pin across processes if pin locks are enabled
+    fn lock_pin(&self, pin: u8) -> Result<Option<lock::PinLock>> {
+        let pin_locks = self.inner.pin_locks.lock().unwrap();
+        if !pin_locks.0 || !self.inner.backend.supports_pin_locks() {
+            return Ok(None);
+        }
+
+        lock::PinLock::acquire(&pin_locks.1, pin, self.inner.backend.as_ref()).map(Some)
+    }
+
+    /// Returns `true` if cross-process pin reservation is enabled.
+    pub fn pin_locks(&self) -> bool {
+        self.inner.pin_locks.lock().unwrap().0 && self.inner.backend.supports_pin_locks()
+    }
+
+    /// Enables or disables cross-process pin reservation.
+    ///
+    /// [`get`] only prevents a pin from being used more than once within the same
+    /// process. When `pin_locks` is set to `true`, [`get`] additionally reserves the pin
+    /// by locking an advisory lock file named `gpioN.lock` in [`pin_lock_dir`], which
+    /// stores the process ID. The lock is held until the [`Pin`] (or a derived
+    /// [`InputPin`], [`OutputPin`] or [`IoPin`]) goes out of scope, or the process exits.
+    ///
+    /// If another process holds the lock, or the kernel reports the pin's GPIO line as
+    /// in use by a kernel driver or another process that requested it through the GPIO
+    /// character device, [`get`] returns `Err(`[`Error::PinNotAvailable`]`)` with the
+    /// process ID or consumer label. Processes that don't enable pin locks aren't prevented from
+    /// accessing the pin through `/dev/gpiomem`.
+    ///
+    /// The setting is shared by all `Gpio` instances returned by [`new`], and only
+    /// affects pins retrieved afterwards. By default, `pin_locks` is set to `false`.
+    ///
+    /// Lock files are named after the BCM GPIO pin number, so only the SoC's GPIO pins
+    /// can be reserved. The setting is ignored for the pins of a port expander or a
+    /// simulated GPIO peripheral, whose pin numbers would collide with the SoC's pins.
+    ///
+    /// [`get`]: #method.get
+    /// [`new`]: #method.new
+    /// [`pin_lock_dir`]: #method.pin_lock_dir
+    /// [`Pin`]: struct.Pin.html
+    /// [`InputPin`]: struct.InputPin.html
+    /// [`OutputPin`]: struct.OutputPin.html
+    /// [`IoPin`]: struct.IoPin.html
+    /// [`Error::PinNotAvailable`]: enum.Error.html#variant.PinNotAvailable
+    pub fn set_pin_locks(&self, pin_locks: bool) {
+        self.inner.pin_locks.lock().unwrap().0 = pin_locks;
+    }
+
+    /// Returns the directory that contains the lock files used for cross-process
+    /// pin reservation.
+    pub fn pin_lock_dir(&self) -> PathBuf {
+        self.inner.pin_locks.lock().unwrap().1.clone()
+    }
+
+    /// Sets the directory that contains the lock files used for cross-process
+    /// pin reservation.
+    ///
+    /// All cooperating processes need to use the same directory, which is created
+    /// if it doesn't exist. Lock files are created with read/write permissions for
+    /// all users, regardless of the process's umask. By default, `pin_lock_dir` is set
+    /// to [`PIN_LOCK_DIR`], which requires root privileges to create.
+    ///
+    /// [`PIN_LOCK_DIR`]: constant.PIN_LOCK_DIR.html
+    pub fn set_pin_lock_dir<P: AsRef<Path>>(&self, dir: P) {
+        self.inner.pin_locks.lock().unwrap().1 = dir.as_ref().to_path_buf();
+    }
+
+    /// Returns the BCM GPIO pin number `id` refers to on the Raspberry Pi model
+    /// `Gpio` is running on.
+    ///
+    /// Returns `Err(`[`Error::UnknownModel`]`)` if the model couldn't be identified
+    /// and `id` isn't a BCM GPIO pin number. See [`PinId::resolve`] for other errors.
+    ///
+    /// [`Error::UnknownModel`]: enum.Error.html#variant.UnknownModel
+    /// [`PinId::resolve`]: enum.PinId.html#method.resolve
+    pub fn resolve(&self, id: &PinId) -> Result<u8> {
+        match (id, self.inner.model) {
+            (&PinId::Bcm(pin), _) => Ok(pin),
+            (_, Some(model)) => id.resolve(model),
+            (_, None) => Err(Error::UnknownModel),
+        }
+    }
+
+    /// Returns a [`Pin`] for the specified [`PinId`].
+    ///
+    /// `id` is resolved to a BCM GPIO pin number through [`resolve`], after which the
+    /// pin is retrieved through [`get`].
+    ///
+    /// ## Example
+    ///
+    /// ```no_run
+    /// use rpi_embedded::gpio::{Gpio, PinId};
+    ///
+    /// # fn main() -> rpi_embedded::gpio::Result<()> {
+    /// let gpio = Gpio::new()?;
+    ///
+    /// let led = gpio.get_by_id(PinId::Physical(11))?.into_output();
+    /// let pwm = gpio.get_by_id("PWM1")?.into_output();
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// [`Pin`]: struct.Pin.html
+    /// [`PinId`]: enum.PinId.html
+    /// [`resolve`]: #method.resolve
+    /// [`get`]: #method.get
+    pub fn get_by_id<T: Into<PinId>>(&self, id: T) -> Result<Pin> {
+        self.get(self.resolve(&id.into())?)
+    }
+
+    /// Returns an [`OutputBus`] for the specified BCM GPIO pin numbers.
+    ///
+    /// Each pin is retrieved through [`get`] and configured as an output. `pins[0]`
+    /// is mapped to bit 0 of the bus value, `pins[1]` to bit 1, and so on. If any of
+    /// the pins is unavailable or listed more than once, `output_bus` returns
+    /// `Err(`[`Error::PinNotAvailable`]`)`.
+    ///
+    /// ## Example
+    ///
+    /// ```no_run
+    /// use rpi_embedded::gpio::Gpio;
+    ///
+    /// # fn main() -> rpi_embedded::gpio::Result<()> {
+    /// let gpio = Gpio::new()?;
+    ///
+    /// // R-2R DAC with the least significant bit on BCM GPIO 5
+    /// let mut dac = gpio.output_bus(&[5, 6, 13, 19, 26, 16, 20, 21])?;
+    ///
+    /// for value in 0..=255 {
+    ///     dac.write(value);
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// [`OutputBus`]: struct.OutputBus.html
+    /// [`get`]: #method.get
+    /// [`Error::PinNotAvailable`]: enum.Error.html#variant.PinNotAvailable
+    pub fn output_bus(&self, pins: &[u8]) -> Result<OutputBus> {
+        let pins = pins
+            .iter()
+            .map(|&pin| Ok(self.get(pin)?.into_output()))
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(OutputBus::new(pins, self.inner.backend.clone()))
+    }
+
+    /// Returns an [`InputBus`] for the specified BCM GPIO pin numbers.
+    ///
+    /// Each pin is retrieved through [`get`] and configured as an input, with its
+    /// built-in pull-up/pull-down resistor set to `pud`. `pins[0]` is mapped to bit 0
+    /// of the bus value, `pins[1]` to bit 1, and so on. If any of the pins is unavailable
+    /// or listed more than once, `input_bus` returns `Err(`[`Error::PinNotAvailable`]`)`.
+    ///
+    /// [`InputBus`]: struct.InputBus.html
+    /// [`get`]: #method.get
+    /// [`Error::PinNotAvailable`]: enum.Error.html#variant.PinNotAvailable
+    pub fn input_bus(&self, pins: &[u8], pud: PullUpDown) -> Result<InputBus> {
+        let pins = pins
+            .iter()
+            .map(|&pin| Ok(InputPin::new(self.get(pin)?, pud)))
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(InputBus::new(pins, self.inner.backend.clone()))
+    }
+
+    /// Returns a [`WaveformEngine`] that sends [`Waveform`]s to the specified BCM GPIO pins.
+    ///
+    /// Each pin is retrieved through [`get`] and configured as an output. If any of the pins
+    /// is unavailable or listed more than once, `waveform_engine` returns
+    /// `Err(`[`Error::PinNotAvailable`]`)`.
+    ///
+    /// ## Example
+    ///
+    /// ```no_run
+    /// use std::time::Duration;
+    ///
+    /// use rpi_embedded::gpio::{Gpio, Waveform};
+    ///
+    /// # fn main() -> rpi_embedded::gpio::Result<()> {
+    /// let gpio = Gpio::new()?;
+    /// let mut engine = gpio.waveform_engine(&[17])?;
+    ///
+    /// // 9 ms burst of a 38 kHz IR carrier, followed by a 4.5 ms pause
+    /// let mut waveform = Waveform::new();
+    /// waveform
+    ///     .carrier(17, 38_000.0, 0.33, Duration::from_millis(9))?
+    ///     .delay(Duration::from_micros(4500));
+    ///
+    /// engine.send(&waveform)?;
+    /// engine.wait();
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// [`WaveformEngine`]: struct.WaveformEngine.html
+    /// [`Waveform`]: struct.Waveform.html
+    /// [`get`]: #method.get
+    /// [`Error::PinNotAvailable`]: enum.Error.html#variant.PinNotAvailable
+    pub fn waveform_engine(&self, pins: &[u8]) -> Result<WaveformEngine> {
+        let pins = pins
+            .iter()
+            .map(|&pin| Ok(self.get(pin)?.into_output()))
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(WaveformEngine::new(pins, self.inner.backend.clone()))
+    }
+
+    /// Returns the name of the peripheral signal that's currently routed to the specified
+    /// BCM GPIO pin, or `None` if the pin is set to [`Input`], [`Output`] or a reserved
+    /// alternate function.
+    ///
+    /// The pin doesn't need to be available, so this can be used to find out which
+    /// peripheral owns a pin that's in use elsewhere. Signal names are listed in
+    /// [`alt_function`].
+    ///
+    /// [`Input`]: enum.Mode.html#variant.Input
+    /// [`Output`]: enum.Mode.html#variant.Output
+    /// [`alt_function`]: fn.alt_function.html
+    pub fn pin_function(&self, pin: u8) -> Result<Option<&'static str>> {
+        if pin as usize >= pin::MAX {
+            return Err(Error::PinNotAvailable(pin, None));
+        }
+
+        Ok(alt_function(
+            self.inner.soc.unwrap_or(SoC::Bcm2835),
+            pin,
+            self.inner.backend.mode(pin),
+        ))
+    }
+
+    /// Returns the specified BCM GPIO pin's mode and pad control settings.
+    ///
+    /// The pin doesn't need to be available. Reading the pad control registers
+    /// requires access to `/dev/mem`.
+    pub fn pin_config(&self, pin: u8) -> Result<PinConfig> {
+        if pin as usize >= pin::MAX {
+            return Err(Error::PinNotAvailable(pin, None));
+        }
+
+        Ok(PinConfig::from_registers(
+            self.inner.backend.mode(pin),
+            self.inner.backend.pad_control(config::pad_bank(pin))?,
+        ))
+    }
+
+    /// Captures the current state of every pin.
+    ///
+    /// The pins don't need to be available, and their state isn't changed. The
+    /// pull-up/pull-down state is read back the same way as [`Pin::pullupdown`].
+    ///
+    /// [`Pin::pullupdown`]: struct.Pin.html#method.pullupdown
+    pub fn snapshot(&self) -> Snapshot {
+        Snapshot::capture(self.inner.backend.as_ref())
+    }
+
+    /// Returns a [`LogicAnalyzer`] that samples the specified BCM GPIO pins.
+    ///
+    /// The pins don't need to be available, and their state isn't changed. All pins are
+    /// sampled at once by reading the `GPLEVn` registers. If any of the pins doesn't
+    /// exist or is listed more than once, `logic_analyzer` returns
+    /// `Err(`[`Error::PinNotAvailable`]`)`.
+    ///
+    /// [`LogicAnalyzer`]: struct.LogicAnalyzer.html
+    /// [`Error::PinNotAvailable`]: enum.Error.html#variant.PinNotAvailable
+    pub fn logic_analyzer(&self, pins: &[u8]) -> Result<LogicAnalyzer> {
+        LogicAnalyzer::with_backend(self.inner.backend.clone(), pins)
+    }
+
+    /// Configures the DMA-based PWM engine used by pins set to [`PwmEngine::Dma`].
+    ///
+    /// `channel` selects the DMA channel (0-14). By default, channel 14 is used, or channel 7
+    /// on the BCM2711 (Raspberry Pi 4, 400 and Compute Module 4), where channels 11-14 are
+    /// DMA4 channels that aren't supported. Unsupported channels and channels in use by the
+    /// firmware are rejected with `Err(`[`Error::DmaChannelNotAvailable`]`)` when the engine
+    /// starts.
+    ///
+    /// `step` sets the time resolution of all DMA-based PWM signals, and is rounded to a
+    /// multiple of 100 ns. By default, the step is 10 µs. A shorter step increases the
+    /// resolution, at the cost of more memory bandwidth and a shorter maximum period.
+    ///
+    /// The new configuration takes effect the next time the engine starts, which happens
+    /// when a pin is configured after all DMA-based PWM signals have been cleared.
+    ///
+    /// [`PwmEngine::Dma`]: enum.PwmEngine.html#variant.Dma
+    /// [`Error::DmaChannelNotAvailable`]: enum.Error.html#variant.DmaChannelNotAvailable
+    pub fn set_dma_pwm_config(&self, channel: u8, step: Duration) {
+        self.inner.dma_pwm.lock().unwrap().configure(channel, step);
+    }
+
     /// Blocks until an interrupt is triggered on any of the specified pins, or until a timeout occurs.
     ///
     /// Only pins that have been previously configured for synchronous interrupts using [`InputPin::set_interrupt`]
@@ -454,7 +1185,7 @@ impl Gpio {
     /// `timeout` can be set to `None` to wait indefinitely.
     ///
     /// When an interrupt event is triggered, `poll_interrupts` returns
-    /// `Ok((&`[`InputPin`]`, `[`Level`]`))` containing the corresponding pin and logic level. If multiple events trigger
+    /// `Ok((&`[`InputPin`]`, `[`InterruptEvent`]`))` containing the corresponding pin and event. If multiple events trigger
     /// at the same time, only the first one is returned. The remaining events are cached and will be returned
     /// the next time [`InputPin::poll_interrupt`] or `poll_interrupts` is called.
     ///
@@ -462,13 +1193,13 @@ impl Gpio {
     /// [`InputPin::poll_interrupt`]: struct.InputPin.html#method.poll_interrupt
     /// [`InputPin::set_async_interrupt`]: struct.InputPin.html#method.set_async_interrupt
     /// [`InputPin`]: struct.InputPin.html
-    /// [`Level`]: enum.Level.html
+    /// [`InterruptEvent`]: struct.InterruptEvent.html
     pub fn poll_interrupts<'a>(
         &self,
         pins: &[&'a InputPin],
         reset: bool,
         timeout: Option<Duration>,
-    ) -> Result<Option<(&'a InputPin, Level)>> {
+    ) -> Result<Option<(&'a InputPin, InterruptEvent)>> {
         (*self.inner.sync_interrupts.lock().unwrap()).poll(pins, reset, timeout)
     }
 }
@@ -0,0 +1,624 @@
+// Register and interrupt access used by GpioState. The hardware backend talks to
+// /dev/gpiomem and /dev/gpiochipN, the cdev backend only uses /dev/gpiochipN, and
+// the simulated backend keeps everything in memory so pin logic can run off-target.
+
+use std::fmt;
+use std::fs::File;
+use std::io;
+use std::os::unix::io::AsRawFd;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use crate::gpio::dma::PeripheralMem;
+use crate::gpio::dma_pwm::{self, PwmOutput};
+use crate::gpio::{config, ioctl, mem, pin, Error, Level, Mode, PullUpDown, Result};
+use crate::system::DeviceInfo;
+
+pub(crate) trait Backend: fmt::Debug + Send + Sync {
+    fn set_high(&self, pin: u8);
+    fn set_low(&self, pin: u8);
+    fn level(&self, pin: u8) -> Level;
+    fn mode(&self, pin: u8) -> Mode;
+    fn set_mode(&self, pin: u8, mode: Mode);
+    fn set_pullupdown(&self, pin: u8, pud: PullUpDown);
+
+    // Returns the number of pins, which are numbered starting at 0
+    fn pin_count(&self) -> usize {
+        pin::MAX
+    }
+
+    // Returns the pull-up/pull-down state as reported by the hardware, if supported
+    fn pullupdown(&self, _pin: u8) -> Option<PullUpDown> {
+        None
+    }
+
+    // Returns the consumer label the kernel reports for the pin's GPIO line
+    fn consumer(&self, _pin: u8) -> Option<String> {
+        None
+    }
+
+    // Returns true if the pins belong to the SoC's GPIO peripheral. Lock files are
+    // named after the BCM GPIO number, so other pins can't be reserved through them.
+    fn supports_pin_locks(&self) -> bool {
+        false
+    }
+
+    // Sets the pins in set_mask high and the pins in clear_mask low, with bit n
+    // representing BCM GPIO n. Backends that can't update multiple pins at once
+    // fall back to changing them one at a time.
+    fn write_levels(&self, set_mask: u64, clear_mask: u64) {
+        for pin in 0..pin::MAX as u8 {
+            if set_mask & (1 << pin) != 0 {
+                self.set_high(pin);
+            } else if clear_mask & (1 << pin) != 0 {
+                self.set_low(pin);
+            }
+        }
+    }
+
+    // Returns the logic levels of the pins in mask, with bit n representing BCM GPIO n
+    fn levels(&self, mask: u64) -> u64 {
+        let mut levels = 0;
+        for pin in 0..pin::MAX as u8 {
+            if mask & (1 << pin) != 0 && self.level(pin) == Level::High {
+                levels |= 1 << pin;
+            }
+        }
+
+        levels
+    }
+
+    // Returns an event handle whose fd becomes readable when an interrupt is triggered
+    fn event_request(&self, pin: u8, config: &ioctl::EventConfig) -> Result<ioctl::EventHandle>;
+
+    // Called when an interrupt is cleared, after which edges shouldn't be detected anymore
+    fn clear_events(&self, _pin: u8) {}
+
+    // Called when a Pin goes out of scope
+    fn release(&self, _pin: u8) {}
+
+    // Reads or writes the pad control register for the specified bank, without the password
+    fn pad_control(&self, _bank: usize) -> Result<u32> {
+        Err(Error::Io(io::ErrorKind::Unsupported.into()))
+    }
+
+    fn set_pad_control(&self, _bank: usize, _value: u32) -> Result<()> {
+        Err(Error::Io(io::ErrorKind::Unsupported.into()))
+    }
+
+    // Starts the DMA-based PWM engine. Only available when the registers can be accessed
+    // through /dev/mem.
+    fn dma_pwm(
+        &self,
+        _channel: u8,
+        _step: Duration,
+        _max_steps: usize,
+    ) -> Result<Box<dyn PwmOutput>> {
+        Err(Error::Io(io::ErrorKind::Unsupported.into()))
+    }
+}
+
+#[derive(Debug)]
+pub(crate) struct HardwareBackend {
+    gpio_mem: mem::GpioMem,
+    cdev: File,
+    v2: bool,
+    // Mapped through /dev/mem on first use, since /dev/gpiomem doesn't cover the pad control registers
+    pads: Mutex<Option<PeripheralMem>>,
+}
+
+impl HardwareBackend {
+    pub(crate) fn open() -> Result<HardwareBackend> {
+        let gpio_mem = mem::GpioMem::open()?;
+        let cdev = ioctl::find_gpiochip()?;
+        let v2 = ioctl::supports_v2(cdev.as_raw_fd());
+
+        Ok(HardwareBackend {
+            gpio_mem,
+            cdev,
+            v2,
+            pads: Mutex::new(None),
+        })
+    }
+
+    fn with_pads<T, F>(&self, f: F) -> Result<T>
+    where
+        F: FnOnce(&PeripheralMem) -> T,
+    {
+        let mut pads = self.pads.lock().unwrap();
+        if pads.is_none() {
+            let device_info = DeviceInfo::new().map_err(|_| Error::UnknownModel)?;
+            *pads = Some(PeripheralMem::open(&device_info, config::PADS_OFFSET)?);
+        }
+
+        Ok(f(pads.as_ref().unwrap()))
+    }
+}
+
+impl Backend for HardwareBackend {
+    #[inline(always)]
+    fn set_high(&self, pin: u8) {
+        self.gpio_mem.set_high(pin);
+    }
+
+    #[inline(always)]
+    fn set_low(&self, pin: u8) {
+        self.gpio_mem.set_low(pin);
+    }
+
+    #[inline(always)]
+    fn level(&self, pin: u8) -> Level {
+        self.gpio_mem.level(pin)
+    }
+
+    #[inline(always)]
+    fn write_levels(&self, set_mask: u64, clear_mask: u64) {
+        self.gpio_mem.write_levels(set_mask, clear_mask);
+    }
+
+    #[inline(always)]
+    fn levels(&self, mask: u64) -> u64 {
+        self.gpio_mem.levels() & mask
+    }
+
+    fn mode(&self, pin: u8) -> Mode {
+        self.gpio_mem.mode(pin)
+    }
+
+    fn set_mode(&self, pin: u8, mode: Mode) {
+        self.gpio_mem.set_mode(pin, mode);
+    }
+
+    fn set_pullupdown(&self, pin: u8, pud: PullUpDown) {
+        self.gpio_mem.set_pullupdown(pin, pud);
+    }
+
+    fn pullupdown(&self, pin: u8) -> Option<PullUpDown> {
+        self.gpio_mem.pullupdown(pin)
+    }
+
+    fn consumer(&self, pin: u8) -> Option<String> {
+        ioctl::LineInfo::get(self.cdev.as_raw_fd(), pin)
+            .ok()
+            .and_then(|line_info| line_info.consumer())
+    }
+
+    fn supports_pin_locks(&self) -> bool {
+        true
+    }
+
+    fn pad_control(&self, bank: usize) -> Result<u32> {
+        self.with_pads(|pads| pads.read(config::PADS_GPIO[bank]) & 0xff)
+    }
+
+    fn set_pad_control(&self, bank: usize, value: u32) -> Result<()> {
+        self.with_pads(|pads| pads.write(config::PADS_GPIO[bank], config::PADS_PASSWD | value))
+    }
+
+    fn dma_pwm(&self, channel: u8, step: Duration, max_steps: usize) -> Result<Box<dyn PwmOutput>> {
+        Ok(Box::new(dma_pwm::DmaPwmOutput::new(
+            channel, step, max_steps,
+        )?))
+    }
+
+    fn event_request(&self, pin: u8, config: &ioctl::EventConfig) -> Result<ioctl::EventHandle> {
+        if self.v2 {
+            // Leave the bias alone, since it's managed through the registers
+            Ok(ioctl::EventHandle::V2(Box::new(
+                ioctl::LineRequest::with_events(self.cdev.as_raw_fd(), pin, config, None)?,
+            )))
+        } else {
+            Ok(ioctl::EventHandle::V1(ioctl::EventRequest::new(
+                self.cdev.as_raw_fd(),
+                pin,
+                config.trigger,
+            )?))
+        }
+    }
+}
+
+#[derive(Debug)]
+struct CdevLine {
+    // Input or Output while we hold the line, None if the kernel should be asked
+    mode: Option<Mode>,
+    pud: Option<PullUpDown>,
+    // Edge detection settings of the active interrupt, which share the line request
+    events: Option<ioctl::EventConfig>,
+    // Pins that are written or read together share a single multi-line request
+    request: Option<Arc<ioctl::LineRequest>>,
+    // Bit representing the pin in the request's values
+    bit: u64,
+}
+
+impl CdevLine {
+    fn shares_request(&self, request: &Arc<ioctl::LineRequest>) -> bool {
+        matches!(self.request, Some(ref own) if Arc::ptr_eq(own, request))
+    }
+}
+
+// Returns the BCM GPIO pin numbers selected by mask
+fn mask_pins(mask: u64) -> Vec<u8> {
+    (0..pin::MAX as u8)
+        .filter(|pin| mask & (1 << pin) != 0)
+        .collect()
+}
+
+// Fallback for when /dev/gpiomem and /dev/mem can't be mapped. Every pin is
+// requested through the v2 uAPI, which supports input, output and bias, but
+// doesn't provide access to the alternate functions.
+#[derive(Debug)]
+pub(crate) struct CdevBackend {
+    cdev: File,
+    lines: Mutex<Vec<CdevLine>>,
+}
+
+impl CdevBackend {
+    pub(crate) fn open() -> Result<CdevBackend> {
+        let cdev = ioctl::find_gpiochip()?;
+        if !ioctl::supports_v2(cdev.as_raw_fd()) {
+            return Err(Error::Io(io::Error::from_raw_os_error(libc::ENOTTY)));
+        }
+
+        let mut lines = Vec::with_capacity(pin::MAX);
+        for _ in 0..pin::MAX {
+            lines.push(CdevLine {
+                mode: None,
+                pud: None,
+                events: None,
+                request: None,
+                bit: 1,
+            });
+        }
+
+        Ok(CdevBackend {
+            cdev,
+            lines: Mutex::new(lines),
+        })
+    }
+
+    fn line_config(&self, pin: u8, line: &CdevLine, mode: Mode) -> ioctl::LineConfig {
+        let bias = ioctl::bias_flags_v2(line.pud);
+
+        if mode == Mode::Output {
+            // Keep the current output level to prevent glitches when switching modes
+            let mut config = ioctl::LineConfig::new(ioctl::LINE_FLAG_V2_OUTPUT | bias);
+            if self.read_level(pin, line) == Level::High {
+                config.set_output_values(1, 1);
+            }
+
+            config
+        } else if let Some(ref events) = line.events {
+            // Reconfiguring the line without the edge flags would disable the interrupt
+            ioctl::LineConfig::with_events(events, line.pud)
+        } else {
+            ioctl::LineConfig::new(ioctl::LINE_FLAG_V2_INPUT | bias)
+        }
+    }
+
+    // Applies the cached mode and bias to the line, requesting it if needed
+    fn configure(&self, pin: u8, line: &mut CdevLine) {
+        let mode = match line.mode {
+            Some(mode) => mode,
+            None => return,
+        };
+
+        let config = self.line_config(pin, line, mode);
+
+        if let Some(request) = line.request.as_mut().and_then(Arc::get_mut) {
+            if request.reconfigure(config).is_ok() {
+                return;
+            }
+        }
+
+        line.request = ioctl::LineRequest::new(self.cdev.as_raw_fd(), &[pin], config)
+            .ok()
+            .map(Arc::new);
+        line.bit = 1;
+    }
+
+    fn read_level(&self, pin: u8, line: &CdevLine) -> Level {
+        let bits = match line.request {
+            Some(ref request) => request.levels(line.bit).map(|bits| bits & line.bit),
+            // Request the line without changing its direction, so we can read its level
+            None => ioctl::LineRequest::new(self.cdev.as_raw_fd(), &[pin], Default::default())
+                .and_then(|request| request.levels(1)),
+        };
+
+        match bits {
+            Ok(bits) if bits != 0 => Level::High,
+            _ => Level::Low,
+        }
+    }
+
+    // Splits up the multi-line request the pin belongs to, and requests each of its
+    // lines separately, so they can be configured independently again
+    fn ungroup(&self, lines: &mut [CdevLine], pin: u8) {
+        let request = match lines[pin as usize].request {
+            Some(ref request) if Arc::strong_count(request) > 1 => request.clone(),
+            _ => return,
+        };
+
+        let members = mask_pins(
+            lines
+                .iter()
+                .enumerate()
+                .filter(|(_, line)| line.shares_request(&request))
+                .fold(0, |mask, (member, _)| mask | (1 << member)),
+        );
+
+        // The kernel reports the lines as busy until the shared request is closed
+        for &member in &members {
+            lines[member as usize].request = None;
+        }
+        drop(request);
+
+        for member in members {
+            self.configure(member, &mut lines[member as usize]);
+        }
+    }
+
+    // Returns a single request that contains every pin in mask, which have to be
+    // held in the same mode. Pins are moved to a new multi-line request when they
+    // don't share one yet, together with the other pins of any requests they were
+    // already grouped in. Returns None if the pins can't be requested together.
+    fn group(
+        &self,
+        lines: &mut [CdevLine],
+        mask: u64,
+        mode: Mode,
+    ) -> Option<Arc<ioctl::LineRequest>> {
+        let pins = mask_pins(mask);
+        if pins.len() < 2 {
+            return None;
+        }
+
+        if let Some(ref request) = lines[pins[0] as usize].request {
+            if pins
+                .iter()
+                .all(|&pin| lines[pin as usize].shares_request(request))
+            {
+                return Some(request.clone());
+            }
+        }
+
+        let mut members = mask;
+        for &pin in &pins {
+            if let Some(ref request) = lines[pin as usize].request {
+                for (member, line) in lines.iter().enumerate() {
+                    if line.shares_request(request) {
+                        members |= 1 << member;
+                    }
+                }
+            }
+        }
+        let pins = mask_pins(members);
+
+        // A request applies the same flags to all of its lines
+        let pud = lines[pins[0] as usize].pud;
+        if !pins.iter().all(|&pin| {
+            let line = &lines[pin as usize];
+            line.mode == Some(mode) && line.pud == pud && line.events.is_none()
+        }) {
+            return None;
+        }
+
+        let config = if mode == Mode::Output {
+            // Keep the current output levels to prevent glitches
+            let mut config =
+                ioctl::LineConfig::new(ioctl::LINE_FLAG_V2_OUTPUT | ioctl::bias_flags_v2(pud));
+            let levels = pins
+                .iter()
+                .enumerate()
+                .filter(|&(_, &pin)| self.read_level(pin, &lines[pin as usize]) == Level::High)
+                .fold(0, |levels, (bit, _)| levels | (1 << bit));
+            config.set_output_values(levels, u64::MAX >> (64 - pins.len()));
+
+            config
+        } else {
+            ioctl::LineConfig::new(ioctl::LINE_FLAG_V2_INPUT | ioctl::bias_flags_v2(pud))
+        };
+
+        for &pin in &pins {
+            lines[pin as usize].request = None;
+        }
+
+        match ioctl::LineRequest::new(self.cdev.as_raw_fd(), &pins, config) {
+            Ok(request) => {
+                let request = Arc::new(request);
+                for (bit, &pin) in pins.iter().enumerate() {
+                    lines[pin as usize].request = Some(request.clone());
+                    lines[pin as usize].bit = 1 << bit;
+                }
+
+                Some(request)
+            }
+            Err(_) => {
+                for &pin in &pins {
+                    self.configure(pin, &mut lines[pin as usize]);
+                }
+
+                None
+            }
+        }
+    }
+
+    // Converts a mask of BCM GPIO pin numbers into the bits of the pins' shared request
+    fn request_bits(lines: &[CdevLine], mask: u64) -> u64 {
+        mask_pins(mask)
+            .into_iter()
+            .fold(0, |bits, pin| bits | lines[pin as usize].bit)
+    }
+
+    fn with_line<T, F>(&self, pin: u8, f: F) -> T
+    where
+        F: FnOnce(&mut CdevLine) -> T,
+    {
+        f(&mut self.lines.lock().unwrap()[pin as usize])
+    }
+
+    // Same as with_line, but removes the pin from any multi-line request first
+    fn with_own_line<T, F>(&self, pin: u8, f: F) -> T
+    where
+        F: FnOnce(&mut CdevLine) -> T,
+    {
+        let mut lines = self.lines.lock().unwrap();
+        self.ungroup(&mut lines, pin);
+
+        f(&mut lines[pin as usize])
+    }
+}
+
+impl Backend for CdevBackend {
+    fn set_high(&self, pin: u8) {
+        self.with_line(pin, |line| {
+            if let Some(ref request) = line.request {
+                let _ = request.set_levels(line.bit, line.bit);
+            }
+        });
+    }
+
+    fn set_low(&self, pin: u8) {
+        self.with_line(pin, |line| {
+            if let Some(ref request) = line.request {
+                let _ = request.set_levels(0, line.bit);
+            }
+        });
+    }
+
+    fn level(&self, pin: u8) -> Level {
+        self.with_line(pin, |line| self.read_level(pin, line))
+    }
+
+    fn write_levels(&self, set_mask: u64, clear_mask: u64) {
+        let clear_mask = clear_mask & !set_mask;
+        let mut lines = self.lines.lock().unwrap();
+
+        // Change all pins at once through a shared request if possible
+        if let Some(request) = self.group(&mut lines, set_mask | clear_mask, Mode::Output) {
+            let bits = CdevBackend::request_bits(&lines, set_mask);
+            let mask = CdevBackend::request_bits(&lines, set_mask | clear_mask);
+            let _ = request.set_levels(bits, mask);
+
+            return;
+        }
+
+        for pin in mask_pins(set_mask | clear_mask) {
+            let line = &lines[pin as usize];
+            if let Some(ref request) = line.request {
+                let bits = if set_mask & (1 << pin) != 0 {
+                    line.bit
+                } else {
+                    0
+                };
+                let _ = request.set_levels(bits, line.bit);
+            }
+        }
+    }
+
+    fn levels(&self, mask: u64) -> u64 {
+        let mut lines = self.lines.lock().unwrap();
+        let pins = mask_pins(mask);
+
+        // Read all pins at once through a shared request if possible
+        let mode = pins.first().and_then(|&pin| lines[pin as usize].mode);
+        if let Some(request) = mode.and_then(|mode| self.group(&mut lines, mask, mode)) {
+            let bits = request
+                .levels(CdevBackend::request_bits(&lines, mask))
+                .unwrap_or(0);
+
+            return pins
+                .into_iter()
+                .filter(|&pin| bits & lines[pin as usize].bit != 0)
+                .fold(0, |levels, pin| levels | (1 << pin));
+        }
+
+        pins.into_iter()
+            .filter(|&pin| self.read_level(pin, &lines[pin as usize]) == Level::High)
+            .fold(0, |levels, pin| levels | (1 << pin))
+    }
+
+    fn mode(&self, pin: u8) -> Mode {
+        self.with_line(pin, |line| {
+            if let Some(mode) = line.mode {
+                return mode;
+            }
+
+            match ioctl::LineInfoV2::new(self.cdev.as_raw_fd(), pin) {
+                Ok(ref info) if info.flags & ioctl::LINE_FLAG_V2_OUTPUT > 0 => Mode::Output,
+                _ => Mode::Input,
+            }
+        })
+    }
+
+    fn set_mode(&self, pin: u8, mode: Mode) {
+        self.with_own_line(pin, |line| match mode {
+            Mode::Input | Mode::Output => {
+                line.mode = Some(mode);
+                self.configure(pin, line);
+            }
+            _ => {
+                // Alternate functions can only be selected through the registers
+                line.mode = None;
+                line.request = None;
+            }
+        });
+    }
+
+    fn set_pullupdown(&self, pin: u8, pud: PullUpDown) {
+        self.with_own_line(pin, |line| {
+            line.pud = Some(pud);
+            self.configure(pin, line);
+        });
+    }
+
+    fn pullupdown(&self, pin: u8) -> Option<PullUpDown> {
+        self.with_line(pin, |line| line.pud)
+    }
+
+    fn consumer(&self, pin: u8) -> Option<String> {
+        ioctl::LineInfo::get(self.cdev.as_raw_fd(), pin)
+            .ok()
+            .and_then(|line_info| line_info.consumer())
+    }
+
+    fn supports_pin_locks(&self) -> bool {
+        true
+    }
+
+    fn event_request(&self, pin: u8, config: &ioctl::EventConfig) -> Result<ioctl::EventHandle> {
+        self.with_own_line(pin, |line| {
+            // Release our own request first, otherwise the kernel reports the line as busy
+            line.request = None;
+
+            let request =
+                ioctl::LineRequest::with_events(self.cdev.as_raw_fd(), pin, config, line.pud)?;
+
+            // Keep a duplicate around so we can still read the line's level
+            line.mode = Some(Mode::Input);
+            line.events = Some(*config);
+            line.request = request.try_clone().ok().map(Arc::new);
+            line.bit = 1;
+
+            Ok(ioctl::EventHandle::V2(Box::new(request)))
+        })
+    }
+
+    fn clear_events(&self, pin: u8) {
+        self.with_own_line(pin, |line| {
+            // The duplicate request shares the line with the event fd, so the edge
+            // flags have to be removed explicitly to stop events from queueing up
+            if line.events.take().is_some() {
+                self.configure(pin, line);
+            }
+        });
+    }
+
+    fn release(&self, pin: u8) {
+        self.with_own_line(pin, |line| {
+            line.mode = None;
+            line.pud = None;
+            line.events = None;
+            line.request = None;
+        });
+    }
+}
@@ -0,0 +1,248 @@
+use std::sync::Arc;
+
+use crate::gpio::backend::Backend;
+use crate::gpio::{InputPin, Level, OutputPin};
+
+// Converts a bus value into a mask of BCM GPIO pin numbers
+fn pin_mask(pins: &[u8], value: u64) -> u64 {
+    pins.iter()
+        .enumerate()
+        .filter(|&(bit, _)| value & (1 << bit) != 0)
+        .fold(0, |mask, (_, &pin)| mask | (1 << pin))
+}
+
+// Converts a mask of BCM GPIO pin numbers into a bus value
+fn bus_value(pins: &[u8], levels: u64) -> u64 {
+    pins.iter()
+        .enumerate()
+        .filter(|&(_, &pin)| levels & (1 << pin) != 0)
+        .fold(0, |value, (bit, _)| value | (1 << bit))
+}
+
+/// A group of GPIO pins configured as outputs, which are written together.
+///
+/// `OutputBus`es are constructed by calling [`Gpio::output_bus`]. Bit 0 of a bus
+/// value corresponds to the first pin passed to [`Gpio::output_bus`], bit 1 to the
+/// second pin, and so on.
+///
+/// [`write`] changes all pins with a single write to the `GPSETn` register followed
+/// by a single write to the `GPCLRn` register for each bank, instead of setting every
+/// pin separately. Pins on the same bank (BCM GPIO 0-31 or 32-53) switch within
+/// nanoseconds of each other, which keeps parallel buses such as 8-bit HD44780 LCD
+/// interfaces, multiplexed 7-segment displays and R-2R DACs free of intermediate
+/// states caused by scheduling delays. If the GPIO registers aren't accessible and
+/// [`Gpio`] fell back to the `gpiochip` character device, the pins are requested as a
+/// single group of lines, and changed together with one `GPIO_V2_LINE_SET_VALUES` call.
+///
+/// [`Gpio`]: struct.Gpio.html
+/// [`Gpio::output_bus`]: struct.Gpio.html#method.output_bus
+/// [`write`]: #method.write
+#[derive(Debug)]
+pub struct OutputBus {
+    pins: Vec<OutputPin>,
+    pin_numbers: Vec<u8>,
+    backend: Arc<dyn Backend>,
+}
+
+impl OutputBus {
+    pub(crate) fn new(pins: Vec<OutputPin>, backend: Arc<dyn Backend>) -> OutputBus {
+        let pin_numbers = pins.iter().map(|pin| pin.pin()).collect();
+
+        OutputBus {
+            pins,
+            pin_numbers,
+            backend,
+        }
+    }
+
+    /// Returns the BCM GPIO pin numbers, ordered from the least significant
+    /// to the most significant bit.
+    pub fn pins(&self) -> &[u8] {
+        &self.pin_numbers
+    }
+
+    /// Returns the number of pins.
+    pub fn len(&self) -> usize {
+        self.pins.len()
+    }
+
+    /// Returns `true` if the bus doesn't contain any pins.
+    pub fn is_empty(&self) -> bool {
+        self.pins.is_empty()
+    }
+
+    /// Sets each pin's output state to the corresponding bit in `value`.
+    ///
+    /// Any bits beyond the number of pins are ignored.
+    pub fn write(&mut self, value: u64) {
+        self.write_masked(value, u64::MAX);
+    }
+
+    /// Sets the output state for the pins selected by `mask` to the corresponding
+    /// bit in `value`. Pins that aren't selected by `mask` keep their current state.
+    pub fn write_masked(&mut self, value: u64, mask: u64) {
+        self.backend.write_levels(
+            pin_mask(&self.pin_numbers, value & mask),
+            pin_mask(&self.pin_numbers, !value & mask),
+        );
+    }
+
+    /// Sets each pin's output state to the corresponding level in `levels`.
+    ///
+    /// Any levels beyond the number of pins are ignored. Pins without a
+    /// corresponding level keep their current state.
+    pub fn write_levels(&mut self, levels: &[Level]) {
+        let (value, mask) = levels.iter().take(self.pins.len()).enumerate().fold(
+            (0, 0),
+            |(value, mask), (bit, &level)| {
+                if level == Level::High {
+                    (value | (1 << bit), mask | (1 << bit))
+                } else {
+                    (value, mask | (1 << bit))
+                }
+            },
+        );
+
+        self.write_masked(value, mask);
+    }
+
+    /// Reads the pins' logic levels with a single read of the `GPLEVn` registers.
+    pub fn read(&self) -> u64 {
+        bus_value(
+            &self.pin_numbers,
+            self.backend.levels(pin_mask(&self.pin_numbers, u64::MAX)),
+        )
+    }
+
+    /// When enabled, resets every pin's mode to its original state and disables the
+    /// built-in pull-up/pull-down resistors when the `OutputBus` goes out of scope.
+    /// By default, this is set to `true`.
+    pub fn set_reset_on_drop(&mut self, reset_on_drop: bool) {
+        for pin in &mut self.pins {
+            pin.set_reset_on_drop(reset_on_drop);
+        }
+    }
+
+    /// Consumes the `OutputBus`, and returns the individual pins.
+    pub fn into_pins(self) -> Vec<OutputPin> {
+        self.pins
+    }
+}
+
+/// A group of GPIO pins configured as inputs, which are read together.
+///
+/// `InputBus`es are constructed by calling [`Gpio::input_bus`]. Bit 0 of a bus
+/// value corresponds to the first pin passed to [`Gpio::input_bus`], bit 1 to the
+/// second pin, and so on.
+///
+/// [`read`] samples all pins with a single read of the `GPLEVn` register for each
+/// bank, so the returned value is a consistent snapshot of the bus.
+///
+/// [`Gpio::input_bus`]: struct.Gpio.html#method.input_bus
+/// [`read`]: #method.read
+#[derive(Debug)]
+pub struct InputBus {
+    pins: Vec<InputPin>,
+    pin_numbers: Vec<u8>,
+    backend: Arc<dyn Backend>,
+}
+
+impl InputBus {
+    pub(crate) fn new(pins: Vec<InputPin>, backend: Arc<dyn Backend>) -> InputBus {
+        let pin_numbers = pins.iter().map(|pin| pin.pin()).collect();
+
+        InputBus {
+            pins,
+            pin_numbers,
+            backend,
+        }
+    }
+
+    /// Returns the BCM GPIO pin numbers, ordered from the least significant
+    /// to the most significant bit.
+    pub fn pins(&self) -> &[u8] {
+        &self.pin_numbers
+    }
+
+    /// Returns the number of pins.
+    pub fn len(&self) -> usize {
+        self.pins.len()
+    }
+
+    /// Returns `true` if the bus doesn't contain any pins.
+    pub fn is_empty(&self) -> bool {
+        self.pins.is_empty()
+    }
+
+    /// Reads the pins' logic levels, and returns them as a bus value.
+    pub fn read(&self) -> u64 {
+        bus_value(
+            &self.pin_numbers,
+            self.backend.levels(pin_mask(&self.pin_numbers, u64::MAX)),
+        )
+    }
+
+    /// When enabled, resets every pin's mode to its original state and disables the
+    /// built-in pull-up/pull-down resistors when the `InputBus` goes out of scope.
+    /// By default, this is set to `true`.
+    pub fn set_reset_on_drop(&mut self, reset_on_drop: bool) {
+        for pin in &mut self.pins {
+            pin.set_reset_on_drop(reset_on_drop);
+        }
+    }
+
+    /// Consumes the `InputBus`, and returns the individual pins.
+    pub fn into_pins(self) -> Vec<InputPin> {
+        self.pins
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::gpio::{Gpio, Level, PullUpDown, Simulator};
+
+    #[test]
+    fn output_bus() {
+        let (gpio, sim) = Gpio::simulated().unwrap();
+        // Bit 0 is GPIO 20, bit 1 is GPIO 4 and bit 2 is GPIO 33
+        let mut bus = gpio.output_bus(&[20, 4, 33]).unwrap();
+        let levels = |sim: &Simulator| [sim.level(20), sim.level(4), sim.level(33)];
+
+        bus.write(0b101);
+        assert_eq!(levels(&sim), [Level::High, Level::Low, Level::High]);
+        assert_eq!(bus.read(), 0b101);
+
+        // Bits beyond the number of pins are ignored
+        bus.write(0b1010);
+        assert_eq!(levels(&sim), [Level::Low, Level::High, Level::Low]);
+
+        // Only the masked pins change
+        bus.write_masked(0b001, 0b101);
+        assert_eq!(levels(&sim), [Level::High, Level::High, Level::Low]);
+        bus.write_masked(0b100, 0b110);
+        assert_eq!(levels(&sim), [Level::High, Level::Low, Level::High]);
+
+        bus.write_levels(&[Level::Low, Level::High]);
+        assert_eq!(levels(&sim), [Level::Low, Level::High, Level::High]);
+        assert_eq!(bus.read(), 0b110);
+    }
+
+    #[test]
+    fn input_bus() {
+        let (gpio, sim) = Gpio::simulated().unwrap();
+        let bus = gpio.input_bus(&[9, 40, 2, 17], PullUpDown::Off).unwrap();
+        assert_eq!(bus.read(), 0);
+
+        sim.set_level(40, Level::High);
+        assert_eq!(bus.read(), 0b0010);
+
+        sim.set_level(17, Level::High);
+        sim.set_level(9, Level::High);
+        assert_eq!(bus.read(), 0b1011);
+
+        // Pins outside the bus aren't reported
+        sim.set_level(3, Level::High);
+        sim.set_level(40, Level::Low);
+        assert_eq!(bus.read(), 0b1001);
+    }
+}
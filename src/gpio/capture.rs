@@ -0,0 +1,864 @@
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use crate::gpio::backend::Backend;
+use crate::gpio::interrupt::AsyncInterrupt;
+use crate::gpio::soft_pwm::{get_time_ns, sleep_ns, wait_until_ns};
+use crate::gpio::{ioctl, Error, InputPin, Level, Result, Trigger};
+
+const NANOS_PER_SEC: f64 = 1_000_000_000.0;
+// Interval at which the event-based capture checks for a trigger timeout
+const POLL_INTERVAL_NS: i64 = 10_000_000;
+
+/// Condition that starts a [`LogicAnalyzer`] capture.
+///
+/// [`LogicAnalyzer`]: struct.LogicAnalyzer.html
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum CaptureTrigger {
+    /// Starts capturing immediately.
+    Immediate,
+    /// Starts capturing when the specified edge occurs on a BCM GPIO pin.
+    Edge(u8, Trigger),
+    /// Starts capturing as soon as every listed BCM GPIO pin is at the specified level.
+    Pattern(Vec<(u8, Level)>),
+}
+
+impl CaptureTrigger {
+    fn pins(&self) -> Vec<u8> {
+        match *self {
+            CaptureTrigger::Immediate => Vec::new(),
+            CaptureTrigger::Edge(pin, _) => vec![pin],
+            CaptureTrigger::Pattern(ref pattern) => pattern.iter().map(|&(pin, _)| pin).collect(),
+        }
+    }
+
+    fn matches(&self, previous: u64, levels: u64) -> bool {
+        match *self {
+            CaptureTrigger::Immediate => true,
+            CaptureTrigger::Edge(pin, trigger) => {
+                let rising = previous & (1 << pin) == 0 && levels & (1 << pin) != 0;
+                let falling = previous & (1 << pin) != 0 && levels & (1 << pin) == 0;
+
+                match trigger {
+                    Trigger::Disabled => false,
+                    Trigger::RisingEdge => rising,
+                    Trigger::FallingEdge => falling,
+                    Trigger::Both => rising || falling,
+                }
+            }
+            CaptureTrigger::Pattern(ref pattern) => pattern
+                .iter()
+                .all(|&(pin, level)| level_of(levels, pin) == level),
+        }
+    }
+}
+
+fn level_of(levels: u64, pin: u8) -> Level {
+    if levels & (1 << pin) != 0 {
+        Level::High
+    } else {
+        Level::Low
+    }
+}
+
+/// A change in logic level recorded by a [`LogicAnalyzer`].
+///
+/// [`LogicAnalyzer`]: struct.LogicAnalyzer.html
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub struct LevelChange {
+    /// Time since the start of the capture.
+    pub timestamp: Duration,
+    /// BCM GPIO pin number.
+    pub pin: u8,
+    /// The pin's new logic level.
+    pub level: Level,
+}
+
+// Collects level changes until the trigger condition is met and the capture
+// duration has passed. All timestamps are CLOCK_MONOTONIC nanoseconds.
+#[derive(Debug)]
+struct Recorder {
+    trigger: CaptureTrigger,
+    pre_trigger: i64,
+    duration: i64,
+    // Levels at the start of the capture window, which moves forward until triggered
+    start: i64,
+    initial: u64,
+    levels: u64,
+    changes: VecDeque<(i64, u64)>,
+    triggered: Option<i64>,
+    samples: u64,
+    first_sample: i64,
+    last_sample: i64,
+    max_interval: i64,
+}
+
+impl Recorder {
+    fn new(
+        trigger: CaptureTrigger,
+        pre_trigger: Duration,
+        duration: Duration,
+        timestamp: i64,
+        levels: u64,
+    ) -> Recorder {
+        // Pattern triggers may already match before the first change
+        let triggered = if trigger.matches(levels, levels) {
+            Some(timestamp)
+        } else {
+            None
+        };
+
+        Recorder {
+            trigger,
+            pre_trigger: pre_trigger.as_nanos() as i64,
+            duration: duration.as_nanos() as i64,
+            start: timestamp,
+            initial: levels,
+            levels,
+            changes: VecDeque::new(),
+            triggered,
+            samples: 0,
+            first_sample: timestamp,
+            last_sample: timestamp,
+            max_interval: 0,
+        }
+    }
+
+    fn update(&mut self, timestamp: i64, levels: u64) {
+        let previous = self.levels;
+        if levels != previous {
+            self.changes.push_back((timestamp, levels));
+            self.levels = levels;
+        }
+
+        if self.triggered.is_none() {
+            if self.trigger.matches(previous, levels) {
+                self.triggered = Some(timestamp);
+            } else {
+                // Only keep the changes that fit in the pre-trigger buffer
+                self.advance(timestamp - self.pre_trigger);
+            }
+        }
+    }
+
+    fn sample(&mut self, timestamp: i64, levels: u64) {
+        if self.samples > 0 {
+            self.max_interval = self.max_interval.max(timestamp - self.last_sample);
+        } else {
+            self.first_sample = timestamp;
+        }
+
+        self.samples += 1;
+        self.last_sample = timestamp;
+        self.update(timestamp, levels);
+    }
+
+    // Moves the start of the capture window forward to `start`
+    fn advance(&mut self, start: i64) {
+        if start <= self.start {
+            return;
+        }
+
+        while let Some(&(timestamp, levels)) = self.changes.front() {
+            if timestamp > start {
+                break;
+            }
+
+            self.initial = levels;
+            self.changes.pop_front();
+        }
+
+        self.start = start;
+    }
+
+    fn is_done(&self, now: i64) -> bool {
+        matches!(self.triggered, Some(triggered) if now >= triggered + self.duration)
+    }
+
+    fn finish(mut self, pins: Vec<u8>, sampled: bool) -> Capture {
+        let triggered = self
+            .triggered
+            .expect("capture is only finished once triggered");
+        self.advance(triggered - self.pre_trigger);
+
+        let end = triggered + self.duration;
+        let mut changes = Vec::new();
+        let mut previous = self.initial;
+        for &(timestamp, levels) in self.changes.iter().take_while(|&&(ts, _)| ts <= end) {
+            for &pin in &pins {
+                if (previous ^ levels) & (1 << pin) != 0 {
+                    changes.push(LevelChange {
+                        timestamp: Duration::from_nanos((timestamp - self.start) as u64),
+                        pin,
+                        level: level_of(levels, pin),
+                    });
+                }
+            }
+
+            previous = levels;
+        }
+
+        let sample_rate = if sampled && self.last_sample > self.first_sample {
+            Some(
+                (self.samples - 1) as f64 * NANOS_PER_SEC
+                    / (self.last_sample - self.first_sample) as f64,
+            )
+        } else {
+            None
+        };
+
+        Capture {
+            initial: pins
+                .iter()
+                .map(|&pin| level_of(self.initial, pin))
+                .collect(),
+            pins,
+            changes,
+            trigger: Duration::from_nanos((triggered - self.start) as u64),
+            duration: Duration::from_nanos((end - self.start) as u64),
+            samples: if sampled { Some(self.samples) } else { None },
+            sample_rate,
+            max_sample_interval: if sampled {
+                Some(Duration::from_nanos(self.max_interval as u64))
+            } else {
+                None
+            },
+        }
+    }
+}
+
+/// Level changes on a group of pins, recorded by a [`LogicAnalyzer`].
+///
+/// All timestamps are relative to the start of the capture, which lies up to the
+/// pre-trigger period before the [`trigger`].
+///
+/// [`LogicAnalyzer`]: struct.LogicAnalyzer.html
+/// [`trigger`]: #method.trigger
+#[derive(Debug, PartialEq, Clone)]
+pub struct Capture {
+    pins: Vec<u8>,
+    initial: Vec<Level>,
+    changes: Vec<LevelChange>,
+    trigger: Duration,
+    duration: Duration,
+    samples: Option<u64>,
+    sample_rate: Option<f64>,
+    max_sample_interval: Option<Duration>,
+}
+
+impl Capture {
+    /// Returns the captured BCM GPIO pin numbers.
+    pub fn pins(&self) -> &[u8] {
+        &self.pins
+    }
+
+    /// Returns the specified pin's logic level at the start of the capture, or `None`
+    /// if the pin wasn't captured.
+    pub fn initial_level(&self, pin: u8) -> Option<Level> {
+        self.pins
+            .iter()
+            .position(|&p| p == pin)
+            .map(|index| self.initial[index])
+    }
+
+    /// Returns every level change, ordered by timestamp.
+    pub fn changes(&self) -> &[LevelChange] {
+        &self.changes
+    }
+
+    /// Returns the time at which the trigger condition was met.
+    pub fn trigger(&self) -> Duration {
+        self.trigger
+    }
+
+    /// Returns the total length of the capture, including the pre-trigger period.
+    pub fn duration(&self) -> Duration {
+        self.duration
+    }
+
+    /// Returns the number of times the pins were sampled, or `None` if the capture
+    /// is based on edge events.
+    pub fn samples(&self) -> Option<u64> {
+        self.samples
+    }
+
+    /// Returns the average sample rate in hertz (Hz), or `None` if the capture is
+    /// based on edge events.
+    ///
+    /// Pulses that are shorter than the sample interval may be missed.
+    pub fn sample_rate(&self) -> Option<f64> {
+        self.sample_rate
+    }
+
+    /// Returns the longest time between two consecutive samples, or `None` if the
+    /// capture is based on edge events.
+    ///
+    /// A maximum interval that's much longer than the average indicates the sampling
+    /// was interrupted by the scheduler.
+    pub fn max_sample_interval(&self) -> Option<Duration> {
+        self.max_sample_interval
+    }
+
+    /// Writes the capture in Value Change Dump (VCD) format, which can be opened in
+    /// waveform viewers such as GTKWave or PulseView.
+    ///
+    /// Each pin is written as a single-bit wire named `GPIO<pin>`, with a timescale
+    /// of 1 ns.
+    pub fn write_vcd<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        let ids: Vec<String> = (0..self.pins.len()).map(vcd_id).collect();
+
+        writeln!(
+            writer,
+            "$version {} {} $end",
+            env!("CARGO_PKG_NAME"),
+            env!("CARGO_PKG_VERSION")
+        )?;
+        write!(writer, "$comment trigger at {} ns", self.trigger.as_nanos())?;
+        if let Some(sample_rate) = self.sample_rate {
+            write!(writer, ", sample rate {:.0} Hz", sample_rate)?;
+        }
+        writeln!(writer, " $end")?;
+        writeln!(writer, "$timescale 1 ns $end")?;
+        writeln!(writer, "$scope module gpio $end")?;
+        for (pin, id) in self.pins.iter().zip(&ids) {
+            writeln!(writer, "$var wire 1 {} GPIO{} $end", id, pin)?;
+        }
+        writeln!(writer, "$upscope $end")?;
+        writeln!(writer, "$enddefinitions $end")?;
+
+        writeln!(writer, "#0")?;
+        writeln!(writer, "$dumpvars")?;
+        for (level, id) in self.initial.iter().zip(&ids) {
+            writeln!(writer, "{}{}", vcd_value(*level), id)?;
+        }
+        writeln!(writer, "$end")?;
+
+        let mut time = Duration::from_secs(0);
+        for change in &self.changes {
+            if change.timestamp != time {
+                time = change.timestamp;
+                writeln!(writer, "#{}", time.as_nanos())?;
+            }
+
+            let index = self
+                .pins
+                .iter()
+                .position(|&pin| pin == change.pin)
+                .expect("changes only contain captured pins");
+            writeln!(writer, "{}{}", vcd_value(change.level), ids[index])?;
+        }
+
+        // Mark the end of the capture, so viewers show the final levels until then
+        if self.duration > time {
+            writeln!(writer, "#{}", self.duration.as_nanos())?;
+        }
+
+        writer.flush()
+    }
+
+    /// Saves the capture as a VCD file.
+    ///
+    /// See [`write_vcd`] for details.
+    ///
+    /// [`write_vcd`]: #method.write_vcd
+    pub fn save_vcd<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        self.write_vcd(BufWriter::new(File::create(path)?))
+    }
+}
+
+// VCD identifiers consist of printable ASCII characters from '!' to '~'
+fn vcd_id(mut index: usize) -> String {
+    let mut id = String::new();
+    loop {
+        id.push((b'!' + (index % 94) as u8) as char);
+        index /= 94;
+        if index == 0 {
+            return id;
+        }
+        index -= 1;
+    }
+}
+
+fn vcd_value(level: Level) -> char {
+    match level {
+        Level::Low => '0',
+        Level::High => '1',
+    }
+}
+
+#[derive(Debug)]
+enum Source {
+    Sampling(Arc<dyn Backend>),
+    Events(Vec<InputPin>),
+}
+
+/// Records the logic levels of a group of pins.
+///
+/// A `LogicAnalyzer` either samples the levels of all pins at once by reading the
+/// `GPLEVn` registers, or records the kernel's timestamped edge events for a group
+/// of [`InputPin`]s. Sampling captures any pin regardless of its mode, but is limited
+/// by the sample rate and scheduling of the calling thread. Edge events don't miss
+/// short pulses as long as the kernel keeps up, and use no CPU time while the pins
+/// are idle.
+///
+/// Sampling-based analyzers are returned by [`Gpio::logic_analyzer`]. Event-based
+/// analyzers are constructed with [`new`].
+///
+/// ## Example
+///
+/// ```no_run
+/// use std::time::Duration;
+///
+/// use rpi_embedded::gpio::{CaptureTrigger, Gpio, Trigger};
+///
+/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// let gpio = Gpio::new()?;
+///
+/// // Capture SPI0 SCLK, MOSI and CE0, starting 1 ms before CE0 goes low
+/// let mut analyzer = gpio.logic_analyzer(&[11, 10, 8])?;
+/// analyzer.set_trigger(CaptureTrigger::Edge(8, Trigger::FallingEdge))?;
+/// analyzer.set_pre_trigger(Duration::from_millis(1));
+///
+/// if let Some(capture) = analyzer.capture(Duration::from_millis(10))? {
+///     println!("{:?} samples per second", capture.sample_rate());
+///     capture.save_vcd("spi.vcd")?;
+/// }
+/// # Ok(())
+/// # }
+/// ```
+///
+/// [`InputPin`]: struct.InputPin.html
+/// [`Gpio::logic_analyzer`]: struct.Gpio.html#method.logic_analyzer
+/// [`new`]: #method.new
+#[derive(Debug)]
+pub struct LogicAnalyzer {
+    source: Source,
+    pins: Vec<u8>,
+    trigger: CaptureTrigger,
+    pre_trigger: Duration,
+    sample_rate: Option<f64>,
+    timeout: Option<Duration>,
+}
+
+impl LogicAnalyzer {
+    pub(crate) fn with_backend(backend: Arc<dyn Backend>, pins: &[u8]) -> Result<LogicAnalyzer> {
+        for (index, &pin) in pins.iter().enumerate() {
+            if pin as usize >= backend.pin_count() || pins[..index].contains(&pin) {
+                return Err(Error::PinNotAvailable(pin, None));
+            }
+        }
+
+        Ok(LogicAnalyzer::with_source(
+            Source::Sampling(backend),
+            pins.to_vec(),
+        ))
+    }
+
+    /// Constructs a new `LogicAnalyzer` that records the edge events of `pins`.
+    ///
+    /// Any interrupts configured on the pins are cleared. Edge events are requested
+    /// without debouncing for each capture, and released once the capture completes.
+    pub fn new(mut pins: Vec<InputPin>) -> Result<LogicAnalyzer> {
+        for pin in &mut pins {
+            pin.clear_interrupt()?;
+            pin.clear_async_interrupt()?;
+        }
+
+        let numbers = pins.iter().map(|pin| pin.pin()).collect();
+
+        Ok(LogicAnalyzer::with_source(Source::Events(pins), numbers))
+    }
+
+    fn with_source(source: Source, pins: Vec<u8>) -> LogicAnalyzer {
+        LogicAnalyzer {
+            source,
+            pins,
+            trigger: CaptureTrigger::Immediate,
+            pre_trigger: Duration::from_secs(0),
+            sample_rate: None,
+            timeout: None,
+        }
+    }
+
+    /// Returns the captured BCM GPIO pin numbers.
+    pub fn pins(&self) -> &[u8] {
+        &self.pins
+    }
+
+    /// Returns the trigger condition.
+    pub fn trigger(&self) -> &CaptureTrigger {
+        &self.trigger
+    }
+
+    /// Sets the condition that starts the capture.
+    ///
+    /// By default, the trigger is set to [`CaptureTrigger::Immediate`]. Returns
+    /// `Err(`[`Error::PinNotAvailable`]`)` if the trigger refers to a pin that
+    /// isn't captured.
+    ///
+    /// [`CaptureTrigger::Immediate`]: enum.CaptureTrigger.html#variant.Immediate
+    /// [`Error::PinNotAvailable`]: enum.Error.html#variant.PinNotAvailable
+    pub fn set_trigger(&mut self, trigger: CaptureTrigger) -> Result<()> {
+        if let Some(pin) = trigger
+            .pins()
+            .into_iter()
+            .find(|pin| !self.pins.contains(pin))
+        {
+            return Err(Error::PinNotAvailable(pin, None));
+        }
+
+        self.trigger = trigger;
+
+        Ok(())
+    }
+
+    /// Returns the pre-trigger period.
+    pub fn pre_trigger(&self) -> Duration {
+        self.pre_trigger
+    }
+
+    /// Sets how much of the signal before the trigger is included in the capture.
+    ///
+    /// The capture starts at the trigger, or at the moment [`capture`] is called,
+    /// minus `pre_trigger`, whichever is later. By default, `pre_trigger` is set to 0.
+    ///
+    /// [`capture`]: #method.capture
+    pub fn set_pre_trigger(&mut self, pre_trigger: Duration) {
+        self.pre_trigger = pre_trigger;
+    }
+
+    /// Returns the target sample rate in hertz (Hz), or `None` if the pins are
+    /// sampled as fast as possible.
+    pub fn sample_rate(&self) -> Option<f64> {
+        self.sample_rate
+    }
+
+    /// Sets the target sample rate in hertz (Hz).
+    ///
+    /// By default, the sample rate is set to `None`, which samples the pins as fast
+    /// as possible, and keeps a CPU core busy for the entire capture. The achieved
+    /// sample rate is reported by [`Capture::sample_rate`]. Event-based analyzers
+    /// ignore the sample rate.
+    ///
+    /// [`Capture::sample_rate`]: struct.Capture.html#method.sample_rate
+    pub fn set_sample_rate(&mut self, sample_rate: Option<f64>) {
+        self.sample_rate = sample_rate.filter(|&rate| rate > 0.0);
+    }
+
+    /// Returns the trigger timeout.
+    pub fn timeout(&self) -> Option<Duration> {
+        self.timeout
+    }
+
+    /// Sets the maximum time [`capture`] waits for the trigger condition.
+    ///
+    /// By default, `timeout` is set to `None`, which waits indefinitely.
+    ///
+    /// [`capture`]: #method.capture
+    pub fn set_timeout(&mut self, timeout: Option<Duration>) {
+        self.timeout = timeout;
+    }
+
+    /// Waits for the trigger condition, and captures the pins for the specified
+    /// duration after the trigger.
+    ///
+    /// Sampling takes place on the calling thread. Running the thread with a real-time
+    /// scheduling policy reduces the number of gaps between samples.
+    ///
+    /// Returns `Ok(None)` if the trigger timeout is reached first.
+    pub fn capture(&mut self, duration: Duration) -> Result<Option<Capture>> {
+        let mask = self.pins.iter().fold(0u64, |mask, &pin| mask | (1 << pin));
+
+        let recorder = match self.source {
+            Source::Sampling(ref backend) => self.sample(backend.as_ref(), mask, duration),
+            Source::Events(ref pins) => self.record_events(pins, duration)?,
+        };
+
+        let sampled = match self.source {
+            Source::Sampling(_) => true,
+            Source::Events(_) => false,
+        };
+
+        Ok(recorder.map(|recorder| recorder.finish(self.pins.clone(), sampled)))
+    }
+
+    fn sample(&self, backend: &dyn Backend, mask: u64, duration: Duration) -> Option<Recorder> {
+        let interval = self
+            .sample_rate
+            .map(|rate| ((NANOS_PER_SEC / rate) as i64).max(1));
+
+        let start = get_time_ns();
+        let deadline = self
+            .timeout
+            .map(|timeout| start + timeout.as_nanos() as i64);
+        let mut recorder = Recorder::new(
+            self.trigger.clone(),
+            self.pre_trigger,
+            duration,
+            start,
+            backend.levels(mask),
+        );
+        recorder.sample(start, recorder.levels);
+
+        let mut next = start;
+        loop {
+            if let Some(interval) = interval {
+                // Skip missed samples instead of catching up
+                next = (next + interval).max(get_time_ns());
+                wait_until_ns(next);
+            }
+
+            let levels = backend.levels(mask);
+            let now = get_time_ns();
+            recorder.sample(now, levels);
+
+            if recorder.is_done(now) {
+                return Some(recorder);
+            }
+
+            if recorder.triggered.is_none() && matches!(deadline, Some(deadline) if now >= deadline)
+            {
+                return None;
+            }
+        }
+    }
+
+    fn record_events(&self, pins: &[InputPin], duration: Duration) -> Result<Option<Recorder>> {
+        let backend = match pins.first() {
+            Some(pin) => pin.backend(),
+            None => return Ok(None),
+        };
+
+        // Request the events before reading the initial levels, so no changes are missed
+        let configs: Vec<_> = pins
+            .iter()
+            .map(|pin| (pin.pin(), ioctl::EventConfig::new(Trigger::Both), None))
+            .collect();
+
+        let shared: Arc<Mutex<Option<Recorder>>> = Arc::new(Mutex::new(None));
+        let thread_shared = shared.clone();
+        let mut interrupt = AsyncInterrupt::with_pins(backend, &configs, move |pin, event| {
+            if let Some(ref mut recorder) = *thread_shared.lock().unwrap() {
+                let timestamp = (event.timestamp.as_nanos() as i64).max(recorder.start);
+                let levels = match event.level {
+                    Level::Low => recorder.levels & !(1 << pin),
+                    Level::High => recorder.levels | (1 << pin),
+                };
+
+                recorder.update(timestamp, levels);
+            }
+        })?;
+
+        let start = get_time_ns();
+        let levels = pins.iter().fold(0u64, |levels, pin| match pin.read() {
+            Level::Low => levels,
+            Level::High => levels | (1 << pin.pin()),
+        });
+        *shared.lock().unwrap() = Some(Recorder::new(
+            self.trigger.clone(),
+            self.pre_trigger,
+            duration,
+            start,
+            levels,
+        ));
+
+        let deadline = self
+            .timeout
+            .map(|timeout| start + timeout.as_nanos() as i64);
+        let triggered = loop {
+            let now = get_time_ns();
+            let triggered = shared.lock().unwrap().as_ref().and_then(|r| r.triggered);
+
+            match triggered {
+                Some(triggered) if now >= triggered + duration.as_nanos() as i64 => break true,
+                Some(triggered) => sleep_ns(triggered + duration.as_nanos() as i64 - now),
+                None if matches!(deadline, Some(deadline) if now >= deadline) => break false,
+                None => sleep_ns(match deadline {
+                    Some(deadline) => (deadline - now).min(POLL_INTERVAL_NS),
+                    None => POLL_INTERVAL_NS,
+                }),
+            }
+        };
+
+        interrupt.stop()?;
+        let recorder = shared.lock().unwrap().take();
+
+        Ok(recorder.filter(|_| triggered))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vcd(capture: &Capture) -> String {
+        let mut output = Vec::new();
+        capture.write_vcd(&mut output).unwrap();
+
+        String::from_utf8(output).unwrap()
+    }
+
+    #[test]
+    fn vcd_identifiers() {
+        assert_eq!(vcd_id(0), "!");
+        assert_eq!(vcd_id(93), "~");
+        assert_eq!(vcd_id(94), "!!");
+        assert_eq!(vcd_id(95), "\"!");
+        assert_eq!(vcd_id(94 + 94 * 94), "!!!");
+    }
+
+    #[test]
+    fn vcd_output() {
+        let capture = Capture {
+            pins: vec![17, 4],
+            initial: vec![Level::Low, Level::High],
+            changes: vec![
+                LevelChange {
+                    timestamp: Duration::from_nanos(1500),
+                    pin: 17,
+                    level: Level::High,
+                },
+                LevelChange {
+                    timestamp: Duration::from_nanos(1500),
+                    pin: 4,
+                    level: Level::Low,
+                },
+                LevelChange {
+                    timestamp: Duration::from_nanos(3000),
+                    pin: 17,
+                    level: Level::Low,
+                },
+            ],
+            trigger: Duration::from_nanos(1000),
+            duration: Duration::from_nanos(5000),
+            samples: Some(6),
+            sample_rate: Some(1_000_000.0),
+            max_sample_interval: Some(Duration::from_nanos(1000)),
+        };
+
+        let expected = format!(
+            "$version {} {} $end
+$comment trigger at 1000 ns, sample rate 1000000 Hz $end
+$timescale 1 ns $end
+$scope module gpio $end
+$var wire 1 ! GPIO17 $end
+$var wire 1 \" GPIO4 $end
+$upscope $end
+$enddefinitions $end
+#0
+$dumpvars
+0!
+1\"
+$end
+#1500
+1!
+0\"
+#3000
+0!
+#5000
+",
+            env!("CARGO_PKG_NAME"),
+            env!("CARGO_PKG_VERSION")
+        );
+
+        assert_eq!(vcd(&capture), expected);
+    }
+
+    #[test]
+    fn vcd_final_change() {
+        let capture = Capture {
+            pins: vec![4],
+            initial: vec![Level::Low],
+            changes: vec![LevelChange {
+                timestamp: Duration::from_nanos(2000),
+                pin: 4,
+                level: Level::High,
+            }],
+            trigger: Duration::from_nanos(0),
+            duration: Duration::from_nanos(2000),
+            samples: None,
+            sample_rate: None,
+            max_sample_interval: None,
+        };
+
+        let output = vcd(&capture);
+
+        assert!(output.contains("$comment trigger at 0 ns $end\n"));
+        assert!(output.ends_with("$end\n#2000\n1!\n"));
+    }
+
+    #[test]
+    fn pre_trigger() {
+        let trigger = CaptureTrigger::Edge(4, Trigger::RisingEdge);
+        let mut recorder = Recorder::new(
+            trigger,
+            Duration::from_nanos(100),
+            Duration::from_nanos(1000),
+            0,
+            0,
+        );
+
+        // Changes before the pre-trigger period are folded into the initial levels
+        recorder.update(100, 1 << 5);
+        recorder.update(500, 0);
+        recorder.update(950, 1 << 5);
+        assert!(recorder.triggered.is_none());
+
+        recorder.update(1000, (1 << 5) | (1 << 4));
+        assert_eq!(recorder.triggered, Some(1000));
+        assert!(!recorder.is_done(1999));
+        assert!(recorder.is_done(2000));
+
+        recorder.update(2500, 1 << 4);
+
+        let capture = recorder.finish(vec![4, 5], true);
+        assert_eq!(capture.initial_level(5), Some(Level::Low));
+        assert_eq!(capture.trigger(), Duration::from_nanos(100));
+        assert_eq!(capture.duration(), Duration::from_nanos(1100));
+        assert_eq!(
+            capture.changes(),
+            &[
+                LevelChange {
+                    timestamp: Duration::from_nanos(50),
+                    pin: 5,
+                    level: Level::High,
+                },
+                LevelChange {
+                    timestamp: Duration::from_nanos(100),
+                    pin: 4,
+                    level: Level::High,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn pattern_trigger() {
+        let trigger = CaptureTrigger::Pattern(vec![(4, Level::High), (5, Level::Low)]);
+
+        let recorder = Recorder::new(
+            trigger.clone(),
+            Duration::from_secs(0),
+            Duration::from_secs(0),
+            0,
+            1 << 4,
+        );
+        assert_eq!(recorder.triggered, Some(0));
+
+        let mut recorder = Recorder::new(
+            trigger,
+            Duration::from_secs(0),
+            Duration::from_secs(0),
+            0,
+            1 << 5,
+        );
+        recorder.update(10, (1 << 4) | (1 << 5));
+        assert!(recorder.triggered.is_none());
+        recorder.update(20, 1 << 4);
+        assert_eq!(recorder.triggered, Some(20));
+    }
+}
@@ -0,0 +1,396 @@
+// Alternate function table and pad control settings. Function names follow the
+// BCM2835 and BCM2711 datasheets.
+
+use std::fmt;
+use std::mem;
+use std::sync::atomic::Ordering;
+
+use crate::gpio::{pin, Error, GpioState, Mode, Result};
+use crate::system::SoC;
+
+// Pad control registers as word offsets from the start of the PADS block, which
+// is located at peripheral base + PADS_OFFSET
+pub(crate) const PADS_OFFSET: u32 = 0x10_0000;
+pub(crate) const PADS_GPIO: [usize; 3] = [
+    0x2c / mem::size_of::<u32>(),
+    0x30 / mem::size_of::<u32>(),
+    0x34 / mem::size_of::<u32>(),
+];
+pub(crate) const PADS_PASSWD: u32 = 0x5a << 24;
+
+const PADS_SLEW: u32 = 1 << 4;
+const PADS_HYST: u32 = 1 << 3;
+const PADS_DRIVE: u32 = 0b111;
+
+const FIRST_INTERNAL_PIN: u8 = 28;
+
+// ALT0-ALT5 for BCM GPIO 0-53. BCM GPIO 46-53 are reserved for internal use.
+#[rustfmt::skip]
+const BCM2835_FUNCTIONS: [[&str; 6]; pin::MAX] = [
+    ["SDA0", "SA5", "", "", "", ""],
+    ["SCL0", "SA4", "", "", "", ""],
+    ["SDA1", "SA3", "", "", "", ""],
+    ["SCL1", "SA2", "", "", "", ""],
+    ["GPCLK0", "SA1", "", "", "", "ARM_TDI"],
+    ["GPCLK1", "SA0", "", "", "", "ARM_TDO"],
+    ["GPCLK2", "SOE_N", "", "", "", "ARM_RTCK"],
+    ["SPI0_CE1_N", "SWE_N", "", "", "", ""],
+    ["SPI0_CE0_N", "SD0", "", "", "", ""],
+    ["SPI0_MISO", "SD1", "", "", "", ""],
+    ["SPI0_MOSI", "SD2", "", "", "", ""],
+    ["SPI0_SCLK", "SD3", "", "", "", ""],
+    ["PWM0", "SD4", "", "", "", "ARM_TMS"],
+    ["PWM1", "SD5", "", "", "", "ARM_TCK"],
+    ["TXD0", "SD6", "", "", "", "TXD1"],
+    ["RXD0", "SD7", "", "", "", "RXD1"],
+    ["", "SD8", "", "CTS0", "SPI1_CE2_N", "CTS1"],
+    ["", "SD9", "", "RTS0", "SPI1_CE1_N", "RTS1"],
+    ["PCM_CLK", "SD10", "", "BSCSL_SDA", "SPI1_CE0_N", "PWM0"],
+    ["PCM_FS", "SD11", "", "BSCSL_SCL", "SPI1_MISO", "PWM1"],
+    ["PCM_DIN", "SD12", "", "BSCSL_MISO", "SPI1_MOSI", "GPCLK0"],
+    ["PCM_DOUT", "SD13", "", "BSCSL_CE_N", "SPI1_SCLK", "GPCLK1"],
+    ["", "SD14", "", "SD1_CLK", "ARM_TRST", ""],
+    ["", "SD15", "", "SD1_CMD", "ARM_RTCK", ""],
+    ["", "SD16", "", "SD1_DAT0", "ARM_TDO", ""],
+    ["", "SD17", "", "SD1_DAT1", "ARM_TCK", ""],
+    ["", "", "", "SD1_DAT2", "ARM_TDI", ""],
+    ["", "", "", "SD1_DAT3", "ARM_TMS", ""],
+    ["SDA0", "SA5", "PCM_CLK", "", "", ""],
+    ["SCL0", "SA4", "PCM_FS", "", "", ""],
+    ["", "SA3", "PCM_DIN", "CTS0", "", "CTS1"],
+    ["", "SA2", "PCM_DOUT", "RTS0", "", "RTS1"],
+    ["GPCLK0", "SA1", "", "TXD0", "", "TXD1"],
+    ["", "SA0", "", "RXD0", "", "RXD1"],
+    ["GPCLK0", "SOE_N", "", "SD1_CLK", "", ""],
+    ["SPI0_CE1_N", "SWE_N", "", "SD1_CMD", "", ""],
+    ["SPI0_CE0_N", "SD0", "TXD0", "SD1_DAT0", "", ""],
+    ["SPI0_MISO", "SD1", "RXD0", "SD1_DAT1", "", ""],
+    ["SPI0_MOSI", "SD2", "RTS0", "SD1_DAT2", "", ""],
+    ["SPI0_SCLK", "SD3", "CTS0", "SD1_DAT3", "", ""],
+    ["PWM0", "SD4", "", "SD1_DAT4", "SPI2_MISO", "TXD1"],
+    ["PWM1", "SD5", "", "SD1_DAT5", "SPI2_MOSI", "RXD1"],
+    ["GPCLK1", "SD6", "", "SD1_DAT6", "SPI2_SCLK", "RTS1"],
+    ["GPCLK2", "SD7", "", "SD1_DAT7", "SPI2_CE0_N", "CTS1"],
+    ["GPCLK1", "SDA0", "SDA1", "", "SPI2_CE1_N", ""],
+    ["PWM1", "SCL0", "SCL1", "", "SPI2_CE2_N", ""],
+    ["", "", "", "", "", ""],
+    ["", "", "", "", "", ""],
+    ["", "", "", "", "", ""],
+    ["", "", "", "", "", ""],
+    ["", "", "", "", "", ""],
+    ["", "", "", "", "", ""],
+    ["", "", "", "", "", ""],
+    ["", "", "", "", "", ""],
+];
+
+// ALT0-ALT5 for BCM GPIO 0-27, which are exposed on the GPIO header
+#[rustfmt::skip]
+const BCM2711_FUNCTIONS: [[&str; 6]; 28] = [
+    ["SDA0", "SA5", "PCLK", "SPI3_CE0_N", "TXD2", "SDA6"],
+    ["SCL0", "SA4", "DE", "SPI3_MISO", "RXD2", "SCL6"],
+    ["SDA1", "SA3", "LCD_VSYNC", "SPI3_MOSI", "CTS2", "SDA3"],
+    ["SCL1", "SA2", "LCD_HSYNC", "SPI3_SCLK", "RTS2", "SCL3"],
+    ["GPCLK0", "SA1", "DPI_D0", "SPI4_CE0_N", "TXD3", "SDA3"],
+    ["GPCLK1", "SA0", "DPI_D1", "SPI4_MISO", "RXD3", "SCL3"],
+    ["GPCLK2", "SOE_N", "DPI_D2", "SPI4_MOSI", "CTS3", "SDA4"],
+    ["SPI0_CE1_N", "SWE_N", "DPI_D3", "SPI4_SCLK", "RTS3", "SCL4"],
+    ["SPI0_CE0_N", "SD0", "DPI_D4", "BSCSL_CE_N", "TXD4", "SDA4"],
+    ["SPI0_MISO", "SD1", "DPI_D5", "BSCSL_MISO", "RXD4", "SCL4"],
+    ["SPI0_MOSI", "SD2", "DPI_D6", "BSCSL_SDA", "CTS4", "SDA5"],
+    ["SPI0_SCLK", "SD3", "DPI_D7", "BSCSL_SCL", "RTS4", "SCL5"],
+    ["PWM0_0", "SD4", "DPI_D8", "SPI5_CE0_N", "TXD5", "SDA5"],
+    ["PWM0_1", "SD5", "DPI_D9", "SPI5_MISO", "RXD5", "SCL5"],
+    ["TXD0", "SD6", "DPI_D10", "SPI5_MOSI", "CTS5", "TXD1"],
+    ["RXD0", "SD7", "DPI_D11", "SPI5_SCLK", "RTS5", "RXD1"],
+    ["", "SD8", "DPI_D12", "CTS0", "SPI1_CE2_N", "CTS1"],
+    ["", "SD9", "DPI_D13", "RTS0", "SPI1_CE1_N", "RTS1"],
+    ["PCM_CLK", "SD10", "DPI_D14", "SPI6_CE0_N", "SPI1_CE0_N", "PWM0_0"],
+    ["PCM_FS", "SD11", "DPI_D15", "SPI6_MISO", "SPI1_MISO", "PWM0_1"],
+    ["PCM_DIN", "SD12", "DPI_D16", "SPI6_MOSI", "SPI1_MOSI", "GPCLK0"],
+    ["PCM_DOUT", "SD13", "DPI_D17", "SPI6_SCLK", "SPI1_SCLK", "GPCLK1"],
+    ["SD0_CLK", "SD14", "DPI_D18", "SD1_CLK", "ARM_TRST", "SDA6"],
+    ["SD0_CMD", "SD15", "DPI_D19", "SD1_CMD", "ARM_RTCK", "SCL6"],
+    ["SD0_DAT0", "SD16", "DPI_D20", "SD1_DAT0", "ARM_TDO", "SPI3_CE1_N"],
+    ["SD0_DAT1", "SD17", "DPI_D21", "SD1_DAT1", "ARM_TCK", "SPI4_CE1_N"],
+    ["SD0_DAT2", "TE0", "DPI_D22", "SD1_DAT2", "ARM_TDI", "SPI5_CE1_N"],
+    ["SD0_DAT3", "TE1", "DPI_D23", "SD1_DAT3", "ARM_TMS", "SPI6_CE1_N"],
+];
+
+/// Returns the name of the peripheral signal that's routed to the specified BCM GPIO
+/// pin when its mode is set to `mode`.
+///
+/// Signal names follow the SoC's datasheet, for instance `PWM0` for BCM GPIO 18 set to
+/// [`Alt5`] on a BCM2835. Returns `None` if `mode` isn't an alternate function, or the
+/// alternate function is reserved. On the BCM2711, only BCM GPIO 0-27 are included.
+///
+/// ## Example
+///
+/// ```
+/// use rpi_embedded::gpio::{alt_function, Mode};
+/// use rpi_embedded::system::SoC;
+///
+/// assert_eq!(alt_function(SoC::Bcm2837B0, 18, Mode::Alt5), Some("PWM0"));
+/// assert_eq!(alt_function(SoC::Bcm2711, 14, Mode::Alt0), Some("TXD0"));
+/// assert_eq!(alt_function(SoC::Bcm2835, 16, Mode::Alt0), None);
+/// ```
+///
+/// [`Alt5`]: enum.Mode.html#variant.Alt5
+pub fn alt_function(soc: SoC, pin: u8, mode: Mode) -> Option<&'static str> {
+    let alt = match mode {
+        Mode::Alt0 => 0,
+        Mode::Alt1 => 1,
+        Mode::Alt2 => 2,
+        Mode::Alt3 => 3,
+        Mode::Alt4 => 4,
+        Mode::Alt5 => 5,
+        Mode::Input | Mode::Output => return None,
+    };
+
+    let functions = match soc {
+        SoC::Bcm2711 => BCM2711_FUNCTIONS.get(pin as usize)?,
+        _ => BCM2835_FUNCTIONS.get(pin as usize)?,
+    };
+
+    match functions[alt] {
+        "" => None,
+        name => Some(name),
+    }
+}
+
+/// Pad output drive strength.
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub enum DriveStrength {
+    Ma2 = 0,
+    Ma4 = 1,
+    Ma6 = 2,
+    Ma8 = 3,
+    Ma10 = 4,
+    Ma12 = 5,
+    Ma14 = 6,
+    Ma16 = 7,
+}
+
+impl DriveStrength {
+    fn from_bits(bits: u32) -> DriveStrength {
+        match bits & PADS_DRIVE {
+            0 => DriveStrength::Ma2,
+            1 => DriveStrength::Ma4,
+            2 => DriveStrength::Ma6,
+            3 => DriveStrength::Ma8,
+            4 => DriveStrength::Ma10,
+            5 => DriveStrength::Ma12,
+            6 => DriveStrength::Ma14,
+            _ => DriveStrength::Ma16,
+        }
+    }
+}
+
+impl fmt::Display for DriveStrength {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} mA", (*self as u8 + 1) * 2)
+    }
+}
+
+/// Pad output slew rate.
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub enum SlewRate {
+    Limited,
+    Unlimited,
+}
+
+impl fmt::Display for SlewRate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            SlewRate::Limited => write!(f, "Limited"),
+            SlewRate::Unlimited => write!(f, "Unlimited"),
+        }
+    }
+}
+
+/// Pin mode and pad control settings.
+///
+/// `PinConfig` is applied through [`IoPin::set_config`], and retrieved through
+/// [`Gpio::pin_config`]. Fields set to `None` are left unchanged.
+///
+/// Drive strength, slew rate and hysteresis are controlled by the pad control
+/// registers, which are shared by all pins within a bank (BCM GPIO 0-27, 28-45 and
+/// 46-53). Changing them for one pin affects every pin in the same bank. Accessing the
+/// pad control registers requires `/dev/mem`.
+///
+/// ## Example
+///
+/// ```no_run
+/// use rpi_embedded::gpio::{DriveStrength, Gpio, Mode, PinConfig};
+///
+/// # fn main() -> rpi_embedded::gpio::Result<()> {
+/// let mut pin = Gpio::new()?.get(18)?.into_io(Mode::Input);
+///
+/// pin.set_config(&PinConfig {
+///     mode: Some(Mode::Alt5),
+///     drive_strength: Some(DriveStrength::Ma16),
+///     ..PinConfig::default()
+/// })?;
+/// # Ok(())
+/// # }
+/// ```
+///
+/// [`IoPin::set_config`]: struct.IoPin.html#method.set_config
+/// [`Gpio::pin_config`]: struct.Gpio.html#method.pin_config
+#[derive(Debug, Default, PartialEq, Eq, Copy, Clone)]
+pub struct PinConfig {
+    /// Pin mode or alternate function.
+    pub mode: Option<Mode>,
+    /// Output drive strength for the pin's bank.
+    pub drive_strength: Option<DriveStrength>,
+    /// Output slew rate for the pin's bank.
+    pub slew_rate: Option<SlewRate>,
+    /// Input hysteresis (Schmitt trigger) for the pin's bank.
+    pub hysteresis: Option<bool>,
+}
+
+impl PinConfig {
+    // Applies the pad settings to a pad control register value
+    pub(crate) fn pad_control(&self, value: u32) -> u32 {
+        let mut value = value & (PADS_SLEW | PADS_HYST | PADS_DRIVE);
+
+        if let Some(drive_strength) = self.drive_strength {
+            value = (value & !PADS_DRIVE) | drive_strength as u32;
+        }
+
+        match self.slew_rate {
+            Some(SlewRate::Limited) => value &= !PADS_SLEW,
+            Some(SlewRate::Unlimited) => value |= PADS_SLEW,
+            None => (),
+        }
+
+        match self.hysteresis {
+            Some(true) => value |= PADS_HYST,
+            Some(false) => value &= !PADS_HYST,
+            None => (),
+        }
+
+        value
+    }
+
+    pub(crate) fn has_pad_settings(&self) -> bool {
+        self.drive_strength.is_some() || self.slew_rate.is_some() || self.hysteresis.is_some()
+    }
+
+    pub(crate) fn from_registers(mode: Mode, pad_control: u32) -> PinConfig {
+        PinConfig {
+            mode: Some(mode),
+            drive_strength: Some(DriveStrength::from_bits(pad_control)),
+            slew_rate: Some(if pad_control & PADS_SLEW != 0 {
+                SlewRate::Unlimited
+            } else {
+                SlewRate::Limited
+            }),
+            hysteresis: Some(pad_control & PADS_HYST != 0),
+        }
+    }
+}
+
+// Returns the pad control bank for the specified pin
+pub(crate) fn pad_bank(pin: u8) -> usize {
+    match pin {
+        0..=27 => 0,
+        28..=45 => 1,
+        _ => 2,
+    }
+}
+
+// BCM GPIO 28-53 aren't exposed on the GPIO header of most models. The firmware
+// routes them to on-board peripherals, for instance PWM0 and PWM1 to GPIO 40 and 41
+// for analog audio. Those pins only count when they're in use through this process.
+fn is_firmware_owned(gpio_state: &GpioState, pin: u8) -> bool {
+    pin >= FIRST_INTERNAL_PIN && !gpio_state.pins_taken[pin as usize].load(Ordering::SeqCst)
+}
+
+// Verifies that applying config to pin won't route a signal to two pins at once, or
+// override pad settings that were explicitly configured through another pin.
+pub(crate) fn check(gpio_state: &GpioState, pin: u8, config: &PinConfig) -> Result<()> {
+    if let Some(mode) = config.mode {
+        if let Mode::Alt0 | Mode::Alt1 | Mode::Alt2 | Mode::Alt3 | Mode::Alt4 | Mode::Alt5 = mode {
+            let soc = gpio_state.soc.unwrap_or(SoC::Bcm2835);
+
+            // The BCM2711 table only covers the header pins, so there's nothing to verify
+            // for the remaining pins
+            if soc != SoC::Bcm2711 || (pin as usize) < BCM2711_FUNCTIONS.len() {
+                let signal =
+                    alt_function(soc, pin, mode).ok_or(Error::FunctionNotAvailable(pin, mode))?;
+
+                for other in (0..pin::MAX as u8)
+                    .filter(|&other| other != pin && !is_firmware_owned(gpio_state, other))
+                {
+                    if alt_function(soc, other, gpio_state.backend.mode(other)) == Some(signal) {
+                        return Err(Error::FunctionConflict(pin, signal, other));
+                    }
+                }
+            }
+        }
+    }
+
+    if config.has_pad_settings() {
+        let bank = pad_bank(pin);
+        let current = gpio_state.backend.pad_control(bank)?;
+
+        if let Some(owner) = gpio_state.pad_owners.lock().unwrap()[bank] {
+            if owner != pin
+                && gpio_state.pins_taken[owner as usize].load(Ordering::SeqCst)
+                && config.pad_control(current) != current
+            {
+                return Err(Error::PadConflict(pin, owner));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gpio::{Gpio, Mode};
+
+    const PWM0: PinConfig = PinConfig {
+        mode: Some(Mode::Alt5),
+        drive_strength: None,
+        slew_rate: None,
+        hysteresis: None,
+    };
+
+    #[test]
+    fn function_conflict() {
+        let (gpio, _sim) = Gpio::simulated().unwrap();
+        let _pwm = gpio.get(12).unwrap().into_io(Mode::Alt0);
+        let mut pin = gpio.get(18).unwrap().into_io(Mode::Input);
+
+        match pin.set_config(&PWM0) {
+            Err(Error::FunctionConflict(18, "PWM0", 12)) => (),
+            result => panic!("unexpected result: {:?}", result),
+        }
+        assert_eq!(pin.mode(), Mode::Input);
+    }
+
+    #[test]
+    fn firmware_owned_function() {
+        let (gpio, _sim) = Gpio::simulated().unwrap();
+
+        // Analog audio output, configured by the firmware
+        let mut audio = gpio.get(40).unwrap().into_io(Mode::Alt0);
+        audio.set_reset_on_drop(false);
+        drop(audio);
+
+        let mut pin = gpio.get(18).unwrap().into_io(Mode::Input);
+        pin.set_config(&PWM0).unwrap();
+        assert_eq!(pin.mode(), Mode::Alt5);
+
+        // Conflicts are still reported while the pin is in use
+        pin.set_mode(Mode::Input);
+        let _audio = gpio.get(40).unwrap().into_io(Mode::Alt0);
+        match pin.set_config(&PWM0) {
+            Err(Error::FunctionConflict(18, "PWM0", 40)) => (),
+            result => panic!("unexpected result: {:?}", result),
+        }
+    }
+}
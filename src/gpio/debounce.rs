@@ -0,0 +1,360 @@
+use std::time::Duration;
+
+use crate::gpio::{InterruptEvent, Level, Trigger};
+
+/// Software debounce filter for interrupt trigger events.
+///
+/// Mechanical switches bounce for a short period after they change state, which
+/// causes a burst of interrupt trigger events for what should be a single edge.
+/// A `Debouncer` accepts the first event of a burst, and rejects any events
+/// that follow within the debounce window. Events are compared based on their
+/// kernel timestamps, so the result doesn't depend on how quickly they're read.
+///
+/// When both edges are reported, an event with the same logic level as the
+/// previously accepted event is rejected as well, since the level didn't change.
+/// If the level changes again within the debounce window, for instance when a
+/// button is released shortly after it's pressed, that change would be lost. Once
+/// the window ends, [`deadline`] returns its timestamp, and the pin's current level
+/// should be passed to [`settle`], which reports the change if the level differs
+/// from the accepted one.
+///
+/// [`InputPin::set_debounce`] configures a `Debouncer` for a pin's (a)synchronous
+/// interrupt triggers, which takes care of calling [`settle`]. `Debouncer` can
+/// also be used on its own to filter a stream of events.
+///
+/// ## Example
+///
+/// ```
+/// use std::time::Duration;
+///
+/// use rpi_embedded::gpio::{Debouncer, InterruptEvent, Level, Trigger};
+///
+/// let event = |ms, level| InterruptEvent {
+///     timestamp: Duration::from_millis(ms),
+///     trigger: if level == Level::High { Trigger::RisingEdge } else { Trigger::FallingEdge },
+///     level,
+///     seqno: 0,
+/// };
+///
+/// let mut debouncer = Debouncer::new(Duration::from_millis(20));
+///
+/// assert!(debouncer.filter(&event(100, Level::Low)));
+/// assert!(!debouncer.filter(&event(101, Level::High)));
+/// assert!(!debouncer.filter(&event(103, Level::Low)));
+/// assert!(debouncer.filter(&event(250, Level::High)));
+/// assert_eq!(debouncer.level(), Some(Level::High));
+///
+/// // Released within the debounce window
+/// assert!(debouncer.filter(&event(400, Level::Low)));
+/// assert!(!debouncer.filter(&event(405, Level::High)));
+/// assert_eq!(debouncer.deadline(), Some(Duration::from_millis(420)));
+/// assert_eq!(debouncer.settle(Level::High).map(|event| event.level), Some(Level::High));
+/// ```
+///
+/// [`deadline`]: #method.deadline
+/// [`settle`]: #method.settle
+/// [`InputPin::set_debounce`]: struct.InputPin.html#method.set_debounce
+#[derive(Debug, Copy, Clone)]
+pub struct Debouncer {
+    window: Duration,
+    trigger: Trigger,
+    last: Option<InterruptEvent>,
+    // Most recent event, accepted or not
+    latest: Option<InterruptEvent>,
+}
+
+impl Debouncer {
+    /// Constructs a new `Debouncer` with the specified debounce window, for a stream
+    /// of events that includes both edges.
+    pub fn new(window: Duration) -> Debouncer {
+        Debouncer::with_trigger(window, Trigger::Both)
+    }
+
+    /// Constructs a new `Debouncer` with the specified debounce window, for a stream
+    /// of events detected with `trigger`.
+    ///
+    /// With [`RisingEdge`] or [`FallingEdge`], every event has the same logic level,
+    /// so events are only compared based on their timestamps.
+    ///
+    /// [`RisingEdge`]: enum.Trigger.html#variant.RisingEdge
+    /// [`FallingEdge`]: enum.Trigger.html#variant.FallingEdge
+    pub fn with_trigger(window: Duration, trigger: Trigger) -> Debouncer {
+        Debouncer {
+            window,
+            trigger,
+            last: None,
+            latest: None,
+        }
+    }
+
+    /// Returns the debounce window.
+    pub fn window(&self) -> Duration {
+        self.window
+    }
+
+    /// Returns the logic level of the most recently accepted event, or `None`
+    /// if no events have been accepted yet.
+    pub fn level(&self) -> Option<Level> {
+        self.last.map(|event| event.level)
+    }
+
+    /// Returns `true` if `event` should be accepted, or `false` if it's part
+    /// of a bounce.
+    ///
+    /// An event is accepted if no other event has been accepted within the debounce
+    /// window before its timestamp, and, when both edges are reported, its logic level
+    /// differs from the level of the previously accepted event. Rejected events don't
+    /// extend the window.
+    pub fn filter(&mut self, event: &InterruptEvent) -> bool {
+        self.latest = Some(*event);
+
+        if let Some(ref last) = self.last {
+            // Timestamps that go backwards (for instance after a realtime clock
+            // adjustment) are treated as outside the window
+            if let Some(elapsed) = event.timestamp.checked_sub(last.timestamp) {
+                if elapsed < self.window {
+                    return false;
+                }
+            }
+
+            // The opposite edge was missed or rejected, so the level didn't change
+            if self.trigger == Trigger::Both && event.level == last.level {
+                return false;
+            }
+        }
+
+        self.last = Some(*event);
+
+        true
+    }
+
+    /// Returns the timestamp at which the debounce window ends, if the events
+    /// rejected within the window left the pin at a different logic level than
+    /// the accepted event.
+    ///
+    /// Once the window has ended, the pin's current level should be passed to
+    /// [`settle`]. Always returns `None` for a single edge trigger.
+    ///
+    /// [`settle`]: #method.settle
+    pub fn deadline(&self) -> Option<Duration> {
+        match (self.last, self.latest) {
+            (Some(last), Some(latest)) if latest.level != last.level => {
+                Some(last.timestamp + self.window)
+            }
+            _ => None,
+        }
+    }
+
+    /// Reports a logic level change that occurred within the debounce window.
+    ///
+    /// `level` should be read from the pin after the timestamp returned by
+    /// [`deadline`]. If it differs from the level of the accepted event, a new
+    /// event is accepted and returned, timestamped at the end of the window.
+    /// Otherwise, the rejected events are considered a bounce, and `None` is
+    /// returned.
+    ///
+    /// [`deadline`]: #method.deadline
+    pub fn settle(&mut self, level: Level) -> Option<InterruptEvent> {
+        let deadline = self.deadline()?;
+        let latest = self.latest?;
+
+        if Some(level) == self.level() {
+            // The pin returned to the accepted level
+            self.latest = self.last;
+            return None;
+        }
+
+        let event = InterruptEvent {
+            timestamp: deadline,
+            trigger: match level {
+                Level::High => Trigger::RisingEdge,
+                Level::Low => Trigger::FallingEdge,
+            },
+            level,
+            seqno: latest.seqno,
+        };
+
+        self.last = Some(event);
+        self.latest = Some(event);
+
+        Some(event)
+    }
+
+    /// Clears the most recently accepted event, so the next event is always accepted.
+    pub fn reset(&mut self) {
+        self.last = None;
+        self.latest = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn event(ms: u64, level: Level) -> InterruptEvent {
+        InterruptEvent {
+            timestamp: Duration::from_millis(ms),
+            trigger: match level {
+                Level::High => Trigger::RisingEdge,
+                Level::Low => Trigger::FallingEdge,
+            },
+            level,
+            seqno: ms as u32,
+        }
+    }
+
+    // Returns the timestamps of the accepted events
+    fn accepted(debouncer: &mut Debouncer, events: &[(u64, Level)]) -> Vec<u64> {
+        events
+            .iter()
+            .filter(|&&(ms, level)| debouncer.filter(&event(ms, level)))
+            .map(|&(ms, _)| ms)
+            .collect()
+    }
+
+    #[test]
+    fn bounces() {
+        let mut debouncer = Debouncer::new(Duration::from_millis(20));
+
+        let events = [
+            (100, Level::Low),
+            (102, Level::High),
+            (104, Level::Low),
+            (119, Level::High),
+            (120, Level::Low),
+            (300, Level::High),
+            (301, Level::Low),
+            (303, Level::High),
+        ];
+        assert_eq!(accepted(&mut debouncer, &events), [100, 300]);
+        assert_eq!(debouncer.level(), Some(Level::High));
+        assert_eq!(debouncer.deadline(), None);
+    }
+
+    #[test]
+    fn same_level() {
+        let mut debouncer = Debouncer::new(Duration::from_millis(20));
+
+        // The rising edge in between was missed
+        let events = [(100, Level::Low), (200, Level::Low), (300, Level::High)];
+        assert_eq!(accepted(&mut debouncer, &events), [100, 300]);
+
+        // Single edge triggers only report one level
+        let mut debouncer =
+            Debouncer::with_trigger(Duration::from_millis(20), Trigger::FallingEdge);
+        let events = [(100, Level::Low), (110, Level::Low), (200, Level::Low)];
+        assert_eq!(accepted(&mut debouncer, &events), [100, 200]);
+        assert_eq!(debouncer.deadline(), None);
+    }
+
+    #[test]
+    fn change_within_window() {
+        let mut debouncer = Debouncer::new(Duration::from_millis(20));
+
+        // Released before the window ends
+        let events = [(100, Level::Low), (105, Level::High)];
+        assert_eq!(accepted(&mut debouncer, &events), [100]);
+        assert_eq!(debouncer.deadline(), Some(Duration::from_millis(120)));
+
+        let settled = debouncer.settle(Level::High).unwrap();
+        assert_eq!(settled.timestamp, Duration::from_millis(120));
+        assert_eq!(settled.trigger, Trigger::RisingEdge);
+        assert_eq!(settled.level, Level::High);
+        assert_eq!(settled.seqno, 105);
+        assert_eq!(debouncer.level(), Some(Level::High));
+        assert_eq!(debouncer.deadline(), None);
+        assert_eq!(debouncer.settle(Level::High), None);
+
+        // The window starts at the settled event
+        let events = [(130, Level::Low), (150, Level::Low)];
+        assert_eq!(accepted(&mut debouncer, &events), [150]);
+    }
+
+    #[test]
+    fn unsettled_window() {
+        let mut debouncer = Debouncer::new(Duration::from_millis(20));
+
+        // Without settling the release, the next press doesn't change the level
+        let events = [(100, Level::Low), (105, Level::High), (200, Level::Low)];
+        assert_eq!(accepted(&mut debouncer, &events), [100]);
+        assert_eq!(debouncer.level(), Some(Level::Low));
+        assert_eq!(debouncer.deadline(), None);
+    }
+
+    #[test]
+    fn bounce_back_within_window() {
+        let mut debouncer = Debouncer::new(Duration::from_millis(20));
+
+        let events = [(100, Level::Low), (105, Level::High)];
+        assert_eq!(accepted(&mut debouncer, &events), [100]);
+
+        // The pin returned to the accepted level before the window ended
+        assert_eq!(debouncer.settle(Level::Low), None);
+        assert_eq!(debouncer.deadline(), None);
+        assert_eq!(debouncer.level(), Some(Level::Low));
+
+        let events = [(200, Level::Low), (300, Level::High)];
+        assert_eq!(accepted(&mut debouncer, &events), [300]);
+    }
+
+    #[test]
+    fn reset() {
+        let mut debouncer = Debouncer::new(Duration::from_millis(20));
+
+        assert!(debouncer.filter(&event(100, Level::Low)));
+        debouncer.reset();
+        assert_eq!(debouncer.level(), None);
+        assert!(debouncer.filter(&event(101, Level::Low)));
+    }
+
+    #[test]
+    fn settle_interrupts() {
+        use std::sync::mpsc;
+
+        use crate::gpio::Gpio;
+
+        let (gpio, sim) = Gpio::simulated().unwrap();
+        let mut pin = gpio.get(17).unwrap().into_input();
+        sim.set_level(17, Level::High);
+        pin.set_debounce(Some(Duration::from_millis(50)));
+
+        // Released within the debounce window
+        pin.set_interrupt(Trigger::Both).unwrap();
+        sim.set_level(17, Level::Low);
+        sim.set_level(17, Level::High);
+
+        let timeout = Some(Duration::from_secs(1));
+        let pressed = pin.poll_interrupt(false, timeout).unwrap().unwrap();
+        assert_eq!(pressed.level, Level::Low);
+        let released = pin.poll_interrupt(false, timeout).unwrap().unwrap();
+        assert_eq!(released.level, Level::High);
+        assert_eq!(
+            released.timestamp,
+            pressed.timestamp + Duration::from_millis(50)
+        );
+
+        // A bounce that returns to the accepted level isn't reported
+        sim.set_level(17, Level::Low);
+        sim.set_level(17, Level::High);
+        sim.set_level(17, Level::Low);
+        let pressed = pin.poll_interrupt(false, timeout).unwrap().unwrap();
+        assert_eq!(pressed.level, Level::Low);
+        assert_eq!(
+            pin.poll_interrupt(false, Some(Duration::from_millis(100)))
+                .unwrap(),
+            None
+        );
+
+        let (tx, rx) = mpsc::channel();
+        pin.set_async_interrupt(Trigger::Both, move |event| {
+            let _ = tx.send(event.level);
+        })
+        .unwrap();
+        sim.set_level(17, Level::High);
+        sim.set_level(17, Level::Low);
+
+        let timeout = Duration::from_secs(1);
+        assert_eq!(rx.recv_timeout(timeout), Ok(Level::High));
+        assert_eq!(rx.recv_timeout(timeout), Ok(Level::Low));
+    }
+}
@@ -0,0 +1,467 @@
+// Low-level access to the DMA controller, the PWM peripheral used to pace DMA
+// transfers, and the VideoCore mailbox that hands out uncached memory the DMA
+// controller can read from. Everything here requires /dev/mem.
+
+use std::fmt;
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::mem;
+use std::os::unix::fs::OpenOptionsExt;
+use std::os::unix::io::AsRawFd;
+use std::ptr;
+use std::thread;
+use std::time::Duration;
+
+use libc::{self, c_ulong, c_void, off_t, MAP_FAILED, MAP_SHARED, O_SYNC, PROT_READ, PROT_WRITE};
+
+use crate::gpio::{Error, Result};
+use crate::system::{DeviceInfo, SoC};
+
+const PATH_DEV_MEM: &str = "/dev/mem";
+const PATH_DEV_VCIO: &str = "/dev/vcio";
+
+const PAGE_SIZE: usize = 4096;
+
+// Peripherals as seen by the DMA controller
+const BUS_PERIPHERAL_BASE: u32 = 0x7e00_0000;
+
+const GPIO_OFFSET: u32 = 0x20_0000;
+const DMA_OFFSET: u32 = 0x7000;
+const CLOCK_OFFSET: u32 = 0x10_1000;
+const PWM_OFFSET: u32 = 0x20_c000;
+
+pub(crate) const BUS_GPSET0: u32 = BUS_PERIPHERAL_BASE + GPIO_OFFSET + 0x1c;
+pub(crate) const BUS_GPCLR0: u32 = BUS_PERIPHERAL_BASE + GPIO_OFFSET + 0x28;
+pub(crate) const BUS_PWM_FIF1: u32 = BUS_PERIPHERAL_BASE + PWM_OFFSET + 0x18;
+
+// DMA channel registers (word offsets)
+const DMA_CS: usize = 0x00;
+const DMA_CONBLK_AD: usize = 0x04 / mem::size_of::<u32>();
+const DMA_DEBUG: usize = 0x20 / mem::size_of::<u32>();
+const DMA_CHANNEL_STRIDE: usize = 0x100 / mem::size_of::<u32>();
+const DMA_ENABLE: usize = 0xff0 / mem::size_of::<u32>();
+
+const DMA_CS_RESET: u32 = 1 << 31;
+const DMA_CS_WAIT_FOR_OUTSTANDING_WRITES: u32 = 1 << 28;
+const DMA_CS_INT: u32 = 1 << 2;
+const DMA_CS_END: u32 = 1 << 1;
+const DMA_CS_ACTIVE: u32 = 1;
+const DMA_DEBUG_CLEAR_ERRORS: u32 = 0b111;
+
+pub(crate) const DMA_TI_NO_WIDE_BURSTS: u32 = 1 << 26;
+pub(crate) const DMA_TI_SRC_INC: u32 = 1 << 8;
+pub(crate) const DMA_TI_DEST_DREQ: u32 = 1 << 6;
+pub(crate) const DMA_TI_DEST_INC: u32 = 1 << 4;
+pub(crate) const DMA_TI_WAIT_RESP: u32 = 1 << 3;
+pub(crate) const DMA_TI_PERMAP_PWM: u32 = 5 << 16;
+
+// Channel 15 lives in a different block, and is used by the VideoCore
+pub(crate) const DMA_CHANNEL_MAX: u8 = 14;
+// On the BCM2711, channels 11-14 are DMA4 channels, which use a different control
+// block layout
+const DMA4_CHANNEL_MIN: u8 = 11;
+
+// Returns the channel used by the DMA-based PWM engine unless configured otherwise.
+// Channel 14 is rarely used by the firmware, but it's a DMA4 channel on the BCM2711,
+// where channel 7 is used instead, similar to pigpio.
+pub(crate) fn default_channel(soc: Option<SoC>) -> u8 {
+    if soc == Some(SoC::Bcm2711) {
+        7
+    } else {
+        DMA_CHANNEL_MAX
+    }
+}
+
+// PWM registers (word offsets)
+const PWM_CTL: usize = 0x00;
+const PWM_DMAC: usize = 0x08 / mem::size_of::<u32>();
+const PWM_RNG1: usize = 0x10 / mem::size_of::<u32>();
+
+const PWM_CTL_CLRF1: u32 = 1 << 6;
+const PWM_CTL_USEF1: u32 = 1 << 5;
+const PWM_CTL_PWEN1: u32 = 1;
+const PWM_DMAC_ENAB: u32 = 1 << 31;
+const PWM_DMAC_THRESHOLD: u32 = (15 << 8) | 15;
+
+// Clock manager registers for the PWM clock (word offsets)
+const CM_PWMCTL: usize = 0xa0 / mem::size_of::<u32>();
+const CM_PWMDIV: usize = 0xa4 / mem::size_of::<u32>();
+
+const CM_PASSWD: u32 = 0x5a << 24;
+const CM_ENAB: u32 = 1 << 4;
+const CM_SRC_PLLD: u32 = 6;
+
+// The PWM clock is set to 10 MHz, so every PWM range unit equals 100 ns
+pub(crate) const PWM_CLOCK_NS: u64 = 100;
+const PWM_CLOCK_HZ: u32 = 10_000_000;
+
+// VideoCore mailbox property interface
+const MBOX_REQUEST: u32 = 0;
+const MBOX_SUCCESS: u32 = 0x8000_0000;
+const MBOX_TAG_GET_DMA_CHANNELS: u32 = 0x0006_0001;
+const MBOX_TAG_ALLOCATE_MEMORY: u32 = 0x0003_000c;
+const MBOX_TAG_LOCK_MEMORY: u32 = 0x0003_000d;
+const MBOX_TAG_UNLOCK_MEMORY: u32 = 0x0003_000e;
+const MBOX_TAG_RELEASE_MEMORY: u32 = 0x0003_000f;
+
+// Allocation flags. The BCM2835 needs the L1 non-allocating alias, later SoCs
+// use the uncached direct alias.
+const MEM_FLAG_DIRECT: u32 = 1 << 2;
+const MEM_FLAG_L1_NONALLOCATING: u32 = 3 << 2;
+
+// _IOWR(100, 0, char *)
+const IOCTL_MBOX_PROPERTY: c_ulong =
+    (3 << 30) | ((mem::size_of::<*mut u8>() as c_ulong) << 16) | (100 << 8);
+
+#[repr(C, align(16))]
+struct MboxBuffer([u32; 32]);
+
+#[derive(Debug)]
+struct Mailbox {
+    vcio: File,
+}
+
+impl Mailbox {
+    fn open() -> Result<Mailbox> {
+        let vcio = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(PATH_DEV_VCIO)
+            .map_err(|e| match e.kind() {
+                io::ErrorKind::PermissionDenied => {
+                    Error::PermissionDenied(String::from(PATH_DEV_VCIO))
+                }
+                _ => Error::Io(e),
+            })?;
+
+        Ok(Mailbox { vcio })
+    }
+
+    // Sends a single property tag, and returns the first word of the response
+    fn property(&self, tag: u32, args: &[u32], response_len: usize) -> Result<u32> {
+        let value_len = args.len().max(response_len);
+        let mut buffer = MboxBuffer([0; 32]);
+
+        buffer.0[0] = ((6 + value_len) * mem::size_of::<u32>()) as u32;
+        buffer.0[1] = MBOX_REQUEST;
+        buffer.0[2] = tag;
+        buffer.0[3] = (value_len * mem::size_of::<u32>()) as u32;
+        buffer.0[4] = mem::size_of_val(args) as u32;
+        buffer.0[5..5 + args.len()].copy_from_slice(args);
+        // The end tag (0) is already in place
+
+        parse_retval!(unsafe {
+            libc::ioctl(
+                self.vcio.as_raw_fd(),
+                IOCTL_MBOX_PROPERTY,
+                buffer.0.as_mut_ptr(),
+            )
+        })?;
+
+        if buffer.0[1] != MBOX_SUCCESS {
+            return Err(Error::Io(io::Error::from_raw_os_error(libc::EIO)));
+        }
+
+        Ok(buffer.0[5])
+    }
+}
+
+// Returns a mask of the DMA channels the firmware doesn't use
+pub(crate) fn available_channels() -> Result<u32> {
+    Mailbox::open()?.property(MBOX_TAG_GET_DMA_CHANNELS, &[], 1)
+}
+
+fn open_devmem() -> Result<File> {
+    OpenOptions::new()
+        .read(true)
+        .write(true)
+        .custom_flags(O_SYNC)
+        .open(PATH_DEV_MEM)
+        .map_err(|e| match e.kind() {
+            io::ErrorKind::PermissionDenied => Error::PermissionDenied(String::from(PATH_DEV_MEM)),
+            _ => Error::Io(e),
+        })
+}
+
+fn map(file: &File, offset: u32, size: usize) -> Result<*mut u32> {
+    let ptr = unsafe {
+        libc::mmap(
+            ptr::null_mut(),
+            size,
+            PROT_READ | PROT_WRITE,
+            MAP_SHARED,
+            file.as_raw_fd(),
+            offset as off_t,
+        )
+    };
+
+    if ptr == MAP_FAILED {
+        return Err(Error::Io(io::Error::last_os_error()));
+    }
+
+    Ok(ptr as *mut u32)
+}
+
+// A single page of peripheral registers mapped through /dev/mem
+pub(crate) struct PeripheralMem {
+    mem_ptr: *mut u32,
+}
+
+impl PeripheralMem {
+    pub(crate) fn open(device_info: &DeviceInfo, offset: u32) -> Result<PeripheralMem> {
+        let mem_ptr = map(
+            &open_devmem()?,
+            device_info.peripheral_base() + offset,
+            PAGE_SIZE,
+        )?;
+
+        Ok(PeripheralMem { mem_ptr })
+    }
+
+    #[inline(always)]
+    pub(crate) fn read(&self, offset: usize) -> u32 {
+        unsafe { ptr::read_volatile(self.mem_ptr.add(offset)) }
+    }
+
+    #[inline(always)]
+    pub(crate) fn write(&self, offset: usize, value: u32) {
+        unsafe {
+            ptr::write_volatile(self.mem_ptr.add(offset), value);
+        }
+    }
+}
+
+impl fmt::Debug for PeripheralMem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PeripheralMem")
+            .field("mem_ptr", &self.mem_ptr)
+            .finish()
+    }
+}
+
+impl Drop for PeripheralMem {
+    fn drop(&mut self) {
+        unsafe {
+            libc::munmap(self.mem_ptr as *mut c_void, PAGE_SIZE);
+        }
+    }
+}
+
+// Required because of the raw pointer to our memory-mapped file
+unsafe impl Send for PeripheralMem {}
+unsafe impl Sync for PeripheralMem {}
+
+// Physically contiguous memory allocated by the VideoCore, mapped without
+// going through the ARM caches so the DMA controller always sees our writes
+pub(crate) struct UncachedMem {
+    mailbox: Mailbox,
+    handle: u32,
+    bus_addr: u32,
+    mem_ptr: *mut u32,
+    size: usize,
+}
+
+impl UncachedMem {
+    pub(crate) fn new(device_info: &DeviceInfo, size: usize) -> Result<UncachedMem> {
+        let size = (size + PAGE_SIZE - 1) & !(PAGE_SIZE - 1);
+        let flags = if device_info.soc() == SoC::Bcm2835 {
+            MEM_FLAG_L1_NONALLOCATING
+        } else {
+            MEM_FLAG_DIRECT
+        };
+
+        let mailbox = Mailbox::open()?;
+        let handle = mailbox.property(
+            MBOX_TAG_ALLOCATE_MEMORY,
+            &[size as u32, PAGE_SIZE as u32, flags],
+            1,
+        )?;
+        if handle == 0 {
+            return Err(Error::Io(io::Error::from_raw_os_error(libc::ENOMEM)));
+        }
+
+        let mut mem = UncachedMem {
+            mailbox,
+            handle,
+            bus_addr: 0,
+            mem_ptr: ptr::null_mut(),
+            size,
+        };
+
+        // Any errors from here on out are cleaned up by drop()
+        mem.bus_addr = mem.mailbox.property(MBOX_TAG_LOCK_MEMORY, &[handle], 1)?;
+        mem.mem_ptr = map(&open_devmem()?, mem.bus_addr & !0xc000_0000, size)?;
+
+        unsafe {
+            ptr::write_bytes(mem.mem_ptr as *mut u8, 0, size);
+        }
+
+        Ok(mem)
+    }
+
+    // Returns the bus address for the specified byte offset
+    #[inline(always)]
+    pub(crate) fn bus_addr(&self, offset: usize) -> u32 {
+        self.bus_addr + offset as u32
+    }
+
+    #[inline(always)]
+    pub(crate) fn write(&self, offset: usize, value: u32) {
+        debug_assert!(offset < self.size);
+
+        unsafe {
+            ptr::write_volatile(self.mem_ptr.add(offset / 4), value);
+        }
+    }
+
+    pub(crate) fn write_control_block(&self, offset: usize, control_block: &ControlBlock) {
+        debug_assert_eq!(offset % mem::size_of::<ControlBlock>(), 0);
+
+        unsafe {
+            ptr::write_volatile(
+                self.mem_ptr.add(offset / 4) as *mut ControlBlock,
+                *control_block,
+            );
+        }
+    }
+}
+
+impl fmt::Debug for UncachedMem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("UncachedMem")
+            .field("handle", &self.handle)
+            .field("bus_addr", &self.bus_addr)
+            .field("mem_ptr", &self.mem_ptr)
+            .field("size", &self.size)
+            .finish()
+    }
+}
+
+impl Drop for UncachedMem {
+    fn drop(&mut self) {
+        unsafe {
+            if !self.mem_ptr.is_null() {
+                libc::munmap(self.mem_ptr as *mut c_void, self.size);
+            }
+        }
+
+        if self.bus_addr != 0 {
+            let _ = self
+                .mailbox
+                .property(MBOX_TAG_UNLOCK_MEMORY, &[self.handle], 1);
+        }
+
+        let _ = self
+            .mailbox
+            .property(MBOX_TAG_RELEASE_MEMORY, &[self.handle], 1);
+    }
+}
+
+unsafe impl Send for UncachedMem {}
+unsafe impl Sync for UncachedMem {}
+
+#[repr(C, align(32))]
+#[derive(Debug, Default, Copy, Clone)]
+pub(crate) struct ControlBlock {
+    pub(crate) ti: u32,
+    pub(crate) source_ad: u32,
+    pub(crate) dest_ad: u32,
+    pub(crate) txfr_len: u32,
+    pub(crate) stride: u32,
+    pub(crate) nextconbk: u32,
+    pub(crate) reserved: [u32; 2],
+}
+
+// A DMA channel whose transfers are paced by the PWM peripheral's FIFO. Every
+// word written to the FIFO takes exactly one step to be consumed.
+#[derive(Debug)]
+pub(crate) struct PacedDma {
+    channel: usize,
+    dma: PeripheralMem,
+    pwm: PeripheralMem,
+    clock: PeripheralMem,
+}
+
+impl PacedDma {
+    pub(crate) fn new(device_info: &DeviceInfo, channel: u8, step: Duration) -> Result<PacedDma> {
+        if channel > DMA_CHANNEL_MAX
+            || (device_info.soc() == SoC::Bcm2711 && channel >= DMA4_CHANNEL_MIN)
+            || available_channels()? & (1 << channel) == 0
+        {
+            return Err(Error::DmaChannelNotAvailable(channel));
+        }
+
+        let paced_dma = PacedDma {
+            channel: channel as usize * DMA_CHANNEL_STRIDE,
+            dma: PeripheralMem::open(device_info, DMA_OFFSET)?,
+            pwm: PeripheralMem::open(device_info, PWM_OFFSET)?,
+            clock: PeripheralMem::open(device_info, CLOCK_OFFSET)?,
+        };
+
+        let plld_hz: u32 = if device_info.soc() == SoC::Bcm2711 {
+            750_000_000
+        } else {
+            500_000_000
+        };
+
+        // Stop the PWM clock, and set it to 10 MHz
+        paced_dma.clock.write(CM_PWMCTL, CM_PASSWD | CM_SRC_PLLD);
+        thread::sleep(Duration::from_micros(100));
+        paced_dma
+            .clock
+            .write(CM_PWMDIV, CM_PASSWD | ((plld_hz / PWM_CLOCK_HZ) << 12));
+        paced_dma
+            .clock
+            .write(CM_PWMCTL, CM_PASSWD | CM_ENAB | CM_SRC_PLLD);
+        thread::sleep(Duration::from_micros(100));
+
+        // Each FIFO entry is shifted out over the configured range
+        let range = (step.as_nanos() as u64 / PWM_CLOCK_NS).max(1) as u32;
+        paced_dma.pwm.write(PWM_CTL, 0);
+        thread::sleep(Duration::from_micros(10));
+        paced_dma.pwm.write(PWM_RNG1, range);
+        paced_dma
+            .pwm
+            .write(PWM_DMAC, PWM_DMAC_ENAB | PWM_DMAC_THRESHOLD);
+        paced_dma.pwm.write(PWM_CTL, PWM_CTL_CLRF1);
+        thread::sleep(Duration::from_micros(10));
+        paced_dma.pwm.write(PWM_CTL, PWM_CTL_USEF1 | PWM_CTL_PWEN1);
+
+        Ok(paced_dma)
+    }
+
+    // Starts executing the control block chain at the specified bus address
+    pub(crate) fn start(&self, control_block: u32) {
+        let enable = self.dma.read(DMA_ENABLE);
+        self.dma.write(
+            DMA_ENABLE,
+            enable | (1 << (self.channel / DMA_CHANNEL_STRIDE)),
+        );
+
+        self.dma.write(self.channel + DMA_CS, DMA_CS_RESET);
+        thread::sleep(Duration::from_micros(10));
+        self.dma
+            .write(self.channel + DMA_CS, DMA_CS_INT | DMA_CS_END);
+        self.dma.write(self.channel + DMA_CONBLK_AD, control_block);
+        self.dma
+            .write(self.channel + DMA_DEBUG, DMA_DEBUG_CLEAR_ERRORS);
+
+        // Priority and panic priority 8
+        self.dma.write(
+            self.channel + DMA_CS,
+            DMA_CS_WAIT_FOR_OUTSTANDING_WRITES | (8 << 20) | (8 << 16) | DMA_CS_ACTIVE,
+        );
+    }
+
+    pub(crate) fn stop(&self) {
+        self.dma.write(self.channel + DMA_CS, DMA_CS_RESET);
+        thread::sleep(Duration::from_micros(10));
+        self.pwm.write(PWM_CTL, 0);
+    }
+}
+
+impl Drop for PacedDma {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
@@ -0,0 +1,549 @@
+use std::fmt;
+use std::mem;
+use std::time::Duration;
+
+use crate::gpio::backend::Backend;
+use crate::gpio::dma::{self, ControlBlock, PacedDma, UncachedMem};
+use crate::gpio::{pin, Error, Level, Result};
+use crate::system::{DeviceInfo, SoC};
+
+pub(crate) const DEFAULT_STEP: Duration = Duration::from_micros(10);
+// Limits the memory used by the control blocks to a little under 1 MiB
+pub(crate) const MAX_STEPS: usize = 8000;
+
+// Each step writes the set mask, writes the clear mask and then waits for
+// the PWM FIFO to accept a word
+const CONTROL_BLOCKS_PER_STEP: usize = 3;
+// GPSET0, GPSET1, GPCLR0 and GPCLR1
+const MASK_WORDS_PER_STEP: usize = 4;
+
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+struct Channel {
+    period: usize,
+    pulse_width: usize,
+}
+
+fn gcd(a: usize, b: usize) -> usize {
+    if b == 0 {
+        a
+    } else {
+        gcd(b, a % b)
+    }
+}
+
+/// A set of PWM signals sampled at a fixed time step.
+///
+/// `PwmSchedule` describes the waveform generated by the DMA-based PWM engine
+/// enabled through [`PwmEngine::Dma`]. Each pin's period and pulse width are
+/// rounded to the nearest whole number of steps. A cycle lasts until all pins
+/// have completed a whole number of periods, so it's the least common multiple
+/// of their periods. For every step in the cycle, the schedule contains a mask
+/// of pins that are set high and a mask of pins that are set low.
+///
+/// `PwmSchedule` doesn't access any hardware, which allows the waveform for a
+/// combination of pins to be inspected on any machine.
+///
+/// ## Example
+///
+/// ```
+/// use std::time::Duration;
+///
+/// use rpi_embedded::gpio::{Level, PwmSchedule};
+///
+/// # fn main() -> rpi_embedded::gpio::Result<()> {
+/// let mut schedule = PwmSchedule::new(Duration::from_micros(10), 8000);
+///
+/// // Servo on BCM GPIO 18, LED dimmed to 25% on BCM GPIO 23
+/// schedule.set(18, Duration::from_millis(20), Duration::from_micros(1500))?;
+/// schedule.set(23, Duration::from_millis(1), Duration::from_micros(250))?;
+///
+/// assert_eq!(schedule.steps(), 2000);
+/// assert_eq!(schedule.cycle(), Duration::from_millis(20));
+///
+/// assert_eq!(schedule.masks(0), ((1 << 18) | (1 << 23), 0));
+/// assert_eq!(schedule.masks(25), (0, 1 << 23));
+/// assert_eq!(schedule.masks(150), (0, 1 << 18));
+///
+/// assert_eq!(schedule.level(18, 149), Level::High);
+/// assert_eq!(schedule.level(18, 150), Level::Low);
+/// assert_eq!(schedule.level(23, 124), Level::High);
+/// assert_eq!(schedule.level(23, 125), Level::Low);
+/// # Ok(())
+/// # }
+/// ```
+///
+/// [`PwmEngine::Dma`]: enum.PwmEngine.html#variant.Dma
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct PwmSchedule {
+    step: Duration,
+    max_steps: usize,
+    steps: usize,
+    channels: Vec<Option<Channel>>,
+}
+
+impl PwmSchedule {
+    /// Constructs a new, empty `PwmSchedule`.
+    ///
+    /// `step` is the time resolution. `max_steps` limits the number of steps
+    /// in a single cycle.
+    ///
+    /// Panics if `step` is zero.
+    pub fn new(step: Duration, max_steps: usize) -> PwmSchedule {
+        assert!(step > Duration::default(), "step must be greater than zero");
+
+        PwmSchedule {
+            step,
+            max_steps,
+            steps: 1,
+            channels: vec![None; pin::MAX],
+        }
+    }
+
+    /// Returns the time resolution.
+    pub fn step(&self) -> Duration {
+        self.step
+    }
+
+    /// Returns the maximum number of steps in a single cycle.
+    pub fn max_steps(&self) -> usize {
+        self.max_steps
+    }
+
+    /// Returns the number of steps in a single cycle.
+    pub fn steps(&self) -> usize {
+        self.steps
+    }
+
+    /// Returns the duration of a single cycle.
+    pub fn cycle(&self) -> Duration {
+        self.step * self.steps as u32
+    }
+
+    /// Returns `true` if no pins are configured.
+    pub fn is_empty(&self) -> bool {
+        self.channels.iter().all(Option::is_none)
+    }
+
+    /// Adds or reconfigures the PWM signal for the specified BCM GPIO pin.
+    ///
+    /// `pulse_width` is limited to `period`. Returns
+    /// `Err(`[`Error::InvalidPwmPeriod`]`)` if `period` is shorter than half a
+    /// step, or if the resulting cycle would exceed the maximum number of steps.
+    /// The schedule isn't changed when an error is returned.
+    ///
+    /// [`Error::InvalidPwmPeriod`]: enum.Error.html#variant.InvalidPwmPeriod
+    pub fn set(&mut self, pin: u8, period: Duration, pulse_width: Duration) -> Result<()> {
+        if pin as usize >= pin::MAX {
+            return Err(Error::PinNotAvailable(pin, None));
+        }
+
+        let period_steps = self.to_steps(period);
+        if period_steps == 0 {
+            return Err(Error::InvalidPwmPeriod(period));
+        }
+
+        let channel = Channel {
+            period: period_steps,
+            pulse_width: self.to_steps(pulse_width).min(period_steps),
+        };
+
+        let prev_channel = self.channels[pin as usize].replace(channel);
+        match self.cycle_steps() {
+            Some(steps) => {
+                self.steps = steps;
+                Ok(())
+            }
+            None => {
+                self.channels[pin as usize] = prev_channel;
+                Err(Error::InvalidPwmPeriod(period))
+            }
+        }
+    }
+
+    /// Removes the PWM signal for the specified BCM GPIO pin.
+    pub fn clear(&mut self, pin: u8) {
+        if let Some(channel) = self.channels.get_mut(pin as usize) {
+            *channel = None;
+        }
+
+        // Removing a channel can only shorten the cycle
+        self.steps = self.cycle_steps().unwrap_or(1);
+    }
+
+    /// Returns the masks of pins that are set high and set low at the start
+    /// of the specified step, with bit n representing BCM GPIO n.
+    ///
+    /// `step` wraps around at the end of a cycle.
+    pub fn masks(&self, step: usize) -> (u64, u64) {
+        let step = step % self.steps;
+        let mut set_mask = 0;
+        let mut clear_mask = 0;
+
+        for (pin, channel) in self.channels.iter().enumerate() {
+            if let Some(channel) = channel {
+                let offset = step % channel.period;
+
+                if offset == 0 && channel.pulse_width > 0 {
+                    set_mask |= 1 << pin;
+                } else if offset == channel.pulse_width % channel.period {
+                    // Pins with a 0% duty cycle are cleared at the start of every period
+                    clear_mask |= 1 << pin;
+                }
+            }
+        }
+
+        (set_mask, clear_mask)
+    }
+
+    /// Returns the specified pin's logic level during the specified step.
+    ///
+    /// `step` wraps around at the end of a cycle. Pins that aren't configured
+    /// return [`Low`].
+    ///
+    /// [`Low`]: enum.Level.html#variant.Low
+    pub fn level(&self, pin: u8, step: usize) -> Level {
+        match self.channels.get(pin as usize) {
+            Some(Some(channel)) if (step % self.steps) % channel.period < channel.pulse_width => {
+                Level::High
+            }
+            _ => Level::Low,
+        }
+    }
+
+    pub(crate) fn contains(&self, pin: u8) -> bool {
+        matches!(self.channels.get(pin as usize), Some(Some(_)))
+    }
+
+    fn to_steps(&self, duration: Duration) -> usize {
+        let step_ns = self.step.as_nanos();
+
+        ((duration.as_nanos() + step_ns / 2) / step_ns) as usize
+    }
+
+    // Returns the least common multiple of all periods, or None if it exceeds max_steps
+    fn cycle_steps(&self) -> Option<usize> {
+        let mut steps = 1;
+
+        for channel in self.channels.iter().flatten() {
+            steps = steps / gcd(steps, channel.period) * channel.period;
+            if steps > self.max_steps {
+                return None;
+            }
+        }
+
+        Some(steps)
+    }
+}
+
+// Generates the waveform described by a PwmSchedule
+pub(crate) trait PwmOutput: fmt::Debug + Send {
+    fn load(&mut self, schedule: &PwmSchedule);
+}
+
+// Control blocks and masks are laid out as a ring with one entry per step. The
+// ring always covers max_steps, and is shortened by linking the last step of the
+// current cycle back to the start, so a running DMA transfer never reaches an
+// unlinked control block.
+#[derive(Debug)]
+pub(crate) struct DmaPwmOutput {
+    // Declared before mem so the DMA channel is stopped before its memory is released
+    dma: PacedDma,
+    mem: UncachedMem,
+    max_steps: usize,
+    steps: usize,
+}
+
+impl DmaPwmOutput {
+    pub(crate) fn new(channel: u8, step: Duration, max_steps: usize) -> Result<DmaPwmOutput> {
+        let device_info = DeviceInfo::new().map_err(|_| Error::UnknownModel)?;
+
+        let size = max_steps
+            * (CONTROL_BLOCKS_PER_STEP * mem::size_of::<ControlBlock>()
+                + MASK_WORDS_PER_STEP * mem::size_of::<u32>())
+            + mem::size_of::<u32>();
+
+        let output = DmaPwmOutput {
+            dma: PacedDma::new(&device_info, channel, step)?,
+            mem: UncachedMem::new(&device_info, size)?,
+            max_steps,
+            steps: max_steps,
+        };
+
+        for step in 0..max_steps {
+            let masks = output.mask_offset(step);
+
+            output.mem.write_control_block(
+                output.control_block_offset(step, 0),
+                &ControlBlock {
+                    ti: dma::DMA_TI_NO_WIDE_BURSTS
+                        | dma::DMA_TI_WAIT_RESP
+                        | dma::DMA_TI_SRC_INC
+                        | dma::DMA_TI_DEST_INC,
+                    source_ad: output.mem.bus_addr(masks),
+                    dest_ad: dma::BUS_GPSET0,
+                    txfr_len: 8,
+                    nextconbk: output.mem.bus_addr(output.control_block_offset(step, 1)),
+                    ..Default::default()
+                },
+            );
+
+            output.mem.write_control_block(
+                output.control_block_offset(step, 1),
+                &ControlBlock {
+                    ti: dma::DMA_TI_NO_WIDE_BURSTS
+                        | dma::DMA_TI_WAIT_RESP
+                        | dma::DMA_TI_SRC_INC
+                        | dma::DMA_TI_DEST_INC,
+                    source_ad: output.mem.bus_addr(masks + 8),
+                    dest_ad: dma::BUS_GPCLR0,
+                    txfr_len: 8,
+                    nextconbk: output.mem.bus_addr(output.control_block_offset(step, 2)),
+                    ..Default::default()
+                },
+            );
+
+            // Blocks until the PWM peripheral requests more data, which happens once per step
+            output.mem.write_control_block(
+                output.control_block_offset(step, 2),
+                &ControlBlock {
+                    ti: dma::DMA_TI_NO_WIDE_BURSTS
+                        | dma::DMA_TI_WAIT_RESP
+                        | dma::DMA_TI_DEST_DREQ
+                        | dma::DMA_TI_PERMAP_PWM,
+                    source_ad: output.mem.bus_addr(output.fifo_offset()),
+                    dest_ad: dma::BUS_PWM_FIF1,
+                    txfr_len: 4,
+                    nextconbk: output
+                        .mem
+                        .bus_addr(output.control_block_offset((step + 1) % max_steps, 0)),
+                    ..Default::default()
+                },
+            );
+        }
+
+        output
+            .dma
+            .start(output.mem.bus_addr(output.control_block_offset(0, 0)));
+
+        Ok(output)
+    }
+
+    fn control_block_offset(&self, step: usize, index: usize) -> usize {
+        (step * CONTROL_BLOCKS_PER_STEP + index) * mem::size_of::<ControlBlock>()
+    }
+
+    fn mask_offset(&self, step: usize) -> usize {
+        self.control_block_offset(self.max_steps, 0)
+            + step * MASK_WORDS_PER_STEP * mem::size_of::<u32>()
+    }
+
+    fn fifo_offset(&self) -> usize {
+        self.mask_offset(self.max_steps)
+    }
+
+    // Points the last control block of the specified step to the start of next_step
+    fn link(&self, step: usize, next_step: usize) {
+        self.mem.write(
+            self.control_block_offset(step, 2) + 20,
+            self.mem.bus_addr(self.control_block_offset(next_step, 0)),
+        );
+    }
+}
+
+impl PwmOutput for DmaPwmOutput {
+    fn load(&mut self, schedule: &PwmSchedule) {
+        let steps = schedule.steps().min(self.max_steps);
+
+        for step in 0..self.max_steps {
+            let (set_mask, clear_mask) = if step < steps {
+                schedule.masks(step)
+            } else {
+                (0, 0)
+            };
+
+            let offset = self.mask_offset(step);
+            self.mem.write(offset, set_mask as u32);
+            self.mem.write(offset + 4, (set_mask >> 32) as u32);
+            self.mem.write(offset + 8, clear_mask as u32);
+            self.mem.write(offset + 12, (clear_mask >> 32) as u32);
+        }
+
+        if steps != self.steps {
+            // Close the new ring before opening the old one
+            self.link(steps - 1, 0);
+            self.link(self.steps - 1, self.steps % self.max_steps);
+            self.steps = steps;
+        }
+    }
+}
+
+// GPIO state shared by all pins that use the DMA-based PWM engine. The engine is
+// started when the first pin is configured, and stopped when the last pin is cleared.
+#[derive(Debug)]
+pub(crate) struct DmaPwm {
+    channel: u8,
+    step: Duration,
+    engine: Option<(PwmSchedule, Box<dyn PwmOutput>)>,
+}
+
+impl DmaPwm {
+    pub(crate) fn new(soc: Option<SoC>) -> DmaPwm {
+        DmaPwm {
+            channel: dma::default_channel(soc),
+            step: DEFAULT_STEP,
+            engine: None,
+        }
+    }
+
+    pub(crate) fn configure(&mut self, channel: u8, step: Duration) {
+        self.channel = channel;
+        self.step = step;
+    }
+
+    pub(crate) fn set(
+        &mut self,
+        backend: &dyn Backend,
+        pin: u8,
+        period: Duration,
+        pulse_width: Duration,
+    ) -> Result<()> {
+        if self.engine.is_none() {
+            let output = backend.dma_pwm(self.channel, self.step, MAX_STEPS)?;
+            self.engine = Some((PwmSchedule::new(self.step, MAX_STEPS), output));
+        }
+
+        let result = match self.engine {
+            Some((ref mut schedule, ref mut output)) => {
+                let result = schedule.set(pin, period, pulse_width);
+                if result.is_ok() {
+                    output.load(schedule);
+                }
+
+                result
+            }
+            None => Ok(()),
+        };
+
+        self.stop_if_idle();
+
+        result
+    }
+
+    pub(crate) fn clear(&mut self, pin: u8) {
+        if let Some((ref mut schedule, ref mut output)) = self.engine {
+            if !schedule.contains(pin) {
+                return;
+            }
+
+            schedule.clear(pin);
+            output.load(schedule);
+        }
+
+        self.stop_if_idle();
+    }
+
+    fn stop_if_idle(&mut self) {
+        if let Some((ref schedule, _)) = self.engine {
+            if schedule.is_empty() {
+                self.engine = None;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const STEP: Duration = Duration::from_micros(10);
+
+    #[test]
+    fn duty_cycle_limits() {
+        let mut schedule = PwmSchedule::new(STEP, MAX_STEPS);
+        schedule
+            .set(4, Duration::from_micros(100), Duration::default())
+            .unwrap();
+        schedule
+            .set(5, Duration::from_micros(100), Duration::from_micros(100))
+            .unwrap();
+
+        // 0% is cleared at the start of every period, 100% is set and never cleared
+        assert_eq!(schedule.masks(0), (1 << 5, 1 << 4));
+        for step in 1..schedule.steps() {
+            assert_eq!(schedule.masks(step), (0, 0));
+            assert_eq!(schedule.level(4, step), Level::Low);
+            assert_eq!(schedule.level(5, step), Level::High);
+        }
+
+        // The pulse width is limited to the period
+        schedule
+            .set(5, Duration::from_micros(100), Duration::from_micros(500))
+            .unwrap();
+        assert_eq!(schedule.masks(0), (1 << 5, 1 << 4));
+    }
+
+    #[test]
+    fn cycle_overflow() {
+        let mut schedule = PwmSchedule::new(STEP, 1000);
+        schedule
+            .set(4, Duration::from_micros(70), Duration::from_micros(10))
+            .unwrap();
+        schedule
+            .set(5, Duration::from_micros(110), Duration::from_micros(10))
+            .unwrap();
+        assert_eq!(schedule.steps(), 77);
+
+        // 7, 11 and 13 steps would need a cycle of 1001 steps
+        let before = schedule.clone();
+        assert!(matches!(
+            schedule.set(6, Duration::from_micros(130), Duration::from_micros(10)),
+            Err(Error::InvalidPwmPeriod(_))
+        ));
+        assert_eq!(schedule, before);
+
+        // A period shorter than half a step can't be represented
+        assert!(matches!(
+            schedule.set(6, Duration::from_micros(4), Duration::default()),
+            Err(Error::InvalidPwmPeriod(_))
+        ));
+        assert!(matches!(
+            schedule.set(54, Duration::from_micros(100), Duration::default()),
+            Err(Error::PinNotAvailable(54, None))
+        ));
+    }
+
+    #[test]
+    fn clear() {
+        let mut schedule = PwmSchedule::new(STEP, MAX_STEPS);
+        schedule
+            .set(4, Duration::from_micros(60), Duration::from_micros(30))
+            .unwrap();
+        schedule
+            .set(5, Duration::from_micros(80), Duration::from_micros(20))
+            .unwrap();
+        assert_eq!(schedule.steps(), 24);
+
+        schedule.clear(5);
+        assert_eq!(schedule.steps(), 6);
+        assert_eq!(schedule.masks(0), (1 << 4, 0));
+        assert_eq!(schedule.masks(3), (0, 1 << 4));
+        assert_eq!(schedule.level(5, 0), Level::Low);
+
+        // Clearing pins that aren't configured or don't exist is ignored
+        schedule.clear(5);
+        schedule.clear(200);
+        assert!(!schedule.is_empty());
+
+        schedule.clear(4);
+        assert!(schedule.is_empty());
+        assert_eq!(schedule.steps(), 1);
+        assert_eq!(schedule.masks(0), (0, 0));
+    }
+
+    #[test]
+    fn default_channel() {
+        assert_eq!(DmaPwm::new(None).channel, 14);
+        assert_eq!(DmaPwm::new(Some(SoC::Bcm2837B0)).channel, 14);
+        assert_eq!(DmaPwm::new(Some(SoC::Bcm2711)).channel, 7);
+    }
+}
@@ -0,0 +1,620 @@
+use std::collections::VecDeque;
+use std::fmt;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use crate::gpio::interrupt::AsyncInterrupt;
+use crate::gpio::soft_pwm::get_time_ns;
+use crate::gpio::{InputPin, InterruptEvent, Level, Result, Trigger};
+
+const NANOS_PER_SEC: f64 = 1_000_000_000.0;
+
+const DEFAULT_VELOCITY_WINDOW: Duration = Duration::from_millis(250);
+
+// Quadrature states are encoded as (A << 1) | B. Channel A leads channel B when
+// turning clockwise: 00 -> 10 -> 11 -> 01 -> 00.
+const INVALID: i8 = i8::MIN;
+
+// Step for each (previous state << 2) | new state. Both channels changing at once
+// means an edge was missed, so the direction is unknown.
+#[rustfmt::skip]
+const TRANSITIONS: [i8; 16] = [
+    // 00        01        10        11
+       0,        -1,       1,        INVALID, // from 00
+       1,        0,        INVALID,  -1,      // from 01
+       -1,       INVALID,  0,        1,       // from 10
+       INVALID,  1,        -1,       0,       // from 11
+];
+
+/// Quadrature counting modes.
+///
+/// A full quadrature cycle consists of 4 edges, 2 on each channel. [`X4`] counts
+/// every edge, [`X2`] counts every other edge, and [`X1`] counts a single step per
+/// cycle, which matches one detent on most mechanical rotary encoders.
+///
+/// [`X1`]: #variant.X1
+/// [`X2`]: #variant.X2
+/// [`X4`]: #variant.X4
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub enum Counting {
+    /// 1 step per quadrature cycle.
+    X1,
+    /// 2 steps per quadrature cycle.
+    X2,
+    /// 4 steps per quadrature cycle.
+    X4,
+}
+
+impl Counting {
+    // Number of edges per step
+    fn edges(self) -> i64 {
+        match self {
+            Counting::X1 => 4,
+            Counting::X2 => 2,
+            Counting::X4 => 1,
+        }
+    }
+}
+
+impl fmt::Display for Counting {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Counting::X1 => write!(f, "X1"),
+            Counting::X2 => write!(f, "X2"),
+            Counting::X4 => write!(f, "X4"),
+        }
+    }
+}
+
+/// Rotation direction.
+///
+/// Rotation is considered clockwise when channel A leads channel B. Swap the
+/// pins to reverse the direction.
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub enum Direction {
+    Clockwise,
+    CounterClockwise,
+}
+
+impl fmt::Display for Direction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Direction::Clockwise => write!(f, "Clockwise"),
+            Direction::CounterClockwise => write!(f, "CounterClockwise"),
+        }
+    }
+}
+
+/// Change event sent by an [`Encoder`].
+///
+/// Events are received through the channel returned by [`Encoder::subscribe`].
+/// `timestamp` is the kernel timestamp of the edge that caused the event.
+///
+/// [`Encoder`]: struct.Encoder.html
+/// [`Encoder::subscribe`]: struct.Encoder.html#method.subscribe
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub enum EncoderEvent {
+    /// The position changed by a single step.
+    Step {
+        position: i64,
+        direction: Direction,
+        timestamp: Duration,
+    },
+    /// The index pin changed to `level`.
+    Index {
+        level: Level,
+        position: i64,
+        timestamp: Duration,
+    },
+}
+
+// Quadrature state machine. Counts every edge, and derives the position for
+// the selected counting mode from the edge count.
+#[derive(Debug)]
+struct Decoder {
+    counting: Counting,
+    state: u8,
+    edges: i64,
+    offset: i64,
+    invalid: u64,
+}
+
+impl Decoder {
+    fn new(a: Level, b: Level, counting: Counting) -> Decoder {
+        Decoder {
+            counting,
+            state: state(a, b),
+            edges: 0,
+            offset: 0,
+            invalid: 0,
+        }
+    }
+
+    fn position(&self) -> i64 {
+        // Round, so steps occur halfway between two X1/X2 rest states, which
+        // keeps a mechanical encoder from jittering around its detent
+        let edges = self.counting.edges();
+
+        (self.edges + edges / 2).div_euclid(edges) + self.offset
+    }
+
+    fn set_position(&mut self, position: i64) {
+        self.offset += position - self.position();
+    }
+
+    // Returns the position change caused by the new state
+    fn update(&mut self, a: Level, b: Level) -> i64 {
+        let new_state = state(a, b);
+        let step = TRANSITIONS[((self.state << 2) | new_state) as usize];
+        self.state = new_state;
+
+        if step == INVALID {
+            self.invalid += 1;
+            return 0;
+        }
+
+        let before = self.position();
+        self.edges += i64::from(step);
+
+        self.position() - before
+    }
+}
+
+fn state(a: Level, b: Level) -> u8 {
+    ((a as u8) << 1) | b as u8
+}
+
+#[derive(Debug)]
+struct Shared {
+    decoder: Decoder,
+    a: Level,
+    b: Level,
+    index: Option<Level>,
+    // Time and position change of recent steps, used to calculate the velocity
+    steps: VecDeque<(i64, i64)>,
+    velocity_window: Duration,
+    subscribers: Vec<Sender<EncoderEvent>>,
+}
+
+impl Shared {
+    fn notify(&mut self, event: EncoderEvent) {
+        // Drop subscribers whose receiver has gone out of scope
+        self.subscribers
+            .retain(|subscriber| subscriber.send(event).is_ok());
+    }
+
+    fn prune(&mut self, now_ns: i64) {
+        let window_ns = self.velocity_window.as_nanos() as i64;
+        while let Some(&(time_ns, _)) = self.steps.front() {
+            if now_ns - time_ns < window_ns {
+                break;
+            }
+
+            self.steps.pop_front();
+        }
+    }
+
+    fn on_event(&mut self, pins: (u8, u8), pin: u8, event: InterruptEvent) {
+        if pin == pins.0 || pin == pins.1 {
+            if pin == pins.0 {
+                self.a = event.level;
+            } else {
+                self.b = event.level;
+            }
+
+            let delta = self.decoder.update(self.a, self.b);
+            if delta == 0 {
+                return;
+            }
+
+            let now_ns = get_time_ns();
+            self.steps.push_back((now_ns, delta));
+            self.prune(now_ns);
+
+            let position = self.decoder.position();
+            self.notify(EncoderEvent::Step {
+                position,
+                direction: if delta > 0 {
+                    Direction::Clockwise
+                } else {
+                    Direction::CounterClockwise
+                },
+                timestamp: event.timestamp,
+            });
+        } else {
+            self.index = Some(event.level);
+
+            let position = self.decoder.position();
+            self.notify(EncoderEvent::Index {
+                level: event.level,
+                position,
+                timestamp: event.timestamp,
+            });
+        }
+    }
+}
+
+/// Quadrature rotary encoder.
+///
+/// An `Encoder` decodes the quadrature signal on two [`InputPin`]s, channel A and
+/// channel B, with an optional third pin for an index pulse or push button. Edges on
+/// all pins are handled in order on a single interrupt thread, and are decoded with a
+/// state table. Transitions where both channels change at once can't be decoded, and
+/// are counted as [`invalid_transitions`] instead of changing the position.
+///
+/// Any (a)synchronous interrupt triggers configured on the pins are cleared. The
+/// pins' software debounce window, kernel debounce period and event clock are applied.
+/// Mechanical encoders usually benefit from a short kernel debounce period of about
+/// 1 ms, configured through [`InputPin::set_kernel_debounce`] before constructing the
+/// `Encoder`.
+///
+/// The position can be read through [`position`], or followed through the change
+/// events sent to every channel returned by [`subscribe`].
+///
+/// ## Example
+///
+/// ```no_run
+/// use std::time::Duration;
+///
+/// use rpi_embedded::gpio::{Counting, Encoder, EncoderEvent, Gpio};
+///
+/// # fn main() -> rpi_embedded::gpio::Result<()> {
+/// let gpio = Gpio::new()?;
+/// let mut a = gpio.get(17)?.into_input_pullup();
+/// let mut b = gpio.get(27)?.into_input_pullup();
+/// let button = gpio.get(22)?.into_input_pullup();
+///
+/// a.set_kernel_debounce(Some(Duration::from_millis(1)));
+/// b.set_kernel_debounce(Some(Duration::from_millis(1)));
+///
+/// let encoder = Encoder::with_index(a, b, button, Counting::X1)?;
+/// for event in encoder.subscribe() {
+///     match event {
+///         EncoderEvent::Step { position, .. } => println!("Position: {}", position),
+///         EncoderEvent::Index { level, .. } => println!("Button: {}", level),
+///     }
+/// }
+/// # Ok(())
+/// # }
+/// ```
+///
+/// [`InputPin`]: struct.InputPin.html
+/// [`InputPin::set_kernel_debounce`]: struct.InputPin.html#method.set_kernel_debounce
+/// [`invalid_transitions`]: #method.invalid_transitions
+/// [`position`]: #method.position
+/// [`subscribe`]: #method.subscribe
+#[derive(Debug)]
+pub struct Encoder {
+    // Dropped first, so the interrupt thread stops before the pins are reset
+    interrupt: AsyncInterrupt,
+    shared: Arc<Mutex<Shared>>,
+    a: InputPin,
+    b: InputPin,
+    index: Option<InputPin>,
+}
+
+impl Encoder {
+    /// Constructs a new `Encoder` for channel A and channel B.
+    pub fn new(a: InputPin, b: InputPin, counting: Counting) -> Result<Encoder> {
+        Encoder::with_pins(a, b, None, counting)
+    }
+
+    /// Constructs a new `Encoder` for channel A, channel B and an index pin.
+    ///
+    /// The index pin is usually connected to the index output of an optical encoder,
+    /// or the push button of a mechanical encoder. Both edges are reported as
+    /// [`EncoderEvent::Index`] events, and don't affect the position.
+    ///
+    /// [`EncoderEvent::Index`]: enum.EncoderEvent.html#variant.Index
+    pub fn with_index(
+        a: InputPin,
+        b: InputPin,
+        index: InputPin,
+        counting: Counting,
+    ) -> Result<Encoder> {
+        Encoder::with_pins(a, b, Some(index), counting)
+    }
+
+    fn with_pins(
+        mut a: InputPin,
+        mut b: InputPin,
+        mut index: Option<InputPin>,
+        counting: Counting,
+    ) -> Result<Encoder> {
+        let mut configs = Vec::with_capacity(3);
+        for pin in [Some(&mut a), Some(&mut b), index.as_mut()]
+            .iter_mut()
+            .flatten()
+        {
+            pin.clear_interrupt()?;
+            pin.clear_async_interrupt()?;
+            configs.push(pin.interrupt_config(Trigger::Both));
+        }
+
+        let shared = Arc::new(Mutex::new(Shared {
+            decoder: Decoder::new(a.read(), b.read(), counting),
+            a: a.read(),
+            b: b.read(),
+            index: index.as_ref().map(|pin| pin.read()),
+            steps: VecDeque::new(),
+            velocity_window: DEFAULT_VELOCITY_WINDOW,
+            subscribers: Vec::new(),
+        }));
+
+        let pins = (a.pin(), b.pin());
+        let thread_shared = shared.clone();
+        let interrupt = AsyncInterrupt::with_pins(a.backend(), &configs, move |pin, event| {
+            thread_shared.lock().unwrap().on_event(pins, pin, event);
+        })?;
+
+        Ok(Encoder {
+            interrupt,
+            shared,
+            a,
+            b,
+            index,
+        })
+    }
+
+    /// Returns the counting mode.
+    pub fn counting(&self) -> Counting {
+        self.shared.lock().unwrap().decoder.counting
+    }
+
+    /// Returns the current position in steps.
+    ///
+    /// The position starts at 0, and is based on the selected counting mode.
+    pub fn position(&self) -> i64 {
+        self.shared.lock().unwrap().decoder.position()
+    }
+
+    /// Sets the current position.
+    pub fn set_position(&self, position: i64) {
+        self.shared.lock().unwrap().decoder.set_position(position);
+    }
+
+    /// Returns the velocity in steps per second, averaged over the velocity window.
+    ///
+    /// Clockwise rotation results in a positive velocity. The velocity drops to 0.0
+    /// once no steps have occurred for the duration of the window.
+    pub fn velocity(&self) -> f64 {
+        let mut shared = self.shared.lock().unwrap();
+        shared.prune(get_time_ns());
+
+        let steps: i64 = shared.steps.iter().map(|&(_, delta)| delta).sum();
+
+        steps as f64 / (shared.velocity_window.as_nanos() as f64 / NANOS_PER_SEC)
+    }
+
+    /// Returns the velocity window.
+    pub fn velocity_window(&self) -> Duration {
+        self.shared.lock().unwrap().velocity_window
+    }
+
+    /// Sets the period over which the velocity is averaged. By default, this is set
+    /// to 250 ms.
+    ///
+    /// A longer window results in a more stable velocity at low speeds, but reacts
+    /// more slowly to changes.
+    pub fn set_velocity_window(&self, window: Duration) {
+        self.shared.lock().unwrap().velocity_window = window.max(Duration::from_millis(1));
+    }
+
+    /// Returns the number of transitions where both channels changed at once.
+    ///
+    /// Invalid transitions occur when edges are missed, which is usually caused by
+    /// contact bounce or by turning the encoder faster than the interrupt thread can
+    /// keep up with.
+    pub fn invalid_transitions(&self) -> u64 {
+        self.shared.lock().unwrap().decoder.invalid
+    }
+
+    /// Returns the most recent logic level of the index pin, or `None` if the
+    /// `Encoder` was constructed without an index pin.
+    pub fn index_level(&self) -> Option<Level> {
+        self.shared.lock().unwrap().index
+    }
+
+    /// Returns a new channel that receives an [`EncoderEvent`] for every position
+    /// change and index pin edge.
+    ///
+    /// Events are only sent to channels whose receiver is still in scope. The channel
+    /// is closed when the `Encoder` goes out of scope.
+    ///
+    /// [`EncoderEvent`]: enum.EncoderEvent.html
+    pub fn subscribe(&self) -> Receiver<EncoderEvent> {
+        let (sender, receiver) = mpsc::channel();
+        self.shared.lock().unwrap().subscribers.push(sender);
+
+        receiver
+    }
+
+    /// Stops decoding, and returns channel A, channel B and the index pin.
+    pub fn into_pins(self) -> (InputPin, InputPin, Option<InputPin>) {
+        let Encoder {
+            mut interrupt,
+            shared,
+            a,
+            b,
+            index,
+        } = self;
+
+        let _ = interrupt.stop();
+        shared.lock().unwrap().subscribers.clear();
+
+        (a, b, index)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::gpio::Level::{High, Low};
+
+    // One full quadrature cycle, starting and ending at 00
+    const CLOCKWISE: [(Level, Level); 4] = [(High, Low), (High, High), (Low, High), (Low, Low)];
+    const COUNTER_CLOCKWISE: [(Level, Level); 4] =
+        [(Low, High), (High, High), (High, Low), (Low, Low)];
+
+    fn turn(decoder: &mut Decoder, cycle: &[(Level, Level)], cycles: usize) -> Vec<i64> {
+        let mut positions = Vec::new();
+        for _ in 0..cycles {
+            for &(a, b) in cycle {
+                decoder.update(a, b);
+                positions.push(decoder.position());
+            }
+        }
+
+        positions
+    }
+
+    #[test]
+    fn x4() {
+        let mut decoder = Decoder::new(Low, Low, Counting::X4);
+
+        assert_eq!(turn(&mut decoder, &CLOCKWISE, 2), [1, 2, 3, 4, 5, 6, 7, 8]);
+        assert_eq!(
+            turn(&mut decoder, &COUNTER_CLOCKWISE, 2),
+            [7, 6, 5, 4, 3, 2, 1, 0]
+        );
+        assert_eq!(decoder.invalid, 0);
+    }
+
+    #[test]
+    fn x2() {
+        let mut decoder = Decoder::new(Low, Low, Counting::X2);
+
+        assert_eq!(turn(&mut decoder, &CLOCKWISE, 2), [1, 1, 2, 2, 3, 3, 4, 4]);
+        assert_eq!(
+            turn(&mut decoder, &COUNTER_CLOCKWISE, 2),
+            [4, 3, 3, 2, 2, 1, 1, 0]
+        );
+        assert_eq!(decoder.invalid, 0);
+    }
+
+    #[test]
+    fn x1() {
+        let mut decoder = Decoder::new(Low, Low, Counting::X1);
+
+        assert_eq!(turn(&mut decoder, &CLOCKWISE, 2), [0, 1, 1, 1, 1, 2, 2, 2]);
+        assert_eq!(
+            turn(&mut decoder, &COUNTER_CLOCKWISE, 2),
+            [2, 2, 1, 1, 1, 1, 0, 0]
+        );
+        assert_eq!(decoder.invalid, 0);
+    }
+
+    #[test]
+    fn jitter_around_detent() {
+        // Bouncing back and forth on a single edge shouldn't change the X1
+        // position more than once
+        let mut decoder = Decoder::new(Low, Low, Counting::X1);
+
+        for _ in 0..5 {
+            assert_eq!(decoder.update(High, Low), 0);
+            assert_eq!(decoder.update(Low, Low), 0);
+        }
+
+        assert_eq!(decoder.position(), 0);
+    }
+
+    #[test]
+    fn invalid_transitions() {
+        let mut decoder = Decoder::new(Low, Low, Counting::X4);
+
+        // Both channels changing at once
+        assert_eq!(decoder.update(High, High), 0);
+        assert_eq!(decoder.update(Low, Low), 0);
+        assert_eq!(decoder.invalid, 2);
+        assert_eq!(decoder.position(), 0);
+
+        // A repeated state isn't a transition
+        assert_eq!(decoder.update(Low, Low), 0);
+        assert_eq!(decoder.invalid, 2);
+
+        // Decoding continues from the new state
+        assert_eq!(turn(&mut decoder, &CLOCKWISE, 1), [1, 2, 3, 4]);
+        assert_eq!(decoder.invalid, 2);
+    }
+
+    #[test]
+    fn set_position() {
+        let mut decoder = Decoder::new(Low, Low, Counting::X2);
+
+        turn(&mut decoder, &CLOCKWISE, 1);
+        assert_eq!(decoder.position(), 2);
+
+        decoder.set_position(-10);
+        assert_eq!(decoder.position(), -10);
+
+        assert_eq!(turn(&mut decoder, &CLOCKWISE, 1), [-9, -9, -8, -8]);
+        assert_eq!(turn(&mut decoder, &COUNTER_CLOCKWISE, 1), [-8, -9, -9, -10]);
+
+        decoder.set_position(0);
+        assert_eq!(decoder.position(), 0);
+    }
+
+    #[test]
+    fn direction() {
+        let (sender, receiver) = mpsc::channel();
+        let mut shared = Shared {
+            decoder: Decoder::new(Low, Low, Counting::X4),
+            a: Low,
+            b: Low,
+            index: None,
+            steps: VecDeque::new(),
+            velocity_window: DEFAULT_VELOCITY_WINDOW,
+            subscribers: vec![sender],
+        };
+
+        let mut edge = |pin: u8, level: Level| {
+            shared.on_event(
+                (1, 2),
+                pin,
+                InterruptEvent {
+                    timestamp: Duration::from_millis(1),
+                    trigger: if level == High {
+                        Trigger::RisingEdge
+                    } else {
+                        Trigger::FallingEdge
+                    },
+                    level,
+                    seqno: 0,
+                },
+            )
+        };
+
+        // A leads B
+        edge(1, High);
+        edge(2, High);
+        // B leads A
+        edge(2, Low);
+        edge(1, Low);
+        // Index pin
+        edge(3, High);
+
+        let steps: Vec<_> = receiver.try_iter().collect();
+        let step = |position, direction| EncoderEvent::Step {
+            position,
+            direction,
+            timestamp: Duration::from_millis(1),
+        };
+
+        assert_eq!(
+            steps,
+            [
+                step(1, Direction::Clockwise),
+                step(2, Direction::Clockwise),
+                step(1, Direction::CounterClockwise),
+                step(0, Direction::CounterClockwise),
+                EncoderEvent::Index {
+                    level: High,
+                    position: 0,
+                    timestamp: Duration::from_millis(1),
+                },
+            ]
+        );
+    }
+}
@@ -77,7 +77,7 @@ impl PwmPin for OutputPin {
     fn set_duty(&mut self, duty: Self::Duty) {
         self.duty_cycle = duty.max(0.0).min(1.0);
 
-        if self.soft_pwm.is_some() {
+        if self.soft_pwm.lock().unwrap().is_some() {
             let _ = self.set_pwm_frequency(self.frequency, self.duty_cycle);
         }
     }
@@ -105,7 +105,7 @@ impl PwmPin for IoPin {
     fn set_duty(&mut self, duty: Self::Duty) {
         self.duty_cycle = duty.max(0.0).min(1.0);
 
-        if self.soft_pwm.is_some() {
+        if self.soft_pwm.lock().unwrap().is_some() {
             let _ = self.set_pwm_frequency(self.frequency, self.duty_cycle);
         }
     }
@@ -153,7 +153,7 @@ impl Pwm for OutputPin {
     fn set_duty(&mut self, _channel: Self::Channel, duty: Self::Duty) {
         self.duty_cycle = duty.max(0.0).min(1.0);
 
-        if self.soft_pwm.is_some() {
+        if self.soft_pwm.lock().unwrap().is_some() {
             let _ = self.set_pwm_frequency(self.frequency, self.duty_cycle);
         }
     }
@@ -167,7 +167,7 @@ impl Pwm for OutputPin {
         self.frequency =
             1.0 / (period.as_secs() as f64 + (f64::from(period.subsec_nanos()) / NANOS_PER_SEC));
 
-        if self.soft_pwm.is_some() {
+        if self.soft_pwm.lock().unwrap().is_some() {
             let _ = self.set_pwm_frequency(self.frequency, self.duty_cycle);
         }
     }
@@ -211,7 +211,7 @@ impl Pwm for IoPin {
     fn set_duty(&mut self, _channel: Self::Channel, duty: Self::Duty) {
         self.duty_cycle = duty.max(0.0).min(1.0);
 
-        if self.soft_pwm.is_some() {
+        if self.soft_pwm.lock().unwrap().is_some() {
             let _ = self.set_pwm_frequency(self.frequency, self.duty_cycle);
         }
     }
@@ -225,7 +225,7 @@ impl Pwm for IoPin {
         self.frequency =
             1.0 / (period.as_secs() as f64 + (f64::from(period.subsec_nanos()) / NANOS_PER_SEC));
 
-        if self.soft_pwm.is_some() {
+        if self.soft_pwm.lock().unwrap().is_some() {
             let _ = self.set_pwm_frequency(self.frequency, self.duty_cycle);
         }
     }
@@ -0,0 +1,443 @@
+use std::fmt;
+
+use crate::gpio::{config, pin, Error, Mode, Result};
+use crate::system::{DeviceInfo, Model};
+
+/// The function of a pin on the Raspberry Pi's GPIO header.
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub enum HeaderPin {
+    /// BCM GPIO pin.
+    Gpio(u8),
+    /// 3.3 V power.
+    Power3v3,
+    /// 5 V power.
+    Power5v,
+    /// Ground.
+    Ground,
+}
+
+impl fmt::Display for HeaderPin {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            HeaderPin::Gpio(pin) => write!(f, "GPIO{}", pin),
+            HeaderPin::Power3v3 => write!(f, "3.3 V"),
+            HeaderPin::Power5v => write!(f, "5 V"),
+            HeaderPin::Ground => write!(f, "GND"),
+        }
+    }
+}
+
+// 40-pin header on the A+, B+, 2B, 3A+, 3B, 3B+, 4B, 400, Zero, Zero W and Zero 2 W.
+// The first 26 pins match the 26-pin header on the A and B Rev 2.
+const HEADER: [HeaderPin; 40] = [
+    HeaderPin::Power3v3, // Physical pin 1
+    HeaderPin::Power5v,  // Physical pin 2
+    HeaderPin::Gpio(2),  // Physical pin 3
+    HeaderPin::Power5v,  // Physical pin 4
+    HeaderPin::Gpio(3),  // Physical pin 5
+    HeaderPin::Ground,   // Physical pin 6
+    HeaderPin::Gpio(4),  // Physical pin 7
+    HeaderPin::Gpio(14), // Physical pin 8
+    HeaderPin::Ground,   // Physical pin 9
+    HeaderPin::Gpio(15), // Physical pin 10
+    HeaderPin::Gpio(17), // Physical pin 11
+    HeaderPin::Gpio(18), // Physical pin 12
+    HeaderPin::Gpio(27), // Physical pin 13
+    HeaderPin::Ground,   // Physical pin 14
+    HeaderPin::Gpio(22), // Physical pin 15
+    HeaderPin::Gpio(23), // Physical pin 16
+    HeaderPin::Power3v3, // Physical pin 17
+    HeaderPin::Gpio(24), // Physical pin 18
+    HeaderPin::Gpio(10), // Physical pin 19
+    HeaderPin::Ground,   // Physical pin 20
+    HeaderPin::Gpio(9),  // Physical pin 21
+    HeaderPin::Gpio(25), // Physical pin 22
+    HeaderPin::Gpio(11), // Physical pin 23
+    HeaderPin::Gpio(8),  // Physical pin 24
+    HeaderPin::Ground,   // Physical pin 25
+    HeaderPin::Gpio(7),  // Physical pin 26
+    HeaderPin::Gpio(0),  // Physical pin 27
+    HeaderPin::Gpio(1),  // Physical pin 28
+    HeaderPin::Gpio(5),  // Physical pin 29
+    HeaderPin::Ground,   // Physical pin 30
+    HeaderPin::Gpio(6),  // Physical pin 31
+    HeaderPin::Gpio(12), // Physical pin 32
+    HeaderPin::Gpio(13), // Physical pin 33
+    HeaderPin::Ground,   // Physical pin 34
+    HeaderPin::Gpio(19), // Physical pin 35
+    HeaderPin::Gpio(16), // Physical pin 36
+    HeaderPin::Gpio(26), // Physical pin 37
+    HeaderPin::Gpio(20), // Physical pin 38
+    HeaderPin::Ground,   // Physical pin 39
+    HeaderPin::Gpio(21), // Physical pin 40
+];
+
+// The 26-pin header on the B Rev 1 swaps a few pins compared to the later models.
+const HEADER_REV1: [HeaderPin; 26] = [
+    HeaderPin::Power3v3, // Physical pin 1
+    HeaderPin::Power5v,  // Physical pin 2
+    HeaderPin::Gpio(0),  // Physical pin 3
+    HeaderPin::Power5v,  // Physical pin 4
+    HeaderPin::Gpio(1),  // Physical pin 5
+    HeaderPin::Ground,   // Physical pin 6
+    HeaderPin::Gpio(4),  // Physical pin 7
+    HeaderPin::Gpio(14), // Physical pin 8
+    HeaderPin::Ground,   // Physical pin 9
+    HeaderPin::Gpio(15), // Physical pin 10
+    HeaderPin::Gpio(17), // Physical pin 11
+    HeaderPin::Gpio(18), // Physical pin 12
+    HeaderPin::Gpio(21), // Physical pin 13
+    HeaderPin::Ground,   // Physical pin 14
+    HeaderPin::Gpio(22), // Physical pin 15
+    HeaderPin::Gpio(23), // Physical pin 16
+    HeaderPin::Power3v3, // Physical pin 17
+    HeaderPin::Gpio(24), // Physical pin 18
+    HeaderPin::Gpio(10), // Physical pin 19
+    HeaderPin::Ground,   // Physical pin 20
+    HeaderPin::Gpio(9),  // Physical pin 21
+    HeaderPin::Gpio(25), // Physical pin 22
+    HeaderPin::Gpio(11), // Physical pin 23
+    HeaderPin::Gpio(8),  // Physical pin 24
+    HeaderPin::Ground,   // Physical pin 25
+    HeaderPin::Gpio(7),  // Physical pin 26
+];
+
+const MAX_PINS_SHORT: usize = 26;
+
+// WiringPi pin numbers 0-31 mapped to BCM GPIO pin numbers. WiringPi 17-20 are
+// located on the P5 header, which is only present on the A and B Rev 2.
+const WIRINGPI: [u8; 32] = [
+    17, 18, 27, 22, 23, 24, 25, 4, 2, 3, 8, 7, 10, 9, 11, 14, 15, 28, 29, 30, 31, 5, 6, 13, 19, 26,
+    12, 16, 20, 21, 0, 1,
+];
+const WIRINGPI_REV1: [u8; 17] = [17, 18, 21, 22, 23, 24, 25, 4, 0, 1, 8, 7, 10, 9, 11, 14, 15];
+const WIRINGPI_P5: std::ops::Range<u8> = 17..21;
+
+/// Returns the layout of the GPIO header for the specified model, ordered by
+/// physical pin number.
+///
+/// Returns `None` for the Compute Modules, which don't have a GPIO header.
+pub fn header(model: Model) -> Option<&'static [HeaderPin]> {
+    match model {
+        Model::RaspberryPiBRev1 => Some(&HEADER_REV1[..]),
+        Model::RaspberryPiA | Model::RaspberryPiBRev2 => Some(&HEADER[..MAX_PINS_SHORT]),
+        Model::RaspberryPiComputeModule
+        | Model::RaspberryPiComputeModule3
+        | Model::RaspberryPiComputeModule3Plus
+        | Model::RaspberryPiComputeModule4 => None,
+        _ => Some(&HEADER[..]),
+    }
+}
+
+/// Identifies a GPIO pin through one of several numbering schemes.
+///
+/// Apart from [`Bcm`], every numbering scheme depends on the Raspberry Pi model.
+/// [`resolve`] converts a `PinId` to a BCM GPIO pin number for a specific model, and
+/// [`Gpio::get_by_id`] retrieves a pin based on the model [`Gpio`] is running on.
+///
+/// `PinId` can be converted from a `u8` BCM GPIO pin number, or from a pin name.
+///
+/// ## Example
+///
+/// ```
+/// use rpi_embedded::gpio::PinId;
+/// use rpi_embedded::system::Model;
+///
+/// # fn main() -> rpi_embedded::gpio::Result<()> {
+/// assert_eq!(PinId::Physical(12).resolve(Model::RaspberryPi3B)?, 18);
+/// assert_eq!(PinId::WiringPi(2).resolve(Model::RaspberryPiBRev1)?, 21);
+/// assert_eq!(PinId::from("PWM0").resolve(Model::RaspberryPi3B)?, 18);
+/// assert!(PinId::Physical(6).resolve(Model::RaspberryPi4B).is_err());
+/// # Ok(())
+/// # }
+/// ```
+///
+/// [`Bcm`]: #variant.Bcm
+/// [`resolve`]: #method.resolve
+/// [`Gpio`]: struct.Gpio.html
+/// [`Gpio::get_by_id`]: struct.Gpio.html#method.get_by_id
+#[derive(Debug, PartialEq, Eq, Hash, Clone)]
+pub enum PinId {
+    /// BCM GPIO pin number.
+    Bcm(u8),
+    /// Physical pin number on the GPIO header.
+    Physical(u8),
+    /// WiringPi pin number.
+    WiringPi(u8),
+    /// Pin name.
+    ///
+    /// `GPIOn` and `BCMn` select BCM GPIO pin `n` (0-53). Any other name is matched against
+    /// the peripheral signals returned by [`alt_function`], such as `PWM0` or `TXD0`,
+    /// and selects the first pin on the GPIO header that supports the signal. Signal
+    /// names follow the SoC's datasheet, so the BCM2711 uses `PWM0_0` instead of `PWM0`.
+    /// Names are case-insensitive.
+    ///
+    /// [`alt_function`]: fn.alt_function.html
+    Name(String),
+}
+
+impl PinId {
+    /// Returns the BCM GPIO pin number `PinId` refers to on the specified model.
+    ///
+    /// If the header pin is a power or ground pin, `resolve` returns
+    /// `Err(`[`Error::NotGpioPin`]`)`. If the pin doesn't exist on the model's
+    /// board revision, `Err(`[`Error::PinIdNotAvailable`]`)` is returned.
+    ///
+    /// [`Error::NotGpioPin`]: enum.Error.html#variant.NotGpioPin
+    /// [`Error::PinIdNotAvailable`]: enum.Error.html#variant.PinIdNotAvailable
+    pub fn resolve(&self, model: Model) -> Result<u8> {
+        let not_available = || Error::PinIdNotAvailable(self.clone(), model);
+
+        match *self {
+            PinId::Bcm(pin) => Ok(pin),
+            PinId::Physical(physical) => {
+                let header = header(model).ok_or_else(not_available)?;
+
+                match header.get((physical as usize).wrapping_sub(1)) {
+                    Some(&HeaderPin::Gpio(pin)) => Ok(pin),
+                    Some(&header_pin) => Err(Error::NotGpioPin(physical, header_pin)),
+                    None => Err(not_available()),
+                }
+            }
+            PinId::WiringPi(wiringpi) => {
+                let pins = match (model, header(model).map(<[HeaderPin]>::len)) {
+                    (Model::RaspberryPiBRev1, _) => &WIRINGPI_REV1[..],
+                    // The A and B Rev 2 have a 26-pin header and the P5 header
+                    (_, Some(MAX_PINS_SHORT)) => &WIRINGPI[..WIRINGPI_P5.end as usize],
+                    // The 40-pin header replaces the P5 header
+                    (_, Some(_)) if WIRINGPI_P5.contains(&wiringpi) => return Err(not_available()),
+                    _ => &WIRINGPI[..],
+                };
+
+                pins.get(wiringpi as usize)
+                    .copied()
+                    .ok_or_else(not_available)
+            }
+            PinId::Name(ref name) => {
+                let name = name.to_uppercase();
+
+                let number = name
+                    .strip_prefix("GPIO")
+                    .or_else(|| name.strip_prefix("BCM"));
+                if let Some(number) = number {
+                    return match number.parse() {
+                        Ok(pin) if (pin as usize) < pin::MAX => Ok(pin),
+                        _ => Err(not_available()),
+                    };
+                }
+
+                let soc = DeviceInfo::from_model(model).soc();
+                let pins: Vec<u8> = match header(model) {
+                    Some(header) => header
+                        .iter()
+                        .filter_map(|header_pin| match *header_pin {
+                            HeaderPin::Gpio(pin) => Some(pin),
+                            _ => None,
+                        })
+                        .collect(),
+                    None => (0..pin::MAX as u8).collect(),
+                };
+
+                let modes = [
+                    Mode::Alt0,
+                    Mode::Alt1,
+                    Mode::Alt2,
+                    Mode::Alt3,
+                    Mode::Alt4,
+                    Mode::Alt5,
+                ];
+
+                pins.into_iter()
+                    .find(|&pin| {
+                        modes
+                            .iter()
+                            .any(|&mode| config::alt_function(soc, pin, mode) == Some(&name[..]))
+                    })
+                    .ok_or_else(not_available)
+            }
+        }
+    }
+}
+
+impl From<u8> for PinId {
+    fn from(pin: u8) -> PinId {
+        PinId::Bcm(pin)
+    }
+}
+
+impl From<&str> for PinId {
+    fn from(name: &str) -> PinId {
+        PinId::Name(name.to_owned())
+    }
+}
+
+impl From<String> for PinId {
+    fn from(name: String) -> PinId {
+        PinId::Name(name)
+    }
+}
+
+impl fmt::Display for PinId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            PinId::Bcm(pin) => write!(f, "BCM GPIO {}", pin),
+            PinId::Physical(pin) => write!(f, "Header pin {}", pin),
+            PinId::WiringPi(pin) => write!(f, "WiringPi pin {}", pin),
+            PinId::Name(ref name) => write!(f, "Pin {}", name),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn resolve<T: Into<PinId>>(id: T, model: Model) -> Result<u8> {
+        id.into().resolve(model)
+    }
+
+    #[test]
+    fn physical() {
+        assert_eq!(
+            resolve(PinId::Physical(3), Model::RaspberryPi4B).unwrap(),
+            2
+        );
+        assert_eq!(
+            resolve(PinId::Physical(40), Model::RaspberryPi4B).unwrap(),
+            21
+        );
+
+        // Power and ground pins
+        assert!(matches!(
+            resolve(PinId::Physical(1), Model::RaspberryPi4B),
+            Err(Error::NotGpioPin(1, HeaderPin::Power3v3))
+        ));
+        assert!(matches!(
+            resolve(PinId::Physical(4), Model::RaspberryPiZero),
+            Err(Error::NotGpioPin(4, HeaderPin::Power5v))
+        ));
+        assert!(matches!(
+            resolve(PinId::Physical(39), Model::RaspberryPi3B),
+            Err(Error::NotGpioPin(39, HeaderPin::Ground))
+        ));
+
+        // Pins that don't exist on the header, or boards without a header
+        for &(physical, model) in [
+            (0, Model::RaspberryPi4B),
+            (41, Model::RaspberryPi4B),
+            (27, Model::RaspberryPiBRev2),
+            (3, Model::RaspberryPiComputeModule4),
+        ]
+        .iter()
+        {
+            assert!(matches!(
+                resolve(PinId::Physical(physical), model),
+                Err(Error::PinIdNotAvailable(PinId::Physical(_), _))
+            ));
+        }
+    }
+
+    #[test]
+    fn rev1() {
+        // The B Rev 1 has BCM GPIO 0, 1 and 21 where later models have 2, 3 and 27
+        let rev1 = [(3, 0), (5, 1), (13, 21)];
+        for &(physical, pin) in rev1.iter() {
+            assert_eq!(
+                resolve(PinId::Physical(physical), Model::RaspberryPiBRev1).unwrap(),
+                pin
+            );
+        }
+        assert_eq!(
+            resolve(PinId::Physical(3), Model::RaspberryPiBRev2).unwrap(),
+            2
+        );
+        assert_eq!(
+            resolve(PinId::Physical(13), Model::RaspberryPiBRev2).unwrap(),
+            27
+        );
+
+        assert_eq!(
+            resolve(PinId::WiringPi(2), Model::RaspberryPiBRev1).unwrap(),
+            21
+        );
+        assert_eq!(
+            resolve(PinId::WiringPi(8), Model::RaspberryPiBRev1).unwrap(),
+            0
+        );
+        assert_eq!(
+            resolve(PinId::WiringPi(16), Model::RaspberryPiBRev1).unwrap(),
+            15
+        );
+        assert_eq!(
+            resolve(PinId::WiringPi(2), Model::RaspberryPiBRev2).unwrap(),
+            27
+        );
+
+        // The B Rev 1 doesn't have a P5 header
+        assert!(resolve(PinId::WiringPi(17), Model::RaspberryPiBRev1).is_err());
+    }
+
+    #[test]
+    fn wiringpi_p5() {
+        // WiringPi 17-20 are only available on boards with a P5 header
+        for wiringpi in 17..21 {
+            assert_eq!(
+                resolve(PinId::WiringPi(wiringpi), Model::RaspberryPiBRev2).unwrap(),
+                wiringpi + 11
+            );
+            assert_eq!(
+                resolve(PinId::WiringPi(wiringpi), Model::RaspberryPiA).unwrap(),
+                wiringpi + 11
+            );
+            assert!(matches!(
+                resolve(PinId::WiringPi(wiringpi), Model::RaspberryPi3B),
+                Err(Error::PinIdNotAvailable(
+                    PinId::WiringPi(_),
+                    Model::RaspberryPi3B
+                ))
+            ));
+        }
+
+        // The 40-pin header adds WiringPi 21-31, which the 26-pin header lacks
+        assert_eq!(
+            resolve(PinId::WiringPi(16), Model::RaspberryPi3B).unwrap(),
+            15
+        );
+        assert_eq!(
+            resolve(PinId::WiringPi(21), Model::RaspberryPi3B).unwrap(),
+            5
+        );
+        assert_eq!(
+            resolve(PinId::WiringPi(31), Model::RaspberryPi3B).unwrap(),
+            1
+        );
+        assert!(resolve(PinId::WiringPi(21), Model::RaspberryPiBRev2).is_err());
+        assert!(resolve(PinId::WiringPi(32), Model::RaspberryPi3B).is_err());
+    }
+
+    #[test]
+    fn names() {
+        assert_eq!(resolve("GPIO4", Model::RaspberryPi4B).unwrap(), 4);
+        assert_eq!(resolve("bcm27", Model::RaspberryPi4B).unwrap(), 27);
+        assert_eq!(
+            resolve("gpio53", Model::RaspberryPiComputeModule4).unwrap(),
+            53
+        );
+
+        // Numbers that aren't valid BCM GPIO pin numbers
+        for &name in ["GPIO54", "GPIO99", "BCM99", "GPIO", "GPIO-1", "BCM1000"].iter() {
+            assert!(matches!(
+                resolve(name, Model::RaspberryPi4B),
+                Err(Error::PinIdNotAvailable(PinId::Name(_), _))
+            ));
+        }
+
+        // Signal names follow the SoC's datasheet, and only pins on the header are searched
+        assert_eq!(resolve("PWM0", Model::RaspberryPi3B).unwrap(), 18);
+        assert_eq!(resolve("txd0", Model::RaspberryPi3B).unwrap(), 14);
+        assert_eq!(resolve("SDA1", Model::RaspberryPi4B).unwrap(), 2);
+        assert_eq!(resolve("PWM0_0", Model::RaspberryPi4B).unwrap(), 18);
+        assert!(resolve("PWM0_0", Model::RaspberryPi3B).is_err());
+        assert!(resolve("FOO", Model::RaspberryPi4B).is_err());
+    }
+}
@@ -21,76 +21,182 @@
 #![allow(dead_code)]
 
 use std::fmt;
+#[cfg(feature = "async")]
+use std::future::{self, Future};
+#[cfg(feature = "async")]
+use std::io;
+#[cfg(feature = "async")]
+use std::marker::PhantomData;
+#[cfg(feature = "async")]
+use std::os::unix::io::{AsRawFd, RawFd};
+#[cfg(feature = "async")]
+use std::pin::Pin;
+use std::sync::Arc;
+#[cfg(feature = "async")]
+use std::task::{Context, Poll};
 use std::thread;
 use std::time::{Duration, Instant};
 
+use crate::gpio::backend::Backend;
+use crate::gpio::debounce::Debouncer;
 use crate::gpio::epoll::{epoll_event, Epoll, EventFd, EPOLLERR, EPOLLET, EPOLLIN, EPOLLPRI};
 use crate::gpio::ioctl;
 use crate::gpio::pin::InputPin;
-use crate::gpio::{Error, Level, Result, Trigger};
+use crate::gpio::{Error, InterruptEvent, Level, Result, Trigger};
 
 #[derive(Debug)]
 struct Interrupt {
     pin: u8,
-    trigger: Trigger,
-    cdev_fd: i32,
-    event_request: ioctl::EventRequest,
+    config: ioctl::EventConfig,
+    backend: Arc<dyn Backend>,
+    event_request: ioctl::EventHandle,
+    seqno: u32,
+    debouncer: Option<Debouncer>,
+    // When the pin's level should be read to settle the debouncer
+    settle_at: Option<Instant>,
 }
 
 impl Interrupt {
-    fn new(cdev_fd: i32, pin: u8, trigger: Trigger) -> Result<Interrupt> {
+    fn new(
+        backend: Arc<dyn Backend>,
+        pin: u8,
+        config: ioctl::EventConfig,
+        debounce: Option<Duration>,
+    ) -> Result<Interrupt> {
+        let event_request = backend.event_request(pin, &config)?;
+
         Ok(Interrupt {
             pin,
-            trigger,
-            cdev_fd,
-            event_request: ioctl::EventRequest::new(cdev_fd, pin, trigger)?,
+            config,
+            backend,
+            event_request,
+            seqno: 0,
+            debouncer: debounce.map(|window| Debouncer::with_trigger(window, config.trigger)),
+            settle_at: None,
         })
     }
 
     fn trigger(&self) -> Trigger {
-        self.trigger
+        self.config.trigger
     }
 
     fn fd(&self) -> i32 {
-        self.event_request.fd
+        self.event_request.fd()
     }
 
     fn pin(&self) -> u8 {
         self.pin
     }
 
-    fn set_trigger(&mut self, trigger: Trigger) -> Result<()> {
-        self.trigger = trigger;
+    fn set_config(&mut self, config: ioctl::EventConfig) -> Result<()> {
+        if self.config.trigger != config.trigger {
+            let window = self.debouncer.map(|debouncer| debouncer.window());
+            self.debouncer = window.map(|window| Debouncer::with_trigger(window, config.trigger));
+            self.settle_at = None;
+        }
+
+        self.config = config;
 
         self.reset()
     }
 
-    fn event(&mut self) -> Result<ioctl::Event> {
+    fn set_debounce(&mut self, debounce: Option<Duration>) {
+        if self.debouncer.map(|debouncer| debouncer.window()) != debounce {
+            let trigger = self.config.trigger;
+            self.debouncer = debounce.map(|window| Debouncer::with_trigger(window, trigger));
+            self.settle_at = None;
+        }
+    }
+
+    fn settle_at(&self) -> Option<Instant> {
+        self.settle_at
+    }
+
+    // Reports a level change the debouncer rejected, once its window has ended
+    fn settle(&mut self) -> Option<InterruptEvent> {
+        match self.settle_at {
+            Some(settle_at) if settle_at <= Instant::now() => self.settle_at = None,
+            _ => return None,
+        }
+
+        let level = self.backend.level(self.pin);
+
+        self.debouncer
+            .as_mut()
+            .and_then(|debouncer| debouncer.settle(level))
+    }
+
+    // Returns None if the event was rejected by the debouncer
+    fn event(&mut self) -> Result<Option<InterruptEvent>> {
         // This might block if there are no events waiting
-        ioctl::get_event(self.event_request.fd)
+        let event = self.event_request.event()?;
+
+        // The v1 uAPI doesn't number its events, so keep count ourselves
+        self.seqno = event.seqno.unwrap_or_else(|| self.seqno.wrapping_add(1));
+
+        let event = InterruptEvent {
+            timestamp: event.timestamp,
+            trigger: event.trigger,
+            level: match event.trigger {
+                Trigger::RisingEdge => Level::High,
+                _ => Level::Low,
+            },
+            seqno: self.seqno,
+        };
+
+        if let Some(ref mut debouncer) = self.debouncer {
+            let accepted = debouncer.filter(&event);
+
+            // Event timestamps may use a different clock, so only the remaining
+            // time is converted
+            self.settle_at = debouncer.deadline().map(|deadline| {
+                Instant::now() + deadline.checked_sub(event.timestamp).unwrap_or_default()
+            });
+
+            if !accepted {
+                return Ok(None);
+            }
+        }
+
+        Ok(Some(event))
     }
 
     fn reset(&mut self) -> Result<()> {
         // Close the old event fd before opening a new one
         self.event_request.close();
-        self.event_request = ioctl::EventRequest::new(self.cdev_fd, self.pin, self.trigger)?;
+        self.event_request = self.backend.event_request(self.pin, &self.config)?;
+        self.seqno = 0;
 
         Ok(())
     }
 }
 
+impl Drop for Interrupt {
+    fn drop(&mut self) {
+        self.event_request.close();
+        self.backend.clear_events(self.pin);
+    }
+}
+
+#[cfg(feature = "async")]
+impl AsRawFd for Interrupt {
+    fn as_raw_fd(&self) -> RawFd {
+        self.fd()
+    }
+}
+
 #[derive(Debug)]
 struct TriggerStatus {
     interrupt: Option<Interrupt>,
     triggered: bool,
-    level: Level,
+    event: InterruptEvent,
 }
 
 pub struct EventLoop {
     poll: Epoll,
     events: Vec<epoll_event>,
     trigger_status: Vec<TriggerStatus>,
-    cdev_fd: i32,
+    backend: Arc<dyn Backend>,
 }
 
 impl fmt::Debug for EventLoop {
@@ -99,13 +205,13 @@ impl fmt::Debug for EventLoop {
             .field("poll", &self.poll)
             .field("events", &format_args!("{{ .. }}"))
             .field("trigger_status", &format_args!("{{ .. }}"))
-            .field("cdev_fd", &self.cdev_fd)
+            .field("backend", &self.backend)
             .finish()
     }
 }
 
 impl EventLoop {
-    pub fn new(cdev_fd: i32, capacity: usize) -> Result<EventLoop> {
+    pub fn new(backend: Arc<dyn Backend>, capacity: usize) -> Result<EventLoop> {
         let mut trigger_status = Vec::with_capacity(capacity);
 
         // Initialize trigger_status while circumventing the Copy/Clone requirement
@@ -113,7 +219,12 @@ impl EventLoop {
             trigger_status.push(TriggerStatus {
                 interrupt: None,
                 triggered: false,
-                level: Level::Low,
+                event: InterruptEvent {
+                    timestamp: Duration::default(),
+                    trigger: Trigger::Disabled,
+                    level: Level::Low,
+                    seqno: 0,
+                },
             });
         }
 
@@ -121,7 +232,7 @@ impl EventLoop {
             poll: Epoll::new()?,
             events: vec![epoll_event { events: 0, u64: 0 }; capacity],
             trigger_status,
-            cdev_fd,
+            backend,
         })
     }
 
@@ -130,7 +241,7 @@ impl EventLoop {
         pins: &[&'a InputPin],
         reset: bool,
         timeout: Option<Duration>,
-    ) -> Result<Option<(&'a InputPin, Level)>> {
+    ) -> Result<Option<(&'a InputPin, InterruptEvent)>> {
         for pin in pins {
             let trigger_status = &mut self.trigger_status[pin.pin() as usize];
 
@@ -139,7 +250,7 @@ impl EventLoop {
                 trigger_status.triggered = false;
 
                 if !reset {
-                    return Ok(Some((pin, trigger_status.level)));
+                    return Ok(Some((pin, trigger_status.event)));
                 }
             }
 
@@ -160,12 +271,16 @@ impl EventLoop {
         // Loop until we get any of the events we're waiting for, or a timeout occurs
         let now = Instant::now();
         loop {
-            let num_events = self.poll.wait(&mut self.events, timeout)?;
+            let remaining = timeout.map(|t| t.checked_sub(now.elapsed()).unwrap_or_default());
+            let settle_at = self
+                .trigger_status
+                .iter()
+                .filter_map(|status| status.interrupt.as_ref().and_then(Interrupt::settle_at))
+                .min();
 
-            // No events means a timeout occurred
-            if num_events == 0 {
-                return Ok(None);
-            }
+            let num_events = self
+                .poll
+                .wait(&mut self.events, wait_timeout(remaining, settle_at))?;
 
             for event in &self.events[0..num_events] {
                 let pin = event.u64 as usize;
@@ -178,16 +293,22 @@ impl EventLoop {
                 );
 
                 if let Some(ref mut interrupt) = trigger_status.interrupt {
-                    trigger_status.level = match interrupt.event()?.trigger {
-                        Trigger::RisingEdge => Level::High,
-                        Trigger::FallingEdge => Level::Low,
-                        _ => unsafe { std::hint::unreachable_unchecked() },
-                    };
-
-                    trigger_status.triggered = true;
+                    if let Some(event) = interrupt.event()? {
+                        trigger_status.event = event;
+                        trigger_status.triggered = true;
+                    }
                 };
             }
 
+            for trigger_status in &mut self.trigger_status {
+                if let Some(ref mut interrupt) = trigger_status.interrupt {
+                    if let Some(event) = interrupt.settle() {
+                        trigger_status.event = event;
+                        trigger_status.triggered = true;
+                    }
+                }
+            }
+
             // Were any interrupts triggered? If so, return one. The rest
             // will be saved for the next poll.
             for pin in pins {
@@ -195,42 +316,49 @@ impl EventLoop {
 
                 if trigger_status.triggered {
                     trigger_status.triggered = false;
-                    return Ok(Some((pin, trigger_status.level)));
+                    return Ok(Some((pin, trigger_status.event)));
                 }
             }
 
             // It's possible a pin we're not waiting for continuously triggers
-            // an interrupt, causing repeated loops with calls to poll() using a
-            // reset timeout value. Make sure we haven't been looping longer than
-            // the requested timeout.
+            // an interrupt, or settles its debouncer, causing repeated loops with
+            // calls to poll() using a reset timeout value. Make sure we haven't been
+            // looping longer than the requested timeout.
             if let Some(t) = timeout {
-                if now.elapsed() > t {
+                if now.elapsed() >= t {
                     return Ok(None);
                 }
             }
         }
     }
 
-    pub fn set_interrupt(&mut self, pin: u8, trigger: Trigger) -> Result<()> {
+    pub fn set_interrupt(
+        &mut self,
+        pin: u8,
+        config: ioctl::EventConfig,
+        debounce: Option<Duration>,
+    ) -> Result<()> {
         let trigger_status = &mut self.trigger_status[pin as usize];
 
         trigger_status.triggered = false;
 
         // Interrupt already exists. We just need to change the trigger.
         if let Some(ref mut interrupt) = trigger_status.interrupt {
-            if interrupt.trigger != trigger {
+            if interrupt.config != config {
                 // This requires a new event request, so the fd might change
                 self.poll.delete(interrupt.fd())?;
-                interrupt.set_trigger(trigger)?;
+                interrupt.set_config(config)?;
                 self.poll
                     .add(interrupt.fd(), u64::from(pin), EPOLLIN | EPOLLPRI)?;
             }
 
+            interrupt.set_debounce(debounce);
+
             return Ok(());
         }
 
         // Register a new interrupt
-        let interrupt = Interrupt::new(self.cdev_fd, pin, trigger)?;
+        let interrupt = Interrupt::new(self.backend.clone(), pin, config, debounce)?;
         self.poll
             .add(interrupt.fd(), u64::from(pin), EPOLLIN | EPOLLPRI)?;
         trigger_status.interrupt = Some(interrupt);
@@ -251,6 +379,20 @@ impl EventLoop {
     }
 }
 
+// Returns the epoll timeout that ends at the earliest of timeout and settle_at. epoll
+// only supports whole milliseconds, so the result is rounded up to avoid spinning.
+fn wait_timeout(timeout: Option<Duration>, settle_at: Option<Instant>) -> Option<Duration> {
+    let settle_timeout =
+        settle_at.map(|settle_at| settle_at.saturating_duration_since(Instant::now()));
+
+    let timeout = match (timeout, settle_timeout) {
+        (Some(timeout), Some(settle_timeout)) => Some(timeout.min(settle_timeout)),
+        (timeout, settle_timeout) => timeout.or(settle_timeout),
+    };
+
+    timeout.map(|timeout| Duration::from_millis((timeout.as_micros() as u64 + 999) / 1000))
+}
+
 #[derive(Debug)]
 pub struct AsyncInterrupt {
     poll_thread: Option<thread::JoinHandle<Result<()>>>,
@@ -258,40 +400,79 @@ pub struct AsyncInterrupt {
 }
 
 impl AsyncInterrupt {
-    pub fn new<C>(fd: i32, pin: u8, trigger: Trigger, mut callback: C) -> Result<AsyncInterrupt>
+    pub fn new<C>(
+        backend: Arc<dyn Backend>,
+        pin: u8,
+        config: ioctl::EventConfig,
+        debounce: Option<Duration>,
+        mut callback: C,
+    ) -> Result<AsyncInterrupt>
+    where
+        C: FnMut(InterruptEvent) + Send + 'static,
+    {
+        AsyncInterrupt::with_pins(backend, &[(pin, config, debounce)], move |_, event| {
+            callback(event)
+        })
+    }
+
+    // Polls multiple pins on a single thread, so the callback sees their events in order
+    pub fn with_pins<C>(
+        backend: Arc<dyn Backend>,
+        pins: &[(u8, ioctl::EventConfig, Option<Duration>)],
+        mut callback: C,
+    ) -> Result<AsyncInterrupt>
     where
-        C: FnMut(Level) + Send + 'static,
+        C: FnMut(u8, InterruptEvent) + Send + 'static,
     {
         let tx = EventFd::new()?;
         let rx = tx.fd();
 
+        // Request the interrupts before spawning the poll thread, so any errors are returned
+        // here, and no trigger events are missed once with_pins() returns
+        let mut interrupts = pins
+            .iter()
+            .map(|&(pin, config, debounce)| Interrupt::new(backend.clone(), pin, config, debounce))
+            .collect::<Result<Vec<_>>>()?;
+
         let poll_thread = thread::spawn(move || -> Result<()> {
             let poll = Epoll::new()?;
 
             // rx becomes readable when the main thread calls notify()
             poll.add(rx, rx as u64, EPOLLERR | EPOLLET | EPOLLIN)?;
 
-            let mut interrupt = Interrupt::new(fd, pin, trigger)?;
-            poll.add(interrupt.fd(), interrupt.fd() as u64, EPOLLIN | EPOLLPRI)?;
+            for interrupt in &interrupts {
+                poll.add(interrupt.fd(), interrupt.fd() as u64, EPOLLIN | EPOLLPRI)?;
+            }
 
-            let mut events = [epoll_event { events: 0, u64: 0 }; 2];
+            let mut events = vec![epoll_event { events: 0, u64: 0 }; interrupts.len() + 1];
+            let mut triggered = Vec::with_capacity(interrupts.len());
             loop {
-                let num_events = poll.wait(&mut events, None)?;
-                if num_events > 0 {
-                    for event in &events[0..num_events] {
-                        let fd = event.u64 as i32;
-                        if fd == rx {
-                            return Ok(()); // The main thread asked us to stop
-                        } else if fd == interrupt.fd() {
-                            let level = match interrupt.event()?.trigger {
-                                Trigger::RisingEdge => Level::High,
-                                _ => Level::Low,
-                            };
-
-                            callback(level);
+                let settle_at = interrupts.iter().filter_map(Interrupt::settle_at).min();
+                let num_events = poll.wait(&mut events, wait_timeout(None, settle_at))?;
+                for event in &events[0..num_events] {
+                    let fd = event.u64 as i32;
+                    if fd == rx {
+                        return Ok(()); // The main thread asked us to stop
+                    }
+
+                    if let Some(interrupt) = interrupts.iter_mut().find(|i| i.fd() == fd) {
+                        if let Some(event) = interrupt.event()? {
+                            triggered.push((interrupt.pin(), event));
                         }
                     }
                 }
+
+                for interrupt in &mut interrupts {
+                    if let Some(event) = interrupt.settle() {
+                        triggered.push((interrupt.pin(), event));
+                    }
+                }
+
+                // epoll doesn't report ready fds in the order their events occurred
+                triggered.sort_by_key(|&(_, event)| event.timestamp);
+                for (pin, event) in triggered.drain(..) {
+                    callback(pin, event);
+                }
             }
         });
 
@@ -325,3 +506,98 @@ impl Drop for AsyncInterrupt {
         }
     }
 }
+
+/// A [`Stream`] of interrupt trigger events for an [`InputPin`].
+///
+/// `InterruptStream` is returned by [`InputPin::interrupt_stream`], and requires
+/// the optional `async` feature. Trigger events are read from the event fd once the
+/// tokio reactor reports it as readable, so no additional threads are used. Level
+/// changes within a debounce window are reported once the window ends, which relies
+/// on tokio's timer.
+///
+/// The `InputPin` stays mutably borrowed while the stream exists. Any (a)synchronous
+/// interrupt triggers configured for the pin are cleared when the stream is created.
+///
+/// [`Stream`]: https://docs.rs/futures-core/0.3/futures_core/stream/trait.Stream.html
+/// [`InputPin`]: struct.InputPin.html
+/// [`InputPin::interrupt_stream`]: struct.InputPin.html#method.interrupt_stream
+#[cfg(feature = "async")]
+#[derive(Debug)]
+pub struct InterruptStream<'a> {
+    interrupt: tokio::io::unix::AsyncFd<Interrupt>,
+    settle_timer: Option<Pin<Box<tokio::time::Sleep>>>,
+    phantom: PhantomData<&'a mut InputPin>,
+}
+
+#[cfg(feature = "async")]
+impl<'a> InterruptStream<'a> {
+    pub(crate) fn new(
+        backend: Arc<dyn Backend>,
+        pin: u8,
+        config: ioctl::EventConfig,
+        debounce: Option<Duration>,
+    ) -> Result<InterruptStream<'a>> {
+        let interrupt = Interrupt::new(backend, pin, config, debounce)?;
+
+        // Reads should return WouldBlock instead of stalling the executor
+        let fd = interrupt.fd();
+        let flags = parse_retval!(unsafe { libc::fcntl(fd, libc::F_GETFL) })?;
+        parse_retval!(unsafe { libc::fcntl(fd, libc::F_SETFL, flags | libc::O_NONBLOCK) })?;
+
+        Ok(InterruptStream {
+            interrupt: tokio::io::unix::AsyncFd::new(interrupt)?,
+            settle_timer: None,
+            phantom: PhantomData,
+        })
+    }
+
+    /// Waits for the next interrupt trigger event.
+    pub async fn next_event(&mut self) -> Result<InterruptEvent> {
+        future::poll_fn(|cx| self.poll_event(cx)).await
+    }
+
+    fn poll_event(&mut self, cx: &mut Context<'_>) -> Poll<Result<InterruptEvent>> {
+        loop {
+            if let Some(settle_at) = self.interrupt.get_ref().settle_at() {
+                let settle_at = tokio::time::Instant::from_std(settle_at);
+                let timer = self
+                    .settle_timer
+                    .get_or_insert_with(|| Box::pin(tokio::time::sleep_until(settle_at)));
+                if timer.deadline() != settle_at {
+                    timer.as_mut().reset(settle_at);
+                }
+
+                if timer.as_mut().poll(cx).is_ready() {
+                    if let Some(event) = self.interrupt.get_mut().settle() {
+                        return Poll::Ready(Ok(event));
+                    }
+                }
+            }
+
+            let mut guard = match self.interrupt.poll_read_ready_mut(cx) {
+                Poll::Ready(Ok(guard)) => guard,
+                Poll::Ready(Err(e)) => return Poll::Ready(Err(Error::Io(e))),
+                Poll::Pending => return Poll::Pending,
+            };
+
+            match guard.get_inner_mut().event() {
+                Ok(Some(event)) => return Poll::Ready(Ok(event)),
+                // Rejected by the debouncer, so check for more events
+                Ok(None) => (),
+                Err(Error::Io(ref e)) if e.kind() == io::ErrorKind::WouldBlock => {
+                    guard.clear_ready()
+                }
+                Err(e) => return Poll::Ready(Err(e)),
+            }
+        }
+    }
+}
+
+#[cfg(feature = "async")]
+impl<'a> futures_core::Stream for InterruptStream<'a> {
+    type Item = Result<InterruptEvent>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.get_mut().poll_event(cx).map(Some)
+    }
+}
@@ -29,7 +29,7 @@ use std::mem;
 use std::os::unix::io::AsRawFd;
 use std::time::Duration;
 
-use crate::gpio::{Error, Level, Result, Trigger};
+use crate::gpio::{Error, EventClock, Level, PullUpDown, Result, Trigger};
 
 #[cfg(target_env = "gnu")]
 type IoctlLong = libc::c_ulong;
@@ -56,6 +56,11 @@ const NR_GET_LINE_HANDLE: IoctlLong = 0x03 << NRSHIFT;
 const NR_GET_LINE_EVENT: IoctlLong = 0x04 << NRSHIFT;
 const NR_GET_LINE_VALUES: IoctlLong = 0x08 << NRSHIFT;
 const NR_SET_LINE_VALUES: IoctlLong = 0x09 << NRSHIFT;
+const NR_GET_LINE_INFO_V2: IoctlLong = 0x05 << NRSHIFT;
+const NR_GET_LINE_V2: IoctlLong = 0x07 << NRSHIFT;
+const NR_LINE_SET_CONFIG_V2: IoctlLong = 0x0D << NRSHIFT;
+const NR_LINE_GET_VALUES_V2: IoctlLong = 0x0E << NRSHIFT;
+const NR_LINE_SET_VALUES_V2: IoctlLong = 0x0F << NRSHIFT;
 
 const TYPE_GPIO: IoctlLong = (0xB4 as IoctlLong) << TYPESHIFT;
 
@@ -64,6 +69,10 @@ const SIZE_LINE_INFO: IoctlLong = (mem::size_of::<LineInfo>() as IoctlLong) << S
 const SIZE_HANDLE_REQUEST: IoctlLong = (mem::size_of::<HandleRequest>() as IoctlLong) << SIZESHIFT;
 const SIZE_EVENT_REQUEST: IoctlLong = (mem::size_of::<EventRequest>() as IoctlLong) << SIZESHIFT;
 const SIZE_HANDLE_DATA: IoctlLong = (mem::size_of::<HandleData>() as IoctlLong) << SIZESHIFT;
+const SIZE_LINE_INFO_V2: IoctlLong = (mem::size_of::<LineInfoV2>() as IoctlLong) << SIZESHIFT;
+const SIZE_LINE_REQUEST: IoctlLong = (mem::size_of::<LineRequest>() as IoctlLong) << SIZESHIFT;
+const SIZE_LINE_CONFIG: IoctlLong = (mem::size_of::<LineConfig>() as IoctlLong) << SIZESHIFT;
+const SIZE_LINE_VALUES: IoctlLong = (mem::size_of::<LineValues>() as IoctlLong) << SIZESHIFT;
 
 const DIR_NONE: c_ulong = 0;
 const DIR_WRITE: IoctlLong = 1 << DIRSHIFT;
@@ -80,6 +89,15 @@ const REQ_GET_LINE_VALUES: IoctlLong =
     DIR_READ_WRITE | TYPE_GPIO | NR_GET_LINE_VALUES | SIZE_HANDLE_DATA;
 const REQ_SET_LINE_VALUES: IoctlLong =
     DIR_READ_WRITE | TYPE_GPIO | NR_SET_LINE_VALUES | SIZE_HANDLE_DATA;
+const REQ_GET_LINE_INFO_V2: IoctlLong =
+    DIR_READ_WRITE | TYPE_GPIO | NR_GET_LINE_INFO_V2 | SIZE_LINE_INFO_V2;
+const REQ_GET_LINE_V2: IoctlLong = DIR_READ_WRITE | TYPE_GPIO | NR_GET_LINE_V2 | SIZE_LINE_REQUEST;
+const REQ_LINE_SET_CONFIG_V2: IoctlLong =
+    DIR_READ_WRITE | TYPE_GPIO | NR_LINE_SET_CONFIG_V2 | SIZE_LINE_CONFIG;
+const REQ_LINE_GET_VALUES_V2: IoctlLong =
+    DIR_READ_WRITE | TYPE_GPIO | NR_LINE_GET_VALUES_V2 | SIZE_LINE_VALUES;
+const REQ_LINE_SET_VALUES_V2: IoctlLong =
+    DIR_READ_WRITE | TYPE_GPIO | NR_LINE_SET_VALUES_V2 | SIZE_LINE_VALUES;
 
 const NAME_BUFSIZE: usize = 32;
 const LABEL_BUFSIZE: usize = 32;
@@ -140,6 +158,31 @@ impl LineInfo {
             consumer: [0u8; LABEL_BUFSIZE],
         }
     }
+
+    pub fn get(cdev_fd: c_int, pin: u8) -> Result<LineInfo> {
+        let mut line_info = LineInfo {
+            line_offset: u32::from(pin),
+            ..LineInfo::new()
+        };
+
+        parse_retval!(unsafe { libc::ioctl(cdev_fd, REQ_GET_LINE_INFO, &mut line_info) })?;
+
+        Ok(line_info)
+    }
+
+    // Returns the consumer label, or None if the line isn't in use
+    pub fn consumer(&self) -> Option<String> {
+        if self.flags & LINE_FLAG_KERNEL == 0 {
+            return None;
+        }
+
+        let consumer = cbuf_to_cstring(&self.consumer).into_string().ok()?;
+        if consumer.is_empty() {
+            None
+        } else {
+            Some(consumer)
+        }
+    }
 }
 
 impl fmt::Debug for LineInfo {
@@ -327,6 +370,22 @@ impl EventRequest {
         }
     }
 
+    // Wraps an fd that produces EventData records without going through the gpiochip device
+    pub(crate) fn from_fd(pin: u8, trigger: Trigger, fd: c_int) -> EventRequest {
+        let mut event_request = EventRequest {
+            line_offset: u32::from(pin),
+            handle_flags: HANDLE_FLAG_INPUT,
+            event_flags: trigger as u32,
+            consumer_label: [0u8; LABEL_BUFSIZE],
+            fd,
+        };
+
+        event_request.consumer_label[0..CONSUMER_LABEL.len()]
+            .copy_from_slice(CONSUMER_LABEL.as_bytes());
+
+        event_request
+    }
+
     pub fn close(&mut self) {
         if self.fd > 0 {
             unsafe {
@@ -361,12 +420,22 @@ const EVENT_TYPE_FALLING_EDGE: u32 = 0x02;
 
 #[derive(Debug, Copy, Clone, Default)]
 #[repr(C)]
-struct EventData {
+pub(crate) struct EventData {
     timestamp: u64,
     id: u32,
 }
 
 impl EventData {
+    pub(crate) fn with_trigger(trigger: Trigger, timestamp: u64) -> EventData {
+        EventData {
+            timestamp,
+            id: match trigger {
+                Trigger::FallingEdge => EVENT_TYPE_FALLING_EDGE,
+                _ => EVENT_TYPE_RISING_EDGE,
+            },
+        }
+    }
+
     fn new(event_fd: c_int) -> Result<EventData> {
         let mut event_data = EventData {
             timestamp: 0,
@@ -397,6 +466,8 @@ impl EventData {
 pub struct Event {
     pub trigger: Trigger,
     pub timestamp: Duration,
+    // Only provided by the v2 uAPI
+    pub seqno: Option<u32>,
 }
 
 impl Event {
@@ -408,6 +479,7 @@ impl Event {
                 _ => unreachable!(),
             },
             timestamp: Duration::from_nanos(event_data.timestamp),
+            seqno: None,
         }
     }
 }
@@ -418,6 +490,421 @@ pub fn get_event(event_fd: c_int) -> Result<Event> {
     Ok(Event::from_event_data(event_data))
 }
 
+// GPIO character device uAPI v2 (Linux 5.10 or later)
+
+const LINES_MAX: usize = 64;
+const LINE_NUM_ATTRS_MAX: usize = 10;
+
+pub const LINE_FLAG_V2_USED: u64 = 1 << 0;
+pub const LINE_FLAG_V2_ACTIVE_LOW: u64 = 1 << 1;
+pub const LINE_FLAG_V2_INPUT: u64 = 1 << 2;
+pub const LINE_FLAG_V2_OUTPUT: u64 = 1 << 3;
+pub const LINE_FLAG_V2_EDGE_RISING: u64 = 1 << 4;
+pub const LINE_FLAG_V2_EDGE_FALLING: u64 = 1 << 5;
+pub const LINE_FLAG_V2_OPEN_DRAIN: u64 = 1 << 6;
+pub const LINE_FLAG_V2_OPEN_SOURCE: u64 = 1 << 7;
+pub const LINE_FLAG_V2_BIAS_PULL_UP: u64 = 1 << 8;
+pub const LINE_FLAG_V2_BIAS_PULL_DOWN: u64 = 1 << 9;
+pub const LINE_FLAG_V2_BIAS_DISABLED: u64 = 1 << 10;
+pub const LINE_FLAG_V2_EVENT_CLOCK_REALTIME: u64 = 1 << 11;
+
+const LINE_ATTR_ID_FLAGS: u32 = 1;
+const LINE_ATTR_ID_OUTPUT_VALUES: u32 = 2;
+const LINE_ATTR_ID_DEBOUNCE: u32 = 3;
+
+const LINE_EVENT_RISING_EDGE: u32 = 1;
+const LINE_EVENT_FALLING_EDGE: u32 = 2;
+
+// Sets the edge, clock and bias flags for an interrupt line
+pub fn event_flags_v2(trigger: Trigger, clock: EventClock, pud: Option<PullUpDown>) -> u64 {
+    let mut flags = LINE_FLAG_V2_INPUT | bias_flags_v2(pud);
+
+    flags |= match trigger {
+        Trigger::Disabled => 0,
+        Trigger::RisingEdge => LINE_FLAG_V2_EDGE_RISING,
+        Trigger::FallingEdge => LINE_FLAG_V2_EDGE_FALLING,
+        Trigger::Both => LINE_FLAG_V2_EDGE_RISING | LINE_FLAG_V2_EDGE_FALLING,
+    };
+
+    if clock == EventClock::Realtime {
+        flags |= LINE_FLAG_V2_EVENT_CLOCK_REALTIME;
+    }
+
+    flags
+}
+
+// Bias flags are only valid in combination with an input or output flag
+pub fn bias_flags_v2(pud: Option<PullUpDown>) -> u64 {
+    match pud {
+        None => 0,
+        Some(PullUpDown::Off) => LINE_FLAG_V2_BIAS_DISABLED,
+        Some(PullUpDown::PullDown) => LINE_FLAG_V2_BIAS_PULL_DOWN,
+        Some(PullUpDown::PullUp) => LINE_FLAG_V2_BIAS_PULL_UP,
+    }
+}
+
+// Interrupt settings passed along when requesting an event fd
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub struct EventConfig {
+    pub trigger: Trigger,
+    pub debounce: Option<Duration>,
+    pub clock: EventClock,
+}
+
+impl EventConfig {
+    pub fn new(trigger: Trigger) -> EventConfig {
+        EventConfig {
+            trigger,
+            debounce: None,
+            clock: EventClock::Monotonic,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, Default)]
+#[repr(C)]
+pub struct LineValues {
+    pub bits: u64,
+    pub mask: u64,
+}
+
+// The kernel defines the value as a union of flags (u64), values (u64) and
+// debounce_period_us (u32). On little-endian targets, storing the debounce
+// period in the lower 32 bits of a u64 produces the same layout.
+#[derive(Debug, Copy, Clone, Default)]
+#[repr(C)]
+pub struct LineAttribute {
+    pub id: u32,
+    pub padding: u32,
+    pub value: u64,
+}
+
+#[derive(Debug, Copy, Clone, Default)]
+#[repr(C)]
+pub struct LineConfigAttribute {
+    pub attr: LineAttribute,
+    pub mask: u64,
+}
+
+#[derive(Debug, Copy, Clone, Default)]
+#[repr(C)]
+pub struct LineConfig {
+    pub flags: u64,
+    pub num_attrs: u32,
+    pub padding: [u32; 5],
+    pub attrs: [LineConfigAttribute; LINE_NUM_ATTRS_MAX],
+}
+
+impl LineConfig {
+    pub fn new(flags: u64) -> LineConfig {
+        LineConfig {
+            flags,
+            ..Default::default()
+        }
+    }
+
+    fn add_attr(&mut self, id: u32, value: u64, mask: u64) {
+        if (self.num_attrs as usize) < LINE_NUM_ATTRS_MAX {
+            self.attrs[self.num_attrs as usize] = LineConfigAttribute {
+                attr: LineAttribute {
+                    id,
+                    padding: 0,
+                    value,
+                },
+                mask,
+            };
+            self.num_attrs += 1;
+        }
+    }
+
+    // Configures a single input line for edge detection
+    pub fn with_events(config: &EventConfig, pud: Option<PullUpDown>) -> LineConfig {
+        let mut line_config = LineConfig::new(event_flags_v2(config.trigger, config.clock, pud));
+        if let Some(debounce) = config.debounce {
+            line_config.set_debounce(debounce, 1);
+        }
+
+        line_config
+    }
+
+    // Sets the initial output levels for the lines selected by mask
+    pub fn set_output_values(&mut self, bits: u64, mask: u64) {
+        self.add_attr(LINE_ATTR_ID_OUTPUT_VALUES, bits, mask);
+    }
+
+    pub fn set_debounce(&mut self, period: Duration, mask: u64) {
+        let period_us = period.as_micros().min(u128::from(u32::MAX)) as u64;
+        self.add_attr(LINE_ATTR_ID_DEBOUNCE, period_us, mask);
+    }
+}
+
+#[repr(C)]
+pub struct LineRequest {
+    pub offsets: [u32; LINES_MAX],
+    pub consumer: [u8; LABEL_BUFSIZE],
+    pub config: LineConfig,
+    pub num_lines: u32,
+    pub event_buffer_size: u32,
+    pub padding: [u32; 5],
+    pub fd: c_int,
+}
+
+impl LineRequest {
+    pub fn new(cdev_fd: c_int, pins: &[u8], config: LineConfig) -> Result<LineRequest> {
+        let mut line_request = LineRequest {
+            offsets: [0u32; LINES_MAX],
+            consumer: [0u8; LABEL_BUFSIZE],
+            config,
+            num_lines: 0,
+            event_buffer_size: 0,
+            padding: [0u32; 5],
+            fd: 0,
+        };
+
+        let pins: &[u8] = if pins.len() > LINES_MAX {
+            &pins[0..LINES_MAX]
+        } else {
+            pins
+        };
+
+        line_request.num_lines = pins.len() as u32;
+        for (idx, pin) in pins.iter().enumerate() {
+            line_request.offsets[idx] = u32::from(*pin);
+        }
+
+        // Set consumer label, so other processes know we're using these pins
+        line_request.consumer[0..CONSUMER_LABEL.len()].copy_from_slice(CONSUMER_LABEL.as_bytes());
+
+        parse_retval!(unsafe { libc::ioctl(cdev_fd, REQ_GET_LINE_V2, &mut line_request) })?;
+
+        // If the line fd is zero or negative, an error occurred
+        if line_request.fd <= 0 {
+            Err(Error::Io(std::io::Error::last_os_error()))
+        } else {
+            Ok(line_request)
+        }
+    }
+
+    // Requests a single line configured for edge detection
+    pub fn with_events(
+        cdev_fd: c_int,
+        pin: u8,
+        config: &EventConfig,
+        pud: Option<PullUpDown>,
+    ) -> Result<LineRequest> {
+        LineRequest::new(cdev_fd, &[pin], LineConfig::with_events(config, pud))
+    }
+
+    // Duplicates the fd, which keeps the lines requested until both copies are closed
+    pub fn try_clone(&self) -> Result<LineRequest> {
+        let fd = parse_retval!(unsafe { libc::fcntl(self.fd, libc::F_DUPFD_CLOEXEC, 0) })?;
+
+        Ok(LineRequest {
+            offsets: self.offsets,
+            consumer: self.consumer,
+            config: self.config,
+            num_lines: self.num_lines,
+            event_buffer_size: self.event_buffer_size,
+            padding: [0u32; 5],
+            fd,
+        })
+    }
+
+    pub fn reconfigure(&mut self, config: LineConfig) -> Result<()> {
+        let mut line_config = config;
+
+        parse_retval!(unsafe { libc::ioctl(self.fd, REQ_LINE_SET_CONFIG_V2, &mut line_config) })?;
+
+        self.config = config;
+
+        Ok(())
+    }
+
+    pub fn levels(&self, mask: u64) -> Result<u64> {
+        let mut line_values = LineValues { bits: 0, mask };
+
+        parse_retval!(unsafe { libc::ioctl(self.fd, REQ_LINE_GET_VALUES_V2, &mut line_values) })?;
+
+        Ok(line_values.bits)
+    }
+
+    pub fn set_levels(&self, bits: u64, mask: u64) -> Result<()> {
+        let mut line_values = LineValues { bits, mask };
+
+        parse_retval!(unsafe { libc::ioctl(self.fd, REQ_LINE_SET_VALUES_V2, &mut line_values) })?;
+
+        Ok(())
+    }
+
+    pub fn close(&mut self) {
+        if self.fd > 0 {
+            unsafe {
+                libc::close(self.fd);
+            }
+
+            self.fd = 0;
+        }
+    }
+}
+
+impl Drop for LineRequest {
+    fn drop(&mut self) {
+        self.close();
+    }
+}
+
+impl fmt::Debug for LineRequest {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("LineRequest")
+            .field(
+                "offsets",
+                &format_args!("{:?}", &self.offsets[..self.num_lines as usize]),
+            )
+            .field("consumer", &cbuf_to_cstring(&self.consumer))
+            .field("config", &self.config)
+            .field("num_lines", &self.num_lines)
+            .field("fd", &self.fd)
+            .finish()
+    }
+}
+
+#[derive(Copy, Clone)]
+#[repr(C)]
+pub struct LineInfoV2 {
+    pub name: [u8; NAME_BUFSIZE],
+    pub consumer: [u8; LABEL_BUFSIZE],
+    pub offset: u32,
+    pub num_attrs: u32,
+    pub flags: u64,
+    pub attrs: [LineAttribute; LINE_NUM_ATTRS_MAX],
+    pub padding: [u32; 4],
+}
+
+impl LineInfoV2 {
+    pub fn new(cdev_fd: c_int, pin: u8) -> Result<LineInfoV2> {
+        let mut line_info = LineInfoV2 {
+            name: [0u8; NAME_BUFSIZE],
+            consumer: [0u8; LABEL_BUFSIZE],
+            offset: u32::from(pin),
+            num_attrs: 0,
+            flags: 0,
+            attrs: [LineAttribute::default(); LINE_NUM_ATTRS_MAX],
+            padding: [0u32; 4],
+        };
+
+        parse_retval!(unsafe { libc::ioctl(cdev_fd, REQ_GET_LINE_INFO_V2, &mut line_info) })?;
+
+        Ok(line_info)
+    }
+}
+
+impl fmt::Debug for LineInfoV2 {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("LineInfoV2")
+            .field("name", &cbuf_to_cstring(&self.name))
+            .field("consumer", &cbuf_to_cstring(&self.consumer))
+            .field("offset", &self.offset)
+            .field("flags", &self.flags)
+            .finish()
+    }
+}
+
+// Returns true if the kernel supports the v2 uAPI
+pub fn supports_v2(cdev_fd: c_int) -> bool {
+    LineInfoV2::new(cdev_fd, 0).is_ok()
+}
+
+#[derive(Debug, Copy, Clone, Default)]
+#[repr(C)]
+struct LineEvent {
+    timestamp_ns: u64,
+    id: u32,
+    offset: u32,
+    seqno: u32,
+    line_seqno: u32,
+    padding: [u32; 6],
+}
+
+impl LineEvent {
+    fn new(line_fd: c_int) -> Result<LineEvent> {
+        let mut line_event = LineEvent::default();
+
+        let bytes_read = parse_retval!(unsafe {
+            libc::read(
+                line_fd,
+                &mut line_event as *mut LineEvent as *mut c_void,
+                mem::size_of::<LineEvent>(),
+            )
+        })?;
+
+        if bytes_read < mem::size_of::<LineEvent>() as isize {
+            Err(std::io::Error::new(
+                std::io::ErrorKind::UnexpectedEof,
+                "failed to fill whole buffer",
+            )
+            .into())
+        } else {
+            Ok(line_event)
+        }
+    }
+}
+
+impl Event {
+    fn from_line_event(line_event: LineEvent) -> Event {
+        Event {
+            trigger: match line_event.id {
+                LINE_EVENT_RISING_EDGE => Trigger::RisingEdge,
+                LINE_EVENT_FALLING_EDGE => Trigger::FallingEdge,
+                _ => unreachable!(),
+            },
+            timestamp: Duration::from_nanos(line_event.timestamp_ns),
+            seqno: Some(line_event.line_seqno),
+        }
+    }
+}
+
+// Layout checks against the kernel's gpio_v2_* structs in include/uapi/linux/gpio.h
+const _: () = assert!(mem::size_of::<LineValues>() == 16);
+const _: () = assert!(mem::size_of::<LineAttribute>() == 16);
+const _: () = assert!(mem::size_of::<LineConfigAttribute>() == 24);
+const _: () = assert!(mem::size_of::<LineConfig>() == 272);
+const _: () = assert!(mem::size_of::<LineRequest>() == 592);
+const _: () = assert!(mem::size_of::<LineInfoV2>() == 256);
+const _: () = assert!(mem::size_of::<LineEvent>() == 48);
+
+// Event fd requested through either the v1 or the v2 uAPI
+#[derive(Debug)]
+pub enum EventHandle {
+    V1(EventRequest),
+    V2(Box<LineRequest>),
+}
+
+impl EventHandle {
+    pub fn fd(&self) -> c_int {
+        match self {
+            EventHandle::V1(event_request) => event_request.fd,
+            EventHandle::V2(line_request) => line_request.fd,
+        }
+    }
+
+    // Read interrupt event. This might block if there are no events waiting.
+    pub fn event(&self) -> Result<Event> {
+        match self {
+            EventHandle::V1(event_request) => get_event(event_request.fd),
+            EventHandle::V2(line_request) => {
+                Ok(Event::from_line_event(LineEvent::new(line_request.fd)?))
+            }
+        }
+    }
+
+    pub fn close(&mut self) {
+        match self {
+            EventHandle::V1(event_request) => event_request.close(),
+            EventHandle::V2(line_request) => line_request.close(),
+        }
+    }
+}
+
 // Find the correct gpiochip device based on its label
 pub fn find_gpiochip() -> Result<File> {
     for id in 0..=255 {
@@ -0,0 +1,642 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::gpio::{InputPin, Level, OutputPin};
+
+const DEFAULT_SCAN_INTERVAL: Duration = Duration::from_millis(5);
+const DEFAULT_SETTLE_TIME: Duration = Duration::from_micros(10);
+const DEFAULT_DEBOUNCE: Duration = Duration::from_millis(20);
+const DEFAULT_LONG_PRESS: Duration = Duration::from_secs(1);
+
+/// Key event sent by a [`Keypad`].
+///
+/// Keys are identified by the index of their row and column pin, in the order
+/// the pins were passed to [`Keypad::new`].
+///
+/// [`Keypad`]: struct.Keypad.html
+/// [`Keypad::new`]: struct.Keypad.html#method.new
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub enum KeypadEvent {
+    /// The key was pressed.
+    Pressed { row: usize, column: usize },
+    /// The key was released.
+    Released { row: usize, column: usize },
+    /// The key has been held down for the long-press duration.
+    LongPress { row: usize, column: usize },
+    /// The key is still held down after the repeat delay or the previous repeat.
+    Repeat { row: usize, column: usize },
+}
+
+#[derive(Debug, Copy, Clone)]
+struct Settings {
+    scan_interval: Duration,
+    settle_time: Duration,
+    debounce: Duration,
+    long_press: Option<Duration>,
+    repeat: Option<(Duration, Duration)>,
+}
+
+#[derive(Debug, Copy, Clone)]
+struct Key {
+    // Most recent reading, and when it last changed
+    raw: bool,
+    raw_since: Instant,
+    // Debounced state
+    pressed: bool,
+    pressed_at: Instant,
+    long_pressed: bool,
+    next_repeat: Option<Instant>,
+}
+
+#[derive(Debug)]
+struct State {
+    settings: Settings,
+    columns: usize,
+    keys: Vec<Key>,
+    ghosting: bool,
+    subscribers: Vec<Sender<KeypadEvent>>,
+}
+
+impl State {
+    fn notify(&mut self, event: KeypadEvent) {
+        // Drop subscribers whose receiver has gone out of scope
+        self.subscribers
+            .retain(|subscriber| subscriber.send(event).is_ok());
+    }
+
+    // Processes a single scan. scan[row] has bit n set when the key in column n
+    // reads as pressed.
+    fn update(&mut self, scan: &[u64], now: Instant) {
+        // Without diodes, pressing three corners of a rectangle makes the fourth
+        // corner read as pressed as well. Keys in such a rectangle keep their
+        // previous reading until the ambiguity is resolved.
+        let mut ambiguous = vec![0u64; scan.len()];
+        for first in 0..scan.len() {
+            for second in first + 1..scan.len() {
+                let shared = scan[first] & scan[second];
+                if shared.count_ones() >= 2 {
+                    ambiguous[first] |= shared;
+                    ambiguous[second] |= shared;
+                }
+            }
+        }
+
+        self.ghosting = ambiguous.iter().any(|&mask| mask != 0);
+
+        let settings = self.settings;
+        for index in 0..self.keys.len() {
+            let (row, column) = (index / self.columns, index % self.columns);
+            let mut key = self.keys[index];
+
+            if ambiguous[row] & (1 << column) == 0 {
+                let raw = scan[row] & (1 << column) != 0;
+                if raw != key.raw {
+                    key.raw = raw;
+                    key.raw_since = now;
+                }
+            }
+
+            let mut events = Vec::new();
+            if key.raw != key.pressed && now.duration_since(key.raw_since) >= settings.debounce {
+                key.pressed = key.raw;
+
+                if key.pressed {
+                    key.pressed_at = now;
+                    key.long_pressed = false;
+                    key.next_repeat = settings.repeat.map(|(delay, _)| now + delay);
+                    events.push(KeypadEvent::Pressed { row, column });
+                } else {
+                    key.next_repeat = None;
+                    events.push(KeypadEvent::Released { row, column });
+                }
+            }
+
+            if key.pressed {
+                if let Some(long_press) = settings.long_press {
+                    if !key.long_pressed && now.duration_since(key.pressed_at) >= long_press {
+                        key.long_pressed = true;
+                        events.push(KeypadEvent::LongPress { row, column });
+                    }
+                }
+
+                if let (Some(next_repeat), Some((_, interval))) = (key.next_repeat, settings.repeat)
+                {
+                    if now >= next_repeat {
+                        key.next_repeat = Some(next_repeat + interval);
+                        events.push(KeypadEvent::Repeat { row, column });
+                    }
+                }
+            }
+
+            self.keys[index] = key;
+            for event in events {
+                self.notify(event);
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Shared {
+    state: Mutex<State>,
+    exit: AtomicBool,
+}
+
+/// Matrix keypad and button array scanner.
+///
+/// A `Keypad` owns the row pins, configured as outputs, and the column pins, configured
+/// as inputs with their pull-up resistors enabled, for instance through
+/// [`Pin::into_input_pullup`] or [`Gpio::pullup`]. A separate thread scans the matrix by
+/// driving one row low at a time, and reading which columns are pulled low through a
+/// pressed key. Inactive rows are driven high.
+///
+/// Every key is debounced individually, so any number of keys can be held down at the
+/// same time (n-key rollover). On a keypad without diodes, pressing three keys that
+/// form the corners of a rectangle causes the fourth corner to read as pressed as well.
+/// `Keypad` detects these ghost keys, and ignores any changes to the affected keys
+/// until the ambiguity is resolved. [`is_ghosting`] reports when this happens.
+///
+/// Key presses, releases, long presses and repeats are sent as [`KeypadEvent`]s to every
+/// channel returned by [`subscribe`].
+///
+/// Pressing two keys in the same column connects a row that's driven low to a row that's
+/// driven high. Add a diode or a series resistor to each row to limit the current.
+///
+/// ## Example
+///
+/// ```no_run
+/// use rpi_embedded::gpio::{Gpio, Keypad, KeypadEvent};
+///
+/// # fn main() -> rpi_embedded::gpio::Result<()> {
+/// const KEYS: [[char; 3]; 4] = [
+///     ['1', '2', '3'],
+///     ['4', '5', '6'],
+///     ['7', '8', '9'],
+///     ['*', '0', '#'],
+/// ];
+///
+/// let gpio = Gpio::new()?;
+/// let rows = vec![
+///     gpio.get(5)?.into_output(),
+///     gpio.get(6)?.into_output(),
+///     gpio.get(13)?.into_output(),
+///     gpio.get(19)?.into_output(),
+/// ];
+/// let columns = vec![
+///     gpio.get(12)?.into_input_pullup(),
+///     gpio.get(16)?.into_input_pullup(),
+///     gpio.get(20)?.into_input_pullup(),
+/// ];
+///
+/// let keypad = Keypad::new(rows, columns);
+/// for event in keypad.subscribe() {
+///     if let KeypadEvent::Pressed { row, column } = event {
+///         println!("Pressed {}", KEYS[row][column]);
+///     }
+/// }
+/// # Ok(())
+/// # }
+/// ```
+///
+/// [`Pin::into_input_pullup`]: struct.Pin.html#method.into_input_pullup
+/// [`Gpio::pullup`]: struct.Gpio.html#method.pullup
+/// [`is_ghosting`]: #method.is_ghosting
+/// [`KeypadEvent`]: enum.KeypadEvent.html
+/// [`subscribe`]: #method.subscribe
+#[derive(Debug)]
+pub struct Keypad {
+    rows: usize,
+    columns: usize,
+    shared: Arc<Shared>,
+    thread: Option<thread::JoinHandle<(Vec<OutputPin>, Vec<InputPin>)>>,
+}
+
+impl Keypad {
+    /// Constructs a new `Keypad`, and starts scanning.
+    ///
+    /// Keys are identified by the index of their pin in `rows` and `columns`.
+    pub fn new(mut rows: Vec<OutputPin>, columns: Vec<InputPin>) -> Keypad {
+        for row in &mut rows {
+            row.set_high();
+        }
+
+        let now = Instant::now();
+        let key = Key {
+            raw: false,
+            raw_since: now,
+            pressed: false,
+            pressed_at: now,
+            long_pressed: false,
+            next_repeat: None,
+        };
+
+        let shared = Arc::new(Shared {
+            state: Mutex::new(State {
+                settings: Settings {
+                    scan_interval: DEFAULT_SCAN_INTERVAL,
+                    settle_time: DEFAULT_SETTLE_TIME,
+                    debounce: DEFAULT_DEBOUNCE,
+                    long_press: Some(DEFAULT_LONG_PRESS),
+                    repeat: None,
+                },
+                columns: columns.len(),
+                keys: vec![key; rows.len() * columns.len()],
+                ghosting: false,
+                subscribers: Vec::new(),
+            }),
+            exit: AtomicBool::new(false),
+        });
+
+        let (num_rows, num_columns) = (rows.len(), columns.len());
+        let thread_shared = shared.clone();
+        let thread = thread::spawn(move || scan(rows, columns, &thread_shared));
+
+        Keypad {
+            rows: num_rows,
+            columns: num_columns,
+            shared,
+            thread: Some(thread),
+        }
+    }
+
+    /// Returns the number of rows.
+    pub fn rows(&self) -> usize {
+        self.rows
+    }
+
+    /// Returns the number of columns.
+    pub fn columns(&self) -> usize {
+        self.columns
+    }
+
+    /// Returns the time between the start of two consecutive scans.
+    pub fn scan_interval(&self) -> Duration {
+        self.shared.state.lock().unwrap().settings.scan_interval
+    }
+
+    /// Sets the time between the start of two consecutive scans. By default, this
+    /// is set to 5 ms.
+    pub fn set_scan_interval(&self, interval: Duration) {
+        self.shared.state.lock().unwrap().settings.scan_interval = interval;
+    }
+
+    /// Returns the settle time.
+    pub fn settle_time(&self) -> Duration {
+        self.shared.state.lock().unwrap().settings.settle_time
+    }
+
+    /// Sets how long to wait after driving a row low before the columns are read.
+    /// By default, this is set to 10 µs.
+    ///
+    /// Long wires or high-value pull-up resistors may need a longer settle time.
+    pub fn set_settle_time(&self, settle_time: Duration) {
+        self.shared.state.lock().unwrap().settings.settle_time = settle_time;
+    }
+
+    /// Returns the debounce period.
+    pub fn debounce(&self) -> Duration {
+        self.shared.state.lock().unwrap().settings.debounce
+    }
+
+    /// Sets how long a key needs to read the same state before a press or release is
+    /// reported. By default, this is set to 20 ms.
+    pub fn set_debounce(&self, debounce: Duration) {
+        self.shared.state.lock().unwrap().settings.debounce = debounce;
+    }
+
+    /// Returns the long-press duration.
+    pub fn long_press(&self) -> Option<Duration> {
+        self.shared.state.lock().unwrap().settings.long_press
+    }
+
+    /// Sets how long a key needs to be held down before a [`LongPress`] event is sent.
+    /// By default, this is set to 1 s. Set `duration` to `None` to disable long-press
+    /// events.
+    ///
+    /// [`LongPress`]: enum.KeypadEvent.html#variant.LongPress
+    pub fn set_long_press(&self, duration: Option<Duration>) {
+        self.shared.state.lock().unwrap().settings.long_press = duration;
+    }
+
+    /// Returns the repeat delay and interval.
+    pub fn repeat(&self) -> Option<(Duration, Duration)> {
+        self.shared.state.lock().unwrap().settings.repeat
+    }
+
+    /// Configures key repeat.
+    ///
+    /// When `repeat` is set to `Some((delay, interval))`, a [`Repeat`] event is sent once a
+    /// key has been held down for `delay`, and every `interval` after that. By default,
+    /// key repeat is disabled. The new setting applies to keys pressed after
+    /// `set_repeat` is called.
+    ///
+    /// [`Repeat`]: enum.KeypadEvent.html#variant.Repeat
+    pub fn set_repeat(&self, repeat: Option<(Duration, Duration)>) {
+        self.shared.state.lock().unwrap().settings.repeat = repeat;
+    }
+
+    /// Returns `true` if the key is currently pressed.
+    ///
+    /// Returns `false` if `row` or `column` is out of range.
+    pub fn is_pressed(&self, row: usize, column: usize) -> bool {
+        if row >= self.rows || column >= self.columns {
+            return false;
+        }
+
+        self.shared.state.lock().unwrap().keys[row * self.columns + column].pressed
+    }
+
+    /// Returns the row and column of every key that's currently pressed.
+    pub fn pressed(&self) -> Vec<(usize, usize)> {
+        self.shared
+            .state
+            .lock()
+            .unwrap()
+            .keys
+            .iter()
+            .enumerate()
+            .filter(|(_, key)| key.pressed)
+            .map(|(index, _)| (index / self.columns, index % self.columns))
+            .collect()
+    }
+
+    /// Returns `true` if the most recent scan contained ghost keys.
+    pub fn is_ghosting(&self) -> bool {
+        self.shared.state.lock().unwrap().ghosting
+    }
+
+    /// Returns a new channel that receives a [`KeypadEvent`] for every key press,
+    /// release, long press and repeat.
+    ///
+    /// Events are only sent to channels whose receiver is still in scope. The channel
+    /// is closed when the `Keypad` goes out of scope.
+    ///
+    /// [`KeypadEvent`]: enum.KeypadEvent.html
+    pub fn subscribe(&self) -> Receiver<KeypadEvent> {
+        let (sender, receiver) = mpsc::channel();
+        self.shared.state.lock().unwrap().subscribers.push(sender);
+
+        receiver
+    }
+
+    /// Stops scanning, and returns the row and column pins.
+    pub fn into_pins(mut self) -> (Vec<OutputPin>, Vec<InputPin>) {
+        self.stop().unwrap_or_default()
+    }
+
+    fn stop(&mut self) -> Option<(Vec<OutputPin>, Vec<InputPin>)> {
+        self.shared.exit.store(true, Ordering::SeqCst);
+
+        let pins = self.thread.take()?.join().ok();
+        self.shared.state.lock().unwrap().subscribers.clear();
+
+        pins
+    }
+}
+
+impl Drop for Keypad {
+    fn drop(&mut self) {
+        // Don't wait for the scan thread if we're panicking, for the same
+        // reasons as SoftPwm
+        if !thread::panicking() {
+            self.stop();
+        }
+    }
+}
+
+fn scan(
+    mut rows: Vec<OutputPin>,
+    columns: Vec<InputPin>,
+    shared: &Shared,
+) -> (Vec<OutputPin>, Vec<InputPin>) {
+    // Each column is a separate pin, so there are never more columns than bits
+    let mut readings = vec![0u64; rows.len()];
+
+    while !shared.exit.load(Ordering::SeqCst) {
+        let start = Instant::now();
+        let settings = shared.state.lock().unwrap().settings;
+
+        for (row, reading) in rows.iter_mut().zip(readings.iter_mut()) {
+            row.set_low();
+            thread::sleep(settings.settle_time);
+
+            *reading = columns
+                .iter()
+                .enumerate()
+                .filter(|(_, column)| column.read() == Level::Low)
+                .fold(0, |mask, (index, _)| mask | (1 << index));
+
+            row.set_high();
+        }
+
+        shared
+            .state
+            .lock()
+            .unwrap()
+            .update(&readings, Instant::now());
+
+        if let Some(remaining) = settings.scan_interval.checked_sub(start.elapsed()) {
+            thread::sleep(remaining);
+        }
+    }
+
+    (rows, columns)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DEBOUNCE: Duration = Duration::from_millis(20);
+
+    fn state(rows: usize, columns: usize, now: Instant) -> (State, Receiver<KeypadEvent>) {
+        let key = Key {
+            raw: false,
+            raw_since: now,
+            pressed: false,
+            pressed_at: now,
+            long_pressed: false,
+            next_repeat: None,
+        };
+
+        let (sender, receiver) = mpsc::channel();
+        let state = State {
+            settings: Settings {
+                scan_interval: DEFAULT_SCAN_INTERVAL,
+                settle_time: DEFAULT_SETTLE_TIME,
+                debounce: DEBOUNCE,
+                long_press: None,
+                repeat: None,
+            },
+            columns,
+            keys: vec![key; rows * columns],
+            ghosting: false,
+            subscribers: vec![sender],
+        };
+
+        (state, receiver)
+    }
+
+    fn ms(start: Instant, ms: u64) -> Instant {
+        start + Duration::from_millis(ms)
+    }
+
+    #[test]
+    fn debounce() {
+        let start = Instant::now();
+        let (mut state, events) = state(2, 2, start);
+
+        // Bounces shorter than the debounce window are ignored
+        state.update(&[0b01, 0], ms(start, 0));
+        state.update(&[0, 0], ms(start, 5));
+        state.update(&[0b01, 0], ms(start, 10));
+        state.update(&[0b01, 0], ms(start, 25));
+        assert_eq!(events.try_iter().count(), 0);
+
+        state.update(&[0b01, 0], ms(start, 30));
+        assert_eq!(
+            events.try_iter().collect::<Vec<_>>(),
+            [KeypadEvent::Pressed { row: 0, column: 0 }]
+        );
+        assert!(state.keys[0].pressed);
+
+        state.update(&[0, 0], ms(start, 40));
+        state.update(&[0b01, 0], ms(start, 45));
+        state.update(&[0, 0], ms(start, 50));
+        state.update(&[0, 0], ms(start, 65));
+        assert_eq!(events.try_iter().count(), 0);
+
+        state.update(&[0, 0], ms(start, 70));
+        assert_eq!(
+            events.try_iter().collect::<Vec<_>>(),
+            [KeypadEvent::Released { row: 0, column: 0 }]
+        );
+        assert!(!state.keys[0].pressed);
+    }
+
+    #[test]
+    fn rollover() {
+        let start = Instant::now();
+        let (mut state, events) = state(2, 3, start);
+
+        state.update(&[0b100, 0b001], ms(start, 0));
+        state.update(&[0b100, 0b001], ms(start, 20));
+
+        assert!(!state.ghosting);
+        assert_eq!(
+            events.try_iter().collect::<Vec<_>>(),
+            [
+                KeypadEvent::Pressed { row: 0, column: 2 },
+                KeypadEvent::Pressed { row: 1, column: 0 },
+            ]
+        );
+    }
+
+    #[test]
+    fn ghosting() {
+        let start = Instant::now();
+        let (mut state, events) = state(2, 2, start);
+
+        // Two keys in the same row
+        state.update(&[0b11, 0], ms(start, 0));
+        state.update(&[0b11, 0], ms(start, 20));
+        assert_eq!(events.try_iter().count(), 2);
+
+        // A third corner makes the fourth corner read as pressed. The keys in the
+        // rectangle keep their previous state.
+        state.update(&[0b11, 0b11], ms(start, 30));
+        state.update(&[0b11, 0b11], ms(start, 100));
+        assert!(state.ghosting);
+        assert_eq!(events.try_iter().count(), 0);
+        assert!(!state.keys[2].pressed);
+        assert!(!state.keys[3].pressed);
+
+        // Releasing one of the keys resolves the ambiguity
+        state.update(&[0b01, 0b01], ms(start, 110));
+        assert!(!state.ghosting);
+        state.update(&[0b01, 0b01], ms(start, 130));
+        assert_eq!(
+            events.try_iter().collect::<Vec<_>>(),
+            [
+                KeypadEvent::Released { row: 0, column: 1 },
+                KeypadEvent::Pressed { row: 1, column: 0 },
+            ]
+        );
+    }
+
+    #[test]
+    fn long_press() {
+        let start = Instant::now();
+        let (mut state, events) = state(1, 1, start);
+        state.settings.long_press = Some(Duration::from_millis(500));
+
+        state.update(&[1], ms(start, 0));
+        state.update(&[1], ms(start, 20));
+        state.update(&[1], ms(start, 519));
+        assert_eq!(
+            events.try_iter().collect::<Vec<_>>(),
+            [KeypadEvent::Pressed { row: 0, column: 0 }]
+        );
+
+        state.update(&[1], ms(start, 520));
+        state.update(&[1], ms(start, 2000));
+        assert_eq!(
+            events.try_iter().collect::<Vec<_>>(),
+            [KeypadEvent::LongPress { row: 0, column: 0 }]
+        );
+
+        // A new press resets the long press
+        state.update(&[0], ms(start, 2000));
+        state.update(&[0], ms(start, 2020));
+        state.update(&[1], ms(start, 2030));
+        state.update(&[1], ms(start, 2050));
+        state.update(&[1], ms(start, 2550));
+        assert_eq!(
+            events.try_iter().collect::<Vec<_>>(),
+            [
+                KeypadEvent::Released { row: 0, column: 0 },
+                KeypadEvent::Pressed { row: 0, column: 0 },
+                KeypadEvent::LongPress { row: 0, column: 0 },
+            ]
+        );
+    }
+
+    #[test]
+    fn repeat() {
+        let start = Instant::now();
+        let (mut state, events) = state(1, 1, start);
+        state.settings.repeat = Some((Duration::from_millis(300), Duration::from_millis(100)));
+
+        state.update(&[1], ms(start, 0));
+        state.update(&[1], ms(start, 20));
+        state.update(&[1], ms(start, 319));
+        assert_eq!(
+            events.try_iter().collect::<Vec<_>>(),
+            [KeypadEvent::Pressed { row: 0, column: 0 }]
+        );
+
+        for now in (320..=520).step_by(50) {
+            state.update(&[1], ms(start, now));
+        }
+        assert_eq!(
+            events.try_iter().collect::<Vec<_>>(),
+            [
+                KeypadEvent::Repeat { row: 0, column: 0 },
+                KeypadEvent::Repeat { row: 0, column: 0 },
+                KeypadEvent::Repeat { row: 0, column: 0 },
+            ]
+        );
+
+        // No repeats after the key is released
+        state.update(&[0], ms(start, 530));
+        state.update(&[0], ms(start, 550));
+        state.update(&[0], ms(start, 1000));
+        assert_eq!(
+            events.try_iter().collect::<Vec<_>>(),
+            [KeypadEvent::Released { row: 0, column: 0 }]
+        );
+    }
+}
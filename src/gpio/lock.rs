@@ -0,0 +1,170 @@
+// Cross-process pin reservation through advisory lock files. Each pin has its own
+// lock file, which is locked with flock() for as long as the Pin exists. The kernel
+// releases the lock when the file is closed, including when the process crashes, so
+// stale lock files don't block other processes.
+
+use std::fmt;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Write};
+use std::os::unix::fs::OpenOptionsExt;
+use std::os::unix::io::AsRawFd;
+use std::path::Path;
+use std::process;
+
+use libc::{LOCK_EX, LOCK_NB};
+
+use crate::gpio::backend::Backend;
+use crate::gpio::{Error, Result};
+
+/// Default directory for the lock files used by [`Gpio::set_pin_locks`].
+///
+/// [`Gpio::set_pin_locks`]: struct.Gpio.html#method.set_pin_locks
+pub const PIN_LOCK_DIR: &str = "/run/rpi_embedded";
+
+/// Identifies who's using a pin that's reserved outside of the current process.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum PinOwner {
+    /// The pin's lock file is held by the process with the specified PID.
+    Process(u32),
+    /// The kernel reports the pin's GPIO line as in use by the specified consumer,
+    /// which is either a kernel driver, or a process that requested the line through
+    /// the GPIO character device.
+    Consumer(String),
+    /// The pin's lock file is held by another process, which hasn't stored its PID yet.
+    Unknown,
+}
+
+impl fmt::Display for PinOwner {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            PinOwner::Process(pid) => write!(f, "process {}", pid),
+            PinOwner::Consumer(ref consumer) => write!(f, "consumer \"{}\"", consumer),
+            PinOwner::Unknown => write!(f, "another process"),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub(crate) struct PinLock {
+    // Closing the file releases the lock
+    _file: File,
+}
+
+impl PinLock {
+    pub(crate) fn acquire(dir: &Path, pin: u8, backend: &dyn Backend) -> Result<PinLock> {
+        let path = dir.join(format!("gpio{}.lock", pin));
+
+        let mut file = fs::create_dir_all(dir)
+            .and_then(|_| {
+                OpenOptions::new()
+                    .read(true)
+                    .write(true)
+                    .create(true)
+                    .truncate(false)
+                    .mode(0o666)
+                    .open(&path)
+            })
+            .map_err(|err| map_error(err, &path))?;
+
+        // The mode passed to open() is masked by the umask. This fails if another user
+        // created the file, which already has the correct permissions in that case.
+        unsafe {
+            libc::fchmod(file.as_raw_fd(), 0o666);
+        }
+
+        if unsafe { libc::flock(file.as_raw_fd(), LOCK_EX | LOCK_NB) } == -1 {
+            let err = io::Error::last_os_error();
+            if err.kind() != io::ErrorKind::WouldBlock {
+                return Err(Error::Io(err));
+            }
+
+            // The PID may not have been written yet
+            let mut contents = String::new();
+            file.read_to_string(&mut contents)?;
+            let owner = match contents.lines().next().and_then(|pid| pid.parse().ok()) {
+                Some(pid) => PinOwner::Process(pid),
+                None => PinOwner::Unknown,
+            };
+
+            return Err(Error::PinNotAvailable(pin, Some(owner)));
+        }
+
+        // Processes that don't use lock files can still request the line through the
+        // GPIO character device, and kernel drivers claim their pins the same way
+        if let Some(consumer) = backend.consumer(pin) {
+            return Err(Error::PinNotAvailable(
+                pin,
+                Some(PinOwner::Consumer(consumer)),
+            ));
+        }
+
+        // Overwrite the previous PID before truncating, so other processes never
+        // read an empty file while we hold the lock
+        let pid = format!("{}\n", process::id());
+        file.write_all(pid.as_bytes())?;
+        file.set_len(pid.len() as u64)?;
+
+        Ok(PinLock { _file: file })
+    }
+}
+
+fn map_error(err: io::Error, path: &Path) -> Error {
+    if err.kind() == io::ErrorKind::PermissionDenied {
+        Error::PermissionDenied(path.to_string_lossy().into_owned())
+    } else {
+        Error::Io(err)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::os::unix::fs::PermissionsExt;
+
+    use crate::gpio::sim::SimBackend;
+    use crate::gpio::Gpio;
+
+    #[test]
+    fn acquire() {
+        let dir = std::env::temp_dir().join(format!("rpi_embedded_lock_{}", process::id()));
+        let backend = SimBackend::new();
+
+        let lock = PinLock::acquire(&dir, 17, &backend).unwrap();
+        let path = dir.join("gpio17.lock");
+        let mode = fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o666);
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            format!("{}\n", process::id())
+        );
+
+        // flock() locks are tied to the open file, so a second lock conflicts
+        assert!(matches!(
+            PinLock::acquire(&dir, 17, &backend),
+            Err(Error::PinNotAvailable(17, Some(PinOwner::Process(pid)))) if pid == process::id()
+        ));
+
+        drop(lock);
+        assert!(PinLock::acquire(&dir, 17, &backend).is_ok());
+
+        // A lock file that doesn't contain a PID yet
+        let file = File::create(dir.join("gpio18.lock")).unwrap();
+        assert_eq!(unsafe { libc::flock(file.as_raw_fd(), LOCK_EX) }, 0);
+        assert!(matches!(
+            PinLock::acquire(&dir, 18, &backend),
+            Err(Error::PinNotAvailable(18, Some(PinOwner::Unknown)))
+        ));
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn simulated_pins_are_not_locked() {
+        let (gpio, _sim) = Gpio::simulated().unwrap();
+        gpio.set_pin_locks(true);
+
+        assert!(!gpio.pin_locks());
+        assert!(gpio.get(17).is_ok());
+    }
+}
@@ -0,0 +1,524 @@
+use std::collections::VecDeque;
+use std::time::Duration;
+
+use crate::gpio::soft_pwm::get_time_ns;
+use crate::gpio::{InterruptEvent, Level};
+
+/// Statistics for a series of pulse widths or periods.
+///
+/// `jitter` is the standard deviation of the samples.
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub struct PulseStats {
+    /// Number of samples.
+    pub count: usize,
+    /// Shortest sample.
+    pub min: Duration,
+    /// Longest sample.
+    pub max: Duration,
+    /// Average of all samples.
+    pub mean: Duration,
+    /// Standard deviation of all samples.
+    pub jitter: Duration,
+}
+
+impl PulseStats {
+    /// Calculates the statistics for `samples`, or returns `None` if `samples`
+    /// is empty.
+    pub fn from_samples<I>(samples: I) -> Option<PulseStats>
+    where
+        I: IntoIterator<Item = Duration>,
+    {
+        let samples: Vec<Duration> = samples.into_iter().collect();
+        if samples.is_empty() {
+            return None;
+        }
+
+        let count = samples.len();
+        let total: u128 = samples.iter().map(|sample| sample.as_nanos()).sum();
+        let mean = total as f64 / count as f64;
+        let variance = samples
+            .iter()
+            .map(|sample| (sample.as_nanos() as f64 - mean).powi(2))
+            .sum::<f64>()
+            / count as f64;
+
+        Some(PulseStats {
+            count,
+            min: *samples.iter().min()?,
+            max: *samples.iter().max()?,
+            mean: Duration::from_nanos((total / count as u128) as u64),
+            jitter: Duration::from_nanos(variance.sqrt().round() as u64),
+        })
+    }
+
+    /// Returns the frequency in hertz (Hz) that corresponds to a period of `mean`,
+    /// or `0.0` if `mean` is zero.
+    pub fn frequency(&self) -> f64 {
+        let mean = self.mean.as_secs_f64();
+
+        if mean > 0.0 {
+            1.0 / mean
+        } else {
+            0.0
+        }
+    }
+}
+
+/// Pulse width, period and duty cycle analyzer for interrupt trigger events.
+///
+/// A `PulseMeter` is fed [`InterruptEvent`]s for both edges of a signal, and
+/// measures the high and low pulse widths and the period (rising edge to rising edge)
+/// based on the events' kernel timestamps. Only the most recent `capacity` samples
+/// of each series are kept.
+///
+/// Pulses are discarded when an edge went missing, which is detected through a gap in
+/// the event sequence numbers or two consecutive events with the same logic level.
+///
+/// [`InputPin::measure_signal`] captures a `PulseMeter` for a pin. `PulseMeter` can also
+/// be fed from an asynchronous interrupt callback or an interrupt stream.
+///
+/// ## Example
+///
+/// ```
+/// use std::time::Duration;
+///
+/// use rpi_embedded::gpio::{InterruptEvent, Level, PulseMeter, Trigger};
+///
+/// let event = |us, level, seqno| InterruptEvent {
+///     timestamp: Duration::from_micros(us),
+///     trigger: if level == Level::High { Trigger::RisingEdge } else { Trigger::FallingEdge },
+///     level,
+///     seqno,
+/// };
+///
+/// let mut meter = PulseMeter::new(16);
+/// meter.push(&event(0, Level::High, 1));
+/// meter.push(&event(1500, Level::Low, 2));
+/// meter.push(&event(20_000, Level::High, 3));
+/// meter.push(&event(21_500, Level::Low, 4));
+///
+/// assert_eq!(meter.pulse_width(Level::High).unwrap().mean, Duration::from_micros(1500));
+/// assert_eq!(meter.period().unwrap().mean, Duration::from_millis(20));
+/// assert_eq!(meter.frequency(), Some(50.0));
+/// assert_eq!(meter.duty_cycle(), Some(0.075));
+/// ```
+///
+/// [`InterruptEvent`]: struct.InterruptEvent.html
+/// [`InputPin::measure_signal`]: struct.InputPin.html#method.measure_signal
+#[derive(Debug, Clone)]
+pub struct PulseMeter {
+    capacity: usize,
+    last: Option<InterruptEvent>,
+    last_rising: Option<Duration>,
+    high: VecDeque<Duration>,
+    low: VecDeque<Duration>,
+    periods: VecDeque<Duration>,
+    edges: u64,
+}
+
+impl PulseMeter {
+    /// Constructs a new `PulseMeter` that keeps the most recent `capacity` samples
+    /// for each series.
+    ///
+    /// `capacity` is set to 1 if it's lower.
+    pub fn new(capacity: usize) -> PulseMeter {
+        let capacity = capacity.max(1);
+
+        PulseMeter {
+            capacity,
+            last: None,
+            last_rising: None,
+            high: VecDeque::with_capacity(capacity),
+            low: VecDeque::with_capacity(capacity),
+            periods: VecDeque::with_capacity(capacity),
+            edges: 0,
+        }
+    }
+
+    /// Returns the number of samples kept for each series.
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Processes an interrupt trigger event.
+    pub fn push(&mut self, event: &InterruptEvent) {
+        self.edges += 1;
+
+        let last = match self.last.replace(*event) {
+            Some(last) => last,
+            None => {
+                if event.level == Level::High {
+                    self.last_rising = Some(event.timestamp);
+                }
+
+                return;
+            }
+        };
+
+        // A missing edge invalidates the pulse and period that were in progress
+        let missed = event.level == last.level
+            || (event.seqno != 0 && event.seqno != last.seqno.wrapping_add(1));
+
+        if !missed {
+            if let Some(width) = event.timestamp.checked_sub(last.timestamp) {
+                let series = match last.level {
+                    Level::High => &mut self.high,
+                    Level::Low => &mut self.low,
+                };
+
+                Self::record(series, self.capacity, width);
+            }
+        }
+
+        if event.level == Level::High {
+            if let (false, Some(last_rising)) = (missed, self.last_rising) {
+                if let Some(period) = event.timestamp.checked_sub(last_rising) {
+                    Self::record(&mut self.periods, self.capacity, period);
+                }
+            }
+
+            self.last_rising = Some(event.timestamp);
+        } else if missed {
+            self.last_rising = None;
+        }
+    }
+
+    fn record(series: &mut VecDeque<Duration>, capacity: usize, sample: Duration) {
+        if series.len() == capacity {
+            series.pop_front();
+        }
+
+        series.push_back(sample);
+    }
+
+    /// Returns the total number of processed events.
+    pub fn edges(&self) -> u64 {
+        self.edges
+    }
+
+    /// Returns the most recently measured width of a pulse at the specified logic level.
+    pub fn last_pulse_width(&self, level: Level) -> Option<Duration> {
+        match level {
+            Level::High => self.high.back().copied(),
+            Level::Low => self.low.back().copied(),
+        }
+    }
+
+    /// Returns the statistics for pulses at the specified logic level, or `None` if
+    /// no complete pulses have been measured.
+    pub fn pulse_width(&self, level: Level) -> Option<PulseStats> {
+        match level {
+            Level::High => PulseStats::from_samples(self.high.iter().copied()),
+            Level::Low => PulseStats::from_samples(self.low.iter().copied()),
+        }
+    }
+
+    /// Returns the statistics for the signal's period, measured from rising edge to
+    /// rising edge, or `None` if no complete periods have been measured.
+    pub fn period(&self) -> Option<PulseStats> {
+        PulseStats::from_samples(self.periods.iter().copied())
+    }
+
+    /// Returns the signal's frequency in hertz (Hz), based on the average period.
+    pub fn frequency(&self) -> Option<f64> {
+        self.period().map(|period| period.frequency())
+    }
+
+    /// Returns the signal's duty cycle as a floating point value between `0.0` (0%)
+    /// and `1.0` (100%), based on the average high and low pulse widths.
+    pub fn duty_cycle(&self) -> Option<f64> {
+        let high = self.pulse_width(Level::High)?.mean.as_secs_f64();
+        let low = self.pulse_width(Level::Low)?.mean.as_secs_f64();
+
+        if high + low > 0.0 {
+            Some(high / (high + low))
+        } else {
+            None
+        }
+    }
+
+    /// Clears all samples and the edge count.
+    pub fn reset(&mut self) {
+        self.last = None;
+        self.last_rising = None;
+        self.high.clear();
+        self.low.clear();
+        self.periods.clear();
+        self.edges = 0;
+    }
+}
+
+/// Edge counter with a sliding window rate for interrupt trigger events.
+///
+/// An `EdgeCounter` counts every event it's fed, and calculates the rate of events
+/// within a sliding window based on the events' kernel timestamps. It's intended for
+/// tachometers, flow meters and other sensors that output a pulse train whose rate is
+/// proportional to the measured value.
+///
+/// [`InputPin::count_edges`] counts the edges on a pin over a fixed period. For
+/// continuous monitoring, an `EdgeCounter` can be fed from an asynchronous interrupt
+/// callback.
+///
+/// ## Example
+///
+/// ```no_run
+/// use std::error::Error;
+/// use std::sync::{Arc, Mutex};
+/// use std::thread;
+/// use std::time::Duration;
+///
+/// use rpi_embedded::gpio::{EdgeCounter, Gpio, Trigger};
+///
+/// # fn main() -> Result<(), Box<dyn Error>> {
+/// let mut pin = Gpio::new()?.get(17)?.into_input_pullup();
+///
+/// let counter = Arc::new(Mutex::new(EdgeCounter::new(Duration::from_secs(1))));
+/// let callback_counter = counter.clone();
+/// pin.set_async_interrupt(Trigger::FallingEdge, move |event| {
+///     callback_counter.lock().unwrap().push(&event);
+/// })?;
+///
+/// loop {
+///     thread::sleep(Duration::from_secs(1));
+///     println!("{:.0} RPM", counter.lock().unwrap().rate() * 60.0);
+/// }
+/// # }
+/// ```
+///
+/// [`InputPin::count_edges`]: struct.InputPin.html#method.count_edges
+#[derive(Debug, Clone)]
+pub struct EdgeCounter {
+    window: Duration,
+    count: u64,
+    recent: VecDeque<Duration>,
+}
+
+impl EdgeCounter {
+    /// Constructs a new `EdgeCounter` with the specified rate window.
+    pub fn new(window: Duration) -> EdgeCounter {
+        EdgeCounter {
+            window,
+            count: 0,
+            recent: VecDeque::new(),
+        }
+    }
+
+    /// Returns the rate window.
+    pub fn window(&self) -> Duration {
+        self.window
+    }
+
+    /// Processes an interrupt trigger event.
+    pub fn push(&mut self, event: &InterruptEvent) {
+        self.count += 1;
+        self.recent.push_back(event.timestamp);
+
+        // Events older than the window no longer contribute to the rate
+        while let Some(&oldest) = self.recent.front() {
+            match event.timestamp.checked_sub(oldest) {
+                Some(elapsed) if elapsed >= self.window => {
+                    self.recent.pop_front();
+                }
+                _ => break,
+            }
+        }
+    }
+
+    /// Returns the total number of processed events.
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    /// Returns the number of events per second within the window that ends now.
+    ///
+    /// The current time is read from `CLOCK_MONOTONIC`, which is the default clock
+    /// for event timestamps. The rate drops to `0.0` once no events have been
+    /// processed for the duration of the window. For timestamps based on a different
+    /// clock, use [`rate_at`] instead.
+    ///
+    /// [`rate_at`]: #method.rate_at
+    pub fn rate(&self) -> f64 {
+        self.rate_at(Duration::from_nanos(get_time_ns() as u64))
+    }
+
+    /// Returns the number of events per second within the window that ends at
+    /// `timestamp`.
+    ///
+    /// `timestamp` needs to be based on the same clock as the event timestamps,
+    /// which is selected through [`InputPin::set_event_clock`].
+    ///
+    /// [`InputPin::set_event_clock`]: struct.InputPin.html#method.set_event_clock
+    pub fn rate_at(&self, timestamp: Duration) -> f64 {
+        let window = self.window.as_secs_f64();
+        if window <= 0.0 {
+            return 0.0;
+        }
+
+        let count = self
+            .recent
+            .iter()
+            .filter(|&&event| event <= timestamp && timestamp - event < self.window)
+            .count();
+
+        count as f64 / window
+    }
+
+    /// Clears the edge count and the rate window.
+    pub fn reset(&mut self) {
+        self.count = 0;
+        self.recent.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::gpio::Trigger;
+
+    fn event(us: u64, level: Level, seqno: u32) -> InterruptEvent {
+        InterruptEvent {
+            timestamp: Duration::from_micros(us),
+            trigger: match level {
+                Level::High => Trigger::RisingEdge,
+                Level::Low => Trigger::FallingEdge,
+            },
+            level,
+            seqno,
+        }
+    }
+
+    fn us(us: u64) -> Duration {
+        Duration::from_micros(us)
+    }
+
+    #[test]
+    fn stats() {
+        assert_eq!(PulseStats::from_samples(Vec::new()), None);
+
+        let stats = PulseStats::from_samples(vec![us(900), us(1000), us(1100), us(1000)]).unwrap();
+        assert_eq!(stats.count, 4);
+        assert_eq!(stats.min, us(900));
+        assert_eq!(stats.max, us(1100));
+        assert_eq!(stats.mean, us(1000));
+        assert_eq!(stats.jitter, Duration::from_nanos(70_711));
+        assert_eq!(stats.frequency(), 1000.0);
+
+        let stats = PulseStats::from_samples(vec![Duration::default()]).unwrap();
+        assert_eq!(stats.jitter, Duration::default());
+        assert_eq!(stats.frequency(), 0.0);
+    }
+
+    #[test]
+    fn pulses() {
+        let mut meter = PulseMeter::new(2);
+        assert_eq!(meter.pulse_width(Level::High), None);
+        assert_eq!(meter.duty_cycle(), None);
+
+        // Starts with a falling edge, so the first period begins at 1000 µs
+        meter.push(&event(0, Level::Low, 1));
+        meter.push(&event(1000, Level::High, 2));
+        meter.push(&event(1250, Level::Low, 3));
+        meter.push(&event(2000, Level::High, 4));
+        meter.push(&event(2300, Level::Low, 5));
+        meter.push(&event(3000, Level::High, 6));
+
+        assert_eq!(meter.edges(), 6);
+        assert_eq!(meter.last_pulse_width(Level::High), Some(us(300)));
+        assert_eq!(meter.last_pulse_width(Level::Low), Some(us(700)));
+
+        // Only the 2 most recent samples are kept
+        let low = meter.pulse_width(Level::Low).unwrap();
+        assert_eq!((low.count, low.min, low.max), (2, us(700), us(750)));
+        let high = meter.pulse_width(Level::High).unwrap();
+        assert_eq!((high.count, high.mean), (2, Duration::from_nanos(275_000)));
+        let period = meter.period().unwrap();
+        assert_eq!((period.count, period.mean), (2, us(1000)));
+        assert_eq!(meter.frequency(), Some(1000.0));
+        assert_eq!(meter.duty_cycle(), Some(0.275));
+
+        meter.reset();
+        assert_eq!(meter.edges(), 0);
+        assert_eq!(meter.period(), None);
+    }
+
+    #[test]
+    fn missed_edges() {
+        let mut meter = PulseMeter::new(16);
+
+        meter.push(&event(0, Level::High, 1));
+        meter.push(&event(100, Level::Low, 2));
+        // Two rising edges in a row, so the falling edge in between went missing
+        meter.push(&event(1000, Level::High, 3));
+        meter.push(&event(2000, Level::High, 4));
+        meter.push(&event(2100, Level::Low, 5));
+
+        assert_eq!(meter.pulse_width(Level::High).unwrap().count, 2);
+        assert_eq!(meter.pulse_width(Level::Low).unwrap().count, 1);
+        assert_eq!(meter.period().unwrap().count, 1);
+        assert_eq!(meter.period().unwrap().mean, us(1000));
+
+        // A gap in the sequence numbers discards the pulse and period in progress
+        meter.push(&event(3000, Level::High, 8));
+        meter.push(&event(3100, Level::Low, 9));
+        meter.push(&event(4000, Level::High, 10));
+
+        assert_eq!(meter.pulse_width(Level::Low).unwrap().count, 2);
+        assert_eq!(meter.pulse_width(Level::High).unwrap().count, 3);
+        assert_eq!(meter.period().unwrap().count, 2);
+        assert_eq!(meter.edges(), 8);
+    }
+
+    #[test]
+    fn seqno_wraps() {
+        let mut meter = PulseMeter::new(16);
+
+        meter.push(&event(0, Level::High, u32::MAX));
+        meter.push(&event(100, Level::Low, 0));
+        meter.push(&event(1000, Level::High, 1));
+
+        // Sequence number 0 is used by sources that don't number their events
+        assert_eq!(meter.pulse_width(Level::High).unwrap().count, 1);
+        assert_eq!(meter.pulse_width(Level::Low).unwrap().count, 1);
+        assert_eq!(meter.period().unwrap().mean, us(1000));
+    }
+
+    #[test]
+    fn edge_rate() {
+        let mut counter = EdgeCounter::new(Duration::from_millis(100));
+        assert_eq!(counter.rate_at(us(0)), 0.0);
+
+        for ms in 0..20 {
+            counter.push(&event(ms * 10_000, Level::Low, ms as u32 + 1));
+        }
+
+        assert_eq!(counter.count(), 20);
+        assert_eq!(counter.rate_at(us(190_000)), 100.0);
+        // Events after the timestamp aren't counted
+        assert_eq!(counter.rate_at(us(145_000)), 50.0);
+        // The rate decays once the signal stops
+        assert_eq!(counter.rate_at(us(240_000)), 50.0);
+        assert_eq!(counter.rate_at(us(290_000)), 0.0);
+
+        counter.reset();
+        assert_eq!(counter.count(), 0);
+        assert_eq!(counter.rate_at(us(190_000)), 0.0);
+    }
+
+    #[test]
+    fn edge_rate_now() {
+        let mut counter = EdgeCounter::new(Duration::from_secs(1));
+
+        let now = get_time_ns() as u64 / 1000;
+        counter.push(&event(now - 1_500_000, Level::Low, 1));
+        counter.push(&event(now - 200_000, Level::Low, 2));
+        counter.push(&event(now - 100_000, Level::Low, 3));
+        assert_eq!(counter.rate(), 2.0);
+
+        // Events stay in the window after the signal stops, but don't count anymore
+        let mut stopped = EdgeCounter::new(Duration::from_secs(1));
+        stopped.push(&event(now - 3_000_000, Level::Low, 1));
+        stopped.push(&event(now - 2_900_000, Level::Low, 2));
+        assert_eq!(stopped.rate(), 0.0);
+    }
+}
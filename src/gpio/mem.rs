@@ -31,13 +31,14 @@ use std::time::Duration;
 use libc::{self, c_void, off_t, size_t, MAP_FAILED, MAP_SHARED, O_SYNC, PROT_READ, PROT_WRITE};
 
 use crate::gpio::{Error, Level, Mode, PullUpDown, Result};
-use crate::system::DeviceInfo;
+use crate::system::{DeviceInfo, SoC};
 
 const PATH_DEV_GPIOMEM: &str = "/dev/gpiomem";
 const PATH_DEV_MEM: &str = "/dev/mem";
 
-// The BCM2835 has 41 32-bit registers related to the GPIO (datasheet @ 6.1).
-const GPIO_MEM_REGISTERS: usize = 41;
+// The BCM2835 has 41 32-bit registers related to the GPIO (datasheet @ 6.1). The
+// BCM2711 adds the GPIO_PUP_PDN_CNTRL registers, which end at 0xf0.
+const GPIO_MEM_REGISTERS: usize = 61;
 const GPIO_MEM_SIZE: usize = GPIO_MEM_REGISTERS * std::mem::size_of::<u32>();
 
 const GPFSEL0: usize = 0x00;
@@ -46,10 +47,18 @@ const GPCLR0: usize = 0x28 / std::mem::size_of::<u32>();
 const GPLEV0: usize = 0x34 / std::mem::size_of::<u32>();
 const GPPUD: usize = 0x94 / std::mem::size_of::<u32>();
 const GPPUDCLK0: usize = 0x98 / std::mem::size_of::<u32>();
+const GPIO_PUP_PDN_CNTRL_REG0: usize = 0xe4 / std::mem::size_of::<u32>();
+
+// GPIO_PUP_PDN_CNTRL values, which differ from the GPPUD values
+const PUP_PDN_OFF: u32 = 0b00;
+const PUP_PDN_PULL_UP: u32 = 0b01;
+const PUP_PDN_PULL_DOWN: u32 = 0b10;
 
 pub struct GpioMem {
     mem_ptr: *mut u32,
     locks: [AtomicBool; GPIO_MEM_REGISTERS],
+    // The BCM2711 replaces GPPUD/GPPUDCLKn with GPIO_PUP_PDN_CNTRL_REGn
+    bcm2711: bool,
 }
 
 impl fmt::Debug for GpioMem {
@@ -57,6 +66,7 @@ impl fmt::Debug for GpioMem {
         f.debug_struct("GpioMem")
             .field("mem_ptr", &self.mem_ptr)
             .field("locks", &format_args!("{{ .. }}"))
+            .field("bcm2711", &self.bcm2711)
             .finish()
     }
 }
@@ -87,7 +97,17 @@ impl GpioMem {
 
         let locks = init_array!(AtomicBool::new(false), GPIO_MEM_REGISTERS);
 
-        Ok(GpioMem { mem_ptr, locks })
+        // /dev/gpiomem doesn't require the model to be identified, so assume the legacy
+        // pull-up/pull-down registers if that fails
+        let bcm2711 = DeviceInfo::new()
+            .map(|device_info| device_info.soc() == SoC::Bcm2711)
+            .unwrap_or(false);
+
+        Ok(GpioMem {
+            mem_ptr,
+            locks,
+            bcm2711,
+        })
     }
 
     fn map_devgpiomem() -> Result<*mut u32> {
@@ -185,6 +205,28 @@ impl GpioMem {
         unsafe { std::mem::transmute((reg_value >> shift) as u8 & 0b1) }
     }
 
+    // Sets the pins in set_mask high and the pins in clear_mask low. Each bank is
+    // updated with back-to-back GPSETn and GPCLRn writes.
+    pub(crate) fn write_levels(&self, set_mask: u64, clear_mask: u64) {
+        for bank in 0..2 {
+            let set = (set_mask >> (bank * 32)) as u32;
+            let clear = (clear_mask >> (bank * 32)) as u32;
+
+            if set != 0 {
+                self.write(GPSET0 + bank, set);
+            }
+
+            if clear != 0 {
+                self.write(GPCLR0 + bank, clear);
+            }
+        }
+    }
+
+    // Returns the logic levels of all pins, with bit n set for BCM GPIO n
+    pub(crate) fn levels(&self) -> u64 {
+        u64::from(self.read(GPLEV0)) | (u64::from(self.read(GPLEV0 + 1)) << 32)
+    }
+
     pub(crate) fn mode(&self, pin: u8) -> Mode {
         let offset = GPFSEL0 + pin as usize / 10;
         let shift = (pin % 10) * 3;
@@ -214,6 +256,11 @@ impl GpioMem {
     }
 
     pub(crate) fn set_pullupdown(&self, pin: u8, pud: PullUpDown) {
+        if self.bcm2711 {
+            self.set_pup_pdn(pin, pud);
+            return;
+        }
+
         let offset = GPPUDCLK0 + pin as usize / 32;
         let shift = pin % 32;
 
@@ -252,6 +299,43 @@ impl GpioMem {
         self.locks[offset].store(false, Ordering::SeqCst);
         self.locks[GPPUD].store(false, Ordering::SeqCst);
     }
+
+    fn set_pup_pdn(&self, pin: u8, pud: PullUpDown) {
+        let offset = GPIO_PUP_PDN_CNTRL_REG0 + pin as usize / 16;
+        let shift = (pin % 16) * 2;
+
+        let value = match pud {
+            PullUpDown::Off => PUP_PDN_OFF,
+            PullUpDown::PullUp => PUP_PDN_PULL_UP,
+            PullUpDown::PullDown => PUP_PDN_PULL_DOWN,
+        };
+
+        while self.locks[offset]
+            .compare_exchange(false, true, Ordering::SeqCst, Ordering::SeqCst)
+            .is_err()
+        {}
+
+        let reg_value = self.read(offset);
+        self.write(offset, (reg_value & !(0b11 << shift)) | (value << shift));
+
+        self.locks[offset].store(false, Ordering::SeqCst);
+    }
+
+    // Only the BCM2711 can read back the pull-up/pull-down state
+    pub(crate) fn pullupdown(&self, pin: u8) -> Option<PullUpDown> {
+        if !self.bcm2711 {
+            return None;
+        }
+
+        let offset = GPIO_PUP_PDN_CNTRL_REG0 + pin as usize / 16;
+        let shift = (pin % 16) * 2;
+
+        match (self.read(offset) >> shift) & 0b11 {
+            PUP_PDN_PULL_UP => Some(PullUpDown::PullUp),
+            PUP_PDN_PULL_DOWN => Some(PullUpDown::PullDown),
+            _ => Some(PullUpDown::Off),
+        }
+    }
 }
 
 impl Drop for GpioMem {
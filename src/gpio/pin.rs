@@ -18,20 +18,37 @@
 // FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
 // DEALINGS IN THE SOFTWARE.
 
-use std::os::unix::io::AsRawFd;
 use std::sync::atomic::Ordering;
-use std::sync::Arc;
-use std::time::Duration;
+use std::sync::{Arc, Mutex, PoisonError, TryLockError};
+use std::thread;
+use std::time::{Duration, Instant};
 
 use super::soft_pwm::SoftPwm;
-use crate::gpio::{interrupt::AsyncInterrupt, GpioState, Level, Mode, PullUpDown, Result, Trigger};
+use crate::gpio::backend::Backend;
+use crate::gpio::lock::PinLock;
+#[cfg(feature = "async")]
+use crate::gpio::InterruptStream;
+use crate::gpio::{
+    config::{self, PinConfig},
+    interrupt::AsyncInterrupt, ioctl::EventConfig, EventClock, GpioState, InterruptEvent, Level,
+    Mode, PullUpDown, PulseMeter, PwmEngine, Result, Trigger,
+};
+use crate::safe_state::{self, Registration};
 
 const NANOS_PER_SEC: f64 = 1_000_000_000.0;
 
+// Number of samples taken during the debounce window by read_debounced()
+const DEBOUNCE_SAMPLES: u32 = 10;
+
 // Maximum GPIO pins on the BCM2835. The actual number of pins
 // exposed through the Pi's GPIO header depends on the model.
 pub const MAX: usize = 54;
 
+// Returns the poll timeout left until the deadline, or None to wait indefinitely
+fn remaining(deadline: Option<Instant>) -> Option<Duration> {
+    deadline.map(|deadline| deadline.saturating_duration_since(Instant::now()))
+}
+
 macro_rules! impl_pin {
     () => {
         /// Returns the GPIO pin number.
@@ -41,6 +58,20 @@ macro_rules! impl_pin {
         pub fn pin(&self) -> u8 {
             self.pin.pin
         }
+
+        /// Returns the built-in pull-up/pull-down resistor state as reported by the
+        /// GPIO peripheral.
+        ///
+        /// The BCM2711 (Raspberry Pi 4, 400 and Compute Module 4) is the only SoC that
+        /// can read back its pull-up/pull-down configuration. On other SoCs, `pullupdown`
+        /// returns `None`, unless [`Gpio`] fell back to the `gpiochip` character device and
+        /// the resistors were configured through RPPAL.
+        ///
+        /// [`Gpio`]: struct.Gpio.html
+        #[inline]
+        pub fn pullupdown(&self) -> Option<PullUpDown> {
+            self.pin.pullupdown()
+        }
     }
 }
 
@@ -107,6 +138,28 @@ macro_rules! impl_output {
             }
         }
 
+        /// Returns the engine used to generate PWM signals configured through [`set_pwm`].
+        ///
+        /// [`set_pwm`]: #method.set_pwm
+        pub fn pwm_engine(&self) -> PwmEngine {
+            self.pwm_engine
+        }
+
+        /// Selects the engine used to generate PWM signals configured through [`set_pwm`].
+        ///
+        /// Any active PWM signal is stopped first. By default, [`PwmEngine::Software`]
+        /// is used. More information can be found [here].
+        ///
+        /// [`set_pwm`]: #method.set_pwm
+        /// [`PwmEngine::Software`]: enum.PwmEngine.html#variant.Software
+        /// [here]: index.html#software-based-pwm
+        pub fn set_pwm_engine(&mut self, engine: PwmEngine) -> Result<()> {
+            self.clear_pwm()?;
+            self.pwm_engine = engine;
+
+            Ok(())
+        }
+
         /// Configures a software-based PWM signal.
         ///
         /// `period` indicates the time it takes to complete one cycle.
@@ -116,23 +169,48 @@ macro_rules! impl_output {
         ///
         /// Software-based PWM is inherently inaccurate on a multi-threaded OS due to
         /// scheduling/preemption. If an accurate or faster PWM signal is required, use the
-        /// hardware [`Pwm`] peripheral instead. More information can be found [here].
+        /// hardware [`Pwm`] peripheral instead, or switch to the DMA-based engine through
+        /// [`set_pwm_engine`]. More information can be found [here].
         ///
         /// If `set_pwm` is called when a PWM thread is already active, the existing thread
         /// will be reconfigured at the end of the current cycle.
         ///
+        /// With [`PwmEngine::Dma`] selected, `period` and `pulse_width` are rounded to the
+        /// engine's time step. The new settings take effect immediately, so the period during
+        /// which the signal is reconfigured may be shortened or lengthened. The engine is
+        /// started when the first pin is configured, which returns an error if `/dev/mem` or
+        /// the selected DMA channel isn't available.
+        ///
         /// [`Pwm`]: ../pwm/struct.Pwm.html
+        /// [`set_pwm_engine`]: #method.set_pwm_engine
+        /// [`PwmEngine::Dma`]: enum.PwmEngine.html#variant.Dma
         /// [here]: index.html#software-based-pwm
         pub fn set_pwm(&mut self, period: Duration, pulse_width: Duration) -> Result<()> {
-            if let Some(ref mut soft_pwm) = self.soft_pwm {
-                soft_pwm.reconfigure(period, pulse_width);
-            } else {
-                self.soft_pwm = Some(SoftPwm::new(
+            if self.pwm_engine == PwmEngine::Dma {
+                let gpio_state = &self.pin.gpio_state;
+                gpio_state.dma_pwm.lock().unwrap().set(
+                    &*gpio_state.backend,
                     self.pin.pin,
-                    self.pin.gpio_state.clone(),
                     period,
                     pulse_width,
-                ));
+                )?;
+            } else {
+                let mut soft_pwm = self.soft_pwm.lock().unwrap();
+                match *soft_pwm {
+                    Some(ref mut soft_pwm) => soft_pwm.reconfigure(period, pulse_width),
+                    None => {
+                        // The safe state locks soft_pwm from the panic hook, so don't
+                        // hold the lock while spawning the thread, which could panic
+                        drop(soft_pwm);
+                        let new_soft_pwm = SoftPwm::new(
+                            self.pin.pin,
+                            self.pin.gpio_state.clone(),
+                            period,
+                            pulse_width,
+                        );
+                        *self.soft_pwm.lock().unwrap() = Some(new_soft_pwm);
+                    }
+                }
             }
 
             // Store frequency/duty cycle for the embedded-hal PwmPin implementation.
@@ -182,12 +260,20 @@ macro_rules! impl_output {
         /// Stops a previously configured software-based PWM signal.
         ///
         /// The thread responsible for emulating the PWM signal is stopped at the end
-        /// of the current cycle.
+        /// of the current cycle. With [`PwmEngine::Dma`] selected, the pin is removed
+        /// from the DMA schedule and set low.
+        ///
+        /// [`PwmEngine::Dma`]: enum.PwmEngine.html#variant.Dma
         pub fn clear_pwm(&mut self) -> Result<()> {
-            if let Some(mut soft_pwm) = self.soft_pwm.take() {
+            if let Some(mut soft_pwm) = self.soft_pwm.lock().unwrap().take() {
                 soft_pwm.stop()?;
             }
 
+            if self.pwm_engine == PwmEngine::Dma {
+                self.pin.gpio_state.dma_pwm.lock().unwrap().clear(self.pin.pin);
+                self.pin.set_low();
+            }
+
             Ok(())
         }
     }
@@ -208,15 +294,81 @@ macro_rules! impl_reset_on_drop {
         ///
         /// Drop methods aren't called when a process is abnormally terminated, for
         /// instance when a user presses <kbd>Ctrl</kbd> + <kbd>C</kbd>, and the `SIGINT` signal
-        /// isn't caught. You can catch those using crates such as [`simple_signal`].
+        /// isn't caught. You can catch those using crates such as [`simple_signal`], or
+        /// register a safe output level that's applied through the [`safe_state`] module.
         ///
         /// [`simple_signal`]: https://crates.io/crates/simple-signal
+        /// [`safe_state`]: ../safe_state/index.html
         pub fn set_reset_on_drop(&mut self, reset_on_drop: bool) {
             self.reset_on_drop = reset_on_drop;
         }
     };
 }
 
+macro_rules! impl_safe_level {
+    () => {
+        /// Returns the level the pin is set to when the safe states are applied, or `None`
+        /// if no safe state is registered.
+        pub fn safe_level(&self) -> Option<Level> {
+            self.safe_level.as_ref().map(|&(level, _)| level)
+        }
+
+        /// Registers the level the pin is set to when the safe states are applied.
+        ///
+        /// The safe states are applied by [`safe_state::apply`], which is called
+        /// automatically when the process receives `SIGINT`, `SIGTERM` or `SIGHUP` once
+        /// [`safe_state::install`] has been called, or when it panics once
+        /// [`safe_state::install_panic_hook`] has been called. Any PWM signal on the pin is
+        /// stopped, and the pin's mode is set to [`Output`]. The safe state is removed when
+        /// the pin goes out of scope, or when `level` is set to `None`.
+        ///
+        /// By default, no safe state is registered.
+        ///
+        /// [`safe_state::apply`]: ../safe_state/fn.apply.html
+        /// [`safe_state::install`]: ../safe_state/fn.install.html
+        /// [`safe_state::install_panic_hook`]: ../safe_state/fn.install_panic_hook.html
+        /// [`Output`]: enum.Mode.html#variant.Output
+        pub fn set_safe_level(&mut self, level: Option<Level>) {
+            // Remove the previous registration first
+            self.safe_level = None;
+
+            if let Some(level) = level {
+                let gpio_state = self.pin.gpio_state.clone();
+                let soft_pwm = self.soft_pwm.clone();
+                let pin = self.pin.pin;
+
+                let registration = safe_state::register(move || {
+                    // Wait for the software-based PWM thread to exit, so it can't
+                    // overwrite the safe level
+                    if let Some(mut soft_pwm) = soft_pwm
+                        .lock()
+                        .unwrap_or_else(PoisonError::into_inner)
+                        .take()
+                    {
+                        let _ = soft_pwm.stop();
+                    }
+
+                    // Skip the DMA-based engine if its lock is held by the panicking thread
+                    match gpio_state.dma_pwm.try_lock() {
+                        Ok(mut dma_pwm) => dma_pwm.clear(pin),
+                        Err(TryLockError::Poisoned(err)) => err.into_inner().clear(pin),
+                        Err(TryLockError::WouldBlock) => (),
+                    }
+
+                    match level {
+                        Level::Low => gpio_state.backend.set_low(pin),
+                        Level::High => gpio_state.backend.set_high(pin),
+                    }
+
+                    gpio_state.backend.set_mode(pin, Mode::Output);
+                });
+
+                self.safe_level = Some((level, registration));
+            }
+        }
+    };
+}
+
 macro_rules! impl_drop {
     ($struct:ident) => {
         impl Drop for $struct {
@@ -285,12 +437,18 @@ macro_rules! impl_eq {
 pub struct Pin {
     pub(crate) pin: u8,
     gpio_state: Arc<GpioState>,
+    // Cross-process reservation, released when the Pin goes out of scope
+    lock: Option<PinLock>,
 }
 
 impl Pin {
     #[inline]
-    pub(crate) fn new(pin: u8, gpio_state: Arc<GpioState>) -> Pin {
-        Pin { pin, gpio_state }
+    pub(crate) fn new(pin: u8, gpio_state: Arc<GpioState>, lock: Option<PinLock>) -> Pin {
+        Pin {
+            pin,
+            gpio_state,
+            lock,
+        }
     }
 
     /// Returns the GPIO pin number.
@@ -301,16 +459,30 @@ impl Pin {
         self.pin
     }
 
+    /// Returns the built-in pull-up/pull-down resistor state as reported by the
+    /// GPIO peripheral.
+    ///
+    /// The BCM2711 (Raspberry Pi 4, 400 and Compute Module 4) is the only SoC that
+    /// can read back its pull-up/pull-down configuration. On other SoCs, `pullupdown`
+    /// returns `None`, unless [`Gpio`] fell back to the `gpiochip` character device and
+    /// the resistors were configured through RPPAL.
+    ///
+    /// [`Gpio`]: struct.Gpio.html
+    #[inline]
+    pub fn pullupdown(&self) -> Option<PullUpDown> {
+        self.gpio_state.backend.pullupdown(self.pin)
+    }
+
     /// Returns the pin's mode.
     #[inline]
     pub fn mode(&self) -> Mode {
-        self.gpio_state.gpio_mem.mode(self.pin)
+        self.gpio_state.backend.mode(self.pin)
     }
 
     /// Reads the pin's logic level.
     #[inline]
     pub fn read(&self) -> Level {
-        self.gpio_state.gpio_mem.level(self.pin)
+        self.gpio_state.backend.level(self.pin)
     }
 
     /// Consumes the `Pin`, returns an [`InputPin`], sets its mode to [`Input`],
@@ -371,22 +543,22 @@ impl Pin {
 
     #[inline]
     pub(crate) fn set_mode(&mut self, mode: Mode) {
-        self.gpio_state.gpio_mem.set_mode(self.pin, mode);
+        self.gpio_state.backend.set_mode(self.pin, mode);
     }
 
     #[inline]
     pub(crate) fn set_pullupdown(&mut self, pud: PullUpDown) {
-        self.gpio_state.gpio_mem.set_pullupdown(self.pin, pud);
+        self.gpio_state.backend.set_pullupdown(self.pin, pud);
     }
 
     #[inline]
     pub(crate) fn set_low(&mut self) {
-        self.gpio_state.gpio_mem.set_low(self.pin);
+        self.gpio_state.backend.set_low(self.pin);
     }
 
     #[inline]
     pub(crate) fn set_high(&mut self) {
-        self.gpio_state.gpio_mem.set_high(self.pin);
+        self.gpio_state.backend.set_high(self.pin);
     }
 
     #[inline]
@@ -400,7 +572,12 @@ impl Pin {
 
 impl Drop for Pin {
     fn drop(&mut self) {
+        // Stop any DMA-based PWM signal before the pin can be retrieved again
+        self.gpio_state.dma_pwm.lock().unwrap().clear(self.pin);
+
         // Release taken pin
+        self.gpio_state.backend.release(self.pin);
+        self.lock.take();
         self.gpio_state.pins_taken[self.pin as usize].store(false, Ordering::SeqCst);
     }
 }
@@ -433,6 +610,9 @@ pub struct InputPin {
     async_interrupt: Option<AsyncInterrupt>,
     reset_on_drop: bool,
     pud_mode: PullUpDown,
+    kernel_debounce: Option<Duration>,
+    event_clock: EventClock,
+    debounce: Option<Duration>,
 }
 
 impl InputPin {
@@ -454,6 +634,9 @@ impl InputPin {
             async_interrupt: None,
             reset_on_drop: true,
             pud_mode,
+            kernel_debounce: None,
+            event_clock: EventClock::Monotonic,
+            debounce: None,
         }
     }
 
@@ -473,7 +656,12 @@ impl InputPin {
         self.clear_async_interrupt()?;
 
         // Each pin can only be configured for a single trigger type
-        (*self.pin.gpio_state.sync_interrupts.lock().unwrap()).set_interrupt(self.pin(), trigger)
+        let config = self.event_config(trigger);
+        (*self.pin.gpio_state.sync_interrupts.lock().unwrap()).set_interrupt(
+            self.pin(),
+            config,
+            self.debounce,
+        )
     }
 
     /// Removes a previously configured synchronous interrupt trigger.
@@ -498,14 +686,18 @@ impl InputPin {
     /// for interrupt trigger events, after which an `Ok(None))` is returned.
     /// `timeout` can be set to `None` to wait indefinitely.
     ///
+    /// When an interrupt event is triggered, `poll_interrupt` returns `Ok(Some(`[`InterruptEvent`]`))`
+    /// containing the event's timestamp, edge, logic level and sequence number.
+    ///
     /// [`set_interrupt`]: #method.set_interrupt
     /// [`Gpio::poll_interrupts`]: struct.Gpio.html#method.poll_interrupts
     /// [`set_async_interrupt`]: #method.set_async_interrupt
+    /// [`InterruptEvent`]: struct.InterruptEvent.html
     pub fn poll_interrupt(
         &mut self,
         reset: bool,
         timeout: Option<Duration>,
-    ) -> Result<Option<Level>> {
+    ) -> Result<Option<InterruptEvent>> {
         let opt =
             (*self.pin.gpio_state.sync_interrupts.lock().unwrap()).poll(&[self], reset, timeout)?;
 
@@ -519,24 +711,25 @@ impl InputPin {
     /// Configures an asynchronous interrupt trigger, which executes the callback on a
     /// separate thread when the interrupt is triggered.
     ///
-    /// The callback closure or function pointer is called with a single [`Level`] argument.
+    /// The callback closure or function pointer is called with a single [`InterruptEvent`] argument.
     ///
     /// Any previously configured (a)synchronous interrupt triggers for this pin are cleared
     /// when `set_async_interrupt` is called, or when `InputPin` goes out of scope.
     ///
     /// [`clear_async_interrupt`]: #method.clear_async_interrupt
-    /// [`Level`]: enum.Level.html
+    /// [`InterruptEvent`]: struct.InterruptEvent.html
     pub fn set_async_interrupt<C>(&mut self, trigger: Trigger, callback: C) -> Result<()>
     where
-        C: FnMut(Level) + Send + 'static,
+        C: FnMut(InterruptEvent) + Send + 'static,
     {
         self.clear_interrupt()?;
         self.clear_async_interrupt()?;
 
         self.async_interrupt = Some(AsyncInterrupt::new(
-            self.pin.gpio_state.cdev.as_raw_fd(),
+            self.pin.gpio_state.backend.clone(),
             self.pin(),
-            trigger,
+            self.event_config(trigger),
+            self.debounce,
             callback,
         )?);
 
@@ -552,6 +745,289 @@ impl InputPin {
         Ok(())
     }
 
+    /// Returns a [`Stream`] of interrupt trigger events.
+    ///
+    /// Any previously configured (a)synchronous interrupt triggers for this pin are
+    /// cleared. The configured software debounce window, kernel debounce period and
+    /// event clock are applied to the stream.
+    ///
+    /// `interrupt_stream` requires the optional `async` feature, and must be called
+    /// from within a tokio runtime.
+    ///
+    /// [`Stream`]: struct.InterruptStream.html
+    #[cfg(feature = "async")]
+    pub fn interrupt_stream(&mut self, trigger: Trigger) -> Result<InterruptStream<'_>> {
+        self.clear_interrupt()?;
+        self.clear_async_interrupt()?;
+
+        InterruptStream::new(
+            self.pin.gpio_state.backend.clone(),
+            self.pin(),
+            self.event_config(trigger),
+            self.debounce,
+        )
+    }
+
+    /// Waits until an interrupt is triggered on the pin.
+    ///
+    /// `wait_for_edge` is the asynchronous equivalent of configuring a synchronous
+    /// interrupt trigger with [`set_interrupt`] and calling [`poll_interrupt`]. Any
+    /// previously configured (a)synchronous interrupt triggers for this pin are cleared.
+    /// Trigger events that occur before `wait_for_edge` is called aren't reported.
+    ///
+    /// Use [`tokio::time::timeout`] to stop waiting after a timeout period.
+    ///
+    /// `wait_for_edge` requires the optional `async` feature, and must be called
+    /// from within a tokio runtime.
+    ///
+    /// [`set_interrupt`]: #method.set_interrupt
+    /// [`poll_interrupt`]: #method.poll_interrupt
+    /// [`tokio::time::timeout`]: https://docs.rs/tokio/1/tokio/time/fn.timeout.html
+    #[cfg(feature = "async")]
+    pub async fn wait_for_edge(&mut self, trigger: Trigger) -> Result<InterruptEvent> {
+        self.interrupt_stream(trigger)?.next_event().await
+    }
+
+    /// Returns the software debounce window.
+    pub fn debounce(&self) -> Option<Duration> {
+        self.debounce
+    }
+
+    /// Configures a software debounce window.
+    ///
+    /// When a debounce window is set, any interrupt trigger events that occur within
+    /// `window` after a previously reported event are discarded, both for [`poll_interrupt`]
+    /// and [`Gpio::poll_interrupts`] results, and for [`set_async_interrupt`] callbacks.
+    /// Events are compared based on their timestamps, as described in [`Debouncer`].
+    /// If the pin's level changed within the window, for instance because a button
+    /// was released shortly after it was pressed, the change is reported once the
+    /// window ends. The window also applies to [`read_debounced`]. Set `window` to `None` to disable
+    /// debouncing.
+    ///
+    /// The new setting takes effect for interrupt triggers the next time [`set_interrupt`] or
+    /// [`set_async_interrupt`] is called.
+    ///
+    /// Unlike [`set_kernel_debounce`], software debouncing works on every kernel, but each
+    /// bounce still wakes up the interrupt thread.
+    ///
+    /// [`poll_interrupt`]: #method.poll_interrupt
+    /// [`Gpio::poll_interrupts`]: struct.Gpio.html#method.poll_interrupts
+    /// [`set_interrupt`]: #method.set_interrupt
+    /// [`set_async_interrupt`]: #method.set_async_interrupt
+    /// [`read_debounced`]: #method.read_debounced
+    /// [`set_kernel_debounce`]: #method.set_kernel_debounce
+    /// [`Debouncer`]: struct.Debouncer.html
+    pub fn set_debounce(&mut self, window: Option<Duration>) {
+        self.debounce = window;
+    }
+
+    /// Reads the pin's logic level once it has been stable for the duration of the
+    /// debounce window.
+    ///
+    /// `read_debounced` repeatedly samples the pin, and blocks until the logic level
+    /// hasn't changed for the window configured through [`set_debounce`]. If no window
+    /// is configured, `read_debounced` is identical to [`read`].
+    ///
+    /// [`set_debounce`]: #method.set_debounce
+    /// [`read`]: #method.read
+    pub fn read_debounced(&self) -> Level {
+        let window = match self.debounce {
+            Some(window) => window,
+            None => return self.read(),
+        };
+
+        let interval = (window / DEBOUNCE_SAMPLES).max(Duration::from_micros(10));
+
+        let mut level = self.read();
+        let mut stable_since = Instant::now();
+        loop {
+            thread::sleep(interval);
+
+            let current = self.read();
+            if current != level {
+                level = current;
+                stable_since = Instant::now();
+            } else if stable_since.elapsed() >= window {
+                return level;
+            }
+        }
+    }
+
+    /// Returns the kernel-side debounce period used for interrupt triggers.
+    pub fn kernel_debounce(&self) -> Option<Duration> {
+        self.kernel_debounce
+    }
+
+    /// Configures a kernel-side debounce period for interrupt triggers.
+    ///
+    /// Edges are only reported after the pin's logic level has been stable for
+    /// the specified period. Set `period` to `None` to disable debouncing.
+    ///
+    /// The debounce period is applied by the `gpiochip` character device, which requires
+    /// the GPIO uAPI v2 (Linux 5.10 or later). On older kernels, the period is ignored.
+    ///
+    /// The new setting takes effect the next time [`set_interrupt`] or
+    /// [`set_async_interrupt`] is called.
+    ///
+    /// [`set_interrupt`]: #method.set_interrupt
+    /// [`set_async_interrupt`]: #method.set_async_interrupt
+    pub fn set_kernel_debounce(&mut self, period: Option<Duration>) {
+        self.kernel_debounce = period;
+    }
+
+    /// Returns the clock used for interrupt event timestamps.
+    pub fn event_clock(&self) -> EventClock {
+        self.event_clock
+    }
+
+    /// Selects the clock used for interrupt event timestamps. By default, this is
+    /// set to [`Monotonic`].
+    ///
+    /// Selecting a clock requires the GPIO uAPI v2 (Linux 5.10 or later). On older
+    /// kernels, the kernel's default clock is used.
+    ///
+    /// The new setting takes effect the next time [`set_interrupt`] or
+    /// [`set_async_interrupt`] is called.
+    ///
+    /// [`Monotonic`]: enum.EventClock.html#variant.Monotonic
+    /// [`set_interrupt`]: #method.set_interrupt
+    /// [`set_async_interrupt`]: #method.set_async_interrupt
+    pub fn set_event_clock(&mut self, clock: EventClock) {
+        self.event_clock = clock;
+    }
+
+    /// Measures the width of a single pulse at the specified logic level.
+    ///
+    /// `measure_pulse` configures a synchronous interrupt trigger for both edges, waits
+    /// for the pin to change to `level`, and returns the time until it changes back,
+    /// based on the kernel timestamps of both edges. Any previously configured
+    /// (a)synchronous interrupt triggers are cleared, and the interrupt trigger is
+    /// removed when `measure_pulse` returns.
+    ///
+    /// The `timeout` duration covers the entire measurement, after which `Ok(None)` is
+    /// returned. `timeout` can be set to `None` to wait indefinitely.
+    ///
+    /// Edges that occur before `measure_pulse` is called aren't reported. When measuring
+    /// a response to a trigger signal, such as the echo pulse of an HC-SR04 ultrasonic
+    /// sensor, call `measure_pulse` immediately after sending the trigger signal.
+    pub fn measure_pulse(
+        &mut self,
+        level: Level,
+        timeout: Option<Duration>,
+    ) -> Result<Option<Duration>> {
+        self.set_interrupt(Trigger::Both)?;
+
+        let deadline = timeout.map(|timeout| Instant::now() + timeout);
+        let result = self.poll_pulse(level, deadline);
+
+        self.clear_interrupt()?;
+
+        result
+    }
+
+    fn poll_pulse(&mut self, level: Level, deadline: Option<Instant>) -> Result<Option<Duration>> {
+        let mut start = None;
+        loop {
+            let event = match self.poll_interrupt(false, remaining(deadline))? {
+                Some(event) => event,
+                None => return Ok(None),
+            };
+
+            if event.level == level {
+                start = Some(event.timestamp);
+            } else if let Some(start) = start.take() {
+                if let Some(width) = event.timestamp.checked_sub(start) {
+                    return Ok(Some(width));
+                }
+            }
+        }
+    }
+
+    /// Measures the pulse widths, period, frequency and duty cycle of a signal.
+    ///
+    /// `measure_signal` configures a synchronous interrupt trigger for both edges, and
+    /// feeds the events to a [`PulseMeter`] until `periods` complete periods have been
+    /// measured. Any previously configured (a)synchronous interrupt triggers are cleared,
+    /// and the interrupt trigger is removed when `measure_signal` returns.
+    ///
+    /// The `timeout` duration covers the entire measurement, after which `Ok(None)` is
+    /// returned. `timeout` can be set to `None` to wait indefinitely. A signal that
+    /// stays at the same logic level, such as a stopped fan's tachometer output,
+    /// always results in a timeout.
+    ///
+    /// [`PulseMeter`]: struct.PulseMeter.html
+    pub fn measure_signal(
+        &mut self,
+        periods: usize,
+        timeout: Option<Duration>,
+    ) -> Result<Option<PulseMeter>> {
+        self.set_interrupt(Trigger::Both)?;
+
+        let deadline = timeout.map(|timeout| Instant::now() + timeout);
+        let mut meter = PulseMeter::new(periods);
+        let result = loop {
+            if meter.period().map_or(0, |period| period.count) >= periods {
+                break Ok(Some(meter));
+            }
+
+            match self.poll_interrupt(false, remaining(deadline)) {
+                Ok(Some(event)) => meter.push(&event),
+                Ok(None) => break Ok(None),
+                Err(e) => break Err(e),
+            }
+        };
+
+        self.clear_interrupt()?;
+
+        result
+    }
+
+    /// Counts the interrupt trigger events that occur during the specified period.
+    ///
+    /// `count_edges` configures a synchronous interrupt trigger, and blocks for
+    /// `period` while counting events. Any previously configured (a)synchronous
+    /// interrupt triggers are cleared, and the interrupt trigger is removed when
+    /// `count_edges` returns. The configured debounce settings are applied.
+    ///
+    /// Divide the result by `period` to calculate the rate. For continuous monitoring,
+    /// feed an [`EdgeCounter`] from an asynchronous interrupt callback instead.
+    ///
+    /// [`EdgeCounter`]: struct.EdgeCounter.html
+    pub fn count_edges(&mut self, trigger: Trigger, period: Duration) -> Result<u64> {
+        self.set_interrupt(trigger)?;
+
+        let deadline = Some(Instant::now() + period);
+        let mut count = 0;
+        let result = loop {
+            match self.poll_interrupt(false, remaining(deadline)) {
+                Ok(Some(_)) => count += 1,
+                Ok(None) => break Ok(count),
+                Err(e) => break Err(e),
+            }
+        };
+
+        self.clear_interrupt()?;
+
+        result
+    }
+
+    fn event_config(&self, trigger: Trigger) -> EventConfig {
+        EventConfig {
+            trigger,
+            debounce: self.kernel_debounce,
+            clock: self.event_clock,
+        }
+    }
+
+    // Interrupt settings for types that poll the pin on their own thread, like Encoder
+    pub(crate) fn interrupt_config(&self, trigger: Trigger) -> (u8, EventConfig, Option<Duration>) {
+        (self.pin(), self.event_config(trigger), self.debounce)
+    }
+
+    pub(crate) fn backend(&self) -> Arc<dyn Backend> {
+        self.pin.gpio_state.backend.clone()
+    }
+
     impl_reset_on_drop!();
 }
 
@@ -585,11 +1061,15 @@ impl_eq!(InputPin);
 /// [`PwmPin`]: ../../embedded_hal/trait.PwmPin.html
 #[derive(Debug)]
 pub struct OutputPin {
+    // Dropped first, so the safe state is removed before the pin is released
+    safe_level: Option<(Level, Registration)>,
     pin: Pin,
     prev_mode: Option<Mode>,
     reset_on_drop: bool,
     pud_mode: PullUpDown,
-    pub(crate) soft_pwm: Option<SoftPwm>,
+    // Shared with the safe state, which stops the PWM thread
+    pub(crate) soft_pwm: Arc<Mutex<Option<SoftPwm>>>,
+    pwm_engine: PwmEngine,
     // Stores the softpwm frequency. Used for embedded_hal::PwmPin.
     #[cfg(feature = "hal")]
     pub(crate) frequency: f64,
@@ -614,7 +1094,9 @@ impl OutputPin {
             prev_mode,
             reset_on_drop: true,
             pud_mode: PullUpDown::Off,
-            soft_pwm: None,
+            soft_pwm: Arc::new(Mutex::new(None)),
+            pwm_engine: PwmEngine::Software,
+            safe_level: None,
             #[cfg(feature = "hal")]
             frequency: 0.0,
             #[cfg(feature = "hal")]
@@ -642,6 +1124,7 @@ impl OutputPin {
 
     impl_output!();
     impl_reset_on_drop!();
+    impl_safe_level!();
 }
 
 impl_drop!(OutputPin);
@@ -677,12 +1160,16 @@ impl_eq!(OutputPin);
 /// [`PwmPin`]: ../../embedded_hal/trait.PwmPin.html
 #[derive(Debug)]
 pub struct IoPin {
+    // Dropped first, so the safe state is removed before the pin is released
+    safe_level: Option<(Level, Registration)>,
     pin: Pin,
     mode: Mode,
     prev_mode: Option<Mode>,
     reset_on_drop: bool,
     pud_mode: PullUpDown,
-    pub(crate) soft_pwm: Option<SoftPwm>,
+    // Shared with the safe state, which stops the PWM thread
+    pub(crate) soft_pwm: Arc<Mutex<Option<SoftPwm>>>,
+    pwm_engine: PwmEngine,
     // Stores the softpwm frequency. Used for embedded_hal::PwmPin.
     #[cfg(feature = "hal")]
     pub(crate) frequency: f64,
@@ -708,7 +1195,9 @@ impl IoPin {
             prev_mode,
             reset_on_drop: true,
             pud_mode: PullUpDown::Off,
-            soft_pwm: None,
+            soft_pwm: Arc::new(Mutex::new(None)),
+            pwm_engine: PwmEngine::Software,
+            safe_level: None,
             #[cfg(feature = "hal")]
             frequency: 0.0,
             #[cfg(feature = "hal")]
@@ -745,9 +1234,48 @@ impl IoPin {
         self.pud_mode = pud;
     }
 
+    /// Applies the pin's mode and pad control settings.
+    ///
+    /// Fields set to `None` are left unchanged. If `config` selects an alternate function
+    /// that isn't available on this pin, `set_config` returns
+    /// `Err(`[`Error::FunctionNotAvailable`]`)`. If the alternate function's peripheral
+    /// signal is already routed to a different pin, `set_config` returns
+    /// `Err(`[`Error::FunctionConflict`]`)`. Signals the firmware routed to BCM GPIO 28-53,
+    /// such as the analog audio output on BCM GPIO 40 and 41, are only taken into account
+    /// if that pin is currently in use.
+    ///
+    /// Pad control settings apply to every pin in the same bank. If they differ from the
+    /// settings configured through another `IoPin` in the same bank that's still in use,
+    /// `set_config` returns `Err(`[`Error::PadConflict`]`)`. Nothing is changed when an
+    /// error is returned.
+    ///
+    /// [`Error::FunctionNotAvailable`]: enum.Error.html#variant.FunctionNotAvailable
+    /// [`Error::FunctionConflict`]: enum.Error.html#variant.FunctionConflict
+    /// [`Error::PadConflict`]: enum.Error.html#variant.PadConflict
+    pub fn set_config(&mut self, config: &PinConfig) -> Result<()> {
+        let gpio_state = self.pin.gpio_state.clone();
+        config::check(&gpio_state, self.pin.pin, config)?;
+
+        if config.has_pad_settings() {
+            let bank = config::pad_bank(self.pin.pin);
+            let current = gpio_state.backend.pad_control(bank)?;
+            gpio_state
+                .backend
+                .set_pad_control(bank, config.pad_control(current))?;
+            gpio_state.pad_owners.lock().unwrap()[bank] = Some(self.pin.pin);
+        }
+
+        if let Some(mode) = config.mode {
+            self.set_mode(mode);
+        }
+
+        Ok(())
+    }
+
     impl_input!();
     impl_output!();
     impl_reset_on_drop!();
+    impl_safe_level!();
 }
 
 impl_drop!(IoPin);
@@ -0,0 +1,708 @@
+use std::fmt;
+use std::mem;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use libc::{
+    self, c_int, c_void, AF_UNIX, MSG_DONTWAIT, MSG_NOSIGNAL, SOCK_CLOEXEC, SOCK_SEQPACKET,
+};
+
+use crate::gpio::backend::Backend;
+use crate::gpio::dma_pwm::{PwmOutput, PwmSchedule};
+use crate::gpio::soft_pwm::get_time_ns;
+use crate::gpio::{ioctl, pin, Level, Mode, PullUpDown, Result, Trigger};
+
+// Write end of a socket pair. The read end is handed to the interrupt code as the
+// event fd, so epoll and ioctl::get_event() work exactly like they do for the
+// gpiochip device.
+#[derive(Debug)]
+pub(crate) struct Listener {
+    trigger: Trigger,
+    fd: c_int,
+}
+
+impl Listener {
+    // Returns the listener together with the event handle for the read end
+    pub(crate) fn new(pin: u8, trigger: Trigger) -> Result<(Listener, ioctl::EventHandle)> {
+        let mut fds: [c_int; 2] = [0; 2];
+        parse_retval!(unsafe {
+            libc::socketpair(AF_UNIX, SOCK_SEQPACKET | SOCK_CLOEXEC, 0, fds.as_mut_ptr())
+        })?;
+
+        Ok((
+            Listener {
+                trigger,
+                fd: fds[1],
+            },
+            ioctl::EventHandle::V1(ioctl::EventRequest::from_fd(pin, trigger, fds[0])),
+        ))
+    }
+
+    // Returns false if the read end has been closed, and the listener should be removed
+    fn send(&self, event_data: &ioctl::EventData) -> bool {
+        let retval = unsafe {
+            libc::send(
+                self.fd,
+                event_data as *const ioctl::EventData as *const c_void,
+                mem::size_of::<ioctl::EventData>(),
+                MSG_DONTWAIT | MSG_NOSIGNAL,
+            )
+        };
+
+        // A full buffer only drops the event. Anything else means nobody is listening anymore.
+        retval != -1 || std::io::Error::last_os_error().kind() == std::io::ErrorKind::WouldBlock
+    }
+}
+
+impl Drop for Listener {
+    fn drop(&mut self) {
+        unsafe {
+            libc::close(self.fd);
+        }
+    }
+}
+
+// Sends a trigger event to all listeners that are interested in it, and removes
+// listeners whose read end has been closed
+pub(crate) fn notify(listeners: &mut Vec<Listener>, trigger: Trigger) {
+    let event_data = ioctl::EventData::with_trigger(trigger, get_time_ns() as u64);
+
+    listeners.retain(|listener| {
+        if listener.trigger == trigger || listener.trigger == Trigger::Both {
+            listener.send(&event_data)
+        } else {
+            true
+        }
+    });
+}
+
+#[derive(Debug)]
+struct SimPin {
+    mode: Mode,
+    output: Level,
+    input: Option<Level>,
+    pud: PullUpDown,
+    listeners: Vec<Listener>,
+}
+
+impl SimPin {
+    fn level(&self) -> Level {
+        if self.mode == Mode::Output {
+            return self.output;
+        }
+
+        // Undriven inputs settle to whatever the pull-up/pull-down resistor dictates
+        match (self.input, self.pud) {
+            (Some(level), _) => level,
+            (None, PullUpDown::PullUp) => Level::High,
+            (None, _) => Level::Low,
+        }
+    }
+
+    fn notify(&mut self, trigger: Trigger) {
+        notify(&mut self.listeners, trigger);
+    }
+
+    // Applies a change and reports an edge if the observed level changed as a result
+    fn update<F>(&mut self, f: F)
+    where
+        F: FnOnce(&mut SimPin),
+    {
+        let prev_level = self.level();
+        f(self);
+
+        match (prev_level, self.level()) {
+            (Level::Low, Level::High) => self.notify(Trigger::RisingEdge),
+            (Level::High, Level::Low) => self.notify(Trigger::FallingEdge),
+            _ => (),
+        }
+    }
+}
+
+// Upper limit for the number of times a device responds to a single pin change, in
+// case it keeps changing its own inputs
+const DEVICE_RESPONSES_MAX: usize = 16;
+
+// A device connected to the simulated pins, which is used to test bit-banged protocols.
+// respond() is called every time the pin types change a pin's mode, pull-up/pull-down
+// setting or output level, and before they read a level, so devices can change their
+// outputs over time. It receives the levels of all pins as seen by the pin types (bit n
+// representing BCM GPIO n). It returns the levels the device drives onto its pins, where
+// None stops driving a pin.
+pub(crate) trait SimDevice: fmt::Debug + Send {
+    fn respond(&mut self, levels: u64) -> Vec<(u8, Option<Level>)>;
+}
+
+fn levels(pins: &[SimPin]) -> u64 {
+    pins.iter()
+        .enumerate()
+        .filter(|(_, p)| p.level() == Level::High)
+        .fold(0, |levels, (pin, _)| levels | (1 << pin))
+}
+
+#[derive(Debug)]
+pub(crate) struct SimBackend {
+    pins: Mutex<Vec<SimPin>>,
+    pads: Mutex<[u32; 3]>,
+    dma_pwm: Arc<Mutex<Option<PwmSchedule>>>,
+    device: Mutex<Option<Box<dyn SimDevice>>>,
+}
+
+impl SimBackend {
+    pub(crate) fn new() -> SimBackend {
+        let mut pins = Vec::with_capacity(pin::MAX);
+        for _ in 0..pin::MAX {
+            pins.push(SimPin {
+                mode: Mode::Input,
+                output: Level::Low,
+                input: None,
+                pud: PullUpDown::Off,
+                listeners: Vec::new(),
+            });
+        }
+
+        SimBackend {
+            pins: Mutex::new(pins),
+            // Power-on default: 8 mA, hysteresis enabled, slew rate unlimited
+            pads: Mutex::new([0x1b; 3]),
+            dma_pwm: Arc::new(Mutex::new(None)),
+            device: Mutex::new(None),
+        }
+    }
+
+    fn with_pin<T, F>(&self, pin: u8, f: F) -> T
+    where
+        F: FnOnce(&mut SimPin) -> T,
+    {
+        f(&mut self.pins.lock().unwrap()[pin as usize])
+    }
+
+    // Same as with_pin, but lets the attached device respond to the change
+    fn change_pin<F>(&self, pin: u8, f: F)
+    where
+        F: FnOnce(&mut SimPin),
+    {
+        let mut pins = self.pins.lock().unwrap();
+        f(&mut pins[pin as usize]);
+        self.respond(&mut pins);
+    }
+
+    fn respond(&self, pins: &mut [SimPin]) {
+        let mut device = self.device.lock().unwrap();
+        let device = match *device {
+            Some(ref mut device) => device,
+            None => return,
+        };
+
+        for _ in 0..DEVICE_RESPONSES_MAX {
+            let mut changed = false;
+            for (pin, input) in device.respond(levels(pins)) {
+                let p = &mut pins[pin as usize];
+                if p.input != input {
+                    p.update(|p| p.input = input);
+                    changed = true;
+                }
+            }
+
+            if !changed {
+                break;
+            }
+        }
+    }
+}
+
+impl Backend for SimBackend {
+    fn set_high(&self, pin: u8) {
+        self.change_pin(pin, |p| p.update(|p| p.output = Level::High));
+    }
+
+    fn set_low(&self, pin: u8) {
+        self.change_pin(pin, |p| p.update(|p| p.output = Level::Low));
+    }
+
+    fn level(&self, pin: u8) -> Level {
+        let mut pins = self.pins.lock().unwrap();
+        self.respond(&mut pins);
+
+        pins[pin as usize].level()
+    }
+
+    // Updates all pins while holding the lock, so other threads never observe
+    // a partially written value
+    fn write_levels(&self, set_mask: u64, clear_mask: u64) {
+        let mut pins = self.pins.lock().unwrap();
+        for (pin, p) in pins.iter_mut().enumerate() {
+            if set_mask & (1 << pin) != 0 {
+                p.update(|p| p.output = Level::High);
+            } else if clear_mask & (1 << pin) != 0 {
+                p.update(|p| p.output = Level::Low);
+            }
+        }
+
+        self.respond(&mut pins);
+    }
+
+    fn levels(&self, mask: u64) -> u64 {
+        let mut pins = self.pins.lock().unwrap();
+        self.respond(&mut pins);
+
+        levels(&pins) & mask
+    }
+
+    fn mode(&self, pin: u8) -> Mode {
+        self.with_pin(pin, |p| p.mode)
+    }
+
+    fn set_mode(&self, pin: u8, mode: Mode) {
+        self.change_pin(pin, |p| p.update(|p| p.mode = mode));
+    }
+
+    fn set_pullupdown(&self, pin: u8, pud: PullUpDown) {
+        self.change_pin(pin, |p| p.update(|p| p.pud = pud));
+    }
+
+    fn pullupdown(&self, pin: u8) -> Option<PullUpDown> {
+        Some(self.with_pin(pin, |p| p.pud))
+    }
+
+    fn pad_control(&self, bank: usize) -> Result<u32> {
+        Ok(self.pads.lock().unwrap()[bank])
+    }
+
+    fn set_pad_control(&self, bank: usize, value: u32) -> Result<()> {
+        self.pads.lock().unwrap()[bank] = value;
+
+        Ok(())
+    }
+
+    fn dma_pwm(
+        &self,
+        _channel: u8,
+        _step: Duration,
+        _max_steps: usize,
+    ) -> Result<Box<dyn PwmOutput>> {
+        Ok(Box::new(SimPwmOutput {
+            schedule: self.dma_pwm.clone(),
+        }))
+    }
+
+    fn event_request(&self, pin: u8, config: &ioctl::EventConfig) -> Result<ioctl::EventHandle> {
+        let (listener, event_handle) = Listener::new(pin, config.trigger)?;
+        self.with_pin(pin, |p| p.listeners.push(listener));
+
+        Ok(event_handle)
+    }
+}
+
+// Records the schedule loaded into the DMA-based PWM engine instead of generating it
+#[derive(Debug)]
+struct SimPwmOutput {
+    schedule: Arc<Mutex<Option<PwmSchedule>>>,
+}
+
+impl PwmOutput for SimPwmOutput {
+    fn load(&mut self, schedule: &PwmSchedule) {
+        *self.schedule.lock().unwrap() = Some(schedule.clone());
+    }
+}
+
+impl Drop for SimPwmOutput {
+    fn drop(&mut self) {
+        *self.schedule.lock().unwrap() = None;
+    }
+}
+
+/// Controls a simulated GPIO peripheral.
+///
+/// A `Simulator` is returned together with a [`Gpio`] instance by [`Gpio::simulated`].
+/// Pins retrieved from that [`Gpio`] are backed by an in-memory register file instead of
+/// `/dev/gpiomem` and `/dev/gpiochipN`, which allows [`InputPin`], [`OutputPin`] and [`IoPin`]
+/// logic to run on machines that aren't a Raspberry Pi.
+///
+/// The `Simulator` acts as the outside world. It drives input levels, injects interrupt
+/// trigger events, and inspects the output levels, modes and pull-up/pull-down settings
+/// configured through the regular pin types.
+///
+/// Any level change on a pin, whether caused by [`set_level`], a pull-up/pull-down resistor
+/// or an output write, generates the matching edge event for configured (a)synchronous
+/// interrupt triggers.
+///
+/// All methods panic if `pin` isn't a valid BCM GPIO pin number (0-53).
+///
+/// [`Gpio`]: struct.Gpio.html
+/// [`Gpio::simulated`]: struct.Gpio.html#method.simulated
+/// [`InputPin`]: struct.InputPin.html
+/// [`OutputPin`]: struct.OutputPin.html
+/// [`IoPin`]: struct.IoPin.html
+/// [`set_level`]: #method.set_level
+#[derive(Debug, Clone)]
+pub struct Simulator {
+    backend: Arc<SimBackend>,
+}
+
+impl Simulator {
+    pub(crate) fn new(backend: Arc<SimBackend>) -> Simulator {
+        Simulator { backend }
+    }
+
+    /// Drives the pin's input to the specified logic level.
+    ///
+    /// The driven level is returned when the pin is read, unless its mode is set to
+    /// [`Output`].
+    ///
+    /// [`Output`]: enum.Mode.html#variant.Output
+    pub fn set_level(&self, pin: u8, level: Level) {
+        self.backend
+            .with_pin(pin, |p| p.update(|p| p.input = Some(level)));
+    }
+
+    /// Stops driving the pin's input.
+    ///
+    /// A floating input reads [`High`] when its pull-up resistor is enabled, and
+    /// [`Low`] otherwise.
+    ///
+    /// [`High`]: enum.Level.html#variant.High
+    /// [`Low`]: enum.Level.html#variant.Low
+    pub fn release(&self, pin: u8) {
+        self.backend.with_pin(pin, |p| p.update(|p| p.input = None));
+    }
+
+    /// Injects an interrupt trigger event without changing the pin's logic level.
+    ///
+    /// [`RisingEdge`] and [`FallingEdge`] inject a single event. [`Both`] injects a
+    /// rising edge followed by a falling edge. [`Disabled`] is ignored.
+    ///
+    /// [`RisingEdge`]: enum.Trigger.html#variant.RisingEdge
+    /// [`FallingEdge`]: enum.Trigger.html#variant.FallingEdge
+    /// [`Both`]: enum.Trigger.html#variant.Both
+    /// [`Disabled`]: enum.Trigger.html#variant.Disabled
+    pub fn inject_edge(&self, pin: u8, trigger: Trigger) {
+        self.backend.with_pin(pin, |p| match trigger {
+            Trigger::RisingEdge | Trigger::FallingEdge => p.notify(trigger),
+            Trigger::Both => {
+                p.notify(Trigger::RisingEdge);
+                p.notify(Trigger::FallingEdge);
+            }
+            Trigger::Disabled => (),
+        });
+    }
+
+    /// Returns the pin's current logic level, as seen by the pin types.
+    pub fn level(&self, pin: u8) -> Level {
+        self.backend.level(pin)
+    }
+
+    /// Returns the level stored in the pin's output latch, regardless of its mode.
+    pub fn output_level(&self, pin: u8) -> Level {
+        self.backend.with_pin(pin, |p| p.output)
+    }
+
+    /// Returns the pin's mode.
+    pub fn mode(&self, pin: u8) -> Mode {
+        self.backend.mode(pin)
+    }
+
+    /// Returns the pin's built-in pull-up/pull-down resistor state.
+    pub fn pullupdown(&self, pin: u8) -> PullUpDown {
+        self.backend.with_pin(pin, |p| p.pud)
+    }
+
+    /// Returns the [`PwmSchedule`] currently loaded into the DMA-based PWM engine,
+    /// or `None` if the engine isn't running.
+    ///
+    /// Simulated pins don't toggle their output level when PWM is generated through
+    /// [`PwmEngine::Dma`]. Use the returned schedule to inspect the waveform instead.
+    ///
+    /// [`PwmSchedule`]: struct.PwmSchedule.html
+    /// [`PwmEngine::Dma`]: enum.PwmEngine.html#variant.Dma
+    pub fn dma_pwm_schedule(&self) -> Option<PwmSchedule> {
+        self.backend.dma_pwm.lock().unwrap().clone()
+    }
+
+    // Connects a device to the pins, replacing any previously attached device. The
+    // device responds to the current levels right away.
+    #[cfg(test)]
+    pub(crate) fn attach(&self, device: Box<dyn SimDevice>) {
+        let mut pins = self.backend.pins.lock().unwrap();
+        *self.backend.device.lock().unwrap() = Some(device);
+        self.backend.respond(&mut pins);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc;
+
+    use super::*;
+    use crate::gpio::{Gpio, PwmEngine};
+
+    const TIMEOUT: Option<Duration> = Some(Duration::from_secs(1));
+
+    #[test]
+    fn inputs() {
+        let (gpio, sim) = Gpio::simulated().unwrap();
+        let pin = gpio.get(17).unwrap().into_input();
+
+        assert_eq!(sim.mode(17), Mode::Input);
+        assert_eq!(pin.read(), Level::Low);
+
+        sim.set_level(17, Level::High);
+        assert_eq!(pin.read(), Level::High);
+        assert_eq!(sim.level(17), Level::High);
+
+        sim.set_level(17, Level::Low);
+        assert_eq!(pin.read(), Level::Low);
+
+        // Floating inputs follow the pull-up/pull-down resistor
+        sim.release(17);
+        drop(pin);
+        let pin = gpio.get(17).unwrap().into_input_pullup();
+        assert_eq!(sim.pullupdown(17), PullUpDown::PullUp);
+        assert_eq!(pin.read(), Level::High);
+
+        // A driven level overrides the pull-up
+        sim.set_level(17, Level::Low);
+        assert_eq!(pin.read(), Level::Low);
+    }
+
+    #[test]
+    fn outputs() {
+        let (gpio, sim) = Gpio::simulated().unwrap();
+        let mut pin = gpio.get(22).unwrap().into_output();
+
+        assert_eq!(sim.mode(22), Mode::Output);
+        assert_eq!(sim.level(22), Level::Low);
+
+        pin.set_high();
+        assert_eq!(sim.level(22), Level::High);
+        assert_eq!(sim.output_level(22), Level::High);
+
+        // Driving an output pin doesn't change its level
+        sim.set_level(22, Level::Low);
+        assert_eq!(sim.level(22), Level::High);
+        assert!(pin.is_set_high());
+
+        pin.toggle();
+        assert_eq!(sim.level(22), Level::Low);
+
+        // The output latch is kept while the pin is an input
+        let mut io = gpio.get(23).unwrap().into_io(Mode::Output);
+        io.set_high();
+        io.set_mode(Mode::Input);
+        assert_eq!(sim.output_level(23), Level::High);
+        assert_eq!(io.read(), Level::Low);
+    }
+
+    #[test]
+    fn dma_pwm() {
+        let (gpio, sim) = Gpio::simulated().unwrap();
+        gpio.set_dma_pwm_config(5, Duration::from_micros(10));
+        let mut servo = gpio.get(18).unwrap().into_output();
+        let mut led = gpio.get(40).unwrap().into_output();
+        assert!(sim.dma_pwm_schedule().is_none());
+
+        servo.set_pwm_engine(PwmEngine::Dma).unwrap();
+        led.set_pwm_engine(PwmEngine::Dma).unwrap();
+        servo
+            .set_pwm(Duration::from_millis(20), Duration::from_micros(1500))
+            .unwrap();
+        led.set_pwm(Duration::from_millis(4), Duration::from_millis(1))
+            .unwrap();
+
+        let schedule = sim.dma_pwm_schedule().unwrap();
+        assert_eq!(schedule.step(), Duration::from_micros(10));
+        assert_eq!(schedule.steps(), 2000);
+        assert_eq!(schedule.cycle(), Duration::from_millis(20));
+
+        // Both pins are set at the start of the cycle, and cleared after their pulse width
+        assert_eq!(schedule.masks(0), ((1 << 18) | (1 << 40), 0));
+        assert_eq!(schedule.masks(100), (0, 1 << 40));
+        assert_eq!(schedule.masks(150), (0, 1 << 18));
+        assert_eq!(schedule.masks(400), (1 << 40, 0));
+        assert_eq!(schedule.masks(151), (0, 0));
+
+        // Clearing the last pin stops the engine
+        servo.clear_pwm().unwrap();
+        assert_eq!(sim.dma_pwm_schedule().unwrap().steps(), 400);
+        led.clear_pwm().unwrap();
+        assert!(sim.dma_pwm_schedule().is_none());
+    }
+
+    #[test]
+    fn interrupts() {
+        let (gpio, sim) = Gpio::simulated().unwrap();
+        let mut pin = gpio.get(5).unwrap().into_input();
+        pin.set_interrupt(Trigger::RisingEdge).unwrap();
+
+        // Only rising edges are reported
+        sim.set_level(5, Level::High);
+        sim.set_level(5, Level::Low);
+        sim.set_level(5, Level::High);
+
+        let first = pin.poll_interrupt(false, TIMEOUT).unwrap().unwrap();
+        assert_eq!(first.trigger, Trigger::RisingEdge);
+        assert_eq!(first.level, Level::High);
+        let second = pin.poll_interrupt(false, TIMEOUT).unwrap().unwrap();
+        assert_eq!(second.trigger, Trigger::RisingEdge);
+        assert!(second.timestamp >= first.timestamp);
+        assert_eq!(
+            pin.poll_interrupt(false, Some(Duration::from_millis(10)))
+                .unwrap(),
+            None
+        );
+
+        // Setting the same level again isn't an edge
+        sim.set_level(5, Level::High);
+        assert_eq!(
+            pin.poll_interrupt(false, Some(Duration::from_millis(10)))
+                .unwrap(),
+            None
+        );
+
+        // Injected edges don't change the level
+        pin.set_interrupt(Trigger::Both).unwrap();
+        sim.inject_edge(5, Trigger::Both);
+        let rising = pin.poll_interrupt(false, TIMEOUT).unwrap().unwrap();
+        let falling = pin.poll_interrupt(false, TIMEOUT).unwrap().unwrap();
+        assert_eq!(rising.trigger, Trigger::RisingEdge);
+        assert_eq!(falling.trigger, Trigger::FallingEdge);
+        assert_eq!(pin.read(), Level::High);
+
+        // A floating input without a pull-up resistor reads low
+        sim.release(5);
+        let falling = pin.poll_interrupt(false, TIMEOUT).unwrap().unwrap();
+        assert_eq!(falling.level, Level::Low);
+    }
+
+    #[test]
+    fn poll_interrupts() {
+        let (gpio, sim) = Gpio::simulated().unwrap();
+        let mut first = gpio.get(5).unwrap().into_input();
+        let mut second = gpio.get(6).unwrap().into_input();
+        first.set_interrupt(Trigger::Both).unwrap();
+        second.set_interrupt(Trigger::Both).unwrap();
+
+        sim.set_level(6, Level::High);
+
+        let (pin, event) = gpio
+            .poll_interrupts(&[&first, &second], false, TIMEOUT)
+            .unwrap()
+            .unwrap();
+        assert_eq!(pin.pin(), 6);
+        assert_eq!(event.level, Level::High);
+        assert_eq!(
+            gpio.poll_interrupts(&[&first, &second], false, Some(Duration::from_millis(10)))
+                .unwrap()
+                .map(|(pin, _)| pin.pin()),
+            None
+        );
+    }
+
+    #[test]
+    fn async_interrupts() {
+        let (gpio, sim) = Gpio::simulated().unwrap();
+        let mut pin = gpio.get(13).unwrap().into_input();
+
+        let (tx, rx) = mpsc::channel();
+        pin.set_async_interrupt(Trigger::FallingEdge, move |event| {
+            let _ = tx.send(event.level);
+        })
+        .unwrap();
+
+        sim.set_level(13, Level::High);
+        sim.set_level(13, Level::Low);
+        sim.inject_edge(13, Trigger::FallingEdge);
+
+        assert_eq!(rx.recv_timeout(TIMEOUT.unwrap()), Ok(Level::Low));
+        assert_eq!(rx.recv_timeout(TIMEOUT.unwrap()), Ok(Level::Low));
+
+        pin.clear_async_interrupt().unwrap();
+        sim.set_level(13, Level::High);
+        sim.set_level(13, Level::Low);
+        assert!(rx.recv_timeout(Duration::from_millis(10)).is_err());
+    }
+
+    #[cfg(feature = "async")]
+    fn block_on<F: std::future::Future>(future: F) -> F::Output {
+        tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap()
+            .block_on(future)
+    }
+
+    #[cfg(feature = "async")]
+    #[test]
+    fn interrupt_stream() {
+        let (gpio, sim) = Gpio::simulated().unwrap();
+        let mut pin = gpio.get(19).unwrap().into_input();
+
+        block_on(async {
+            let mut stream = pin.interrupt_stream(Trigger::Both).unwrap();
+
+            sim.set_level(19, Level::High);
+            sim.set_level(19, Level::Low);
+
+            let rising = stream.next_event().await.unwrap();
+            assert_eq!(rising.trigger, Trigger::RisingEdge);
+            assert_eq!(rising.level, Level::High);
+            let falling = stream.next_event().await.unwrap();
+            assert_eq!(falling.trigger, Trigger::FallingEdge);
+            assert_eq!(falling.level, Level::Low);
+
+            let timeout = tokio::time::timeout(Duration::from_millis(10), stream.next_event());
+            assert!(timeout.await.is_err());
+        });
+    }
+
+    #[cfg(feature = "async")]
+    #[test]
+    fn interrupt_stream_debounce() {
+        let (gpio, sim) = Gpio::simulated().unwrap();
+        let mut pin = gpio.get(19).unwrap().into_input();
+        pin.set_debounce(Some(Duration::from_millis(50)));
+
+        block_on(async {
+            let mut stream = pin.interrupt_stream(Trigger::Both).unwrap();
+
+            // The bounce is discarded, and the final level is reported once the
+            // window ends
+            sim.set_level(19, Level::High);
+            sim.set_level(19, Level::Low);
+            sim.set_level(19, Level::High);
+            sim.set_level(19, Level::Low);
+
+            let first = stream.next_event().await.unwrap();
+            assert_eq!(first.level, Level::High);
+            let settled = stream.next_event().await.unwrap();
+            assert_eq!(settled.level, Level::Low);
+        });
+    }
+
+    #[cfg(feature = "async")]
+    #[test]
+    fn wait_for_edge() {
+        let (gpio, sim) = Gpio::simulated().unwrap();
+        let mut pin = gpio.get(26).unwrap().into_input();
+
+        block_on(async {
+            // Nothing happens without an edge
+            let timeout = tokio::time::timeout(
+                Duration::from_millis(10),
+                pin.wait_for_edge(Trigger::RisingEdge),
+            );
+            assert!(timeout.await.is_err());
+
+            let driver = sim.clone();
+            let handle = std::thread::spawn(move || {
+                std::thread::sleep(Duration::from_millis(20));
+                driver.set_level(26, Level::High);
+            });
+
+            let event = pin.wait_for_edge(Trigger::RisingEdge).await.unwrap();
+            assert_eq!(event.trigger, Trigger::RisingEdge);
+            assert_eq!(event.level, Level::High);
+            handle.join().unwrap();
+        });
+    }
+}
@@ -0,0 +1,285 @@
+use std::fmt;
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use crate::gpio::backend::Backend;
+use crate::gpio::{pin, Level, Mode, PullUpDown};
+
+/// The state of a single GPIO pin at the time a [`Snapshot`] was taken.
+///
+/// [`Snapshot`]: struct.Snapshot.html
+#[derive(Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct PinState {
+    /// BCM GPIO pin number.
+    pub pin: u8,
+    /// Function select.
+    pub mode: Mode,
+    /// Logic level.
+    pub level: Level,
+    /// Built-in pull-up/pull-down resistor state, or `None` if the SoC can't read it back.
+    pub pullupdown: Option<PullUpDown>,
+    /// Label of the kernel driver or process that claimed the pin's GPIO line, or `None`
+    /// if the line isn't in use.
+    pub consumer: Option<String>,
+}
+
+impl fmt::Display for PinState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "GPIO{} {} {}", self.pin, self.mode, self.level)?;
+
+        match self.pullupdown {
+            Some(pud) => write!(f, " {}", pud)?,
+            None => write!(f, " -")?,
+        }
+
+        if let Some(ref consumer) = self.consumer {
+            write!(f, " {}", consumer)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// A change to a single GPIO pin between two [`Snapshot`]s.
+///
+/// `before` or `after` is `None` if the pin is missing from the corresponding
+/// snapshot, which happens when comparing snapshots taken on different SoCs.
+///
+/// [`Snapshot`]: struct.Snapshot.html
+#[derive(Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct PinChange {
+    /// BCM GPIO pin number.
+    pub pin: u8,
+    /// The pin's state in the original snapshot.
+    pub before: Option<PinState>,
+    /// The pin's state in the new snapshot.
+    pub after: Option<PinState>,
+}
+
+impl fmt::Display for PinChange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (&self.before, &self.after) {
+            (Some(before), Some(after)) => write!(f, "- {}\n+ {}", before, after),
+            (Some(before), None) => write!(f, "- {}", before),
+            (None, Some(after)) => write!(f, "+ {}", after),
+            (None, None) => write!(f, "GPIO{}", self.pin),
+        }
+    }
+}
+
+/// The state of every GPIO pin, captured by [`Gpio::snapshot`].
+///
+/// A `Snapshot` records each pin's mode, logic level, pull-up/pull-down state and
+/// consumer label without changing the pins, and regardless of whether they're
+/// currently in use. With the optional `serde` feature enabled, `Snapshot`
+/// implements `Serialize` and `Deserialize`, so snapshots can be stored and
+/// compared later with [`diff`].
+///
+/// [`Gpio::snapshot`]: struct.Gpio.html#method.snapshot
+/// [`diff`]: #method.diff
+#[derive(Debug, PartialEq, Eq, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Snapshot {
+    pins: Vec<PinState>,
+}
+
+impl Snapshot {
+    pub(crate) fn capture(backend: &dyn Backend) -> Snapshot {
+        let pins = (0..pin::MAX as u8)
+            .map(|pin| PinState {
+                pin,
+                mode: backend.mode(pin),
+                level: backend.level(pin),
+                pullupdown: backend.pullupdown(pin),
+                consumer: backend.consumer(pin),
+            })
+            .collect();
+
+        Snapshot { pins }
+    }
+
+    /// Returns the state of every pin, ordered by BCM GPIO pin number.
+    pub fn pins(&self) -> &[PinState] {
+        &self.pins
+    }
+
+    /// Returns the state of the specified BCM GPIO pin, or `None` if the snapshot
+    /// doesn't contain the pin.
+    pub fn get(&self, pin: u8) -> Option<&PinState> {
+        self.pins.iter().find(|state| state.pin == pin)
+    }
+
+    /// Returns the pins whose state differs between `self` and `other`, ordered by
+    /// BCM GPIO pin number.
+    pub fn diff(&self, other: &Snapshot) -> Vec<PinChange> {
+        let mut pins: Vec<u8> = self
+            .pins
+            .iter()
+            .chain(other.pins.iter())
+            .map(|state| state.pin)
+            .collect();
+        pins.sort_unstable();
+        pins.dedup();
+
+        pins.into_iter()
+            .filter_map(|pin| {
+                let before = self.get(pin);
+                let after = other.get(pin);
+
+                if before == after {
+                    None
+                } else {
+                    Some(PinChange {
+                        pin,
+                        before: before.cloned(),
+                        after: after.cloned(),
+                    })
+                }
+            })
+            .collect()
+    }
+}
+
+impl From<Vec<PinState>> for Snapshot {
+    fn from(mut pins: Vec<PinState>) -> Snapshot {
+        pins.sort_by_key(|state| state.pin);
+
+        Snapshot { pins }
+    }
+}
+
+impl fmt::Display for Snapshot {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for state in &self.pins {
+            writeln!(f, "{}", state)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gpio::Gpio;
+
+    fn state(pin: u8, mode: Mode, level: Level) -> PinState {
+        PinState {
+            pin,
+            mode,
+            level,
+            pullupdown: Some(PullUpDown::Off),
+            consumer: None,
+        }
+    }
+
+    #[test]
+    fn from_vec_sorts_pins() {
+        let snapshot = Snapshot::from(vec![
+            state(7, Mode::Input, Level::Low),
+            state(2, Mode::Output, Level::High),
+            state(4, Mode::Alt0, Level::Low),
+        ]);
+
+        let pins: Vec<u8> = snapshot.pins().iter().map(|state| state.pin).collect();
+        assert_eq!(pins, [2, 4, 7]);
+        assert_eq!(snapshot.get(4), Some(&state(4, Mode::Alt0, Level::Low)));
+        assert_eq!(snapshot.get(5), None);
+    }
+
+    #[test]
+    fn diff() {
+        let before = Snapshot::from(vec![
+            state(9, Mode::Input, Level::Low),
+            state(3, Mode::Input, Level::Low),
+            state(1, Mode::Input, Level::Low),
+            state(5, Mode::Output, Level::Low),
+        ]);
+
+        let mut consumer = state(5, Mode::Output, Level::Low);
+        consumer.consumer = Some("led".to_string());
+        let after = Snapshot::from(vec![
+            consumer.clone(),
+            state(9, Mode::Input, Level::High),
+            state(1, Mode::Input, Level::Low),
+            state(3, Mode::Alt5, Level::Low),
+        ]);
+
+        assert_eq!(before.diff(&before), []);
+        assert_eq!(
+            before.diff(&after),
+            [
+                PinChange {
+                    pin: 3,
+                    before: Some(state(3, Mode::Input, Level::Low)),
+                    after: Some(state(3, Mode::Alt5, Level::Low)),
+                },
+                PinChange {
+                    pin: 5,
+                    before: Some(state(5, Mode::Output, Level::Low)),
+                    after: Some(consumer),
+                },
+                PinChange {
+                    pin: 9,
+                    before: Some(state(9, Mode::Input, Level::Low)),
+                    after: Some(state(9, Mode::Input, Level::High)),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn diff_missing_pins() {
+        let before = Snapshot::from(vec![
+            state(0, Mode::Input, Level::Low),
+            state(40, Mode::Alt0, Level::Low),
+        ]);
+        let after = Snapshot::from(vec![
+            state(0, Mode::Input, Level::Low),
+            state(30, Mode::Input, Level::High),
+        ]);
+
+        let changes = before.diff(&after);
+        assert_eq!(
+            changes,
+            [
+                PinChange {
+                    pin: 30,
+                    before: None,
+                    after: Some(state(30, Mode::Input, Level::High)),
+                },
+                PinChange {
+                    pin: 40,
+                    before: Some(state(40, Mode::Alt0, Level::Low)),
+                    after: None,
+                },
+            ]
+        );
+
+        assert_eq!(changes[0].to_string(), "+ GPIO30 In High Off");
+        assert_eq!(changes[1].to_string(), "- GPIO40 Alt0 Low Off");
+    }
+
+    #[test]
+    fn simulated() {
+        let (gpio, sim) = Gpio::simulated().unwrap();
+        let before = gpio.snapshot();
+        assert_eq!(before.pins().len(), pin::MAX);
+
+        let mut pin = gpio.get(17).unwrap().into_output();
+        pin.set_high();
+        sim.set_level(4, Level::High);
+
+        let changes = before.diff(&gpio.snapshot());
+        let pins: Vec<u8> = changes.iter().map(|change| change.pin).collect();
+        assert_eq!(pins, [4, 17]);
+
+        let change = &changes[1];
+        assert_eq!(change.before.as_ref().unwrap().mode, Mode::Input);
+        assert_eq!(change.after.as_ref().unwrap().mode, Mode::Output);
+        assert_eq!(change.after.as_ref().unwrap().level, Level::High);
+    }
+}
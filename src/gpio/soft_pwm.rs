@@ -22,6 +22,8 @@
 #![allow(clippy::cast_lossless)]
 #![allow(dead_code)]
 
+#[cfg(test)]
+use std::cell::Cell;
 use std::ptr;
 use std::sync::mpsc::{self, Receiver, Sender};
 use std::sync::Arc;
@@ -65,37 +67,7 @@ impl SoftPwm {
         let (sender, receiver): (Sender<Msg>, Receiver<Msg>) = mpsc::channel();
 
         let pwm_thread = thread::spawn(move || -> Result<()> {
-            // Set the scheduling policy to real-time round robin at the highest priority. This
-            // will silently fail if we're not running as root.
-            #[cfg(target_env = "gnu")]
-            let params = sched_param {
-                sched_priority: unsafe { libc::sched_get_priority_max(SCHED_RR) },
-            };
-
-            #[cfg(target_env = "musl")]
-            let params = sched_param {
-                sched_priority: unsafe { libc::sched_get_priority_max(SCHED_RR) },
-                sched_ss_low_priority: 0,
-                sched_ss_repl_period: timespec {
-                    tv_sec: 0,
-                    tv_nsec: 0,
-                },
-                sched_ss_init_budget: timespec {
-                    tv_sec: 0,
-                    tv_nsec: 0,
-                },
-                sched_ss_max_repl: 0,
-            };
-
-            unsafe {
-                libc::sched_setscheduler(0, SCHED_RR, &params);
-            }
-
-            // Set timer slack to 1 ns (default = 50 µs). This is only relevant if we're unable
-            // to set a real-time scheduling policy.
-            unsafe {
-                libc::prctl(PR_SET_TIMERSLACK, 1);
-            }
+            set_realtime_priority();
 
             let mut period_ns = period.as_nanos() as i64;
             let mut pulse_width_ns = pulse_width.as_nanos() as i64;
@@ -105,7 +77,7 @@ impl SoftPwm {
             loop {
                 // PWM active
                 if pulse_width_ns > 0 {
-                    gpio_state.gpio_mem.set_high(pin);
+                    gpio_state.backend.set_high(pin);
                 }
 
                 // Sleep if we have enough time remaining, while reserving some time
@@ -123,7 +95,7 @@ impl SoftPwm {
                 }
 
                 // PWM inactive
-                gpio_state.gpio_mem.set_low(pin);
+                gpio_state.backend.set_low(pin);
 
                 while let Ok(msg) = receiver.try_recv() {
                     match msg {
@@ -201,8 +173,65 @@ impl Drop for SoftPwm {
 // safe because all usage of Sender::send() is locked behind &mut self.
 unsafe impl Sync for SoftPwm {}
 
+// Sets the current thread's scheduling policy to real-time round robin at the
+// highest priority. This will silently fail if we're not running as root.
+pub(crate) fn set_realtime_priority() {
+    #[cfg(target_env = "gnu")]
+    let params = sched_param {
+        sched_priority: unsafe { libc::sched_get_priority_max(SCHED_RR) },
+    };
+
+    #[cfg(target_env = "musl")]
+    let params = sched_param {
+        sched_priority: unsafe { libc::sched_get_priority_max(SCHED_RR) },
+        sched_ss_low_priority: 0,
+        sched_ss_repl_period: timespec {
+            tv_sec: 0,
+            tv_nsec: 0,
+        },
+        sched_ss_init_budget: timespec {
+            tv_sec: 0,
+            tv_nsec: 0,
+        },
+        sched_ss_max_repl: 0,
+    };
+
+    unsafe {
+        libc::sched_setscheduler(0, SCHED_RR, &params);
+    }
+
+    // Set timer slack to 1 ns (default = 50 µs). This is only relevant if we're unable
+    // to set a real-time scheduling policy.
+    unsafe {
+        libc::prctl(PR_SET_TIMERSLACK, 1);
+    }
+}
+
+#[cfg(test)]
+thread_local! {
+    // Simulated time for the current thread, which keeps tests of bit-banged
+    // protocols from depending on the scheduler. A const initializer would
+    // require Rust 1.59.
+    #[allow(clippy::missing_const_for_thread_local)]
+    static SIM_TIME_NS: Cell<Option<i64>> = Cell::new(None);
+}
+
+// Switches the current thread to simulated time, which only advances when
+// wait_until_ns() is called
+#[cfg(test)]
+pub(crate) fn simulate_time() {
+    SIM_TIME_NS.with(|time| time.set(Some(0)));
+}
+
 #[inline(always)]
-fn get_time_ns() -> i64 {
+pub(crate) fn get_time_ns() -> i64 {
+    #[cfg(test)]
+    {
+        if let Some(time_ns) = SIM_TIME_NS.with(Cell::get) {
+            return time_ns;
+        }
+    }
+
     let mut ts = timespec {
         tv_sec: 0,
         tv_nsec: 0,
@@ -216,7 +245,7 @@ fn get_time_ns() -> i64 {
 }
 
 #[inline(always)]
-fn sleep_ns(ns: i64) {
+pub(crate) fn sleep_ns(ns: i64) {
     let ts = timespec {
         tv_sec: (ns / NANOS_PER_SEC) as time_t,
         tv_nsec: (ns % NANOS_PER_SEC) as c_long,
@@ -226,3 +255,27 @@ fn sleep_ns(ns: i64) {
         libc::clock_nanosleep(CLOCK_MONOTONIC, 0, &ts, ptr::null_mut());
     }
 }
+
+// Sleeps until shortly before target_ns, and busy-waits for the remainder
+#[inline(always)]
+pub(crate) fn wait_until_ns(target_ns: i64) {
+    #[cfg(test)]
+    {
+        if let Some(time_ns) = SIM_TIME_NS.with(Cell::get) {
+            SIM_TIME_NS.with(|time| time.set(Some(time_ns.max(target_ns))));
+            return;
+        }
+    }
+
+    let remaining_ns = target_ns - get_time_ns();
+
+    // Sleep if we have enough time remaining, while reserving some time
+    // for busy waiting to compensate for sleep taking longer than needed.
+    if remaining_ns >= SLEEP_THRESHOLD {
+        sleep_ns(remaining_ns - BUSYWAIT_MAX);
+    }
+
+    // Busy-wait for the remaining time, minus BUSYWAIT_REMAINDER
+    // to account for get_time_ns() overhead
+    while target_ns - get_time_ns() > BUSYWAIT_REMAINDER {}
+}
@@ -0,0 +1,635 @@
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use crate::gpio::backend::Backend;
+use crate::gpio::soft_pwm::{get_time_ns, set_realtime_priority, wait_until_ns};
+use crate::gpio::{pin, Error, OutputPin, Result};
+
+const NANOS_PER_SEC: f64 = 1_000_000_000.0;
+
+// Returns the mask bit for a single pin
+fn pin_bit(pin: u8) -> Result<u64> {
+    if pin as usize >= pin::MAX {
+        return Err(Error::PinNotAvailable(pin, None));
+    }
+
+    Ok(1 << pin)
+}
+
+/// A single step of a [`Waveform`].
+///
+/// The pins in `set_mask` are set high and the pins in `clear_mask` are set low,
+/// with bit n representing BCM GPIO n. The next pulse starts `delay` after this
+/// pulse started. A pin that's present in both masks is set high.
+///
+/// [`Waveform`]: struct.Waveform.html
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub struct Pulse {
+    /// Pins that are set high.
+    pub set_mask: u64,
+    /// Pins that are set low.
+    pub clear_mask: u64,
+    /// Time until the next pulse.
+    pub delay: Duration,
+}
+
+impl Pulse {
+    /// Constructs a new `Pulse`.
+    pub fn new(set_mask: u64, clear_mask: u64, delay: Duration) -> Pulse {
+        Pulse {
+            set_mask,
+            clear_mask,
+            delay,
+        }
+    }
+}
+
+/// A sequence of timed pulses on one or more GPIO pins.
+///
+/// A `Waveform` is built from [`Pulse`]s, each of which changes the output state for a
+/// group of pins at once and then waits for the specified delay. Waveforms can be
+/// concatenated with [`append`] and repeated with [`repeat`], and are sent to the pins
+/// of a [`WaveformEngine`].
+///
+/// Building a `Waveform` doesn't access any hardware.
+///
+/// ## Example
+///
+/// ```
+/// use std::time::Duration;
+///
+/// use rpi_embedded::gpio::Waveform;
+///
+/// # fn main() -> rpi_embedded::gpio::Result<()> {
+/// // 200 step pulses for a stepper driver on BCM GPIO 20, with the direction on BCM GPIO 21
+/// let mut steps = Waveform::new();
+/// steps
+///     .high(20, Duration::from_micros(10))?
+///     .low(20, Duration::from_micros(490))?
+///     .repeat(200);
+///
+/// let mut waveform = Waveform::new();
+/// waveform.high(21, Duration::from_micros(5))?.append(&steps);
+///
+/// assert_eq!(waveform.len(), 401);
+/// assert_eq!(waveform.duration(), Duration::from_micros(100_005));
+/// assert_eq!(waveform.pin_mask(), (1 << 20) | (1 << 21));
+/// # Ok(())
+/// # }
+/// ```
+///
+/// [`Pulse`]: struct.Pulse.html
+/// [`append`]: #method.append
+/// [`repeat`]: #method.repeat
+/// [`WaveformEngine`]: struct.WaveformEngine.html
+#[derive(Debug, Default, PartialEq, Eq, Clone)]
+pub struct Waveform {
+    pulses: Vec<Pulse>,
+}
+
+impl Waveform {
+    /// Constructs a new, empty `Waveform`.
+    pub fn new() -> Waveform {
+        Waveform { pulses: Vec::new() }
+    }
+
+    /// Adds a pulse that sets the pins in `set_mask` high and the pins in
+    /// `clear_mask` low, and then waits for `delay`.
+    pub fn pulse(&mut self, set_mask: u64, clear_mask: u64, delay: Duration) -> &mut Waveform {
+        self.pulses.push(Pulse::new(set_mask, clear_mask, delay));
+        self
+    }
+
+    /// Adds a pulse that sets a single pin high, and then waits for `delay`.
+    ///
+    /// Returns `Err(`[`Error::PinNotAvailable`]`)` if `pin` isn't a valid BCM GPIO
+    /// pin number.
+    ///
+    /// [`Error::PinNotAvailable`]: enum.Error.html#variant.PinNotAvailable
+    pub fn high(&mut self, pin: u8, delay: Duration) -> Result<&mut Waveform> {
+        Ok(self.pulse(pin_bit(pin)?, 0, delay))
+    }
+
+    /// Adds a pulse that sets a single pin low, and then waits for `delay`.
+    ///
+    /// Returns `Err(`[`Error::PinNotAvailable`]`)` if `pin` isn't a valid BCM GPIO
+    /// pin number.
+    ///
+    /// [`Error::PinNotAvailable`]: enum.Error.html#variant.PinNotAvailable
+    pub fn low(&mut self, pin: u8, delay: Duration) -> Result<&mut Waveform> {
+        Ok(self.pulse(0, pin_bit(pin)?, delay))
+    }
+
+    /// Adds a pulse that waits for `delay` without changing any pins.
+    pub fn delay(&mut self, delay: Duration) -> &mut Waveform {
+        self.pulse(0, 0, delay)
+    }
+
+    /// Adds a square wave on a single pin, such as the carrier for an IR remote
+    /// control.
+    ///
+    /// `frequency` is specified in hertz (Hz). `duty_cycle` is specified as a floating
+    /// point value between `0.0` (0%) and `1.0` (100%). The carrier is generated for
+    /// as many whole cycles as fit within `duration`, and the pin is left low.
+    ///
+    /// Returns `Err(`[`Error::PinNotAvailable`]`)` if `pin` isn't a valid BCM GPIO
+    /// pin number.
+    ///
+    /// [`Error::PinNotAvailable`]: enum.Error.html#variant.PinNotAvailable
+    pub fn carrier(
+        &mut self,
+        pin: u8,
+        frequency: f64,
+        duty_cycle: f64,
+        duration: Duration,
+    ) -> Result<&mut Waveform> {
+        let bit = pin_bit(pin)?;
+        if frequency <= 0.0 {
+            return Ok(self);
+        }
+
+        let period_ns = NANOS_PER_SEC / frequency;
+        let pulse_width_ns = (period_ns * duty_cycle.clamp(0.0, 1.0)) as u64;
+        let period_ns = period_ns as u64;
+        if period_ns == 0 {
+            return Ok(self);
+        }
+
+        let cycles = duration.as_nanos() as u64 / period_ns;
+        for _ in 0..cycles {
+            self.pulse(bit, 0, Duration::from_nanos(pulse_width_ns));
+            self.pulse(0, bit, Duration::from_nanos(period_ns - pulse_width_ns));
+        }
+
+        Ok(self)
+    }
+
+    /// Adds all pulses from `other` to the end of this `Waveform`.
+    pub fn append(&mut self, other: &Waveform) -> &mut Waveform {
+        self.pulses.extend_from_slice(&other.pulses);
+        self
+    }
+
+    /// Repeats the current pulses, so they're sent `count` times in total.
+    ///
+    /// Setting `count` to `0` removes all pulses.
+    pub fn repeat(&mut self, count: usize) -> &mut Waveform {
+        self.pulses = self.pulses.repeat(count);
+        self
+    }
+
+    /// Returns the pulses.
+    pub fn pulses(&self) -> &[Pulse] {
+        &self.pulses
+    }
+
+    /// Returns the number of pulses.
+    pub fn len(&self) -> usize {
+        self.pulses.len()
+    }
+
+    /// Returns `true` if the `Waveform` doesn't contain any pulses.
+    pub fn is_empty(&self) -> bool {
+        self.pulses.is_empty()
+    }
+
+    /// Returns the total duration.
+    pub fn duration(&self) -> Duration {
+        self.pulses.iter().map(|pulse| pulse.delay).sum()
+    }
+
+    /// Returns a mask of all pins changed by the `Waveform`, with bit n
+    /// representing BCM GPIO n.
+    pub fn pin_mask(&self) -> u64 {
+        self.pulses
+            .iter()
+            .fold(0, |mask, pulse| mask | pulse.set_mask | pulse.clear_mask)
+    }
+}
+
+impl From<Vec<Pulse>> for Waveform {
+    fn from(pulses: Vec<Pulse>) -> Waveform {
+        Waveform { pulses }
+    }
+}
+
+#[derive(Debug)]
+struct Entry {
+    pulses: Arc<[Pulse]>,
+    looped: bool,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    queue: VecDeque<Entry>,
+    busy: bool,
+    looped: bool,
+    exit: bool,
+}
+
+#[derive(Debug, Default)]
+struct Shared {
+    state: Mutex<State>,
+    cond: Condvar,
+    // Checked before every pulse, so stop() doesn't have to wait for the current waveform
+    abort: AtomicBool,
+}
+
+/// Sends [`Waveform`]s to a group of GPIO pins configured as outputs.
+///
+/// `WaveformEngine`s are constructed by calling [`Gpio::waveform_engine`]. Waveforms
+/// are sent by a separate thread that runs at the highest real-time priority when
+/// possible, and combines sleep with busy-waiting like the software-based PWM
+/// implementation. Each pulse updates all of its pins with a single write to the
+/// `GPSETn` and `GPCLRn` registers. Pulse timing is based on the start of the
+/// waveform rather than on the previous pulse, so any delays don't accumulate.
+///
+/// Queued waveforms are sent back to back without a gap. A looped waveform is repeated
+/// until another waveform is queued, at which point the loop ends after its current
+/// repetition, or until [`stop`] is called.
+///
+/// Like software-based PWM, timing is subject to scheduling and preemption by the OS.
+/// Expect jitter of a few microseconds on release builds, which is sufficient for stepper
+/// drivers, IR remote controls and most custom protocols, but not for protocols that need
+/// sub-microsecond timing.
+///
+/// [`Waveform`]: struct.Waveform.html
+/// [`Gpio::waveform_engine`]: struct.Gpio.html#method.waveform_engine
+/// [`stop`]: #method.stop
+#[derive(Debug)]
+pub struct WaveformEngine {
+    pins: Vec<OutputPin>,
+    pin_mask: u64,
+    shared: Arc<Shared>,
+    thread: Option<thread::JoinHandle<()>>,
+}
+
+impl WaveformEngine {
+    pub(crate) fn new(pins: Vec<OutputPin>, backend: Arc<dyn Backend>) -> WaveformEngine {
+        let pin_mask = pins.iter().fold(0, |mask, pin| mask | (1 << pin.pin()));
+        let shared = Arc::new(Shared::default());
+
+        let thread_shared = shared.clone();
+        let thread = thread::spawn(move || run(&*backend, &thread_shared));
+
+        WaveformEngine {
+            pins,
+            pin_mask,
+            shared,
+            thread: Some(thread),
+        }
+    }
+
+    /// Returns the BCM GPIO pin numbers.
+    pub fn pins(&self) -> Vec<u8> {
+        self.pins.iter().map(|pin| pin.pin()).collect()
+    }
+
+    /// Adds a `Waveform` to the end of the queue. The `Waveform` is sent once.
+    ///
+    /// Returns `Err(`[`Error::PinNotAvailable`]`)` if the `Waveform` changes any pins
+    /// that don't belong to this `WaveformEngine`.
+    ///
+    /// [`Error::PinNotAvailable`]: enum.Error.html#variant.PinNotAvailable
+    pub fn send(&mut self, waveform: &Waveform) -> Result<()> {
+        self.queue(waveform, false)
+    }
+
+    /// Adds a `Waveform` to the end of the queue. The `Waveform` is repeated until
+    /// another `Waveform` is queued, or until [`stop`] is called.
+    ///
+    /// Returns `Err(`[`Error::PinNotAvailable`]`)` if the `Waveform` changes any pins
+    /// that don't belong to this `WaveformEngine`.
+    ///
+    /// [`stop`]: #method.stop
+    /// [`Error::PinNotAvailable`]: enum.Error.html#variant.PinNotAvailable
+    pub fn send_looped(&mut self, waveform: &Waveform) -> Result<()> {
+        self.queue(waveform, true)
+    }
+
+    /// Returns `true` if a `Waveform` is currently being sent.
+    pub fn is_busy(&self) -> bool {
+        let state = self.shared.state.lock().unwrap();
+
+        state.busy || !state.queue.is_empty()
+    }
+
+    /// Blocks until all queued waveforms have been sent, or until a looped
+    /// `Waveform` is being repeated.
+    pub fn wait(&self) {
+        let mut state = self.shared.state.lock().unwrap();
+        while !state.queue.is_empty() || (state.busy && !state.looped) {
+            state = self.shared.cond.wait(state).unwrap();
+        }
+    }
+
+    /// Stops the current `Waveform` after its current pulse, and clears the queue.
+    ///
+    /// The pins keep the output state set by the last pulse that was sent.
+    pub fn stop(&mut self) {
+        let mut state = self.shared.state.lock().unwrap();
+        state.queue.clear();
+
+        self.shared.abort.store(true, Ordering::SeqCst);
+        while state.busy {
+            state = self.shared.cond.wait(state).unwrap();
+        }
+        self.shared.abort.store(false, Ordering::SeqCst);
+    }
+
+    /// When enabled, resets every pin's mode to its original state and disables the
+    /// built-in pull-up/pull-down resistors when the `WaveformEngine` goes out of scope.
+    /// By default, this is set to `true`.
+    pub fn set_reset_on_drop(&mut self, reset_on_drop: bool) {
+        for pin in &mut self.pins {
+            pin.set_reset_on_drop(reset_on_drop);
+        }
+    }
+
+    /// Stops the current `Waveform`, and returns the individual pins.
+    pub fn into_pins(mut self) -> Vec<OutputPin> {
+        self.exit();
+
+        std::mem::take(&mut self.pins)
+    }
+
+    fn queue(&mut self, waveform: &Waveform, looped: bool) -> Result<()> {
+        let foreign = waveform.pin_mask() & !self.pin_mask;
+        if foreign != 0 {
+            return Err(Error::PinNotAvailable(foreign.trailing_zeros() as u8, None));
+        }
+
+        if waveform.is_empty() {
+            return Ok(());
+        }
+
+        let mut state = self.shared.state.lock().unwrap();
+        state.queue.push_back(Entry {
+            pulses: waveform.pulses().into(),
+            looped,
+        });
+        self.shared.cond.notify_all();
+
+        Ok(())
+    }
+
+    fn exit(&mut self) {
+        self.stop();
+
+        self.shared.state.lock().unwrap().exit = true;
+        self.shared.cond.notify_all();
+
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+impl Drop for WaveformEngine {
+    fn drop(&mut self) {
+        // Don't wait for the waveform thread if we're panicking, for the same
+        // reasons as SoftPwm
+        if !thread::panicking() {
+            self.exit();
+        }
+    }
+}
+
+fn run(backend: &dyn Backend, shared: &Shared) {
+    set_realtime_priority();
+
+    let mut state = shared.state.lock().unwrap();
+    // Start time of the next pulse. Continues across queued waveforms so they're sent back to back.
+    let mut next_ns = 0;
+
+    loop {
+        let entry = match state.queue.pop_front() {
+            Some(entry) => entry,
+            None => {
+                state.busy = false;
+                shared.cond.notify_all();
+
+                if state.exit {
+                    return;
+                }
+
+                state = shared.cond.wait(state).unwrap();
+                next_ns = 0;
+                continue;
+            }
+        };
+
+        state.busy = true;
+        state.looped = entry.looped;
+        shared.cond.notify_all();
+        drop(state);
+
+        if next_ns == 0 {
+            next_ns = get_time_ns();
+        }
+
+        'waveform: loop {
+            for pulse in entry.pulses.iter() {
+                if shared.abort.load(Ordering::SeqCst) {
+                    break 'waveform;
+                }
+
+                wait_until_ns(next_ns);
+                backend.write_levels(pulse.set_mask, pulse.clear_mask & !pulse.set_mask);
+                next_ns += pulse.delay.as_nanos() as i64;
+            }
+
+            if !entry.looped || !shared.state.lock().unwrap().queue.is_empty() {
+                break;
+            }
+        }
+
+        // Let the delay of the final pulse run out before reporting we're done
+        if !shared.abort.load(Ordering::SeqCst) {
+            wait_until_ns(next_ns);
+        }
+
+        state = shared.state.lock().unwrap();
+        if shared.abort.load(Ordering::SeqCst) {
+            next_ns = 0;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use super::*;
+    use crate::gpio::sim::SimDevice;
+    use crate::gpio::{Gpio, Level};
+
+    const MS: Duration = Duration::from_millis(1);
+    // Lower bound for a 1 ms delay, to account for timer granularity
+    const MS_MIN_NS: i64 = 900_000;
+
+    // Timestamps and levels of the recorded changes
+    type Records = Arc<Mutex<Vec<(i64, u64)>>>;
+
+    // Records the levels of the selected pins every time they change
+    #[derive(Debug)]
+    struct Recorder {
+        mask: u64,
+        levels: Option<u64>,
+        records: Records,
+    }
+
+    impl SimDevice for Recorder {
+        fn respond(&mut self, levels: u64) -> Vec<(u8, Option<Level>)> {
+            let levels = levels & self.mask;
+            if self.levels != Some(levels) {
+                self.levels = Some(levels);
+                self.records.lock().unwrap().push((get_time_ns(), levels));
+            }
+
+            Vec::new()
+        }
+    }
+
+    fn engine(pins: &[u8]) -> (WaveformEngine, Records) {
+        let (gpio, sim) = Gpio::simulated().unwrap();
+        let engine = gpio.waveform_engine(pins).unwrap();
+
+        let records = Arc::new(Mutex::new(Vec::new()));
+        sim.attach(Box::new(Recorder {
+            mask: pins.iter().fold(0, |mask, pin| mask | (1 << pin)),
+            levels: None,
+            records: records.clone(),
+        }));
+
+        (engine, records)
+    }
+
+    fn levels(records: &Mutex<Vec<(i64, u64)>>) -> Vec<u64> {
+        records
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|&(_, levels)| levels)
+            .collect()
+    }
+
+    #[test]
+    fn invalid_pins() {
+        let mut waveform = Waveform::new();
+        assert!(matches!(
+            waveform.high(54, MS),
+            Err(Error::PinNotAvailable(54, None))
+        ));
+        assert!(matches!(
+            waveform.low(64, MS),
+            Err(Error::PinNotAvailable(64, None))
+        ));
+        assert!(matches!(
+            waveform.carrier(255, 38_000.0, 0.5, MS),
+            Err(Error::PinNotAvailable(255, None))
+        ));
+        assert!(waveform.is_empty());
+
+        waveform.high(53, MS).unwrap();
+        assert_eq!(waveform.pin_mask(), 1 << 53);
+    }
+
+    #[test]
+    fn send() {
+        let (mut engine, records) = engine(&[20, 21]);
+
+        let mut waveform = Waveform::new();
+        waveform
+            .high(20, MS)
+            .unwrap()
+            .pulse(1 << 21, 1 << 20, MS)
+            .pulse((1 << 20) | (1 << 21), 0, MS)
+            .pulse(0, (1 << 20) | (1 << 21), MS);
+
+        // Pins that don't belong to the engine are rejected
+        let mut foreign = Waveform::new();
+        foreign.high(22, MS).unwrap();
+        assert!(matches!(
+            engine.send(&foreign),
+            Err(Error::PinNotAvailable(22, None))
+        ));
+
+        let start = get_time_ns();
+        engine.send(&waveform).unwrap();
+        engine.wait();
+        assert!(!engine.is_busy());
+
+        // Each pulse changes both pins with a single write
+        assert_eq!(
+            levels(&records),
+            [0, 1 << 20, 1 << 21, (1 << 20) | (1 << 21), 0]
+        );
+
+        // Pulses are scheduled relative to the start of the waveform, so a late write
+        // shortens the next delay instead of pushing back the remaining pulses
+        let records = records.lock().unwrap();
+        for (index, pulse) in records[1..].iter().enumerate() {
+            assert!(pulse.0 - start >= index as i64 * MS_MIN_NS);
+        }
+        assert!(get_time_ns() - start >= 4 * MS_MIN_NS);
+    }
+
+    #[test]
+    fn queue() {
+        let (mut engine, records) = engine(&[5, 6]);
+
+        let mut first = Waveform::new();
+        first.high(5, MS).unwrap().low(5, MS).unwrap();
+        let mut second = Waveform::new();
+        second.high(6, MS).unwrap().low(6, MS).unwrap();
+
+        engine.send(&first).unwrap();
+        engine.send(&second).unwrap();
+        engine.wait();
+
+        assert_eq!(levels(&records), [0, 1 << 5, 0, 1 << 6, 0]);
+    }
+
+    #[test]
+    fn repeat_and_stop() {
+        let (mut engine, records) = engine(&[12]);
+
+        let mut waveform = Waveform::new();
+        waveform
+            .high(12, MS)
+            .unwrap()
+            .low(12, MS)
+            .unwrap()
+            .repeat(3);
+        assert_eq!(waveform.len(), 6);
+
+        engine.send(&waveform).unwrap();
+        engine.wait();
+        assert_eq!(levels(&records), [0, 1 << 12, 0, 1 << 12, 0, 1 << 12, 0]);
+        records.lock().unwrap().clear();
+
+        // A looped waveform keeps going until it's stopped
+        let mut waveform = Waveform::new();
+        waveform.high(12, MS).unwrap().low(12, MS).unwrap();
+        engine.send_looped(&waveform).unwrap();
+        engine.wait();
+        thread::sleep(MS * 20);
+        assert!(engine.is_busy());
+        engine.stop();
+        assert!(!engine.is_busy());
+
+        let stopped = levels(&records);
+        assert!(stopped.len() >= 6);
+        for (idx, &levels) in stopped.iter().enumerate() {
+            assert_eq!(levels, if idx % 2 == 0 { 1 << 12 } else { 0 });
+        }
+
+        // Nothing is written after stop() returns
+        thread::sleep(MS * 5);
+        assert_eq!(levels(&records), stopped);
+    }
+}
@@ -88,6 +88,12 @@
 //! device tree overlay. More details on enabling and configuring `i2c-gpio`
 //! can be found in `/boot/overlays/README`.
 //!
+//! Alternatively, [`SoftI2c`] drives a bit-banged I2C bus directly from user
+//! space, without any device tree changes. It supports clock stretching at any
+//! point during the transfer, and can recover a bus that's held low by a slave
+//! device. Both [`I2c`] and [`SoftI2c`] implement the [`I2cBus`] trait, so device
+//! drivers that are generic over [`I2cBus`] work with either.
+//!
 //! ## Transmission speed
 //!
 //! The BSC supports I2C data transfer rates up to 400 kbit/s (Fast-mode).
@@ -129,6 +135,9 @@
 //! [`new`]: struct.I2c.html#method.new
 //! [`with_bus`]: struct.I2c.html#method.with_bus
 //! [`set_timeout`]: struct.I2c.html#method.set_timeout
+//! [`I2c`]: struct.I2c.html
+//! [`SoftI2c`]: struct.SoftI2c.html
+//! [`I2cBus`]: trait.I2cBus.html
 
 #![allow(dead_code)]
 
@@ -143,14 +152,25 @@ use std::result;
 
 use libc::c_ulong;
 
+use self::scan::{Probe, FIRST_ADDRESS, LAST_ADDRESS};
 use crate::system;
 use crate::system::{DeviceInfo, Model};
 
+#[cfg(feature = "async")]
+mod aio;
+mod bus;
 #[cfg(feature = "hal")]
 mod hal;
 mod ioctl;
+mod scan;
+mod soft;
 
+#[cfg(feature = "async")]
+pub use self::aio::AsyncI2c;
+pub use self::bus::I2cBus;
 pub use self::ioctl::Capabilities;
+pub use self::scan::BusScan;
+pub use self::soft::SoftI2c;
 
 /// Errors that can occur when accessing the I2C peripheral.
 #[derive(Debug)]
@@ -398,6 +418,99 @@ impl I2c {
         Ok(())
     }
 
+    /// Probes every 7-bit slave address from 0x03 to 0x77 for a responding device,
+    /// similar to `i2cdetect`.
+    ///
+    /// Like `i2cdetect`, addresses 0x30-0x37 and 0x50-0x5F are probed with an SMBus
+    /// Receive Byte transaction, because a Quick Command write can corrupt some EEPROMs.
+    /// All other addresses are probed with a Quick Command write. Addresses that require
+    /// a transaction the underlying drivers don't support are skipped. Addresses that
+    /// are in use by a kernel driver aren't probed, and are reported separately.
+    ///
+    /// Probing a device can change its state, so only scan buses with known devices,
+    /// or when other means of detection aren't available.
+    ///
+    /// The slave address and 10-bit addressing setting are restored afterwards. If the
+    /// underlying drivers support neither transaction, `scan` returns
+    /// `Err(`[`Error::FeatureNotSupported`]`)`.
+    ///
+    /// ## Example
+    ///
+    /// ```no_run
+    /// use rpi_embedded::i2c::I2c;
+    ///
+    /// # fn main() -> rpi_embedded::i2c::Result<()> {
+    /// let mut i2c = I2c::new()?;
+    ///
+    /// let scan = i2c.scan()?;
+    /// println!("{}", scan);
+    ///
+    /// if scan.contains(0x68) {
+    ///     println!("Found a DS3231 real-time clock");
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// [`Error::FeatureNotSupported`]: enum.Error.html#variant.FeatureNotSupported
+    pub fn scan(&mut self) -> Result<BusScan> {
+        let quick_command = self.capabilities().smbus_quick_command();
+        let receive_byte = self.capabilities().smbus_receive_byte();
+        if !quick_command && !receive_byte {
+            return Err(Error::FeatureNotSupported);
+        }
+
+        let fd = self.i2cdev.as_raw_fd();
+        if self.addr_10bit {
+            ioctl::set_addr_10bit(fd, 0)?;
+        }
+
+        let mut scan = BusScan::default();
+        let result = (FIRST_ADDRESS..=LAST_ADDRESS).try_for_each(|address| {
+            let probe = Probe::for_address(address);
+            let supported = match probe {
+                Probe::QuickCommand => quick_command,
+                Probe::ReceiveByte => receive_byte,
+            };
+
+            if !supported {
+                scan.skipped.push(address);
+                return Ok(());
+            }
+
+            // The kernel refuses addresses that are claimed by a driver
+            match ioctl::set_slave_address(fd, c_ulong::from(address)) {
+                Ok(()) => (),
+                Err(ref err) if err.raw_os_error() == Some(libc::EBUSY) => {
+                    scan.busy.push(address);
+                    return Ok(());
+                }
+                Err(err) => return Err(err),
+            }
+
+            let responded = match probe {
+                Probe::QuickCommand => ioctl::smbus_quick_command(fd, false).is_ok(),
+                Probe::ReceiveByte => ioctl::smbus_receive_byte(fd).is_ok(),
+            };
+
+            if responded {
+                scan.present.push(address);
+            }
+
+            Ok(())
+        });
+
+        // Restore the previous settings, even if the scan failed
+        if self.addr_10bit {
+            ioctl::set_addr_10bit(fd, 1)?;
+        }
+        ioctl::set_slave_address(fd, c_ulong::from(self.address))?;
+
+        result?;
+
+        Ok(scan)
+    }
+
     /// Receives incoming data from the slave device and writes it to `buffer`.
     ///
     /// `read` reads as many bytes as can fit in `buffer`.
@@ -0,0 +1,194 @@
+use std::sync::{Arc, Mutex, PoisonError};
+
+use super::{Error, I2c, Result};
+
+/// Asynchronous wrapper for [`I2c`].
+///
+/// `AsyncI2c` runs each transfer on tokio's blocking thread pool, so awaiting a
+/// transfer doesn't block the executor. It requires the optional `async` feature,
+/// and its methods must be called from within a tokio runtime.
+///
+/// Cloning an `AsyncI2c` returns a handle to the same [`I2c`] instance. Transfers
+/// from different handles are executed one at a time.
+///
+/// [`I2c`]: struct.I2c.html
+#[derive(Debug, Clone)]
+pub struct AsyncI2c {
+    inner: Arc<Mutex<I2c>>,
+}
+
+impl AsyncI2c {
+    /// Constructs a new `AsyncI2c`.
+    pub fn new(i2c: I2c) -> AsyncI2c {
+        AsyncI2c {
+            inner: Arc::new(Mutex::new(i2c)),
+        }
+    }
+
+    /// Runs `f` with exclusive access to the wrapped `I2c` on the blocking thread pool.
+    ///
+    /// `run` can be used for any operation that doesn't have an awaitable equivalent,
+    /// or to combine multiple operations that shouldn't be interleaved with other
+    /// transfers.
+    pub async fn run<F, T>(&self, f: F) -> Result<T>
+    where
+        F: FnOnce(&mut I2c) -> Result<T> + Send + 'static,
+        T: Send + 'static,
+    {
+        let inner = self.inner.clone();
+
+        // A panic in an earlier closure doesn't leave the device in an invalid state
+        tokio::task::spawn_blocking(move || {
+            f(&mut inner.lock().unwrap_or_else(PoisonError::into_inner))
+        })
+        .await
+        .map_err(|e| Error::Io(e.into()))?
+    }
+
+    /// Sets a 7-bit or 10-bit slave address.
+    ///
+    /// See [`I2c::set_slave_address`] for more information.
+    ///
+    /// [`I2c::set_slave_address`]: struct.I2c.html#method.set_slave_address
+    pub async fn set_slave_address(&self, slave_address: u16) -> Result<()> {
+        self.run(move |i2c| i2c.set_slave_address(slave_address))
+            .await
+    }
+
+    /// Receives `len` bytes from the slave.
+    ///
+    /// See [`I2c::read`] for more information.
+    ///
+    /// [`I2c::read`]: struct.I2c.html#method.read
+    pub async fn read(&self, len: usize) -> Result<Vec<u8>> {
+        self.run(move |i2c| {
+            let mut buffer = vec![0u8; len];
+            let len = i2c.read(&mut buffer)?;
+            buffer.truncate(len);
+
+            Ok(buffer)
+        })
+        .await
+    }
+
+    /// Sends the contents of `buffer` to the slave, and returns how many bytes
+    /// were written.
+    ///
+    /// See [`I2c::write`] for more information.
+    ///
+    /// [`I2c::write`]: struct.I2c.html#method.write
+    pub async fn write(&self, buffer: Vec<u8>) -> Result<usize> {
+        self.run(move |i2c| i2c.write(&buffer)).await
+    }
+
+    /// Sends the contents of `write_buffer` to the slave, and then receives
+    /// `read_len` bytes, without releasing the bus in between.
+    ///
+    /// See [`I2c::write_read`] for more information.
+    ///
+    /// [`I2c::write_read`]: struct.I2c.html#method.write_read
+    pub async fn write_read(&self, write_buffer: Vec<u8>, read_len: usize) -> Result<Vec<u8>> {
+        self.run(move |i2c| {
+            let mut read_buffer = vec![0u8; read_len];
+            i2c.write_read(&write_buffer, &mut read_buffer)?;
+
+            Ok(read_buffer)
+        })
+        .await
+    }
+
+    /// Sends an 8-bit `command`, and then receives `len` bytes.
+    ///
+    /// See [`I2c::block_read`] for more information.
+    ///
+    /// [`I2c::block_read`]: struct.I2c.html#method.block_read
+    pub async fn block_read(&self, command: u8, len: usize) -> Result<Vec<u8>> {
+        self.run(move |i2c| {
+            let mut buffer = vec![0u8; len];
+            i2c.block_read(command, &mut buffer)?;
+
+            Ok(buffer)
+        })
+        .await
+    }
+
+    /// Sends an 8-bit `command` followed by the contents of `buffer`.
+    ///
+    /// See [`I2c::block_write`] for more information.
+    ///
+    /// [`I2c::block_write`]: struct.I2c.html#method.block_write
+    pub async fn block_write(&self, command: u8, buffer: Vec<u8>) -> Result<()> {
+        self.run(move |i2c| i2c.block_write(command, &buffer)).await
+    }
+}
+
+impl From<I2c> for AsyncI2c {
+    fn from(i2c: I2c) -> AsyncI2c {
+        AsyncI2c::new(i2c)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::OpenOptions;
+    use std::future::Future;
+    use std::marker::PhantomData;
+
+    use super::*;
+    use crate::i2c::ioctl::Capabilities;
+
+    // Backed by /dev/null, which discards writes, returns no data for reads, and
+    // rejects any ioctl() calls
+    fn null_i2c() -> I2c {
+        I2c {
+            bus: 0,
+            funcs: Capabilities::new(0),
+            i2cdev: OpenOptions::new()
+                .read(true)
+                .write(true)
+                .open("/dev/null")
+                .unwrap(),
+            addr_10bit: false,
+            address: 0,
+            not_sync: PhantomData,
+        }
+    }
+
+    fn block_on<F: Future>(future: F) -> F::Output {
+        tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap()
+            .block_on(future)
+    }
+
+    #[test]
+    fn transfers() {
+        let i2c = AsyncI2c::new(null_i2c());
+
+        block_on(async {
+            assert_eq!(i2c.write(vec![1, 2, 3]).await.unwrap(), 3);
+            assert_eq!(i2c.read(4).await.unwrap(), []);
+            assert!(matches!(
+                i2c.set_slave_address(0x20).await,
+                Err(Error::Io(_))
+            ));
+            assert_eq!(i2c.run(|i2c| Ok(i2c.bus())).await.unwrap(), 0);
+        });
+    }
+
+    #[test]
+    fn panic_in_closure() {
+        let i2c = AsyncI2c::new(null_i2c());
+        let handle = i2c.clone();
+
+        block_on(async {
+            let result = handle
+                .run(|_| -> Result<()> { panic!("closure panicked") })
+                .await;
+            assert!(matches!(result, Err(Error::Io(_))));
+
+            // The device is still available to other handles
+            assert_eq!(i2c.write(vec![0]).await.unwrap(), 1);
+        });
+    }
+}
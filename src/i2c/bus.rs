@@ -0,0 +1,187 @@
+use super::{I2c, Result};
+
+/// I2C and SMBus transactions supported by both [`I2c`] and [`SoftI2c`].
+///
+/// Device drivers that are generic over `I2cBus` can be used with the hardware
+/// I2C peripheral, and with a bit-banged bus on arbitrary GPIO pins. The methods
+/// behave like their [`I2c`] counterparts.
+///
+/// [`I2c`]: struct.I2c.html
+/// [`SoftI2c`]: struct.SoftI2c.html
+pub trait I2cBus {
+    /// Sets the 7-bit slave address. See [`I2c::set_slave_address`].
+    ///
+    /// [`I2c::set_slave_address`]: struct.I2c.html#method.set_slave_address
+    fn set_slave_address(&mut self, slave_address: u16) -> Result<()>;
+
+    /// Fills `buffer` with incoming data. See [`I2c::read`].
+    ///
+    /// [`I2c::read`]: struct.I2c.html#method.read
+    fn read(&mut self, buffer: &mut [u8]) -> Result<usize>;
+
+    /// Sends the data in `buffer`. See [`I2c::write`].
+    ///
+    /// [`I2c::write`]: struct.I2c.html#method.write
+    fn write(&mut self, buffer: &[u8]) -> Result<usize>;
+
+    /// Sends `write_buffer`, and then fills `read_buffer` after a repeated START.
+    /// See [`I2c::write_read`].
+    ///
+    /// [`I2c::write_read`]: struct.I2c.html#method.write_read
+    fn write_read(&self, write_buffer: &[u8], read_buffer: &mut [u8]) -> Result<()>;
+
+    /// Sends an 8-bit `command`, and then fills `buffer`. See [`I2c::block_read`].
+    ///
+    /// [`I2c::block_read`]: struct.I2c.html#method.block_read
+    fn block_read(&self, command: u8, buffer: &mut [u8]) -> Result<()>;
+
+    /// Sends an 8-bit `command` followed by `buffer`. See [`I2c::block_write`].
+    ///
+    /// [`I2c::block_write`]: struct.I2c.html#method.block_write
+    fn block_write(&self, command: u8, buffer: &[u8]) -> Result<()>;
+
+    /// Sends a 1-bit `command` in place of the R/W bit. See [`I2c::smbus_quick_command`].
+    ///
+    /// [`I2c::smbus_quick_command`]: struct.I2c.html#method.smbus_quick_command
+    fn smbus_quick_command(&self, command: bool) -> Result<()>;
+
+    /// Receives an 8-bit value. See [`I2c::smbus_receive_byte`].
+    ///
+    /// [`I2c::smbus_receive_byte`]: struct.I2c.html#method.smbus_receive_byte
+    fn smbus_receive_byte(&self) -> Result<u8>;
+
+    /// Sends an 8-bit `value`. See [`I2c::smbus_send_byte`].
+    ///
+    /// [`I2c::smbus_send_byte`]: struct.I2c.html#method.smbus_send_byte
+    fn smbus_send_byte(&self, value: u8) -> Result<()>;
+
+    /// Sends an 8-bit `command`, and receives an 8-bit value. See [`I2c::smbus_read_byte`].
+    ///
+    /// [`I2c::smbus_read_byte`]: struct.I2c.html#method.smbus_read_byte
+    fn smbus_read_byte(&self, command: u8) -> Result<u8>;
+
+    /// Sends an 8-bit `command` and an 8-bit `value`. See [`I2c::smbus_write_byte`].
+    ///
+    /// [`I2c::smbus_write_byte`]: struct.I2c.html#method.smbus_write_byte
+    fn smbus_write_byte(&self, command: u8, value: u8) -> Result<()>;
+
+    /// Sends an 8-bit `command`, and receives a 16-bit value. See [`I2c::smbus_read_word`].
+    ///
+    /// [`I2c::smbus_read_word`]: struct.I2c.html#method.smbus_read_word
+    fn smbus_read_word(&self, command: u8) -> Result<u16>;
+
+    /// Sends an 8-bit `command`, and receives a 16-bit value in a non-standard swapped
+    /// byte order. See [`I2c::smbus_read_word_swapped`].
+    ///
+    /// [`I2c::smbus_read_word_swapped`]: struct.I2c.html#method.smbus_read_word_swapped
+    fn smbus_read_word_swapped(&self, command: u8) -> Result<u16> {
+        Ok(self.smbus_read_word(command)?.swap_bytes())
+    }
+
+    /// Sends an 8-bit `command` and a 16-bit `value`. See [`I2c::smbus_write_word`].
+    ///
+    /// [`I2c::smbus_write_word`]: struct.I2c.html#method.smbus_write_word
+    fn smbus_write_word(&self, command: u8, value: u16) -> Result<()>;
+
+    /// Sends an 8-bit `command` and a 16-bit `value` in a non-standard swapped byte order.
+    /// See [`I2c::smbus_write_word_swapped`].
+    ///
+    /// [`I2c::smbus_write_word_swapped`]: struct.I2c.html#method.smbus_write_word_swapped
+    fn smbus_write_word_swapped(&self, command: u8, value: u16) -> Result<()> {
+        self.smbus_write_word(command, value.swap_bytes())
+    }
+
+    /// Sends an 8-bit `command` and a 16-bit `value`, and then receives a 16-bit value
+    /// in response. See [`I2c::smbus_process_call`].
+    ///
+    /// [`I2c::smbus_process_call`]: struct.I2c.html#method.smbus_process_call
+    fn smbus_process_call(&self, command: u8, value: u16) -> Result<u16>;
+
+    /// Sends an 8-bit `command` and a 16-bit `value`, and then receives a 16-bit value
+    /// in response, in a non-standard byte order. See [`I2c::smbus_process_call_swapped`].
+    ///
+    /// [`I2c::smbus_process_call_swapped`]: struct.I2c.html#method.smbus_process_call_swapped
+    fn smbus_process_call_swapped(&self, command: u8, value: u16) -> Result<u16> {
+        Ok(self
+            .smbus_process_call(command, value.swap_bytes())?
+            .swap_bytes())
+    }
+
+    /// Sends an 8-bit `command`, and then receives an 8-bit byte count along with a
+    /// multi-byte `buffer`. See [`I2c::smbus_block_read`].
+    ///
+    /// [`I2c::smbus_block_read`]: struct.I2c.html#method.smbus_block_read
+    fn smbus_block_read(&self, command: u8, buffer: &mut [u8]) -> Result<usize>;
+
+    /// Sends an 8-bit `command` and an 8-bit byte count along with a multi-byte
+    /// `buffer`. See [`I2c::smbus_block_write`].
+    ///
+    /// [`I2c::smbus_block_write`]: struct.I2c.html#method.smbus_block_write
+    fn smbus_block_write(&self, command: u8, buffer: &[u8]) -> Result<()>;
+}
+
+impl I2cBus for I2c {
+    fn set_slave_address(&mut self, slave_address: u16) -> Result<()> {
+        I2c::set_slave_address(self, slave_address)
+    }
+
+    fn read(&mut self, buffer: &mut [u8]) -> Result<usize> {
+        I2c::read(self, buffer)
+    }
+
+    fn write(&mut self, buffer: &[u8]) -> Result<usize> {
+        I2c::write(self, buffer)
+    }
+
+    fn write_read(&self, write_buffer: &[u8], read_buffer: &mut [u8]) -> Result<()> {
+        I2c::write_read(self, write_buffer, read_buffer)
+    }
+
+    fn block_read(&self, command: u8, buffer: &mut [u8]) -> Result<()> {
+        I2c::block_read(self, command, buffer)
+    }
+
+    fn block_write(&self, command: u8, buffer: &[u8]) -> Result<()> {
+        I2c::block_write(self, command, buffer)
+    }
+
+    fn smbus_quick_command(&self, command: bool) -> Result<()> {
+        I2c::smbus_quick_command(self, command)
+    }
+
+    fn smbus_receive_byte(&self) -> Result<u8> {
+        I2c::smbus_receive_byte(self)
+    }
+
+    fn smbus_send_byte(&self, value: u8) -> Result<()> {
+        I2c::smbus_send_byte(self, value)
+    }
+
+    fn smbus_read_byte(&self, command: u8) -> Result<u8> {
+        I2c::smbus_read_byte(self, command)
+    }
+
+    fn smbus_write_byte(&self, command: u8, value: u8) -> Result<()> {
+        I2c::smbus_write_byte(self, command, value)
+    }
+
+    fn smbus_read_word(&self, command: u8) -> Result<u16> {
+        I2c::smbus_read_word(self, command)
+    }
+
+    fn smbus_write_word(&self, command: u8, value: u16) -> Result<()> {
+        I2c::smbus_write_word(self, command, value)
+    }
+
+    fn smbus_process_call(&self, command: u8, value: u16) -> Result<u16> {
+        I2c::smbus_process_call(self, command, value)
+    }
+
+    fn smbus_block_read(&self, command: u8, buffer: &mut [u8]) -> Result<usize> {
+        I2c::smbus_block_read(self, command, buffer)
+    }
+
+    fn smbus_block_write(&self, command: u8, buffer: &[u8]) -> Result<()> {
+        I2c::smbus_block_write(self, command, buffer)
+    }
+}
@@ -68,7 +68,7 @@ impl Capabilities {
     ///
     /// `Capabilities` indicates which I2C features and SMBus protocols
     /// are supported by the underlying drivers.
-    fn new(funcs: c_ulong) -> Capabilities {
+    pub(super) fn new(funcs: c_ulong) -> Capabilities {
         Capabilities { funcs }
     }
 
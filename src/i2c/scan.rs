@@ -0,0 +1,136 @@
+use std::fmt;
+
+// Valid 7-bit slave addresses. The remaining addresses are reserved.
+pub(crate) const FIRST_ADDRESS: u8 = 0x03;
+pub(crate) const LAST_ADDRESS: u8 = 0x77;
+
+// SMBus transaction used to probe an address
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub(crate) enum Probe {
+    QuickCommand,
+    ReceiveByte,
+}
+
+impl Probe {
+    // Same selection as i2cdetect. A Quick Command write can corrupt some EEPROMs,
+    // which are usually found at 0x50-0x5F, with their write protection registers at
+    // 0x30-0x37. Those addresses are probed with a read instead.
+    pub(crate) fn for_address(address: u8) -> Probe {
+        match address {
+            0x30..=0x37 | 0x50..=0x5f => Probe::ReceiveByte,
+            _ => Probe::QuickCommand,
+        }
+    }
+}
+
+/// The I2C slave addresses found by [`I2c::scan`].
+///
+/// The [`Display`] implementation prints the results in the same grid format
+/// as `i2cdetect`. Addresses with a responding device are shown in hexadecimal,
+/// addresses in use by a kernel driver are shown as `UU`, and addresses that
+/// weren't probed are left blank.
+///
+/// [`I2c::scan`]: struct.I2c.html#method.scan
+/// [`Display`]: https://doc.rust-lang.org/std/fmt/trait.Display.html
+#[derive(Debug, PartialEq, Eq, Clone, Default)]
+pub struct BusScan {
+    pub(crate) present: Vec<u8>,
+    pub(crate) busy: Vec<u8>,
+    pub(crate) skipped: Vec<u8>,
+}
+
+impl BusScan {
+    /// Returns the addresses of the devices that responded, in ascending order.
+    pub fn present(&self) -> &[u8] {
+        &self.present
+    }
+
+    /// Returns the addresses that are in use by a kernel driver, in ascending order.
+    ///
+    /// These addresses aren't probed, but usually belong to a device that's present.
+    pub fn busy(&self) -> &[u8] {
+        &self.busy
+    }
+
+    /// Returns the addresses that weren't probed, because the underlying drivers
+    /// don't support the required SMBus transaction.
+    pub fn skipped(&self) -> &[u8] {
+        &self.skipped
+    }
+
+    /// Returns `true` if a device responded at `address`, or `address` is in use by
+    /// a kernel driver.
+    pub fn contains(&self, address: u8) -> bool {
+        self.present.contains(&address) || self.busy.contains(&address)
+    }
+}
+
+impl fmt::Display for BusScan {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "   ")?;
+        for column in 0..16 {
+            write!(f, "  {:x}", column)?;
+        }
+
+        for row in (0..0x80u32).step_by(16) {
+            write!(f, "\n{:02x}:", row)?;
+
+            // Leave the trailing cells of the last row empty
+            let last = (row + 15).min(u32::from(LAST_ADDRESS));
+            for address in row..=last {
+                let address = address as u8;
+
+                if address < FIRST_ADDRESS || self.skipped.contains(&address) {
+                    write!(f, "   ")?;
+                } else if self.busy.contains(&address) {
+                    write!(f, " UU")?;
+                } else if self.present.contains(&address) {
+                    write!(f, " {:02x}", address)?;
+                } else {
+                    write!(f, " --")?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn probe_selection() {
+        assert_eq!(Probe::for_address(0x2f), Probe::QuickCommand);
+        assert_eq!(Probe::for_address(0x30), Probe::ReceiveByte);
+        assert_eq!(Probe::for_address(0x37), Probe::ReceiveByte);
+        assert_eq!(Probe::for_address(0x48), Probe::QuickCommand);
+        assert_eq!(Probe::for_address(0x50), Probe::ReceiveByte);
+        assert_eq!(Probe::for_address(0x5f), Probe::ReceiveByte);
+        assert_eq!(Probe::for_address(0x60), Probe::QuickCommand);
+    }
+
+    #[test]
+    fn grid() {
+        let scan = BusScan {
+            present: vec![0x1d, 0x68],
+            busy: vec![0x3c],
+            skipped: vec![0x50],
+        };
+
+        let expected = "     0  1  2  3  4  5  6  7  8  9  a  b  c  d  e  f
+00:          -- -- -- -- -- -- -- -- -- -- -- -- --
+10: -- -- -- -- -- -- -- -- -- -- -- -- -- 1d -- --
+20: -- -- -- -- -- -- -- -- -- -- -- -- -- -- -- --
+30: -- -- -- -- -- -- -- -- -- -- -- -- UU -- -- --
+40: -- -- -- -- -- -- -- -- -- -- -- -- -- -- -- --
+50:    -- -- -- -- -- -- -- -- -- -- -- -- -- -- --
+60: -- -- -- -- -- -- -- -- 68 -- -- -- -- -- -- --
+70: -- -- -- -- -- -- -- --";
+
+        assert_eq!(scan.to_string(), expected);
+        assert!(scan.contains(0x3c));
+        assert!(!scan.contains(0x50));
+    }
+}
@@ -0,0 +1,851 @@
+use std::cell::RefCell;
+use std::io;
+use std::time::Duration;
+
+use libc::{EBUSY, EIO, ENXIO, EPROTO};
+
+use super::{Error, I2cBus, Result};
+use crate::gpio::soft_pwm::{get_time_ns, wait_until_ns};
+use crate::gpio::{IoPin, Level, Mode, PullUpDown};
+
+const NANOS_PER_SEC: i64 = 1_000_000_000;
+
+const DEFAULT_CLOCK_SPEED: u32 = 100_000;
+// Matches the SMBus clock low timeout
+const DEFAULT_TIMEOUT: Duration = Duration::from_millis(35);
+
+// SMBus block transfers are limited to 32 bytes
+const BLOCK_MAX: usize = 32;
+
+// Number of clock pulses that release a slave device stuck in the middle of a byte
+const RECOVERY_CLOCKS: usize = 9;
+
+// Error codes match the kernel's i2c-algo-bit driver, which is used by i2c-gpio
+fn nack_address() -> Error {
+    Error::Io(io::Error::from_raw_os_error(ENXIO))
+}
+
+fn nack_data() -> Error {
+    Error::Io(io::Error::from_raw_os_error(EIO))
+}
+
+fn timed_out() -> Error {
+    Error::Io(io::Error::new(
+        io::ErrorKind::TimedOut,
+        "SCL held low by slave device",
+    ))
+}
+
+#[derive(Debug)]
+struct Lines {
+    sda: IoPin,
+    scl: IoPin,
+    half_period_ns: i64,
+    timeout: Duration,
+}
+
+impl Lines {
+    // The output latch is kept low, so switching to output mode pulls the line low,
+    // and switching to input mode releases it. The pull-up resistor pulls it high.
+    fn set(pin: &mut IoPin, level: Level) {
+        pin.set_mode(match level {
+            Level::Low => Mode::Output,
+            Level::High => Mode::Input,
+        });
+    }
+
+    fn delay(&self) {
+        wait_until_ns(get_time_ns() + self.half_period_ns);
+    }
+
+    fn set_sda(&mut self, level: Level) {
+        Lines::set(&mut self.sda, level);
+    }
+
+    fn scl_low(&mut self) {
+        Lines::set(&mut self.scl, Level::Low);
+    }
+
+    // Releases SCL, and waits while a slave device stretches the clock
+    fn scl_high(&mut self) -> Result<()> {
+        Lines::set(&mut self.scl, Level::High);
+
+        let deadline_ns = get_time_ns() + self.timeout.as_nanos() as i64;
+        while self.scl.read() == Level::Low {
+            if get_time_ns() > deadline_ns {
+                return Err(timed_out());
+            }
+        }
+
+        Ok(())
+    }
+
+    fn is_idle(&self) -> bool {
+        self.sda.read() == Level::High && self.scl.read() == Level::High
+    }
+
+    fn start(&mut self) -> Result<()> {
+        // Also works as a repeated START while SCL is low
+        self.set_sda(Level::High);
+        self.delay();
+        self.scl_high()?;
+        self.delay();
+        self.set_sda(Level::Low);
+        self.delay();
+        self.scl_low();
+        self.delay();
+
+        Ok(())
+    }
+
+    fn stop(&mut self) -> Result<()> {
+        self.set_sda(Level::Low);
+        self.delay();
+        self.scl_high()?;
+        self.delay();
+        self.set_sda(Level::High);
+        self.delay();
+
+        Ok(())
+    }
+
+    fn write_bit(&mut self, bit: bool) -> Result<()> {
+        self.set_sda(if bit { Level::High } else { Level::Low });
+        self.delay();
+        self.scl_high()?;
+        self.delay();
+        self.scl_low();
+
+        Ok(())
+    }
+
+    fn read_bit(&mut self) -> Result<bool> {
+        self.set_sda(Level::High);
+        self.delay();
+        self.scl_high()?;
+        self.delay();
+        let bit = self.sda.read() == Level::High;
+        self.scl_low();
+
+        Ok(bit)
+    }
+
+    // Returns true if the byte was acknowledged
+    fn write_byte(&mut self, value: u8) -> Result<bool> {
+        for bit in (0..8).rev() {
+            self.write_bit(value & (1 << bit) != 0)?;
+        }
+
+        Ok(!self.read_bit()?)
+    }
+
+    fn read_byte(&mut self, ack: bool) -> Result<u8> {
+        let mut value = 0;
+        for _ in 0..8 {
+            value = (value << 1) | self.read_bit()? as u8;
+        }
+
+        self.write_bit(!ack)?;
+
+        Ok(value)
+    }
+
+    fn address(&mut self, address: u16, read: bool) -> Result<()> {
+        self.start()?;
+
+        if self.write_byte(((address as u8) << 1) | read as u8)? {
+            Ok(())
+        } else {
+            Err(nack_address())
+        }
+    }
+
+    fn write_all(&mut self, buffer: &[u8]) -> Result<()> {
+        for &value in buffer {
+            if !self.write_byte(value)? {
+                return Err(nack_data());
+            }
+        }
+
+        Ok(())
+    }
+
+    fn read_all(&mut self, buffer: &mut [u8]) -> Result<()> {
+        let len = buffer.len();
+        for (index, value) in buffer.iter_mut().enumerate() {
+            // The master NACKs the final byte
+            *value = self.read_byte(index + 1 < len)?;
+        }
+
+        Ok(())
+    }
+
+    fn recover(&mut self) -> Result<()> {
+        self.set_sda(Level::High);
+        self.scl_high()?;
+
+        // A slave device that's holding SDA low releases it once it has clocked out
+        // the rest of its byte
+        for _ in 0..RECOVERY_CLOCKS {
+            if self.sda.read() == Level::High {
+                break;
+            }
+
+            self.delay();
+            self.scl_low();
+            self.delay();
+            self.scl_high()?;
+        }
+
+        self.delay();
+        self.scl_low();
+        self.stop()?;
+
+        if self.is_idle() {
+            Ok(())
+        } else {
+            Err(Error::Io(io::Error::from_raw_os_error(EBUSY)))
+        }
+    }
+}
+
+/// Bit-banged I2C master on arbitrary GPIO pins.
+///
+/// `SoftI2c` emulates open-drain outputs on two [`IoPin`]s by switching between
+/// [`Output`] mode with the output latch set low, which pulls the line low, and
+/// [`Input`] mode, which releases the line. The pins' built-in pull-up resistors are
+/// enabled, but they're too weak for anything but short wires and low clock speeds.
+/// Add external pull-up resistors to 3.3 V, typically 4.7 kΩ.
+///
+/// The bus is clocked by the calling thread through busy-waiting. Slave devices can
+/// stretch the clock at any point of the transfer. Transactions that take too long
+/// are subject to the same scheduling limitations as software-based PWM, but I2C slave
+/// devices only sample data on clock edges, so delays don't corrupt the transfer.
+///
+/// Before each transaction, `SoftI2c` checks whether the bus is idle. If a slave device
+/// is holding SDA low, for instance because a previous transfer was interrupted, the bus
+/// is recovered automatically as described in [`recover`].
+///
+/// `SoftI2c` implements [`I2cBus`], which offers the same read, write, `write_read`
+/// and SMBus methods as [`I2c`], including SMBus Block Read. 10-bit addresses and
+/// SMBus Packet Error Checking aren't supported. Errors are reported like the kernel's
+/// `i2c-gpio` driver does: an `io::Error` with `ENXIO` when the slave address isn't
+/// acknowledged, `EIO` when a data byte isn't acknowledged, and `io::ErrorKind::TimedOut`
+/// when a slave device stretches the clock for longer than the timeout.
+///
+/// ## Example
+///
+/// ```no_run
+/// use std::error::Error;
+///
+/// use rpi_embedded::gpio::{Gpio, Mode};
+/// use rpi_embedded::i2c::{I2cBus, SoftI2c};
+///
+/// # fn main() -> Result<(), Box<dyn Error>> {
+/// let gpio = Gpio::new()?;
+/// let sda = gpio.get(23)?.into_io(Mode::Input);
+/// let scl = gpio.get(24)?.into_io(Mode::Input);
+///
+/// let mut i2c = SoftI2c::new(sda, scl);
+/// i2c.set_clock_speed(400_000);
+/// i2c.set_slave_address(0x53)?;
+///
+/// let id = i2c.smbus_read_byte(0x00)?;
+/// println!("Device ID: {:#04x}", id);
+/// # Ok(())
+/// # }
+/// ```
+///
+/// [`IoPin`]: ../gpio/struct.IoPin.html
+/// [`Output`]: ../gpio/enum.Mode.html#variant.Output
+/// [`Input`]: ../gpio/enum.Mode.html#variant.Input
+/// [`recover`]: #method.recover
+/// [`I2cBus`]: trait.I2cBus.html
+/// [`I2c`]: struct.I2c.html
+#[derive(Debug)]
+pub struct SoftI2c {
+    lines: RefCell<Lines>,
+    clock_speed: u32,
+    address: u16,
+}
+
+impl SoftI2c {
+    /// Constructs a new `SoftI2c` on the specified SDA and SCL pins.
+    ///
+    /// The clock speed is set to 100 kHz, and the slave address to 0.
+    pub fn new(mut sda: IoPin, mut scl: IoPin) -> SoftI2c {
+        for pin in [&mut sda, &mut scl].iter_mut() {
+            pin.set_mode(Mode::Input);
+            pin.set_pullupdown(PullUpDown::PullUp);
+            pin.set_low();
+        }
+
+        SoftI2c {
+            lines: RefCell::new(Lines {
+                sda,
+                scl,
+                half_period_ns: half_period_ns(DEFAULT_CLOCK_SPEED),
+                timeout: DEFAULT_TIMEOUT,
+            }),
+            clock_speed: DEFAULT_CLOCK_SPEED,
+            address: 0,
+        }
+    }
+
+    /// Returns the clock frequency in hertz (Hz).
+    pub fn clock_speed(&self) -> u32 {
+        self.clock_speed
+    }
+
+    /// Sets the clock frequency in hertz (Hz).
+    ///
+    /// The actual clock frequency is lower, because of the time it takes to change
+    /// the pin modes and clock stretching by slave devices. Standard-mode (100 kHz)
+    /// is reliable on every model. Fast-mode (400 kHz) requires strong pull-up resistors.
+    pub fn set_clock_speed(&mut self, clock_speed: u32) {
+        self.clock_speed = clock_speed.max(1);
+        self.lines.borrow_mut().half_period_ns = half_period_ns(self.clock_speed);
+    }
+
+    /// Returns the clock stretching timeout.
+    pub fn timeout(&self) -> Duration {
+        self.lines.borrow().timeout
+    }
+
+    /// Sets how long a slave device is allowed to hold SCL low before the transaction
+    /// is aborted with an `io::ErrorKind::TimedOut` error. By default, this is set
+    /// to 35 ms.
+    pub fn set_timeout(&mut self, timeout: Duration) {
+        self.lines.borrow_mut().timeout = timeout;
+    }
+
+    /// Returns the slave address.
+    pub fn slave_address(&self) -> u16 {
+        self.address
+    }
+
+    /// Recovers the bus when a slave device is holding SDA low.
+    ///
+    /// A slave device that was interrupted in the middle of a transfer, for instance
+    /// because the master was reset, may keep SDA low while it waits for the remaining
+    /// clock pulses. `recover` releases SDA, sends up to 9 clock pulses until the slave
+    /// device releases SDA, and then sends a STOP condition.
+    ///
+    /// Returns an `io::Error` with `EBUSY` if the bus is still not idle afterwards.
+    pub fn recover(&self) -> Result<()> {
+        self.lines.borrow_mut().recover()
+    }
+
+    /// Stops using the bus, and returns the SDA and SCL pins.
+    pub fn into_pins(self) -> (IoPin, IoPin) {
+        let lines = self.lines.into_inner();
+
+        (lines.sda, lines.scl)
+    }
+
+    // Runs a single transaction, which always ends with a STOP condition
+    fn transaction<T, F>(&self, transfer: F) -> Result<T>
+    where
+        F: FnOnce(&mut Lines, u16) -> Result<T>,
+    {
+        let mut lines = self.lines.borrow_mut();
+
+        if !lines.is_idle() {
+            lines.recover()?;
+        }
+
+        let result = transfer(&mut lines, self.address);
+        let stop = lines.stop();
+
+        let value = result?;
+        stop?;
+
+        Ok(value)
+    }
+}
+
+fn half_period_ns(clock_speed: u32) -> i64 {
+    NANOS_PER_SEC / (2 * i64::from(clock_speed))
+}
+
+impl I2cBus for SoftI2c {
+    fn set_slave_address(&mut self, slave_address: u16) -> Result<()> {
+        // Filter out invalid and unsupported addresses
+        if (slave_address >> 3) == 0b1111 || slave_address > 0x7F {
+            return Err(Error::InvalidSlaveAddress(slave_address));
+        }
+
+        self.address = slave_address;
+
+        Ok(())
+    }
+
+    fn read(&mut self, buffer: &mut [u8]) -> Result<usize> {
+        self.transaction(|lines, address| {
+            lines.address(address, true)?;
+            lines.read_all(buffer)
+        })?;
+
+        Ok(buffer.len())
+    }
+
+    fn write(&mut self, buffer: &[u8]) -> Result<usize> {
+        self.transaction(|lines, address| {
+            lines.address(address, false)?;
+            lines.write_all(buffer)
+        })?;
+
+        Ok(buffer.len())
+    }
+
+    fn write_read(&self, write_buffer: &[u8], read_buffer: &mut [u8]) -> Result<()> {
+        self.transaction(|lines, address| {
+            lines.address(address, false)?;
+            lines.write_all(write_buffer)?;
+
+            if read_buffer.is_empty() {
+                return Ok(());
+            }
+
+            lines.address(address, true)?;
+            lines.read_all(read_buffer)
+        })
+    }
+
+    fn block_read(&self, command: u8, buffer: &mut [u8]) -> Result<()> {
+        let len = buffer.len().min(BLOCK_MAX);
+
+        self.write_read(&[command], &mut buffer[..len])
+    }
+
+    fn block_write(&self, command: u8, buffer: &[u8]) -> Result<()> {
+        let len = buffer.len().min(BLOCK_MAX);
+
+        self.transaction(|lines, address| {
+            lines.address(address, false)?;
+            lines.write_all(&[command])?;
+            lines.write_all(&buffer[..len])
+        })
+    }
+
+    fn smbus_quick_command(&self, command: bool) -> Result<()> {
+        self.transaction(|lines, address| lines.address(address, command))
+    }
+
+    fn smbus_receive_byte(&self) -> Result<u8> {
+        let mut buffer = [0u8; 1];
+        self.transaction(|lines, address| {
+            lines.address(address, true)?;
+            lines.read_all(&mut buffer)
+        })?;
+
+        Ok(buffer[0])
+    }
+
+    fn smbus_send_byte(&self, value: u8) -> Result<()> {
+        self.transaction(|lines, address| {
+            lines.address(address, false)?;
+            lines.write_all(&[value])
+        })
+    }
+
+    fn smbus_read_byte(&self, command: u8) -> Result<u8> {
+        let mut buffer = [0u8; 1];
+        self.write_read(&[command], &mut buffer)?;
+
+        Ok(buffer[0])
+    }
+
+    fn smbus_write_byte(&self, command: u8, value: u8) -> Result<()> {
+        self.block_write(command, &[value])
+    }
+
+    fn smbus_read_word(&self, command: u8) -> Result<u16> {
+        let mut buffer = [0u8; 2];
+        self.write_read(&[command], &mut buffer)?;
+
+        Ok(u16::from_le_bytes(buffer))
+    }
+
+    fn smbus_write_word(&self, command: u8, value: u16) -> Result<()> {
+        self.block_write(command, &value.to_le_bytes())
+    }
+
+    fn smbus_process_call(&self, command: u8, value: u16) -> Result<u16> {
+        let value = value.to_le_bytes();
+        let mut buffer = [0u8; 2];
+        self.write_read(&[command, value[0], value[1]], &mut buffer)?;
+
+        Ok(u16::from_le_bytes(buffer))
+    }
+
+    fn smbus_block_read(&self, command: u8, buffer: &mut [u8]) -> Result<usize> {
+        self.transaction(|lines, address| {
+            lines.address(address, false)?;
+            lines.write_all(&[command])?;
+            lines.address(address, true)?;
+
+            let count = lines.read_byte(true)? as usize;
+            if count == 0 || count > BLOCK_MAX {
+                // Finish the transfer with a NACK before reporting the error
+                lines.read_byte(false)?;
+                return Err(Error::Io(io::Error::from_raw_os_error(EPROTO)));
+            }
+
+            // The slave device sends count bytes, even if they don't fit in buffer
+            let mut block = [0u8; BLOCK_MAX];
+            lines.read_all(&mut block[..count])?;
+
+            let len = count.min(buffer.len());
+            buffer[..len].copy_from_slice(&block[..len]);
+
+            Ok(len)
+        })
+    }
+
+    fn smbus_block_write(&self, command: u8, buffer: &[u8]) -> Result<()> {
+        let len = buffer.len().min(BLOCK_MAX);
+
+        self.transaction(|lines, address| {
+            lines.address(address, false)?;
+            lines.write_all(&[command, len as u8])?;
+            lines.write_all(&buffer[..len])
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::VecDeque;
+    use std::sync::{Arc, Mutex};
+    use std::time::Instant;
+
+    use super::*;
+    use crate::gpio::sim::SimDevice;
+    use crate::gpio::{Gpio, Simulator};
+
+    const SDA: u8 = 2;
+    const SCL: u8 = 3;
+    const ADDRESS: u8 = 0x20;
+
+    #[derive(Debug, PartialEq, Eq, Copy, Clone)]
+    enum Event {
+        Start,
+        Stop,
+        // Rising edge on SCL
+        Clock,
+        // Address byte, and whether it was acknowledged
+        Address(u8, bool),
+        Write(u8),
+        // Byte sent by the slave device, and whether the master acknowledged it
+        Read(u8, bool),
+    }
+
+    // I2C slave device at ADDRESS that logs bus conditions and transferred bytes
+    #[derive(Debug, Default)]
+    struct Slave {
+        sda: bool,
+        scl: bool,
+        sda_drive: Option<Level>,
+        scl_drive: Option<Level>,
+        // Clocks seen in the current byte, including the ACK clock
+        bit: u8,
+        shift: u8,
+        // Waiting for the address byte after a START condition
+        addressing: bool,
+        // Not addressed, so the bus is ignored until the next START condition
+        idle: bool,
+        reading: bool,
+        // Sending data bytes after the read address was acknowledged
+        transmitting: bool,
+        master_ack: bool,
+        read_data: VecDeque<u8>,
+        // Holds SCL low after acknowledging its address
+        stretch: bool,
+        // Holds SDA low until this many clock pulses have been received
+        stuck: usize,
+        clocks: usize,
+        events: Vec<Event>,
+    }
+
+    impl Slave {
+        fn start(&mut self) {
+            self.events.push(Event::Start);
+            self.bit = 0;
+            self.addressing = true;
+            self.idle = false;
+            self.reading = false;
+            self.transmitting = false;
+            self.sda_drive = None;
+        }
+
+        fn stop(&mut self) {
+            self.events.push(Event::Stop);
+            self.idle = true;
+            self.sda_drive = None;
+        }
+
+        fn clock_rising(&mut self) {
+            self.bit += 1;
+            if self.bit <= 8 {
+                self.shift = (self.shift << 1) | self.sda as u8;
+            } else if self.transmitting {
+                self.master_ack = !self.sda;
+                let value = self.read_data.pop_front().unwrap_or(0xff);
+                self.events.push(Event::Read(value, self.master_ack));
+            }
+        }
+
+        fn clock_falling(&mut self) {
+            match self.bit {
+                8 if self.transmitting => self.sda_drive = None,
+                8 => {
+                    let value = self.shift;
+                    if self.addressing {
+                        self.addressing = false;
+                        let ack = value >> 1 == ADDRESS;
+                        self.events.push(Event::Address(value, ack));
+                        if !ack {
+                            self.idle = true;
+                            return;
+                        }
+
+                        self.reading = value & 1 == 1;
+                        self.master_ack = true;
+                        if self.stretch {
+                            self.scl_drive = Some(Level::Low);
+                        }
+                    } else {
+                        self.events.push(Event::Write(value));
+                    }
+
+                    self.sda_drive = Some(Level::Low);
+                }
+                9 => {
+                    self.bit = 0;
+                    self.sda_drive = None;
+                    if self.reading {
+                        if self.master_ack {
+                            self.transmitting = true;
+                            self.drive_bit(7);
+                        } else {
+                            self.idle = true;
+                        }
+                    }
+                }
+                bit if self.transmitting => self.drive_bit(7 - bit),
+                _ => (),
+            }
+        }
+
+        fn drive_bit(&mut self, bit: u8) {
+            let value = self.read_data.front().copied().unwrap_or(0xff);
+            self.sda_drive = if value & (1 << bit) == 0 {
+                Some(Level::Low)
+            } else {
+                None
+            };
+        }
+    }
+
+    #[derive(Debug)]
+    struct Device(Arc<Mutex<Slave>>);
+
+    impl SimDevice for Device {
+        fn respond(&mut self, levels: u64) -> Vec<(u8, Option<Level>)> {
+            let mut slave = self.0.lock().unwrap();
+            let sda = levels & (1 << SDA) != 0;
+            let scl = levels & (1 << SCL) != 0;
+
+            if slave.stuck > 0 {
+                if slave.scl && !scl {
+                    slave.stuck -= 1;
+                }
+                slave.sda_drive = if slave.stuck > 0 {
+                    Some(Level::Low)
+                } else {
+                    None
+                };
+            } else if scl && slave.scl {
+                if slave.sda && !sda {
+                    slave.start();
+                } else if !slave.sda && sda {
+                    slave.stop();
+                }
+            } else if scl && !slave.scl && !slave.idle {
+                slave.sda = sda;
+                slave.clock_rising();
+            } else if !scl && slave.scl && !slave.idle {
+                slave.clock_falling();
+            }
+
+            if scl && !slave.scl {
+                slave.clocks += 1;
+                slave.events.push(Event::Clock);
+            }
+
+            slave.sda = sda;
+            slave.scl = scl;
+
+            vec![(SDA, slave.sda_drive), (SCL, slave.scl_drive)]
+        }
+    }
+
+    fn bus(slave: Slave) -> (SoftI2c, Simulator, Arc<Mutex<Slave>>) {
+        let (gpio, sim) = Gpio::simulated().unwrap();
+        let mut i2c = SoftI2c::new(
+            gpio.get(SDA).unwrap().into_io(Mode::Input),
+            gpio.get(SCL).unwrap().into_io(Mode::Input),
+        );
+        i2c.set_slave_address(u16::from(ADDRESS)).unwrap();
+
+        let slave = Arc::new(Mutex::new(Slave {
+            sda: true,
+            scl: true,
+            idle: true,
+            ..slave
+        }));
+        sim.attach(Box::new(Device(slave.clone())));
+
+        (i2c, sim, slave)
+    }
+
+    // Returns the logged events without the clock pulses
+    fn events(slave: &Mutex<Slave>) -> Vec<Event> {
+        let mut slave = slave.lock().unwrap();
+        slave.clocks = 0;
+        slave
+            .events
+            .drain(..)
+            .filter(|&event| event != Event::Clock)
+            .collect()
+    }
+
+    #[test]
+    fn transfers() {
+        let (mut i2c, _sim, slave) = bus(Slave {
+            read_data: vec![0xa5, 0x3c].into(),
+            ..Slave::default()
+        });
+
+        assert_eq!(i2c.write(&[0x12, 0x80]).unwrap(), 2);
+        assert_eq!(
+            events(&slave),
+            [
+                Event::Start,
+                Event::Address(0x40, true),
+                Event::Write(0x12),
+                Event::Write(0x80),
+                Event::Stop
+            ]
+        );
+
+        // Every byte takes nine clock pulses, and the STOP condition one more
+        i2c.write(&[0x00]).unwrap();
+        assert_eq!(slave.lock().unwrap().clocks, 19);
+        events(&slave);
+
+        // The master acknowledges every byte except the final one, and uses a
+        // repeated START condition to switch to reading
+        let mut buffer = [0u8; 2];
+        i2c.write_read(&[0x01], &mut buffer).unwrap();
+        assert_eq!(buffer, [0xa5, 0x3c]);
+        assert_eq!(
+            events(&slave),
+            [
+                Event::Start,
+                Event::Address(0x40, true),
+                Event::Write(0x01),
+                Event::Start,
+                Event::Address(0x41, true),
+                Event::Read(0xa5, true),
+                Event::Read(0x3c, false),
+                Event::Stop
+            ]
+        );
+        assert!(i2c.lines.borrow().is_idle());
+    }
+
+    #[test]
+    fn nack() {
+        let (mut i2c, _sim, slave) = bus(Slave::default());
+
+        i2c.set_slave_address(0x21).unwrap();
+        let err = i2c.write(&[0x12]).unwrap_err();
+        assert!(matches!(err, Error::Io(ref e) if e.raw_os_error() == Some(ENXIO)));
+        assert_eq!(
+            events(&slave),
+            [Event::Start, Event::Address(0x42, false), Event::Stop]
+        );
+
+        // Addresses are 7 bits
+        assert!(i2c.set_slave_address(0x80).is_err());
+    }
+
+    #[test]
+    fn clock_stretch_timeout() {
+        let (mut i2c, _sim, slave) = bus(Slave {
+            stretch: true,
+            ..Slave::default()
+        });
+
+        i2c.set_timeout(Duration::from_millis(5));
+        let start = Instant::now();
+        let err = i2c.write(&[0x12]).unwrap_err();
+        assert!(start.elapsed() >= Duration::from_millis(5));
+        assert!(matches!(err, Error::Io(ref e) if e.kind() == io::ErrorKind::TimedOut));
+
+        // The transfer doesn't continue past the stretched clock
+        assert_eq!(events(&slave), [Event::Start, Event::Address(0x40, true)]);
+    }
+
+    #[test]
+    fn recovery() {
+        // A slave device stuck in the middle of a byte is clocked until it releases SDA
+        let (mut i2c, _sim, slave) = bus(Slave {
+            stuck: 4,
+            ..Slave::default()
+        });
+
+        i2c.recover().unwrap();
+        assert_eq!(
+            slave.lock().unwrap().events,
+            [
+                Event::Clock,
+                Event::Clock,
+                Event::Clock,
+                Event::Clock,
+                Event::Clock,
+                Event::Stop
+            ]
+        );
+        events(&slave);
+
+        // The bus is usable afterwards
+        i2c.write(&[0x12]).unwrap();
+        assert_eq!(
+            events(&slave),
+            [
+                Event::Start,
+                Event::Address(0x40, true),
+                Event::Write(0x12),
+                Event::Stop
+            ]
+        );
+    }
+
+    #[test]
+    fn recovery_failure() {
+        let (mut i2c, _sim, slave) = bus(Slave {
+            stuck: usize::MAX,
+            ..Slave::default()
+        });
+
+        // Nine clock pulses, followed by the clock pulse of the STOP condition, which
+        // can't complete while SDA is held low
+        let err = i2c.write(&[0x12]).unwrap_err();
+        assert!(matches!(err, Error::Io(ref e) if e.raw_os_error() == Some(EBUSY)));
+        assert_eq!(slave.lock().unwrap().clocks, 10);
+        assert_eq!(events(&slave), []);
+    }
+}
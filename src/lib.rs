@@ -20,19 +20,39 @@
 
 //! rpi_embedded is a fork of the RPPAL library. This fork is made to increase the usability
 //! of the RPPAL library. Spesificaly making it more user friendly and beginer friendly
-//! rpi_embedded provides access to the Raspberry Pi's GPIO, I2C, PWM, SPI, UART and Bluetooth
-//! peripherals. There is also a ADXL345 and pwm servo library included for ease of use.
+//! rpi_embedded provides access to the Raspberry Pi's GPIO, I2C, PWM, SPI, UART, general-purpose
+//! clock and Bluetooth peripherals, and to 1-Wire buses. There is also a ADXL345, DS18B20,
+//! DHT11/DHT22, GPIO port expander and pwm servo library included for ease of use.
 //! RPPAL also offers support for USB to serial adapters. The library
 //! can be used in conjunction with a variety of platform-agnostic drivers
 //! through its `embedded-hal` trait implementations by enabling the optional
 //! `hal` feature. However the new functions included in rpi_embedded might fail.
 //!
+//! The optional `async` feature adds tokio-based async/await support: awaitable GPIO
+//! interrupts and interrupt streams, [`AsyncRead`]/[`AsyncWrite`] for the UART through
+//! [`uart::AsyncUart`], and I2C and SPI transfers that run on tokio's blocking thread pool
+//! through [`i2c::AsyncI2c`] and [`spi::AsyncSpi`].
+//!
+//! The optional `serde` feature implements `Serialize` and `Deserialize` for GPIO
+//! snapshots taken with [`gpio::Gpio::snapshot`].
+//!
+//! [`safe_state`] drives registered GPIO outputs and PWM channels to a safe state when
+//! the process is interrupted by a signal or panics, which destructors can't guarantee.
+//!
 //! rpi_embedded requires Raspbian or any similar, recent, Linux distribution.
 //! rpie_embedded has only been tested on Rpi Zero W but RPPAL is compatible with
 //! the Raspberry Pi A, A+, B, B+, 2B, 3A+, 3B, 3B+, 4B, CM, CM 3, CM 3+, Zero and
 //! Zero W. In theory it should all work except for bluetooth maybe.
 //!
 //! Note that this fork is still in production, and might change massivly from version to version
+//!
+//! [`AsyncRead`]: https://docs.rs/tokio/1/tokio/io/trait.AsyncRead.html
+//! [`AsyncWrite`]: https://docs.rs/tokio/1/tokio/io/trait.AsyncWrite.html
+//! [`uart::AsyncUart`]: uart/struct.AsyncUart.html
+//! [`i2c::AsyncI2c`]: i2c/struct.AsyncI2c.html
+//! [`spi::AsyncSpi`]: spi/struct.AsyncSpi.html
+//! [`gpio::Gpio::snapshot`]: gpio/struct.Gpio.html#method.snapshot
+//! [`safe_state`]: safe_state/index.html
 
 
 // Used by rustdoc to link other crates to rppal's docs
@@ -41,11 +61,16 @@
 #[macro_use]
 mod macros;
 
+pub mod clock;
+pub mod dht;
+pub mod expander;
 pub mod gpio;
 #[cfg(feature = "hal")]
 pub mod hal;
 pub mod i2c;
+pub mod onewire;
 pub mod pwm;
+pub mod safe_state;
 pub mod spi;
 pub mod system;
 pub mod uart;
@@ -0,0 +1,298 @@
+//! Interface for 1-Wire buses and DS18B20 temperature sensors.
+//!
+//! 1-Wire buses can be accessed through one of two backends. Both implement the
+//! [`OneWire`] trait, which is used by device drivers such as [`Ds18b20`].
+//!
+//! ## Kernel w1 subsystem
+//!
+//! [`W1`] uses the devices the kernel's `w1` subsystem has detected, which are listed in
+//! `/sys/bus/w1/devices`. To enable the `w1-gpio` bus master on BCM GPIO 4 (physical pin 7),
+//! add `dtoverlay=w1-gpio` to `/boot/config.txt`. A different pin can be selected with
+//! `dtoverlay=w1-gpio,gpiopin=X`. Remember to reboot the Raspberry Pi afterwards.
+//!
+//! The kernel searches the bus for new devices periodically, and handles the timing
+//! critical parts of the protocol, which makes `W1` the most reliable choice.
+//!
+//! `W1` sends raw commands through each device's `rw` file, which the kernel only
+//! provides for devices that aren't bound to a family driver. Temperature sensors such
+//! as the DS18B20 are bound to `w1_therm` by default. [`Ds18b20`] detects this, and
+//! uses the driver's `temperature`, `resolution`, `ext_power` and `eeprom_cmd` files
+//! instead, as well as the bus master's `therm_bulk_read` file to convert the
+//! temperature on all sensors at once. Raw commands sent to these devices return
+//! [`Error::RawAccessUnavailable`].
+//!
+//! ## Bit-banged master
+//!
+//! [`SoftOneWire`] is a bit-banged 1-Wire master on any available GPIO pin, which doesn't
+//! require any configuration changes. The 1-Wire protocol relies on time slots of a few
+//! microseconds, so an untimely context switch can corrupt a transfer. ROM codes and
+//! most device data are protected by a CRC, and transfers that fail the CRC check return
+//! [`Error::Crc`]. Running the calling thread with a real-time scheduling policy
+//! considerably reduces the number of failed transfers.
+//!
+//! ## Parasite power
+//!
+//! Devices that use parasite power draw their supply current from the data line. During
+//! temperature conversions and EEPROM writes, the bus master has to actively drive the
+//! line high to provide enough current, which is handled by [`OneWire::power`].
+//! [`SoftOneWire`] drives its pin high for the specified duration. The kernel doesn't
+//! offer this feature to user space, so [`W1`] waits instead, and parasite-powered
+//! devices require an external strong pull-up.
+//!
+//! [`OneWire`]: trait.OneWire.html
+//! [`OneWire::power`]: trait.OneWire.html#method.power
+//! [`Ds18b20`]: struct.Ds18b20.html
+//! [`W1`]: struct.W1.html
+//! [`SoftOneWire`]: struct.SoftOneWire.html
+//! [`Error::Crc`]: enum.Error.html#variant.Crc
+//! [`Error::RawAccessUnavailable`]: enum.Error.html#variant.RawAccessUnavailable
+
+use std::error;
+use std::fmt;
+use std::io;
+use std::result;
+use std::str::FromStr;
+use std::thread;
+use std::time::Duration;
+
+mod ds18b20;
+mod soft;
+mod sysfs;
+
+pub use self::ds18b20::{Ds18b20, Resolution};
+pub use self::soft::SoftOneWire;
+pub use self::sysfs::W1;
+
+/// Errors that can occur when accessing a 1-Wire bus.
+#[derive(Debug)]
+pub enum Error {
+    /// I/O error.
+    Io(io::Error),
+    /// No device responded with a presence pulse after a bus reset.
+    NoDevice,
+    /// The data read from the bus failed the CRC check.
+    Crc,
+    /// The specified string isn't a valid ROM code.
+    InvalidRom(String),
+    /// The device isn't supported by the driver.
+    UnsupportedDevice(Rom),
+    /// The device is bound to a kernel family driver, such as `w1_therm`, which
+    /// prevents raw access through the `w1` subsystem.
+    RawAccessUnavailable(Rom),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Error::Io(ref err) => write!(f, "I/O error: {}", err),
+            Error::NoDevice => write!(f, "No device present"),
+            Error::Crc => write!(f, "CRC mismatch"),
+            Error::InvalidRom(ref rom) => write!(f, "Invalid ROM code: {}", rom),
+            Error::UnsupportedDevice(rom) => write!(f, "Unsupported device: {}", rom),
+            Error::RawAccessUnavailable(rom) => write!(
+                f,
+                "No raw access to device {}, which is bound to a kernel driver",
+                rom
+            ),
+        }
+    }
+}
+
+impl error::Error for Error {}
+
+impl From<io::Error> for Error {
+    fn from(err: io::Error) -> Error {
+        Error::Io(err)
+    }
+}
+
+/// Result type returned from methods that can have `onewire::Error`s.
+pub type Result<T> = result::Result<T, Error>;
+
+/// Calculates the Dallas/Maxim CRC-8 of `data`.
+///
+/// The CRC of a ROM code or a block of data that includes its own CRC as the
+/// final byte is 0.
+pub fn crc8(data: &[u8]) -> u8 {
+    data.iter().fold(0, |crc, &byte| {
+        (0..8).fold(crc ^ byte, |crc, _| {
+            if crc & 0x01 != 0 {
+                (crc >> 1) ^ 0x8C
+            } else {
+                crc >> 1
+            }
+        })
+    })
+}
+
+/// A 64-bit 1-Wire ROM code.
+///
+/// Every 1-Wire device has a unique ROM code, which consists of an 8-bit family code,
+/// a 48-bit serial number and an 8-bit CRC, in the order they're sent on the bus.
+///
+/// `Rom` is formatted and parsed the same way the kernel names its devices in
+/// `/sys/bus/w1/devices`, for instance `28-0000056a2f1c`.
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Copy, Clone)]
+pub struct Rom(u64);
+
+impl Rom {
+    /// Constructs a new `Rom` from the 8 bytes as they're sent on the bus.
+    ///
+    /// Returns `Err(`[`Error::Crc`]`)` if the final byte doesn't contain a valid CRC.
+    ///
+    /// [`Error::Crc`]: enum.Error.html#variant.Crc
+    pub fn from_bytes(bytes: [u8; 8]) -> Result<Rom> {
+        if crc8(&bytes) != 0 {
+            return Err(Error::Crc);
+        }
+
+        Ok(Rom(u64::from_le_bytes(bytes)))
+    }
+
+    /// Constructs a new `Rom` from a family code and a 48-bit serial number, and
+    /// calculates the CRC.
+    pub fn with_serial(family: u8, serial: u64) -> Rom {
+        let value = u64::from(family) | ((serial & 0xffff_ffff_ffff) << 8);
+        let crc = crc8(&value.to_le_bytes()[..7]);
+
+        Rom(value | (u64::from(crc) << 56))
+    }
+
+    /// Returns the 8 bytes in the order they're sent on the bus.
+    pub fn to_bytes(self) -> [u8; 8] {
+        self.0.to_le_bytes()
+    }
+
+    /// Returns the family code, which identifies the type of device.
+    pub fn family(self) -> u8 {
+        self.0 as u8
+    }
+
+    /// Returns the 48-bit serial number.
+    pub fn serial(self) -> u64 {
+        (self.0 >> 8) & 0xffff_ffff_ffff
+    }
+
+    /// Returns the CRC.
+    pub fn crc(self) -> u8 {
+        (self.0 >> 56) as u8
+    }
+}
+
+impl fmt::Display for Rom {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:02x}-{:012x}", self.family(), self.serial())
+    }
+}
+
+impl FromStr for Rom {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Rom> {
+        let invalid = || Error::InvalidRom(s.to_owned());
+
+        let mut parts = s.splitn(2, '-');
+        let family = parts.next().ok_or_else(invalid)?;
+        let serial = parts.next().ok_or_else(invalid)?;
+        if family.len() != 2 || serial.len() != 12 {
+            return Err(invalid());
+        }
+
+        let family = u8::from_str_radix(family, 16).map_err(|_| invalid())?;
+        let serial = u64::from_str_radix(serial, 16).map_err(|_| invalid())?;
+
+        Ok(Rom::with_serial(family, serial))
+    }
+}
+
+/// Transactions on a 1-Wire bus.
+///
+/// `OneWire` is implemented by [`W1`] and [`SoftOneWire`], and is used by 1-Wire
+/// device drivers such as [`Ds18b20`].
+///
+/// [`W1`]: struct.W1.html
+/// [`SoftOneWire`]: struct.SoftOneWire.html
+/// [`Ds18b20`]: struct.Ds18b20.html
+pub trait OneWire {
+    /// Returns the ROM codes of all devices on the bus.
+    fn search(&mut self) -> Result<Vec<Rom>>;
+
+    /// Resets the bus, selects the device with the specified ROM code, or all devices
+    /// if `rom` is `None`, and sends the data in `buffer`.
+    fn write(&mut self, rom: Option<Rom>, buffer: &[u8]) -> Result<()>;
+
+    /// Resets the bus, selects all devices with the specified family code, and sends
+    /// the data in `buffer`.
+    ///
+    /// By default, `broadcast` calls [`write`] with a `rom` of `None`, which selects
+    /// all devices regardless of their family code.
+    ///
+    /// [`write`]: #tymethod.write
+    fn broadcast(&mut self, _family: u8, buffer: &[u8]) -> Result<()> {
+        self.write(None, buffer)
+    }
+
+    /// Reads data from the device selected by the previous call to [`write`].
+    ///
+    /// [`write`]: #tymethod.write
+    fn read(&mut self, buffer: &mut [u8]) -> Result<()>;
+
+    /// Powers parasite-powered devices by driving the bus high for the specified
+    /// duration.
+    ///
+    /// By default, `power` blocks for `duration` without actively driving the bus.
+    fn power(&mut self, duration: Duration) -> Result<()> {
+        thread::sleep(duration);
+
+        Ok(())
+    }
+
+    /// Returns the bus as a [`W1`], which lets device drivers fall back to the
+    /// kernel's family drivers for devices that don't provide raw access.
+    ///
+    /// [`W1`]: struct.W1.html
+    #[doc(hidden)]
+    fn as_w1(&mut self) -> Option<&mut W1> {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn crc() {
+        let rom = [0x28, 0x1c, 0x2f, 0x6a, 0x05, 0x00, 0x00];
+        let crc = crc8(&rom);
+
+        let mut bytes = [0u8; 8];
+        bytes[..7].copy_from_slice(&rom);
+        bytes[7] = crc;
+        assert_eq!(crc8(&bytes), 0);
+
+        // Example from Maxim application note 27
+        assert_eq!(crc8(&[0x02, 0x1c, 0xb8, 0x01, 0x00, 0x00, 0x00, 0xa2]), 0);
+    }
+
+    #[test]
+    fn rom() {
+        let rom: Rom = "28-0000056a2f1c".parse().unwrap();
+        assert_eq!(rom.family(), 0x28);
+        assert_eq!(rom.serial(), 0x0000_056a_2f1c);
+        assert_eq!(rom.to_string(), "28-0000056a2f1c");
+        assert_eq!(
+            &rom.to_bytes()[..7],
+            &[0x28, 0x1c, 0x2f, 0x6a, 0x05, 0x00, 0x00]
+        );
+        assert_eq!(Rom::from_bytes(rom.to_bytes()).unwrap(), rom);
+
+        let mut corrupted = rom.to_bytes();
+        corrupted[3] ^= 0x01;
+        assert!(Rom::from_bytes(corrupted).is_err());
+
+        assert!("w1_bus_master1".parse::<Rom>().is_err());
+        assert!("28-0000056a2f1".parse::<Rom>().is_err());
+        assert!("zz-0000056a2f1c".parse::<Rom>().is_err());
+    }
+}
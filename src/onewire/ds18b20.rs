@@ -0,0 +1,383 @@
+use std::io;
+use std::thread;
+use std::time::Duration;
+
+use super::{crc8, Error, OneWire, Result, Rom, W1};
+
+const CONVERT_T: u8 = 0x44;
+const WRITE_SCRATCHPAD: u8 = 0x4e;
+const READ_SCRATCHPAD: u8 = 0xbe;
+const COPY_SCRATCHPAD: u8 = 0x48;
+const RECALL_E2: u8 = 0xb8;
+const READ_POWER_SUPPLY: u8 = 0xb4;
+
+// Maximum time it takes to copy the scratchpad to EEPROM
+const COPY_TIME: Duration = Duration::from_millis(10);
+
+/// DS18B20 temperature conversion resolutions.
+///
+/// Higher resolutions take longer to convert. The DS18B20 defaults to `Bits12`.
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Copy, Clone)]
+pub enum Resolution {
+    /// 0.5 °C, 93.75 ms.
+    Bits9 = 9,
+    /// 0.25 °C, 187.5 ms.
+    Bits10 = 10,
+    /// 0.125 °C, 375 ms.
+    Bits11 = 11,
+    /// 0.0625 °C, 750 ms.
+    Bits12 = 12,
+}
+
+impl Resolution {
+    /// Returns the maximum time a temperature conversion takes.
+    pub fn conversion_time(self) -> Duration {
+        match self {
+            Resolution::Bits9 => Duration::from_micros(93_750),
+            Resolution::Bits10 => Duration::from_micros(187_500),
+            Resolution::Bits11 => Duration::from_millis(375),
+            Resolution::Bits12 => Duration::from_millis(750),
+        }
+    }
+
+    fn from_bits(bits: u8) -> Option<Resolution> {
+        match bits {
+            9 => Some(Resolution::Bits9),
+            10 => Some(Resolution::Bits10),
+            11 => Some(Resolution::Bits11),
+            12 => Some(Resolution::Bits12),
+            _ => None,
+        }
+    }
+
+    fn from_config(config: u8) -> Resolution {
+        match (config >> 5) & 0x03 {
+            0 => Resolution::Bits9,
+            1 => Resolution::Bits10,
+            2 => Resolution::Bits11,
+            _ => Resolution::Bits12,
+        }
+    }
+
+    fn config(self) -> u8 {
+        ((self as u8 - 9) << 5) | 0x1f
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
+struct Scratchpad([u8; 9]);
+
+impl Scratchpad {
+    fn temperature(&self) -> f64 {
+        // The undefined low bits at lower resolutions are cleared
+        let mask = match self.resolution() {
+            Resolution::Bits9 => !0x07,
+            Resolution::Bits10 => !0x03,
+            Resolution::Bits11 => !0x01,
+            Resolution::Bits12 => !0x00,
+        };
+
+        f64::from(i16::from_le_bytes([self.0[0], self.0[1]]) & mask) / 16.0
+    }
+
+    fn resolution(&self) -> Resolution {
+        Resolution::from_config(self.0[4])
+    }
+}
+
+/// Maxim DS18B20 digital temperature sensor.
+///
+/// `Ds18b20` only stores the sensor's ROM code, and takes the bus as a parameter for
+/// each operation, so any number of sensors can share the same [`OneWire`] bus.
+///
+/// Temperature conversions can be started on all sensors at once with
+/// [`convert_all`] or [`measure_all`], which takes as long as converting a
+/// single temperature.
+///
+/// On a [`W1`] bus, sensors that are bound to the kernel's `w1_therm` driver are
+/// accessed through the driver's files in their device directory, and the kernel
+/// handles parasite power.
+///
+/// ## Example
+///
+/// ```no_run
+/// use std::error::Error;
+///
+/// use rpi_embedded::onewire::{Ds18b20, Resolution, W1};
+///
+/// # fn main() -> Result<(), Box<dyn Error>> {
+/// let mut bus = W1::new();
+/// let sensors = Ds18b20::find_all(&mut bus)?;
+///
+/// for sensor in &sensors {
+///     sensor.set_resolution(&mut bus, Resolution::Bits10)?;
+/// }
+///
+/// for (sensor, temperature) in sensors.iter().zip(Ds18b20::measure_all(&mut bus, &sensors)?) {
+///     println!("{}: {} °C", sensor.rom(), temperature);
+/// }
+/// # Ok(())
+/// # }
+/// ```
+///
+/// [`OneWire`]: trait.OneWire.html
+/// [`convert_all`]: #method.convert_all
+/// [`measure_all`]: #method.measure_all
+/// [`W1`]: struct.W1.html
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub struct Ds18b20 {
+    rom: Rom,
+}
+
+impl Ds18b20 {
+    /// The DS18B20's family code.
+    pub const FAMILY: u8 = 0x28;
+
+    /// Constructs a new `Ds18b20` for the sensor with the specified ROM code.
+    ///
+    /// Returns `Err(`[`Error::UnsupportedDevice`]`)` if the family code doesn't
+    /// belong to a DS18B20.
+    ///
+    /// [`Error::UnsupportedDevice`]: enum.Error.html#variant.UnsupportedDevice
+    pub fn new(rom: Rom) -> Result<Ds18b20> {
+        if rom.family() != Ds18b20::FAMILY {
+            return Err(Error::UnsupportedDevice(rom));
+        }
+
+        Ok(Ds18b20 { rom })
+    }
+
+    /// Returns all DS18B20 sensors on the bus.
+    pub fn find_all<B: OneWire>(bus: &mut B) -> Result<Vec<Ds18b20>> {
+        Ok(bus
+            .search()?
+            .into_iter()
+            .filter(|rom| rom.family() == Ds18b20::FAMILY)
+            .map(|rom| Ds18b20 { rom })
+            .collect())
+    }
+
+    /// Returns the sensor's ROM code.
+    pub fn rom(&self) -> Rom {
+        self.rom
+    }
+
+    /// Returns `true` if the sensor uses parasite power.
+    pub fn is_parasite_powered<B: OneWire>(&self, bus: &mut B) -> Result<bool> {
+        if let Some(w1) = self.therm(bus) {
+            // ext_power is 0 for parasite-powered sensors
+            return Ok(w1.read_attribute::<u8>(self.rom, "ext_power")? == 0);
+        }
+
+        let mut buffer = [0u8; 1];
+        bus.write(Some(self.rom), &[READ_POWER_SUPPLY])?;
+        bus.read(&mut buffer)?;
+
+        // Parasite-powered sensors pull the bus low
+        Ok(buffer[0] == 0)
+    }
+
+    /// Returns the conversion resolution.
+    pub fn resolution<B: OneWire>(&self, bus: &mut B) -> Result<Resolution> {
+        if let Some(w1) = self.therm(bus) {
+            let bits = w1.read_attribute(self.rom, "resolution")?;
+
+            return Resolution::from_bits(bits).ok_or_else(|| {
+                Error::Io(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("invalid resolution: {}", bits),
+                ))
+            });
+        }
+
+        Ok(self.read_scratchpad(bus)?.resolution())
+    }
+
+    /// Sets the conversion resolution.
+    ///
+    /// The new resolution is lost when the sensor loses power, unless it's
+    /// stored in EEPROM with [`save`].
+    ///
+    /// [`save`]: #method.save
+    pub fn set_resolution<B: OneWire>(&self, bus: &mut B, resolution: Resolution) -> Result<()> {
+        if let Some(w1) = self.therm(bus) {
+            return w1.write_attribute(self.rom, "resolution", &(resolution as u8).to_string());
+        }
+
+        // Write Scratchpad overwrites the alarm thresholds as well
+        let scratchpad = self.read_scratchpad(bus)?;
+
+        bus.write(
+            Some(self.rom),
+            &[
+                WRITE_SCRATCHPAD,
+                scratchpad.0[2],
+                scratchpad.0[3],
+                resolution.config(),
+            ],
+        )
+    }
+
+    /// Stores the resolution and alarm thresholds in EEPROM.
+    ///
+    /// Parasite-powered sensors are powered through [`OneWire::power`] while
+    /// the EEPROM is written.
+    ///
+    /// [`OneWire::power`]: trait.OneWire.html#method.power
+    pub fn save<B: OneWire>(&self, bus: &mut B) -> Result<()> {
+        if let Some(w1) = self.therm(bus) {
+            return w1.write_attribute(self.rom, "eeprom_cmd", "save");
+        }
+
+        let parasite = self.is_parasite_powered(bus)?;
+
+        bus.write(Some(self.rom), &[COPY_SCRATCHPAD])?;
+
+        wait(bus, COPY_TIME, parasite)
+    }
+
+    /// Restores the resolution and alarm thresholds from EEPROM.
+    pub fn restore<B: OneWire>(&self, bus: &mut B) -> Result<()> {
+        if let Some(w1) = self.therm(bus) {
+            return w1.write_attribute(self.rom, "eeprom_cmd", "restore");
+        }
+
+        bus.write(Some(self.rom), &[RECALL_E2])
+    }
+
+    /// Starts a temperature conversion, and returns immediately.
+    ///
+    /// Wait for at least [`Resolution::conversion_time`] before calling
+    /// [`read_temperature`]. Parasite-powered sensors need to be powered through
+    /// [`OneWire::power`] during the conversion.
+    ///
+    /// Sensors bound to `w1_therm` can't be converted individually. Instead, the
+    /// conversion is started on all `w1_therm` sensors through the bus master's
+    /// `therm_bulk_read` file.
+    ///
+    /// [`Resolution::conversion_time`]: enum.Resolution.html#method.conversion_time
+    /// [`read_temperature`]: #method.read_temperature
+    /// [`OneWire::power`]: trait.OneWire.html#method.power
+    pub fn start_conversion<B: OneWire>(&self, bus: &mut B) -> Result<()> {
+        if let Some(w1) = self.therm(bus) {
+            return w1.trigger_bulk_read();
+        }
+
+        bus.write(Some(self.rom), &[CONVERT_T])
+    }
+
+    /// Returns the result of the most recent temperature conversion in degrees
+    /// Celsius (°C).
+    ///
+    /// A sensor that hasn't completed a conversion since it was powered up
+    /// returns 85 °C. For sensors bound to `w1_therm`, the kernel converts the
+    /// temperature first unless a conversion was started through `therm_bulk_read`,
+    /// which blocks for the conversion time.
+    pub fn read_temperature<B: OneWire>(&self, bus: &mut B) -> Result<f64> {
+        if let Some(w1) = self.therm(bus) {
+            // The driver reports the temperature in millidegrees
+            return Ok(f64::from(w1.read_attribute::<i32>(self.rom, "temperature")?) / 1000.0);
+        }
+
+        Ok(self.read_scratchpad(bus)?.temperature())
+    }
+
+    /// Converts and returns the temperature in degrees Celsius (°C).
+    ///
+    /// `measure` blocks for the conversion time of the sensor's current resolution,
+    /// and powers parasite-powered sensors through [`OneWire::power`].
+    ///
+    /// [`OneWire::power`]: trait.OneWire.html#method.power
+    pub fn measure<B: OneWire>(&self, bus: &mut B) -> Result<f64> {
+        // w1_therm converts the temperature when it's read
+        if self.therm(bus).is_some() {
+            return self.read_temperature(bus);
+        }
+
+        let resolution = self.resolution(bus)?;
+        let parasite = self.is_parasite_powered(bus)?;
+
+        self.start_conversion(bus)?;
+        wait(bus, resolution.conversion_time(), parasite)?;
+
+        self.read_temperature(bus)
+    }
+
+    /// Starts a temperature conversion on all DS18B20 sensors on the bus at the same
+    /// time, and blocks until the conversion completes.
+    ///
+    /// `resolution` should be the highest resolution any of the sensors is configured
+    /// for. If `parasite` is `true`, the sensors are powered through [`OneWire::power`]
+    /// during the conversion. Call [`read_temperature`] on each sensor afterwards.
+    ///
+    /// On a [`W1`] bus, sensors bound to `w1_therm` are converted through the bus
+    /// master's `therm_bulk_read` file.
+    ///
+    /// [`OneWire::power`]: trait.OneWire.html#method.power
+    /// [`read_temperature`]: #method.read_temperature
+    /// [`W1`]: struct.W1.html
+    pub fn convert_all<B: OneWire>(
+        bus: &mut B,
+        resolution: Resolution,
+        parasite: bool,
+    ) -> Result<()> {
+        if let Some(w1) = bus.as_w1() {
+            w1.trigger_bulk_read()?;
+        }
+
+        bus.broadcast(Ds18b20::FAMILY, &[CONVERT_T])?;
+
+        wait(bus, resolution.conversion_time(), parasite)
+    }
+
+    /// Converts the temperature on all specified sensors at the same time, and returns
+    /// the results in degrees Celsius (°C), in the same order as `sensors`.
+    ///
+    /// The conversion time and the need for parasite power are determined by querying
+    /// each sensor first.
+    pub fn measure_all<B: OneWire>(bus: &mut B, sensors: &[Ds18b20]) -> Result<Vec<f64>> {
+        let mut resolution = Resolution::Bits9;
+        let mut parasite = false;
+        for sensor in sensors {
+            resolution = resolution.max(sensor.resolution(bus)?);
+            parasite |= sensor.is_parasite_powered(bus)?;
+        }
+
+        Ds18b20::convert_all(bus, resolution, parasite)?;
+
+        sensors
+            .iter()
+            .map(|sensor| sensor.read_temperature(bus))
+            .collect()
+    }
+
+    // Sensors bound to the kernel's w1_therm driver don't provide raw access, and
+    // are accessed through the driver's files instead
+    fn therm<'a, B: OneWire>(&self, bus: &'a mut B) -> Option<&'a mut W1> {
+        let rom = self.rom;
+
+        bus.as_w1().filter(|w1| w1.is_therm(rom))
+    }
+
+    fn read_scratchpad<B: OneWire>(&self, bus: &mut B) -> Result<Scratchpad> {
+        let mut buffer = [0u8; 9];
+        bus.write(Some(self.rom), &[READ_SCRATCHPAD])?;
+        bus.read(&mut buffer)?;
+
+        // An all-zero scratchpad passes the CRC check, but means no sensor responded
+        if crc8(&buffer) != 0 || buffer.iter().all(|&byte| byte == 0) {
+            return Err(Error::Crc);
+        }
+
+        Ok(Scratchpad(buffer))
+    }
+}
+
+fn wait<B: OneWire>(bus: &mut B, duration: Duration, parasite: bool) -> Result<()> {
+    if parasite {
+        bus.power(duration)
+    } else {
+        thread::sleep(duration);
+        Ok(())
+    }
+}
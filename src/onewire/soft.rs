@@ -0,0 +1,610 @@
+use std::time::Duration;
+
+use super::{Error, OneWire, Result, Rom};
+use crate::gpio::soft_pwm::{get_time_ns, wait_until_ns};
+use crate::gpio::{IoPin, Level, Mode, PullUpDown};
+
+const SEARCH_ROM: u8 = 0xf0;
+const READ_ROM: u8 = 0x33;
+const MATCH_ROM: u8 = 0x55;
+const SKIP_ROM: u8 = 0xcc;
+
+// Standard speed timing in nanoseconds
+const RESET_LOW: i64 = 480_000;
+const PRESENCE_SAMPLE: i64 = 70_000;
+const RESET_SLOT: i64 = 960_000;
+const WRITE_ONE_LOW: i64 = 6_000;
+const WRITE_ZERO_LOW: i64 = 60_000;
+const READ_LOW: i64 = 6_000;
+const READ_SAMPLE: i64 = 15_000;
+const SLOT: i64 = 70_000;
+
+/// Bit-banged 1-Wire master on a single GPIO pin.
+///
+/// `SoftOneWire` emulates an open-drain output on an [`IoPin`] by switching between
+/// [`Output`] mode with the output latch set low, which pulls the bus low, and [`Input`]
+/// mode, which releases the bus. The pin's built-in pull-up resistor is enabled, but
+/// it's too weak for most setups. Add an external 4.7 kΩ pull-up resistor to 3.3 V.
+///
+/// All time slots are timed by busy-waiting on the calling thread. More information
+/// on timing issues and parasite power can be found [here].
+///
+/// ## Example
+///
+/// ```no_run
+/// use std::error::Error;
+///
+/// use rpi_embedded::gpio::{Gpio, Mode};
+/// use rpi_embedded::onewire::{Ds18b20, SoftOneWire};
+///
+/// # fn main() -> Result<(), Box<dyn Error>> {
+/// let pin = Gpio::new()?.get(17)?.into_io(Mode::Input);
+/// let mut bus = SoftOneWire::new(pin);
+///
+/// let rom = bus.read_rom()?;
+/// let sensor = Ds18b20::new(rom)?;
+/// println!("{} °C", sensor.measure(&mut bus)?);
+/// # Ok(())
+/// # }
+/// ```
+///
+/// [`IoPin`]: ../gpio/struct.IoPin.html
+/// [`Output`]: ../gpio/enum.Mode.html#variant.Output
+/// [`Input`]: ../gpio/enum.Mode.html#variant.Input
+/// [here]: index.html
+#[derive(Debug)]
+pub struct SoftOneWire {
+    pin: IoPin,
+}
+
+impl SoftOneWire {
+    /// Constructs a new `SoftOneWire` on the specified pin.
+    pub fn new(mut pin: IoPin) -> SoftOneWire {
+        pin.set_mode(Mode::Input);
+        pin.set_pullupdown(PullUpDown::PullUp);
+        pin.set_low();
+
+        SoftOneWire { pin }
+    }
+
+    /// Resets the bus, and returns `true` if any device responded with a presence pulse.
+    pub fn reset(&mut self) -> Result<bool> {
+        // A bus that's held low can't be reset
+        if self.pin.read() == Level::Low {
+            return Err(Error::NoDevice);
+        }
+
+        let start = get_time_ns();
+        self.pin.set_mode(Mode::Output);
+        wait_until_ns(start + RESET_LOW);
+        self.pin.set_mode(Mode::Input);
+        wait_until_ns(start + RESET_LOW + PRESENCE_SAMPLE);
+        let presence = self.pin.read() == Level::Low;
+        wait_until_ns(start + RESET_SLOT);
+
+        Ok(presence)
+    }
+
+    /// Sends a single bit.
+    pub fn write_bit(&mut self, bit: bool) {
+        let start = get_time_ns();
+        self.pin.set_mode(Mode::Output);
+        wait_until_ns(start + if bit { WRITE_ONE_LOW } else { WRITE_ZERO_LOW });
+        self.pin.set_mode(Mode::Input);
+        wait_until_ns(start + SLOT);
+    }
+
+    /// Reads a single bit.
+    pub fn read_bit(&mut self) -> bool {
+        let start = get_time_ns();
+        self.pin.set_mode(Mode::Output);
+        wait_until_ns(start + READ_LOW);
+        self.pin.set_mode(Mode::Input);
+        wait_until_ns(start + READ_SAMPLE);
+        let bit = self.pin.read() == Level::High;
+        wait_until_ns(start + SLOT);
+
+        bit
+    }
+
+    /// Sends a byte, least-significant bit first.
+    pub fn write_byte(&mut self, value: u8) {
+        for bit in 0..8 {
+            self.write_bit(value & (1 << bit) != 0);
+        }
+    }
+
+    /// Reads a byte, least-significant bit first.
+    pub fn read_byte(&mut self) -> u8 {
+        (0..8).fold(0, |value, bit| value | ((self.read_bit() as u8) << bit))
+    }
+
+    /// Returns the ROM code of the only device on the bus.
+    ///
+    /// If more than one device is connected, their responses collide, which results
+    /// in an [`Error::Crc`].
+    ///
+    /// [`Error::Crc`]: enum.Error.html#variant.Crc
+    pub fn read_rom(&mut self) -> Result<Rom> {
+        self.select(READ_ROM)?;
+
+        let mut bytes = [0u8; 8];
+        for byte in bytes.iter_mut() {
+            *byte = self.read_byte();
+        }
+
+        Rom::from_bytes(bytes)
+    }
+
+    /// Releases the bus, and returns the pin.
+    pub fn into_pin(self) -> IoPin {
+        self.pin
+    }
+
+    fn select(&mut self, command: u8) -> Result<()> {
+        if !self.reset()? {
+            return Err(Error::NoDevice);
+        }
+
+        self.write_byte(command);
+
+        Ok(())
+    }
+}
+
+impl OneWire for SoftOneWire {
+    /// Searches the bus for devices using the Search ROM command.
+    fn search(&mut self) -> Result<Vec<Rom>> {
+        let mut roms = Vec::new();
+        let mut last_rom = 0u64;
+        // Bit position of the last branch where the 0 path was taken, starting at 1
+        let mut last_discrepancy = 0;
+
+        loop {
+            if !self.reset()? {
+                return Ok(roms);
+            }
+
+            self.write_byte(SEARCH_ROM);
+
+            let mut rom = 0u64;
+            let mut discrepancy = 0;
+            for position in 1..=64 {
+                let id_bit = self.read_bit();
+                let complement_bit = self.read_bit();
+
+                let direction = match (id_bit, complement_bit) {
+                    // All participating devices have left the search
+                    (true, true) => return Err(Error::NoDevice),
+                    (true, false) => true,
+                    (false, true) => false,
+                    // Devices with both values are present
+                    (false, false) => {
+                        let direction = if position < last_discrepancy {
+                            last_rom & (1 << (position - 1)) != 0
+                        } else {
+                            position == last_discrepancy
+                        };
+
+                        if !direction {
+                            discrepancy = position;
+                        }
+
+                        direction
+                    }
+                };
+
+                if direction {
+                    rom |= 1 << (position - 1);
+                }
+
+                self.write_bit(direction);
+            }
+
+            roms.push(Rom::from_bytes(rom.to_le_bytes())?);
+
+            if discrepancy == 0 {
+                return Ok(roms);
+            }
+
+            last_rom = rom;
+            last_discrepancy = discrepancy;
+        }
+    }
+
+    fn write(&mut self, rom: Option<Rom>, buffer: &[u8]) -> Result<()> {
+        match rom {
+            Some(rom) => {
+                self.select(MATCH_ROM)?;
+                for &byte in rom.to_bytes().iter() {
+                    self.write_byte(byte);
+                }
+            }
+            None => self.select(SKIP_ROM)?,
+        }
+
+        for &byte in buffer {
+            self.write_byte(byte);
+        }
+
+        Ok(())
+    }
+
+    fn read(&mut self, buffer: &mut [u8]) -> Result<()> {
+        for byte in buffer.iter_mut() {
+            *byte = self.read_byte();
+        }
+
+        Ok(())
+    }
+
+    /// Drives the bus high for the specified duration, which powers
+    /// parasite-powered devices.
+    fn power(&mut self, duration: Duration) -> Result<()> {
+        let start = get_time_ns();
+        self.pin.set_high();
+        self.pin.set_mode(Mode::Output);
+        wait_until_ns(start + duration.as_nanos() as i64);
+        self.pin.set_mode(Mode::Input);
+        self.pin.set_low();
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use super::*;
+    use crate::gpio::sim::SimDevice;
+    use crate::gpio::soft_pwm::simulate_time;
+    use crate::gpio::{Gpio, Simulator};
+
+    const PIN: u8 = 17;
+
+    // Low pulses of at least this length reset the bus
+    const RESET_MIN: i64 = 400_000;
+    // Write slots with a shorter low pulse send a 1
+    const WRITE_ONE_MAX: i64 = 30_000;
+    const PRESENCE: i64 = 120_000;
+    // How long a device holds the bus low to send a 0
+    const READ_ZERO: i64 = 45_000;
+
+    #[derive(Debug, PartialEq, Eq, Copy, Clone)]
+    enum Event {
+        // Reset pulse, and whether any device responded with a presence pulse
+        Reset(bool),
+        Command(u8),
+        // Devices selected by Match ROM or Skip ROM
+        Selected(usize),
+        Data(u8),
+    }
+
+    #[derive(Debug, Copy, Clone)]
+    enum State {
+        Idle,
+        // Receiving a ROM command
+        Command,
+        // Search ROM step for the specified bit position: sending the bit, sending its
+        // complement, or receiving the direction
+        Search(u8, u8),
+        // Read ROM bit position
+        ReadRom(u8),
+        // Match ROM bit position
+        MatchRom(u8),
+        // Receiving data after a device has been selected
+        Data,
+    }
+
+    // Any number of 1-Wire devices sharing the bus, which respond to resets and
+    // ROM commands, and log the data they receive
+    #[derive(Debug)]
+    struct Devices {
+        roms: Vec<Rom>,
+        // Devices taking part in the current search, or selected by Match ROM
+        active: Vec<bool>,
+        state: State,
+        bus: bool,
+        falling_edge: i64,
+        // The current slot is a read slot
+        reading: bool,
+        drive_until: Option<i64>,
+        shift: u64,
+        bits: u8,
+        // Holds the bus low, as if it were shorted
+        stuck: bool,
+        events: Vec<Event>,
+    }
+
+    impl Devices {
+        fn new(roms: Vec<Rom>) -> Devices {
+            Devices {
+                active: vec![false; roms.len()],
+                roms,
+                state: State::Idle,
+                bus: true,
+                falling_edge: 0,
+                reading: false,
+                drive_until: None,
+                shift: 0,
+                bits: 0,
+                stuck: false,
+                events: Vec::new(),
+            }
+        }
+
+        // Bit of the ROM code at the specified position, for every active device
+        fn rom_bits(&self, position: u8) -> Vec<bool> {
+            self.roms
+                .iter()
+                .zip(&self.active)
+                .filter(|(_, &active)| active)
+                .map(|(rom, _)| u64::from_le_bytes(rom.to_bytes()) & (1 << position) != 0)
+                .collect()
+        }
+
+        // Start of a slot where the devices send a bit. Any device sending a 0 pulls
+        // the bus low.
+        fn read_slot(&mut self) {
+            let bits = match self.state {
+                State::Search(position, step @ 0..=1) => {
+                    let bits = self.rom_bits(position);
+                    self.state = State::Search(position, step + 1);
+                    if step == 0 {
+                        bits
+                    } else {
+                        bits.iter().map(|bit| !bit).collect()
+                    }
+                }
+                State::ReadRom(position) => {
+                    self.state = if position == 63 {
+                        State::Data
+                    } else {
+                        State::ReadRom(position + 1)
+                    };
+                    self.rom_bits(position)
+                }
+                _ => return,
+            };
+
+            self.reading = true;
+            if bits.contains(&false) {
+                self.drive_until = Some(self.falling_edge + READ_ZERO);
+                self.bus = false;
+            }
+        }
+
+        // End of a slot where the master sends a bit
+        fn write_slot(&mut self, bit: bool) {
+            match self.state {
+                State::Command | State::Data => {
+                    self.shift |= u64::from(bit) << self.bits;
+                    self.bits += 1;
+                    if self.bits == 8 {
+                        let value = self.shift as u8;
+                        self.shift = 0;
+                        self.bits = 0;
+                        if let State::Command = self.state {
+                            self.command(value);
+                        } else {
+                            self.events.push(Event::Data(value));
+                        }
+                    }
+                }
+                State::Search(position, 2) => {
+                    for (rom, active) in self.roms.iter().zip(self.active.iter_mut()) {
+                        let rom_bit = u64::from_le_bytes(rom.to_bytes()) & (1 << position) != 0;
+                        *active &= rom_bit == bit;
+                    }
+
+                    self.state = if position == 63 {
+                        State::Idle
+                    } else {
+                        State::Search(position + 1, 0)
+                    };
+                }
+                State::MatchRom(position) => {
+                    for (rom, active) in self.roms.iter().zip(self.active.iter_mut()) {
+                        let rom_bit = u64::from_le_bytes(rom.to_bytes()) & (1 << position) != 0;
+                        *active &= rom_bit == bit;
+                    }
+
+                    if position == 63 {
+                        self.select();
+                    } else {
+                        self.state = State::MatchRom(position + 1);
+                    }
+                }
+                _ => (),
+            }
+        }
+
+        fn command(&mut self, command: u8) {
+            self.events.push(Event::Command(command));
+            self.active = vec![true; self.roms.len()];
+            self.state = match command {
+                SEARCH_ROM => State::Search(0, 0),
+                READ_ROM => State::ReadRom(0),
+                MATCH_ROM => State::MatchRom(0),
+                SKIP_ROM => {
+                    self.select();
+                    return;
+                }
+                _ => State::Idle,
+            };
+        }
+
+        fn select(&mut self) {
+            let selected = self.active.iter().filter(|&&active| active).count();
+            self.events.push(Event::Selected(selected));
+            self.state = State::Data;
+        }
+    }
+
+    #[derive(Debug)]
+    struct Device(Arc<Mutex<Devices>>);
+
+    impl SimDevice for Device {
+        fn respond(&mut self, levels: u64) -> Vec<(u8, Option<Level>)> {
+            let mut devices = self.0.lock().unwrap();
+            if devices.stuck {
+                return vec![(PIN, Some(Level::Low))];
+            }
+
+            let now = get_time_ns();
+            if let Some(drive_until) = devices.drive_until {
+                if now < drive_until {
+                    return vec![(PIN, Some(Level::Low))];
+                }
+
+                // Any low level after releasing the bus starts a new slot
+                devices.drive_until = None;
+                devices.reading = false;
+                devices.bus = true;
+                return vec![(PIN, None)];
+            }
+
+            let bus = levels & (1 << PIN) != 0;
+            if devices.bus && !bus {
+                devices.falling_edge = now;
+                devices.bus = false;
+                devices.read_slot();
+            } else if !devices.bus && bus {
+                devices.bus = true;
+
+                let low = now - devices.falling_edge;
+                if low >= RESET_MIN {
+                    let presence = !devices.roms.is_empty();
+                    devices.events.push(Event::Reset(presence));
+                    devices.state = State::Command;
+                    devices.shift = 0;
+                    devices.bits = 0;
+                    if presence {
+                        devices.drive_until = Some(now + PRESENCE);
+                        devices.bus = false;
+                    }
+                } else if !devices.reading {
+                    devices.write_slot(low < WRITE_ONE_MAX);
+                }
+
+                devices.reading = false;
+            }
+
+            let drive = devices.drive_until.map(|_| Level::Low);
+
+            vec![(PIN, drive)]
+        }
+    }
+
+    // The time slots are too short to be reliably timed by the test thread, so the
+    // bus runs on simulated time
+    fn simulated(devices: Devices) -> (SoftOneWire, Simulator, Arc<Mutex<Devices>>) {
+        simulate_time();
+
+        let (gpio, sim) = Gpio::simulated().unwrap();
+        let bus = SoftOneWire::new(gpio.get(PIN).unwrap().into_io(Mode::Input));
+
+        let devices = Arc::new(Mutex::new(devices));
+        sim.attach(Box::new(Device(devices.clone())));
+
+        (bus, sim, devices)
+    }
+
+    fn events(devices: &Mutex<Devices>) -> Vec<Event> {
+        devices.lock().unwrap().events.drain(..).collect()
+    }
+
+    fn roms() -> Vec<Rom> {
+        vec![
+            Rom::with_serial(0x28, 0x0000_056a_2f1c),
+            Rom::with_serial(0x28, 0x0000_056a_2f1d),
+            Rom::with_serial(0x28, 0x0000_0c6a_2f1c),
+            Rom::with_serial(0x10, 0x0008_02b4_f1a9),
+        ]
+    }
+
+    #[test]
+    fn reset() {
+        let (mut bus, _sim, devices) = simulated(Devices::new(Vec::new()));
+        assert!(!bus.reset().unwrap());
+        assert_eq!(events(&devices), [Event::Reset(false)]);
+
+        let (mut bus, sim, devices) = simulated(Devices::new(roms()));
+        assert!(bus.reset().unwrap());
+        // The presence pulse ends by itself
+        assert!(bus.reset().unwrap());
+        assert_eq!(events(&devices), [Event::Reset(true), Event::Reset(true)]);
+        assert_eq!(sim.level(PIN), Level::High);
+
+        // A bus that's held low can't be reset
+        devices.lock().unwrap().stuck = true;
+        assert!(matches!(bus.reset(), Err(Error::NoDevice)));
+    }
+
+    #[test]
+    fn search() {
+        let (mut bus, _sim, devices) = simulated(Devices::new(roms()));
+
+        let mut found = bus.search().unwrap();
+        found.sort();
+        let mut expected = roms();
+        expected.sort();
+        assert_eq!(found, expected);
+
+        // Every device is found with a separate search
+        let events = events(&devices);
+        assert_eq!(
+            events
+                .iter()
+                .filter(|&&event| event == Event::Command(SEARCH_ROM))
+                .count(),
+            4
+        );
+
+        let (mut bus, _sim, _devices) = simulated(Devices::new(Vec::new()));
+        assert!(bus.search().unwrap().is_empty());
+    }
+
+    #[test]
+    fn read_rom() {
+        let rom = roms()[0];
+        let (mut bus, _sim, _devices) = simulated(Devices::new(vec![rom]));
+        assert_eq!(bus.read_rom().unwrap(), rom);
+
+        // Multiple devices respond at the same time, which corrupts the CRC
+        let (mut bus, _sim, _devices) = simulated(Devices::new(roms()));
+        assert!(matches!(bus.read_rom(), Err(Error::Crc)));
+
+        let (mut bus, _sim, _devices) = simulated(Devices::new(Vec::new()));
+        assert!(matches!(bus.read_rom(), Err(Error::NoDevice)));
+    }
+
+    #[test]
+    fn write() {
+        let (mut bus, _sim, devices) = simulated(Devices::new(roms()));
+
+        bus.write(Some(roms()[1]), &[0x4e, 0x81]).unwrap();
+        assert_eq!(
+            events(&devices),
+            [
+                Event::Reset(true),
+                Event::Command(MATCH_ROM),
+                Event::Selected(1),
+                Event::Data(0x4e),
+                Event::Data(0x81)
+            ]
+        );
+
+        bus.write(None, &[0x44]).unwrap();
+        assert_eq!(
+            events(&devices),
+            [
+                Event::Reset(true),
+                Event::Command(SKIP_ROM),
+                Event::Selected(4),
+                Event::Data(0x44)
+            ]
+        );
+    }
+}
@@ -0,0 +1,406 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use super::{Error, OneWire, Result, Rom};
+
+const DEVICES_PATH: &str = "/sys/bus/w1/devices";
+const MASTER_PREFIX: &str = "w1_bus_master";
+
+/// Provides access to 1-Wire devices through the kernel's `w1` subsystem.
+///
+/// Every device the kernel has detected shows up as a directory in
+/// `/sys/bus/w1/devices`, named after its ROM code. Raw transactions are sent
+/// through the device's `rw` file. Writing to `rw` resets the bus, selects the
+/// device with a Match ROM command and sends the data, while reading from `rw`
+/// continues the transaction.
+///
+/// The kernel only provides `rw` for devices that aren't bound to a family driver.
+/// Transactions with a device that doesn't have an `rw` file return
+/// [`Error::RawAccessUnavailable`]. DS18B20 sensors are bound to `w1_therm` by default,
+/// in which case [`Ds18b20`] uses the driver's files instead.
+///
+/// More information on enabling the `w1-gpio` bus master can be found [here].
+///
+/// [here]: index.html
+/// [`Error::RawAccessUnavailable`]: enum.Error.html#variant.RawAccessUnavailable
+/// [`Ds18b20`]: struct.Ds18b20.html
+#[derive(Debug)]
+pub struct W1 {
+    path: PathBuf,
+    selected: Option<File>,
+}
+
+impl W1 {
+    /// Constructs a new `W1` for the devices listed in `/sys/bus/w1/devices`.
+    pub fn new() -> W1 {
+        W1::with_path(DEVICES_PATH)
+    }
+
+    /// Constructs a new `W1` for the devices listed in the specified directory
+    /// instead of `/sys/bus/w1/devices`.
+    pub fn with_path<P: AsRef<Path>>(path: P) -> W1 {
+        W1 {
+            path: path.as_ref().to_path_buf(),
+            selected: None,
+        }
+    }
+
+    /// Returns the path of the device directory for the specified ROM code.
+    ///
+    /// Any additional files provided by the kernel's device driver, such as
+    /// `w1_slave` or `temperature` for temperature sensors, can be found here.
+    pub fn device_path(&self, rom: Rom) -> PathBuf {
+        self.path.join(rom.to_string())
+    }
+
+    fn open(&self, rom: Rom) -> Result<File> {
+        let path = self.device_path(rom);
+
+        match OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(path.join("rw"))
+        {
+            Ok(file) => Ok(file),
+            // Devices bound to a family driver don't get an rw file
+            Err(ref err) if err.kind() == io::ErrorKind::NotFound && path.is_dir() => {
+                Err(Error::RawAccessUnavailable(rom))
+            }
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    // Sends the data in `buffer` to every device with an rw file, optionally limited
+    // to a single family
+    fn write_all(&mut self, family: Option<u8>, buffer: &[u8]) -> Result<()> {
+        self.selected = None;
+
+        for rom in self.search()? {
+            if matches!(family, Some(family) if family != rom.family()) {
+                continue;
+            }
+
+            match self.open(rom) {
+                Ok(mut file) => file.write_all(buffer)?,
+                // Devices bound to a family driver can't be addressed this way
+                Err(Error::RawAccessUnavailable(_)) => (),
+                Err(err) => return Err(err),
+            }
+        }
+
+        Ok(())
+    }
+
+    // Returns true if the device is bound to w1_therm, which provides a temperature
+    // file instead of rw
+    pub(super) fn is_therm(&self, rom: Rom) -> bool {
+        let path = self.device_path(rom);
+
+        !path.join("rw").exists() && path.join("temperature").exists()
+    }
+
+    pub(super) fn read_attribute<T: FromStr>(&self, rom: Rom, name: &str) -> Result<T> {
+        let value = fs::read_to_string(self.device_path(rom).join(name))?;
+
+        value.trim().parse().map_err(|_| {
+            Error::Io(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("invalid value in {}: {}", name, value.trim()),
+            ))
+        })
+    }
+
+    pub(super) fn write_attribute(&self, rom: Rom, name: &str, value: &str) -> Result<()> {
+        Ok(fs::write(self.device_path(rom).join(name), value)?)
+    }
+
+    // Starts a temperature conversion on all devices bound to w1_therm through each
+    // bus master's therm_bulk_read file
+    pub(super) fn trigger_bulk_read(&self) -> Result<()> {
+        for entry in fs::read_dir(&self.path)? {
+            let entry = entry?;
+            if !entry
+                .file_name()
+                .to_string_lossy()
+                .starts_with(MASTER_PREFIX)
+            {
+                continue;
+            }
+
+            // therm_bulk_read is only available while w1_therm is loaded
+            let path = entry.path().join("therm_bulk_read");
+            if path.exists() {
+                fs::write(path, "trigger")?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl Default for W1 {
+    fn default() -> W1 {
+        W1::new()
+    }
+}
+
+impl OneWire for W1 {
+    /// Returns the ROM codes of the devices the kernel has detected.
+    ///
+    /// The kernel periodically searches the bus, so newly connected devices may take
+    /// a few seconds to show up.
+    fn search(&mut self) -> Result<Vec<Rom>> {
+        let mut roms: Vec<Rom> = Vec::new();
+
+        for entry in fs::read_dir(&self.path)? {
+            // Skip bus masters and anything else that isn't named after a ROM code
+            if let Some(rom) = entry?
+                .file_name()
+                .to_str()
+                .and_then(|name| name.parse().ok())
+            {
+                roms.push(rom);
+            }
+        }
+
+        roms.sort_by_key(|rom| (rom.family(), rom.serial()));
+
+        Ok(roms)
+    }
+
+    /// Sends the data in `buffer` to the device with the specified ROM code.
+    ///
+    /// The kernel doesn't offer a way to address all devices at once from user
+    /// space. If `rom` is `None`, the data is sent to every device in turn, skipping
+    /// devices that are bound to a family driver. Each write only takes a few
+    /// milliseconds, so commands such as starting a temperature conversion still run
+    /// in parallel. [`read`] isn't available afterwards.
+    ///
+    /// [`read`]: #method.read
+    fn write(&mut self, rom: Option<Rom>, buffer: &[u8]) -> Result<()> {
+        let rom = match rom {
+            Some(rom) => rom,
+            None => return self.write_all(None, buffer),
+        };
+
+        self.selected = None;
+        let mut file = self.open(rom)?;
+        file.write_all(buffer)?;
+        self.selected = Some(file);
+
+        Ok(())
+    }
+
+    /// Sends the data in `buffer` to every device with the specified family code in
+    /// turn, skipping devices that are bound to a family driver.
+    fn broadcast(&mut self, family: u8, buffer: &[u8]) -> Result<()> {
+        self.write_all(Some(family), buffer)
+    }
+
+    fn read(&mut self, buffer: &mut [u8]) -> Result<()> {
+        match self.selected {
+            Some(ref mut file) => Ok(file.read_exact(buffer)?),
+            None => Err(io::Error::new(io::ErrorKind::InvalidInput, "no device selected").into()),
+        }
+    }
+
+    fn as_w1(&mut self) -> Option<&mut W1> {
+        Some(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::onewire::{Ds18b20, Resolution};
+    use std::process;
+
+    // Builds a fake /sys/bus/w1/devices tree in a temporary directory
+    struct Fixture {
+        path: PathBuf,
+    }
+
+    impl Fixture {
+        fn new(name: &str) -> Fixture {
+            let path =
+                std::env::temp_dir().join(format!("rpi_embedded_w1_{}_{}", name, process::id()));
+            let _ = fs::remove_dir_all(&path);
+            fs::create_dir_all(path.join("w1_bus_master1")).unwrap();
+
+            Fixture { path }
+        }
+
+        fn add_device(&self, name: &str, rw: &[u8]) {
+            let device = self.path.join(name);
+            fs::create_dir_all(&device).unwrap();
+            fs::write(device.join("rw"), rw).unwrap();
+        }
+
+        // Devices bound to w1_therm only provide the driver's files
+        fn add_therm_device(&self, name: &str, temperature: &str, ext_power: &str) {
+            let device = self.path.join(name);
+            fs::create_dir_all(&device).unwrap();
+            fs::write(device.join("temperature"), temperature).unwrap();
+            fs::write(device.join("resolution"), "12\n").unwrap();
+            fs::write(device.join("ext_power"), ext_power).unwrap();
+            fs::write(self.master_path().join("therm_bulk_read"), "0\n").unwrap();
+        }
+
+        fn master_path(&self) -> PathBuf {
+            self.path.join("w1_bus_master1")
+        }
+
+        fn rw(&self, name: &str) -> Vec<u8> {
+            fs::read(self.path.join(name).join("rw")).unwrap()
+        }
+
+        fn attribute(&self, name: &str, attribute: &str) -> String {
+            fs::read_to_string(self.path.join(name).join(attribute)).unwrap()
+        }
+    }
+
+    impl Drop for Fixture {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.path);
+        }
+    }
+
+    #[test]
+    fn search() {
+        let fixture = Fixture::new("search");
+        fixture.add_device("28-0000056a2f1c", &[]);
+        fixture.add_device("10-000802b4f1a9", &[]);
+        fixture.add_device("00-400000000000", &[]);
+
+        let mut w1 = W1::with_path(&fixture.path);
+        let roms: Vec<String> = w1.search().unwrap().iter().map(Rom::to_string).collect();
+        assert_eq!(
+            roms,
+            ["00-400000000000", "10-000802b4f1a9", "28-0000056a2f1c"]
+        );
+
+        let rom: Rom = "28-0000056a2f1c".parse().unwrap();
+        assert_eq!(w1.device_path(rom), fixture.path.join("28-0000056a2f1c"));
+    }
+
+    #[test]
+    fn write_read() {
+        let fixture = Fixture::new("write_read");
+        fixture.add_device("28-0000056a2f1c", &[0x00, 0x00, 0x11, 0x22]);
+        fixture.add_device("28-0000056a2f1d", &[0x00]);
+        fixture.add_device("10-000802b4f1a9", &[0x00]);
+        fixture.add_therm_device("28-0000056a2f1e", "23125\n", "1\n");
+
+        let mut w1 = W1::with_path(&fixture.path);
+        assert!(w1.read(&mut [0u8; 1]).is_err());
+
+        let rom = "28-0000056a2f1c".parse().unwrap();
+        w1.write(Some(rom), &[0xbe, 0x01]).unwrap();
+        let mut buffer = [0u8; 2];
+        w1.read(&mut buffer).unwrap();
+        assert_eq!(buffer, [0x11, 0x22]);
+        assert_eq!(fixture.rw("28-0000056a2f1c"), [0xbe, 0x01, 0x11, 0x22]);
+
+        // Broadcasts are sent to every device with raw access, or only to those
+        // with a matching family code
+        w1.broadcast(0x28, &[0x44]).unwrap();
+        assert_eq!(fixture.rw("28-0000056a2f1c")[0], 0x44);
+        assert_eq!(fixture.rw("28-0000056a2f1d"), [0x44]);
+        assert_eq!(fixture.rw("10-000802b4f1a9"), [0x00]);
+        assert!(w1.read(&mut buffer).is_err());
+
+        w1.write(None, &[0xb4]).unwrap();
+        assert_eq!(fixture.rw("28-0000056a2f1d"), [0xb4]);
+        assert_eq!(fixture.rw("10-000802b4f1a9"), [0xb4]);
+
+        let missing = "28-0000056a2f1f".parse().unwrap();
+        assert!(matches!(
+            w1.write(Some(missing), &[0x44]),
+            Err(Error::Io(_))
+        ));
+    }
+
+    #[test]
+    fn ds18b20() {
+        let fixture = Fixture::new("ds18b20");
+        // Read Scratchpad command followed by the scratchpad for 23.125 °C at 12 bits
+        fixture.add_device(
+            "28-0000056a2f1c",
+            &[0xbe, 0x72, 0x01, 0x4b, 0x46, 0x7f, 0xff, 0x0e, 0x10, 0x57],
+        );
+        fixture.add_device("10-000802b4f1a9", &[]);
+
+        let mut w1 = W1::with_path(&fixture.path);
+        let sensors = Ds18b20::find_all(&mut w1).unwrap();
+        assert_eq!(sensors.len(), 1);
+
+        let sensor = sensors[0];
+        assert_eq!(sensor.read_temperature(&mut w1).unwrap(), 23.125);
+        assert_eq!(sensor.resolution(&mut w1).unwrap(), Resolution::Bits12);
+
+        // Corrupt the CRC
+        fixture.add_device(
+            "28-0000056a2f1c",
+            &[0xbe, 0x72, 0x01, 0x4b, 0x46, 0x7f, 0xff, 0x0e, 0x10, 0x58],
+        );
+        assert!(matches!(sensor.read_temperature(&mut w1), Err(Error::Crc)));
+    }
+
+    #[test]
+    fn therm() {
+        let fixture = Fixture::new("therm");
+        fixture.add_therm_device("28-0000056a2f1c", "23125\n", "1\n");
+        fixture.add_therm_device("28-0000056a2f1d", "-10062\n", "0\n");
+
+        let mut w1 = W1::with_path(&fixture.path);
+        let sensors = Ds18b20::find_all(&mut w1).unwrap();
+        assert_eq!(sensors.len(), 2);
+
+        let sensor = sensors[0];
+        assert_eq!(sensor.read_temperature(&mut w1).unwrap(), 23.125);
+        assert_eq!(sensor.measure(&mut w1).unwrap(), 23.125);
+        assert_eq!(sensors[1].read_temperature(&mut w1).unwrap(), -10.062);
+
+        assert!(!sensor.is_parasite_powered(&mut w1).unwrap());
+        assert!(sensors[1].is_parasite_powered(&mut w1).unwrap());
+
+        assert_eq!(sensor.resolution(&mut w1).unwrap(), Resolution::Bits12);
+        sensor.set_resolution(&mut w1, Resolution::Bits9).unwrap();
+        assert_eq!(fixture.attribute("28-0000056a2f1c", "resolution"), "9");
+        assert_eq!(sensor.resolution(&mut w1).unwrap(), Resolution::Bits9);
+
+        fs::write(fixture.path.join("28-0000056a2f1c/resolution"), "8\n").unwrap();
+        assert!(matches!(sensor.resolution(&mut w1), Err(Error::Io(_))));
+        sensor.set_resolution(&mut w1, Resolution::Bits9).unwrap();
+
+        sensor.save(&mut w1).unwrap();
+        assert_eq!(fixture.attribute("28-0000056a2f1c", "eeprom_cmd"), "save");
+        sensor.restore(&mut w1).unwrap();
+        assert_eq!(
+            fixture.attribute("28-0000056a2f1c", "eeprom_cmd"),
+            "restore"
+        );
+
+        // Conversions are started on all sensors through the bus master
+        sensor.start_conversion(&mut w1).unwrap();
+        let bulk_read = fixture.master_path().join("therm_bulk_read");
+        assert_eq!(fs::read_to_string(&bulk_read).unwrap(), "trigger");
+
+        fs::write(&bulk_read, "0\n").unwrap();
+        fs::write(fixture.path.join("28-0000056a2f1d/resolution"), "9\n").unwrap();
+        assert_eq!(
+            Ds18b20::measure_all(&mut w1, &sensors).unwrap(),
+            [23.125, -10.062]
+        );
+        assert_eq!(fs::read_to_string(&bulk_read).unwrap(), "trigger");
+
+        // Raw commands aren't available
+        let rom = sensor.rom();
+        assert!(matches!(
+            w1.write(Some(rom), &[0x44]),
+            Err(Error::RawAccessUnavailable(unavailable)) if unavailable == rom
+        ));
+    }
+}
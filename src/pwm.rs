@@ -85,6 +85,8 @@ use std::io;
 use std::result;
 use std::time::Duration;
 
+use crate::safe_state::{self, Registration};
+
 #[cfg(feature = "hal")]
 mod hal;
 #[cfg(feature = "hal-unproven")]
@@ -176,6 +178,7 @@ impl fmt::Display for Polarity {
 pub struct Pwm {
     channel: Channel,
     reset_on_drop: bool,
+    safe_disabled: Option<Registration>,
 }
 
 impl Pwm {
@@ -191,6 +194,7 @@ impl Pwm {
         let pwm = Pwm {
             channel,
             reset_on_drop: true,
+            safe_disabled: None,
         };
 
         // Always reset "enable" to 0. The sysfs interface has a bug where a previous
@@ -231,6 +235,7 @@ impl Pwm {
         let pwm = Pwm {
             channel,
             reset_on_drop: true,
+            safe_disabled: None,
         };
 
         // Always reset "enable" to 0. The sysfs pwm interface has a bug where a previous
@@ -281,6 +286,7 @@ impl Pwm {
         let pwm = Pwm {
             channel,
             reset_on_drop: true,
+            safe_disabled: None,
         };
 
         // Always reset "enable" to 0. The sysfs pwm interface has a bug where a previous
@@ -472,12 +478,43 @@ impl Pwm {
     ///
     /// Drop methods aren't called when a process is abnormally terminated, for
     /// instance when a user presses <kbd>Ctrl</kbd> + <kbd>C</kbd>, and the `SIGINT` signal
-    /// isn't caught. You can catch those using crates such as [`simple_signal`].
+    /// isn't caught. You can catch those using crates such as [`simple_signal`], or
+    /// through [`set_safe_disabled`].
     ///
     /// [`simple_signal`]: https://crates.io/crates/simple-signal
+    /// [`set_safe_disabled`]: #method.set_safe_disabled
     pub fn set_reset_on_drop(&mut self, reset_on_drop: bool) {
         self.reset_on_drop = reset_on_drop;
     }
+
+    /// Returns `true` if the PWM channel is disabled when the safe states are applied.
+    pub fn safe_disabled(&self) -> bool {
+        self.safe_disabled.is_some()
+    }
+
+    /// When enabled, registers the PWM channel to be disabled when the safe states
+    /// are applied.
+    ///
+    /// The safe states are applied by [`safe_state::apply`], which is called
+    /// automatically when the process receives `SIGINT`, `SIGTERM` or `SIGHUP` once
+    /// [`safe_state::install`] has been called, or when it panics once
+    /// [`safe_state::install_panic_hook`] has been called. The safe state is removed
+    /// when the `Pwm` instance goes out of scope. By default, this is set to `false`.
+    ///
+    /// [`safe_state::apply`]: ../safe_state/fn.apply.html
+    /// [`safe_state::install`]: ../safe_state/fn.install.html
+    /// [`safe_state::install_panic_hook`]: ../safe_state/fn.install_panic_hook.html
+    pub fn set_safe_disabled(&mut self, safe_disabled: bool) {
+        let channel = self.channel as u8;
+
+        self.safe_disabled = if safe_disabled {
+            Some(safe_state::register(move || {
+                let _ = sysfs::set_enabled(channel, false);
+            }))
+        } else {
+            None
+        };
+    }
 }
 
 impl Drop for Pwm {
@@ -0,0 +1,333 @@
+//! Drives outputs to a safe state when the process is interrupted or panics.
+//!
+//! [`OutputPin::set_reset_on_drop`] and [`Pwm::set_reset_on_drop`] rely on destructors,
+//! which don't run when the process is terminated by a signal, such as `SIGINT` when a
+//! user presses <kbd>Ctrl</kbd> + <kbd>C</kbd>, or when a panic aborts the process. A
+//! motor driver or heater that's switched on at that moment stays on.
+//!
+//! Outputs register their safe state through [`OutputPin::set_safe_level`],
+//! [`IoPin::set_safe_level`] and [`Pwm::set_safe_disabled`]. The registered states are
+//! applied by [`apply`], which is called automatically once [`install`] has set up the
+//! signal handlers, or [`install_panic_hook`] has set up the panic hook. A registration
+//! is removed when its output goes out of scope.
+//!
+//! ## Example
+//!
+//! ```no_run
+//! use std::error::Error;
+//!
+//! use rpi_embedded::gpio::{Gpio, Level};
+//! use rpi_embedded::safe_state;
+//!
+//! # fn main() -> Result<(), Box<dyn Error>> {
+//! safe_state::install()?;
+//!
+//! let mut motor = Gpio::new()?.get(23)?.into_output();
+//! motor.set_safe_level(Some(Level::Low));
+//! motor.set_high();
+//!
+//! // Pressing Ctrl + C now switches the motor off before the process exits
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! [`OutputPin::set_reset_on_drop`]: ../gpio/struct.OutputPin.html#method.set_reset_on_drop
+//! [`Pwm::set_reset_on_drop`]: ../pwm/struct.Pwm.html#method.set_reset_on_drop
+//! [`OutputPin::set_safe_level`]: ../gpio/struct.OutputPin.html#method.set_safe_level
+//! [`IoPin::set_safe_level`]: ../gpio/struct.IoPin.html#method.set_safe_level
+//! [`Pwm::set_safe_disabled`]: ../pwm/struct.Pwm.html#method.set_safe_disabled
+//! [`apply`]: fn.apply.html
+//! [`install`]: fn.install.html
+//! [`install_panic_hook`]: fn.install_panic_hook.html
+
+use std::io;
+use std::mem;
+use std::panic;
+use std::ptr;
+use std::sync::atomic::{AtomicI32, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::thread;
+
+use lazy_static::lazy_static;
+use libc::{c_int, c_void, SIGHUP, SIGINT, SIGTERM, SIG_DFL};
+
+type Action = Arc<dyn Fn() + Send + Sync>;
+
+// Signals that terminate the process by default, and are commonly used to stop it
+const SIGNALS: [c_int; 3] = [SIGINT, SIGTERM, SIGHUP];
+
+lazy_static! {
+    static ref REGISTRY: Mutex<Vec<(u64, Action)>> = Mutex::new(Vec::new());
+    static ref INSTALLED: Mutex<bool> = Mutex::new(false);
+    static ref PANIC_HOOK_INSTALLED: Mutex<bool> = Mutex::new(false);
+}
+
+static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+// Write end of the pipe the signal handler uses to wake up the signal thread
+static SIGNAL_FD: AtomicI32 = AtomicI32::new(-1);
+
+// Removes the registered safe state when dropped
+#[derive(Debug)]
+pub(crate) struct Registration {
+    id: u64,
+}
+
+impl Drop for Registration {
+    fn drop(&mut self) {
+        registry().retain(|&(id, _)| id != self.id);
+    }
+}
+
+// Registers an action that drives an output to its safe state. Actions are called
+// from the panic hook and the signal thread, so they shouldn't panic, or block for
+// longer than it takes to stop the output.
+pub(crate) fn register<F>(action: F) -> Registration
+where
+    F: Fn() + Send + Sync + 'static,
+{
+    let id = NEXT_ID.fetch_add(1, Ordering::SeqCst);
+    registry().push((id, Arc::new(action)));
+
+    Registration { id }
+}
+
+// A panic while holding the lock shouldn't prevent the safe states from being applied
+fn registry() -> MutexGuard<'static, Vec<(u64, Action)>> {
+    REGISTRY.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Drives all registered outputs to their safe states.
+///
+/// `apply` is called automatically after [`install`] or [`install_panic_hook`], but
+/// can also be called directly, for instance from a custom signal handler. The safe
+/// states stay registered, so `apply` can be called more than once.
+///
+/// [`install`]: fn.install.html
+/// [`install_panic_hook`]: fn.install_panic_hook.html
+pub fn apply() {
+    // Release the lock before calling the actions, which may drop registrations
+    let actions: Vec<Action> = registry()
+        .iter()
+        .map(|(_, action)| action.clone())
+        .collect();
+    for action in actions {
+        action();
+    }
+}
+
+/// Installs signal handlers for `SIGINT`, `SIGTERM` and `SIGHUP`, which [`apply`] the
+/// registered safe states.
+///
+/// The signal handlers replace any existing handlers for those signals. After the
+/// safe states are applied, the signal's default action is restored and the signal
+/// is raised again, so the process exits with the expected status. Applications that
+/// handle these signals themselves should call [`apply`] instead.
+///
+/// Calling `install` more than once has no effect.
+///
+/// [`apply`]: fn.apply.html
+pub fn install() -> io::Result<()> {
+    let mut installed = INSTALLED.lock().unwrap_or_else(PoisonError::into_inner);
+    if *installed {
+        return Ok(());
+    }
+
+    let mut fds = [0; 2];
+    if unsafe { libc::pipe2(fds.as_mut_ptr(), libc::O_CLOEXEC) } == -1 {
+        return Err(io::Error::last_os_error());
+    }
+
+    // The signal handler must never block on a full pipe
+    if unsafe { libc::fcntl(fds[1], libc::F_SETFL, libc::O_NONBLOCK) } == -1 {
+        let err = io::Error::last_os_error();
+        unsafe {
+            libc::close(fds[0]);
+            libc::close(fds[1]);
+        }
+
+        return Err(err);
+    }
+
+    SIGNAL_FD.store(fds[1], Ordering::SeqCst);
+    let read_fd = fds[0];
+    thread::spawn(move || {
+        if let Some(signal) = wait_for_signal(read_fd) {
+            apply();
+
+            unsafe {
+                libc::signal(signal, SIG_DFL);
+                libc::raise(signal);
+            }
+        }
+    });
+
+    for &signal in SIGNALS.iter() {
+        let mut action: libc::sigaction = unsafe { mem::zeroed() };
+        action.sa_sigaction = handle_signal as extern "C" fn(c_int) as libc::sighandler_t;
+        action.sa_flags = libc::SA_RESTART;
+
+        if unsafe { libc::sigaction(signal, &action, ptr::null_mut()) } == -1 {
+            return Err(io::Error::last_os_error());
+        }
+    }
+
+    *installed = true;
+
+    Ok(())
+}
+
+/// Installs a panic hook, which [`apply`]s the registered safe states before calling
+/// the previously installed hook.
+///
+/// A panic hook can't tell whether a panic will end the process. The safe states are
+/// applied for every panic, including panics that are caught with
+/// [`catch_unwind`], panics in threads that are joined, and panics in tasks
+/// spawned by an async runtime. Only install the hook if any panic should stop the
+/// outputs, for instance when the process is built with `panic = "abort"`.
+///
+/// Calling `install_panic_hook` more than once has no effect.
+///
+/// [`apply`]: fn.apply.html
+/// [`catch_unwind`]: https://doc.rust-lang.org/std/panic/fn.catch_unwind.html
+pub fn install_panic_hook() {
+    let mut installed = PANIC_HOOK_INSTALLED
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner);
+    if *installed {
+        return;
+    }
+
+    let previous_hook = panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        apply();
+        previous_hook(info);
+    }));
+
+    *installed = true;
+}
+
+// Only async-signal-safe functions can be called here, so the actual work is done
+// on the signal thread
+extern "C" fn handle_signal(signal: c_int) {
+    let errno = io::Error::last_os_error().raw_os_error().unwrap_or(0);
+    let signal = signal as u8;
+
+    unsafe {
+        libc::write(
+            SIGNAL_FD.load(Ordering::SeqCst),
+            &signal as *const u8 as *const c_void,
+            1,
+        );
+
+        *libc::__errno_location() = errno;
+    }
+}
+
+fn wait_for_signal(fd: c_int) -> Option<c_int> {
+    let mut signal = 0u8;
+
+    loop {
+        match unsafe { libc::read(fd, &mut signal as *mut u8 as *mut c_void, 1) } {
+            1 => return Some(c_int::from(signal)),
+            -1 if io::Error::last_os_error().kind() == io::ErrorKind::Interrupted => (),
+            _ => return None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::sync::atomic::AtomicUsize;
+    use std::time::Duration;
+
+    use crate::gpio::{Gpio, Level};
+
+    lazy_static! {
+        // The registry is shared by all tests, so apply() calls shouldn't overlap
+        static ref SERIAL: Mutex<()> = Mutex::new(());
+    }
+
+    fn counter() -> (Arc<AtomicUsize>, impl Fn() + Send + Sync + 'static) {
+        let count = Arc::new(AtomicUsize::new(0));
+        let action_count = count.clone();
+
+        (count, move || {
+            action_count.fetch_add(1, Ordering::SeqCst);
+        })
+    }
+
+    #[test]
+    fn apply_calls_registered_actions() {
+        let _serial = SERIAL.lock().unwrap_or_else(PoisonError::into_inner);
+
+        let (first, action) = counter();
+        let _first = register(action);
+        let (second, action) = counter();
+        let _second = register(action);
+
+        apply();
+        assert_eq!(first.load(Ordering::SeqCst), 1);
+        assert_eq!(second.load(Ordering::SeqCst), 1);
+
+        // Registrations stay in place after they're applied
+        apply();
+        assert_eq!(first.load(Ordering::SeqCst), 2);
+        assert_eq!(second.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn drop_removes_registration() {
+        let _serial = SERIAL.lock().unwrap_or_else(PoisonError::into_inner);
+
+        let (dropped, action) = counter();
+        let registration = register(action);
+        let (kept, action) = counter();
+        let _kept = register(action);
+
+        drop(registration);
+        apply();
+
+        assert_eq!(dropped.load(Ordering::SeqCst), 0);
+        assert_eq!(kept.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn action_can_drop_registration() {
+        let _serial = SERIAL.lock().unwrap_or_else(PoisonError::into_inner);
+
+        // An action that removes its own registration must not deadlock
+        let slot: Arc<Mutex<Option<Registration>>> = Arc::new(Mutex::new(None));
+        let (count, increment) = counter();
+        let action_slot = slot.clone();
+        *slot.lock().unwrap() = Some(register(move || {
+            increment();
+            action_slot.lock().unwrap().take();
+        }));
+
+        apply();
+        apply();
+
+        assert_eq!(count.load(Ordering::SeqCst), 1);
+        assert!(slot.lock().unwrap().is_none());
+    }
+
+    #[test]
+    fn apply_stops_software_pwm() {
+        let _serial = SERIAL.lock().unwrap_or_else(PoisonError::into_inner);
+
+        let (gpio, sim) = Gpio::simulated().unwrap();
+        let mut pin = gpio.get(18).unwrap().into_output();
+        pin.set_pwm(Duration::from_micros(200), Duration::from_micros(100))
+            .unwrap();
+        pin.set_safe_level(Some(Level::High));
+
+        apply();
+
+        // The PWM thread has exited, so it can't toggle the pin anymore
+        assert!(pin.soft_pwm.lock().unwrap().is_none());
+        thread::sleep(Duration::from_millis(5));
+        assert_eq!(sim.output_level(18), Level::High);
+    }
+}
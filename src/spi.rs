@@ -146,12 +146,24 @@
 //! slave device to any other available GPIO pin on the Pi, and manually
 //! changing it to high and low as needed.
 //!
+//! ## Software SPI
+//!
+//! If you need an additional SPI bus on pins that aren't routed to any of the SPI
+//! peripherals, or a feature the hardware doesn't support, [`SoftSpi`] offers a
+//! bit-banged SPI master on arbitrary GPIO pins. It supports all four SPI modes,
+//! both bit orders, 1 to 32 bits per word and a GPIO Slave Select pin, and accepts
+//! the same [`Segment`]s as [`Spi::transfer_segments`]. The clock speed is limited
+//! to a few hundred kHz at most.
+//!
 //! [`Ss0`]: enum.SlaveSelect.html
 //! [`Ss1`]: enum.SlaveSelect.html
 //! [`Ss2`]: enum.SlaveSelect.html
 //! [`Mode1`]: enum.Mode.html
 //! [`Mode3`]: enum.Mode.html
 //! [`reverse_bits`]: fn.reverse_bits.html
+//! [`SoftSpi`]: struct.SoftSpi.html
+//! [`Segment`]: struct.Segment.html
+//! [`Spi::transfer_segments`]: struct.Spi.html#method.transfer_segments
 
 use std::error;
 use std::fmt;
@@ -162,12 +174,18 @@ use std::marker::PhantomData;
 use std::os::unix::io::AsRawFd;
 use std::result;
 
+#[cfg(feature = "async")]
+mod aio;
 #[cfg(feature = "hal")]
 mod hal;
 mod ioctl;
 mod segment;
+mod soft;
 
+#[cfg(feature = "async")]
+pub use self::aio::AsyncSpi;
 pub use self::segment::Segment;
+pub use self::soft::SoftSpi;
 
 /// Errors that can occur when accessing the SPI peripheral.
 #[derive(Debug)]
@@ -0,0 +1,155 @@
+use std::sync::{Arc, Mutex, PoisonError};
+
+use super::{Error, Result, Spi};
+
+/// Asynchronous wrapper for [`Spi`].
+///
+/// `AsyncSpi` runs each transfer on tokio's blocking thread pool, so awaiting a
+/// transfer doesn't block the executor. It requires the optional `async` feature,
+/// and its methods must be called from within a tokio runtime.
+///
+/// Cloning an `AsyncSpi` returns a handle to the same [`Spi`] instance. Transfers
+/// from different handles are executed one at a time.
+///
+/// [`Spi`]: struct.Spi.html
+#[derive(Debug, Clone)]
+pub struct AsyncSpi {
+    inner: Arc<Mutex<Spi>>,
+}
+
+impl AsyncSpi {
+    /// Constructs a new `AsyncSpi`.
+    pub fn new(spi: Spi) -> AsyncSpi {
+        AsyncSpi {
+            inner: Arc::new(Mutex::new(spi)),
+        }
+    }
+
+    /// Runs `f` with exclusive access to the wrapped `Spi` on the blocking thread pool.
+    ///
+    /// `run` can be used for any operation that doesn't have an awaitable equivalent,
+    /// such as [`Spi::transfer_segments`], or to combine multiple operations that
+    /// shouldn't be interleaved with other transfers.
+    ///
+    /// [`Spi::transfer_segments`]: struct.Spi.html#method.transfer_segments
+    pub async fn run<F, T>(&self, f: F) -> Result<T>
+    where
+        F: FnOnce(&mut Spi) -> Result<T> + Send + 'static,
+        T: Send + 'static,
+    {
+        let inner = self.inner.clone();
+
+        // A panic in an earlier closure doesn't leave the device in an invalid state
+        tokio::task::spawn_blocking(move || {
+            f(&mut inner.lock().unwrap_or_else(PoisonError::into_inner))
+        })
+        .await
+        .map_err(|e| Error::Io(e.into()))?
+    }
+
+    /// Receives `len` bytes from the slave device.
+    ///
+    /// See [`Spi::read`] for more information.
+    ///
+    /// [`Spi::read`]: struct.Spi.html#method.read
+    pub async fn read(&self, len: usize) -> Result<Vec<u8>> {
+        self.run(move |spi| {
+            let mut buffer = vec![0u8; len];
+            let len = spi.read(&mut buffer)?;
+            buffer.truncate(len);
+
+            Ok(buffer)
+        })
+        .await
+    }
+
+    /// Sends the contents of `buffer` to the slave device, and returns how many
+    /// bytes were written.
+    ///
+    /// See [`Spi::write`] for more information.
+    ///
+    /// [`Spi::write`]: struct.Spi.html#method.write
+    pub async fn write(&self, buffer: Vec<u8>) -> Result<usize> {
+        self.run(move |spi| spi.write(&buffer)).await
+    }
+
+    /// Sends the contents of `write_buffer` while simultaneously receiving the
+    /// same number of bytes, which are returned.
+    ///
+    /// See [`Spi::transfer`] for more information.
+    ///
+    /// [`Spi::transfer`]: struct.Spi.html#method.transfer
+    pub async fn transfer(&self, write_buffer: Vec<u8>) -> Result<Vec<u8>> {
+        self.run(move |spi| {
+            let mut read_buffer = vec![0u8; write_buffer.len()];
+            spi.transfer(&mut read_buffer, &write_buffer)?;
+
+            Ok(read_buffer)
+        })
+        .await
+    }
+}
+
+impl From<Spi> for AsyncSpi {
+    fn from(spi: Spi) -> AsyncSpi {
+        AsyncSpi::new(spi)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::OpenOptions;
+    use std::future::Future;
+    use std::marker::PhantomData;
+
+    use super::*;
+
+    // Backed by /dev/null, which discards writes, returns no data for reads, and
+    // rejects any ioctl() calls
+    fn null_spi() -> Spi {
+        Spi {
+            spidev: OpenOptions::new()
+                .read(true)
+                .write(true)
+                .open("/dev/null")
+                .unwrap(),
+            #[cfg(feature = "hal")]
+            last_read: 0,
+            not_sync: PhantomData,
+        }
+    }
+
+    fn block_on<F: Future>(future: F) -> F::Output {
+        tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap()
+            .block_on(future)
+    }
+
+    #[test]
+    fn transfers() {
+        let spi = AsyncSpi::new(null_spi());
+
+        block_on(async {
+            assert_eq!(spi.write(vec![1, 2, 3]).await.unwrap(), 3);
+            assert_eq!(spi.read(4).await.unwrap(), []);
+            assert!(matches!(spi.transfer(vec![1, 2]).await, Err(Error::Io(_))));
+        });
+    }
+
+    #[test]
+    fn panic_in_closure() {
+        let spi = AsyncSpi::new(null_spi());
+        let handle = spi.clone();
+
+        block_on(async {
+            let result = handle
+                .run(|_| -> Result<()> { panic!("closure panicked") })
+                .await;
+            assert!(matches!(result, Err(Error::Io(_))));
+
+            // The device is still available to other handles
+            assert_eq!(spi.write(vec![0]).await.unwrap(), 1);
+        });
+    }
+}
@@ -20,6 +20,7 @@
 
 use std::fmt;
 use std::marker;
+use std::slice;
 
 /// Part of a multi-segment transfer.
 ///
@@ -158,7 +159,7 @@ impl<'a, 'b> Segment<'a, 'b> {
             if (len > buffer.len() as u32) || tx_buf == 0 {
                 len = buffer.len() as u32;
             }
-            buffer.as_ptr() as u64
+            buffer.as_mut_ptr() as u64
         } else {
             0
         };
@@ -261,6 +262,28 @@ impl<'a, 'b> Segment<'a, 'b> {
     pub fn set_ss_change(&mut self, ss_change: bool) {
         self.cs_change = ss_change as u8;
     }
+
+    // Returns the outgoing data, if a write buffer was supplied. Used by SoftSpi.
+    pub(crate) fn write_buffer(&self) -> Option<&'b [u8]> {
+        if self.tx_buf == 0 {
+            return None;
+        }
+
+        // The buffer is borrowed for 'b, and holds at least len bytes
+        Some(unsafe { slice::from_raw_parts(self.tx_buf as *const u8, self.len as usize) })
+    }
+
+    // Returns the read buffer, if one was supplied. Used by SoftSpi, which writes
+    // incoming data to it like spidev does.
+    pub(crate) fn read_buffer(&mut self) -> Option<&mut [u8]> {
+        if self.rx_buf == 0 {
+            return None;
+        }
+
+        // The buffer is mutably borrowed for 'a, holds at least len bytes, and can
+        // only be accessed while the segment is mutably borrowed
+        Some(unsafe { slice::from_raw_parts_mut(self.rx_buf as *mut u8, self.len as usize) })
+    }
 }
 
 impl<'a, 'b> fmt::Debug for Segment<'a, 'b> {
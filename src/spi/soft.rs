@@ -0,0 +1,720 @@
+use std::cell::{Cell, RefCell};
+use std::io;
+
+use super::{BitOrder, Error, Mode, Polarity, Result, Segment};
+use crate::gpio::soft_pwm::{get_time_ns, wait_until_ns};
+use crate::gpio::{InputPin, Level, OutputPin};
+
+const NANOS_PER_SEC: i64 = 1_000_000_000;
+const BITS_PER_WORD_MAX: u8 = 32;
+
+#[derive(Debug, Copy, Clone)]
+struct Settings {
+    clock_speed: u32,
+    mode: Mode,
+    bit_order: BitOrder,
+    bits_per_word: u8,
+    ss_polarity: Polarity,
+}
+
+impl Settings {
+    fn idle_clock(&self) -> Level {
+        match self.mode {
+            Mode::Mode0 | Mode::Mode1 => Level::Low,
+            Mode::Mode2 | Mode::Mode3 => Level::High,
+        }
+    }
+
+    fn ss_level(&self, active: bool) -> Level {
+        match (self.ss_polarity, active) {
+            (Polarity::ActiveLow, true) | (Polarity::ActiveHigh, false) => Level::Low,
+            (Polarity::ActiveLow, false) | (Polarity::ActiveHigh, true) => Level::High,
+        }
+    }
+}
+
+// Number of bytes used to store a single word in the read and write buffers,
+// following the spidev conventions
+fn word_size(bits_per_word: u8) -> usize {
+    match bits_per_word {
+        0..=8 => 1,
+        9..=16 => 2,
+        _ => 4,
+    }
+}
+
+fn half_period_ns(clock_speed: u32) -> i64 {
+    NANOS_PER_SEC / (2 * i64::from(clock_speed))
+}
+
+#[derive(Debug)]
+struct Lines {
+    sclk: OutputPin,
+    mosi: Option<OutputPin>,
+    miso: Option<InputPin>,
+    ss: Option<OutputPin>,
+    ss_active: bool,
+}
+
+impl Lines {
+    fn set_ss(&mut self, settings: &Settings, active: bool) {
+        if let Some(ss) = &mut self.ss {
+            ss.write(settings.ss_level(active));
+        }
+
+        self.ss_active = active;
+    }
+
+    fn set_mosi(&mut self, bit: bool) {
+        if let Some(mosi) = &mut self.mosi {
+            mosi.write(if bit { Level::High } else { Level::Low });
+        }
+    }
+
+    fn miso(&self) -> bool {
+        match &self.miso {
+            Some(miso) => miso.read() == Level::High,
+            None => false,
+        }
+    }
+
+    fn transfer_word(
+        &mut self,
+        settings: &Settings,
+        half_period_ns: i64,
+        bits: u8,
+        word: u32,
+    ) -> u32 {
+        let idle = settings.idle_clock();
+        let active = !idle;
+        let late_phase = match settings.mode {
+            Mode::Mode0 | Mode::Mode2 => false,
+            Mode::Mode1 | Mode::Mode3 => true,
+        };
+
+        let mut received = 0;
+        for index in 0..bits {
+            let bit = match settings.bit_order {
+                BitOrder::MsbFirst => bits - 1 - index,
+                BitOrder::LsbFirst => index,
+            };
+            let outgoing = word & (1 << bit) != 0;
+
+            let incoming = if late_phase {
+                // CPHA 1: data changes on the leading edge, and is sampled on the trailing edge
+                self.sclk.write(active);
+                self.set_mosi(outgoing);
+                wait_until_ns(get_time_ns() + half_period_ns);
+                self.sclk.write(idle);
+                let incoming = self.miso();
+                wait_until_ns(get_time_ns() + half_period_ns);
+                incoming
+            } else {
+                // CPHA 0: data changes before the leading edge, and is sampled on the leading edge
+                self.set_mosi(outgoing);
+                wait_until_ns(get_time_ns() + half_period_ns);
+                self.sclk.write(active);
+                let incoming = self.miso();
+                wait_until_ns(get_time_ns() + half_period_ns);
+                self.sclk.write(idle);
+                incoming
+            };
+
+            if incoming {
+                received |= 1 << bit;
+            }
+        }
+
+        received
+    }
+}
+
+/// Bit-banged SPI master on arbitrary GPIO pins.
+///
+/// `SoftSpi` drives the clock (SCLK), data out (MOSI) and Slave Select pins
+/// as [`OutputPin`]s, and reads data in (MISO) through an [`InputPin`]. MOSI, MISO and
+/// Slave Select are optional, which allows for devices that only send or receive data,
+/// or that don't use a Slave Select line.
+///
+/// Unlike [`Spi`], `SoftSpi` supports all four SPI modes on any pins, both bit orders,
+/// and 1 to 32 bits per word. Following the `spidev` conventions, words of 9 to 16 bits
+/// are stored as 2 bytes, and words of 17 to 32 bits as 4 bytes, in little-endian byte
+/// order. Buffer lengths have to be a multiple of the word size.
+///
+/// The clock is generated by the calling thread through busy-waiting, so the actual
+/// clock speed is lower than configured, and an occasional clock period may be stretched
+/// when the thread is preempted. SPI slave devices sample data on clock edges, which
+/// makes them tolerant to these delays.
+///
+/// ## Example
+///
+/// ```no_run
+/// use std::error::Error;
+///
+/// use rpi_embedded::gpio::Gpio;
+/// use rpi_embedded::spi::{Mode, Segment, SoftSpi};
+///
+/// # fn main() -> Result<(), Box<dyn Error>> {
+/// let gpio = Gpio::new()?;
+/// let sclk = gpio.get(21)?.into_output();
+/// let mosi = gpio.get(20)?.into_output();
+/// let miso = gpio.get(19)?.into_input();
+/// let ss = gpio.get(16)?.into_output();
+///
+/// let spi = SoftSpi::new(sclk, Some(mosi), Some(miso), Some(ss), 100_000, Mode::Mode0);
+///
+/// let mut buffer = [0u8; 2];
+/// spi.transfer_segments(&mut [
+///     Segment::with_write(&[0x0b, 0x00]),
+///     Segment::with_read(&mut buffer),
+/// ])?;
+/// # Ok(())
+/// # }
+/// ```
+///
+/// [`OutputPin`]: ../gpio/struct.OutputPin.html
+/// [`InputPin`]: ../gpio/struct.InputPin.html
+/// [`Spi`]: struct.Spi.html
+#[derive(Debug)]
+pub struct SoftSpi {
+    lines: RefCell<Lines>,
+    settings: Cell<Settings>,
+}
+
+impl SoftSpi {
+    /// Constructs a new `SoftSpi`.
+    ///
+    /// `clock_speed` is the clock frequency in hertz (Hz), and `mode` selects the clock
+    /// polarity and phase. The bit order is set to [`MsbFirst`], the number of bits per
+    /// word to 8, and the Slave Select polarity to [`ActiveLow`].
+    ///
+    /// A `clock_speed` of 0 is changed to 1 Hz.
+    ///
+    /// [`MsbFirst`]: enum.BitOrder.html
+    /// [`ActiveLow`]: enum.Polarity.html
+    pub fn new(
+        sclk: OutputPin,
+        mosi: Option<OutputPin>,
+        miso: Option<InputPin>,
+        ss: Option<OutputPin>,
+        clock_speed: u32,
+        mode: Mode,
+    ) -> SoftSpi {
+        let settings = Settings {
+            clock_speed: clock_speed.max(1),
+            mode,
+            bit_order: BitOrder::MsbFirst,
+            bits_per_word: 8,
+            ss_polarity: Polarity::ActiveLow,
+        };
+
+        let mut lines = Lines {
+            sclk,
+            mosi,
+            miso,
+            ss,
+            ss_active: false,
+        };
+
+        lines.sclk.write(settings.idle_clock());
+        lines.set_mosi(false);
+        lines.set_ss(&settings, false);
+
+        SoftSpi {
+            lines: RefCell::new(lines),
+            settings: Cell::new(settings),
+        }
+    }
+
+    /// Returns the bit order.
+    pub fn bit_order(&self) -> BitOrder {
+        self.settings.get().bit_order
+    }
+
+    /// Sets the order in which bits are shifted out and in.
+    ///
+    /// By default, `bit_order` is set to [`MsbFirst`].
+    ///
+    /// [`MsbFirst`]: enum.BitOrder.html
+    pub fn set_bit_order(&self, bit_order: BitOrder) {
+        self.update(|settings| settings.bit_order = bit_order);
+    }
+
+    /// Returns the number of bits per word.
+    pub fn bits_per_word(&self) -> u8 {
+        self.settings.get().bits_per_word
+    }
+
+    /// Sets the number of bits per word.
+    ///
+    /// Values from 1 to 32 are supported. Any other value returns
+    /// `Err(`[`Error::BitsPerWordNotSupported`]`)`. By default, `bits_per_word` is set to 8.
+    ///
+    /// [`Error::BitsPerWordNotSupported`]: enum.Error.html#variant.BitsPerWordNotSupported
+    pub fn set_bits_per_word(&self, bits_per_word: u8) -> Result<()> {
+        if bits_per_word == 0 || bits_per_word > BITS_PER_WORD_MAX {
+            return Err(Error::BitsPerWordNotSupported(bits_per_word));
+        }
+
+        self.update(|settings| settings.bits_per_word = bits_per_word);
+
+        Ok(())
+    }
+
+    /// Returns the clock frequency in hertz (Hz).
+    pub fn clock_speed(&self) -> u32 {
+        self.settings.get().clock_speed
+    }
+
+    /// Sets the clock frequency in hertz (Hz).
+    ///
+    /// A `clock_speed` of 0 returns `Err(`[`Error::ClockSpeedNotSupported`]`)`.
+    ///
+    /// [`Error::ClockSpeedNotSupported`]: enum.Error.html#variant.ClockSpeedNotSupported
+    pub fn set_clock_speed(&self, clock_speed: u32) -> Result<()> {
+        if clock_speed == 0 {
+            return Err(Error::ClockSpeedNotSupported(clock_speed));
+        }
+
+        self.update(|settings| settings.clock_speed = clock_speed);
+
+        Ok(())
+    }
+
+    /// Returns the SPI mode.
+    pub fn mode(&self) -> Mode {
+        self.settings.get().mode
+    }
+
+    /// Sets the SPI mode, and changes SCLK to the new idle level.
+    pub fn set_mode(&self, mode: Mode) {
+        self.update(|settings| settings.mode = mode);
+
+        let idle = self.settings.get().idle_clock();
+        self.lines.borrow_mut().sclk.write(idle);
+    }
+
+    /// Returns the Slave Select polarity.
+    pub fn ss_polarity(&self) -> Polarity {
+        self.settings.get().ss_polarity
+    }
+
+    /// Sets Slave Select polarity.
+    ///
+    /// By default, `ss_polarity` is set to [`ActiveLow`].
+    ///
+    /// [`ActiveLow`]: enum.Polarity.html
+    pub fn set_ss_polarity(&self, polarity: Polarity) {
+        self.update(|settings| settings.ss_polarity = polarity);
+
+        let settings = self.settings.get();
+        let mut lines = self.lines.borrow_mut();
+        let active = lines.ss_active;
+        lines.set_ss(&settings, active);
+    }
+
+    /// Receives incoming data from the slave device and writes it to `buffer`.
+    ///
+    /// A zero value word is shifted out on MOSI for every word received.
+    ///
+    /// Returns how many bytes were read.
+    pub fn read(&mut self, buffer: &mut [u8]) -> Result<usize> {
+        let len = buffer.len();
+        self.transfer_segments(&mut [Segment::with_read(buffer)])?;
+
+        Ok(len)
+    }
+
+    /// Sends the outgoing data contained in `buffer` to the slave device.
+    ///
+    /// Returns how many bytes were written.
+    pub fn write(&mut self, buffer: &[u8]) -> Result<usize> {
+        self.transfer_segments(&mut [Segment::with_write(buffer)])?;
+
+        Ok(buffer.len())
+    }
+
+    /// Sends and receives data at the same time.
+    ///
+    /// `transfer` only transfers as many bytes as the shortest of the two buffers contains.
+    ///
+    /// Returns how many bytes were transferred.
+    pub fn transfer(&self, read_buffer: &mut [u8], write_buffer: &[u8]) -> Result<usize> {
+        let mut segments = [Segment::new(read_buffer, write_buffer)];
+        self.transfer_segments(&mut segments)?;
+
+        Ok(segments[0].len())
+    }
+
+    /// Transfers multiple half-duplex or full-duplex segments.
+    ///
+    /// `transfer_segments` accepts the same [`Segment`]s as [`Spi::transfer_segments`],
+    /// including their custom clock speed, delay, bits per word and Slave Select change
+    /// settings.
+    ///
+    /// Slave Select is set to active at the start of the transfer, and stays active until
+    /// all segments have been transferred, unless [`Segment::set_ss_change`] is used.
+    ///
+    /// Unlike [`Spi::transfer_segments`], the segments are borrowed mutably, because
+    /// incoming data is written to their read buffers by the calling thread.
+    ///
+    /// [`Segment`]: struct.Segment.html
+    /// [`Spi::transfer_segments`]: struct.Spi.html#method.transfer_segments
+    /// [`Segment::set_ss_change`]: struct.Segment.html#method.set_ss_change
+    pub fn transfer_segments(&self, segments: &mut [Segment<'_, '_>]) -> Result<()> {
+        let settings = self.settings.get();
+
+        // Check all segments first, so an invalid segment doesn't result in a partial transfer
+        for segment in segments.iter() {
+            let bits_per_word = segment_bits_per_word(segment, &settings);
+            if bits_per_word > BITS_PER_WORD_MAX {
+                return Err(Error::BitsPerWordNotSupported(bits_per_word));
+            }
+
+            if segment.len() % word_size(bits_per_word) != 0 {
+                return Err(Error::Io(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "buffer length isn't a multiple of the word size",
+                )));
+            }
+        }
+
+        let mut lines = self.lines.borrow_mut();
+        let half_period_ns = half_period_ns(settings.clock_speed);
+
+        let count = segments.len();
+        for (index, segment) in segments.iter_mut().enumerate() {
+            let bits_per_word = segment_bits_per_word(segment, &settings);
+            let size = word_size(bits_per_word);
+            let half_period_ns = match segment.clock_speed() {
+                0 => half_period_ns,
+                clock_speed => self::half_period_ns(clock_speed),
+            };
+
+            if !lines.ss_active {
+                lines.set_ss(&settings, true);
+                wait_until_ns(get_time_ns() + half_period_ns);
+            }
+
+            let len = segment.len();
+            let delay = segment.delay();
+            let ss_change = segment.ss_change();
+            let write_buffer = segment.write_buffer();
+            let mut read_buffer = segment.read_buffer();
+
+            for offset in (0..len).step_by(size) {
+                let mut word = [0u8; 4];
+                if let Some(buffer) = write_buffer {
+                    word[..size].copy_from_slice(&buffer[offset..offset + size]);
+                }
+
+                let received = lines
+                    .transfer_word(
+                        &settings,
+                        half_period_ns,
+                        bits_per_word,
+                        u32::from_le_bytes(word),
+                    )
+                    .to_le_bytes();
+
+                if let Some(buffer) = &mut read_buffer {
+                    buffer[offset..offset + size].copy_from_slice(&received[..size]);
+                }
+            }
+
+            if delay > 0 {
+                wait_until_ns(get_time_ns() + i64::from(delay) * 1_000);
+            }
+
+            let last = index + 1 == count;
+            match (ss_change, last) {
+                // Keep Slave Select active after the final segment
+                (true, true) => (),
+                // Briefly set Slave Select inactive before the next segment
+                (true, false) | (false, true) => {
+                    wait_until_ns(get_time_ns() + half_period_ns);
+                    lines.set_ss(&settings, false);
+                    wait_until_ns(get_time_ns() + half_period_ns);
+                }
+                (false, false) => (),
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Returns the SCLK, MOSI, MISO and Slave Select pins.
+    pub fn into_pins(
+        self,
+    ) -> (
+        OutputPin,
+        Option<OutputPin>,
+        Option<InputPin>,
+        Option<OutputPin>,
+    ) {
+        let lines = self.lines.into_inner();
+
+        (lines.sclk, lines.mosi, lines.miso, lines.ss)
+    }
+
+    fn update<F>(&self, f: F)
+    where
+        F: FnOnce(&mut Settings),
+    {
+        let mut settings = self.settings.get();
+        f(&mut settings);
+        self.settings.set(settings);
+    }
+}
+
+fn segment_bits_per_word(segment: &Segment<'_, '_>, settings: &Settings) -> u8 {
+    match segment.bits_per_word() {
+        0 => settings.bits_per_word,
+        bits_per_word => bits_per_word,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use super::*;
+    use crate::gpio::sim::SimDevice;
+    use crate::gpio::{Gpio, Simulator};
+
+    const SCLK: u8 = 11;
+    const MOSI: u8 = 10;
+    const MISO: u8 = 9;
+    const SS: u8 = 8;
+    const CLOCK_SPEED: u32 = 10_000_000;
+
+    #[derive(Debug, PartialEq, Eq, Copy, Clone)]
+    enum Event {
+        Select,
+        Deselect,
+        // MOSI level on the sampling edge
+        Bit(bool),
+    }
+
+    // SPI slave device that logs Slave Select changes and the bits it samples,
+    // and loops MOSI back to MISO
+    #[derive(Debug)]
+    struct Slave {
+        mode: Mode,
+        sclk: bool,
+        selected: bool,
+        events: Arc<Mutex<Vec<Event>>>,
+    }
+
+    impl SimDevice for Slave {
+        fn respond(&mut self, levels: u64) -> Vec<(u8, Option<Level>)> {
+            let sclk = levels & (1 << SCLK) != 0;
+            let mosi = levels & (1 << MOSI) != 0;
+            let selected = levels & (1 << SS) == 0;
+            let mut events = self.events.lock().unwrap();
+
+            if selected != self.selected {
+                events.push(if selected {
+                    Event::Select
+                } else {
+                    Event::Deselect
+                });
+            }
+
+            let (idle, late_phase) = match self.mode {
+                Mode::Mode0 => (false, false),
+                Mode::Mode1 => (false, true),
+                Mode::Mode2 => (true, false),
+                Mode::Mode3 => (true, true),
+            };
+
+            // CPHA 0 samples on the leading edge, CPHA 1 on the trailing edge
+            if selected && sclk != self.sclk && (self.sclk == idle) != late_phase {
+                events.push(Event::Bit(mosi));
+            }
+
+            self.sclk = sclk;
+            self.selected = selected;
+
+            vec![(MISO, Some(if mosi { Level::High } else { Level::Low }))]
+        }
+    }
+
+    fn bus(mode: Mode) -> (SoftSpi, Simulator, Arc<Mutex<Vec<Event>>>) {
+        let (gpio, sim) = Gpio::simulated().unwrap();
+        let spi = SoftSpi::new(
+            gpio.get(SCLK).unwrap().into_output(),
+            Some(gpio.get(MOSI).unwrap().into_output()),
+            Some(gpio.get(MISO).unwrap().into_input()),
+            Some(gpio.get(SS).unwrap().into_output()),
+            CLOCK_SPEED,
+            mode,
+        );
+
+        let events = Arc::new(Mutex::new(Vec::new()));
+        sim.attach(Box::new(Slave {
+            mode,
+            sclk: spi.settings.get().idle_clock() == Level::High,
+            selected: false,
+            events: events.clone(),
+        }));
+
+        (spi, sim, events)
+    }
+
+    // Bits of every word in the order they're expected on MOSI
+    fn bits(words: &[u32], bits_per_word: u8, bit_order: BitOrder) -> Vec<Event> {
+        let mut events = Vec::new();
+        for word in words {
+            for index in 0..bits_per_word {
+                let bit = match bit_order {
+                    BitOrder::MsbFirst => bits_per_word - 1 - index,
+                    BitOrder::LsbFirst => index,
+                };
+                events.push(Event::Bit(word & (1 << bit) != 0));
+            }
+        }
+
+        events
+    }
+
+    fn transaction(bits: Vec<Event>) -> Vec<Event> {
+        let mut events = vec![Event::Select];
+        events.extend(bits);
+        events.push(Event::Deselect);
+
+        events
+    }
+
+    #[test]
+    fn loopback() {
+        let write = [0xa5, 0x3c, 0x01];
+
+        for &mode in &[Mode::Mode0, Mode::Mode1, Mode::Mode2, Mode::Mode3] {
+            for &bit_order in &[BitOrder::MsbFirst, BitOrder::LsbFirst] {
+                let (spi, _sim, events) = bus(mode);
+                spi.set_bit_order(bit_order);
+
+                let mut read = [0u8; 3];
+                assert_eq!(spi.transfer(&mut read, &write).unwrap(), 3);
+                assert_eq!(read, write, "{} {}", mode, bit_order);
+                assert_eq!(
+                    *events.lock().unwrap(),
+                    transaction(bits(&[0xa5, 0x3c, 0x01], 8, bit_order)),
+                    "{} {}",
+                    mode,
+                    bit_order
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn bits_per_word() {
+        let (mut spi, _sim, events) = bus(Mode::Mode0);
+
+        // 9 to 16 bit words are stored as 2 little-endian bytes, and any bits
+        // above bits_per_word are ignored
+        spi.set_bits_per_word(12).unwrap();
+        let mut read = [0u8; 4];
+        spi.transfer(&mut read, &[0x34, 0xfa, 0xcd, 0x0b]).unwrap();
+        assert_eq!(read, [0x34, 0x0a, 0xcd, 0x0b]);
+        assert_eq!(
+            events.lock().unwrap().drain(..).collect::<Vec<_>>(),
+            transaction(bits(&[0xa34, 0xbcd], 12, BitOrder::MsbFirst))
+        );
+
+        // 17 to 32 bit words are stored as 4 little-endian bytes
+        spi.set_bits_per_word(20).unwrap();
+        let mut read = [0u8; 4];
+        spi.transfer(&mut read, &[0x56, 0x34, 0xf2, 0xff]).unwrap();
+        assert_eq!(read, [0x56, 0x34, 0x02, 0x00]);
+        assert_eq!(
+            events.lock().unwrap().drain(..).collect::<Vec<_>>(),
+            transaction(bits(&[0x2_3456], 20, BitOrder::MsbFirst))
+        );
+
+        // Words of fewer than 8 bits use a single byte
+        spi.set_bits_per_word(5).unwrap();
+        spi.write(&[0x13]).unwrap();
+        assert_eq!(
+            events.lock().unwrap().drain(..).collect::<Vec<_>>(),
+            transaction(bits(&[0x13], 5, BitOrder::MsbFirst))
+        );
+
+        // Segments override the default bits per word
+        let mut segment = Segment::with_write(&[0xff, 0x01]);
+        segment.set_bits_per_word(9);
+        spi.transfer_segments(&mut [segment]).unwrap();
+        assert_eq!(
+            events.lock().unwrap().drain(..).collect::<Vec<_>>(),
+            transaction(bits(&[0x1ff], 9, BitOrder::MsbFirst))
+        );
+
+        for &bits_per_word in &[0, 33] {
+            match spi.set_bits_per_word(bits_per_word) {
+                Err(Error::BitsPerWordNotSupported(bits)) => assert_eq!(bits, bits_per_word),
+                result => panic!("unexpected result: {:?}", result),
+            }
+        }
+
+        // Nothing is transferred when any of the buffers has an invalid length
+        spi.set_bits_per_word(16).unwrap();
+        let mut segments = [
+            Segment::with_write(&[0x01, 0x02]),
+            Segment::with_write(&[0x03, 0x04, 0x05]),
+        ];
+        match spi.transfer_segments(&mut segments) {
+            Err(Error::Io(err)) => assert_eq!(err.kind(), io::ErrorKind::InvalidInput),
+            result => panic!("unexpected result: {:?}", result),
+        }
+        assert!(events.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn ss_change() {
+        let (mut spi, _sim, events) = bus(Mode::Mode0);
+
+        // Slave Select stays active between segments by default
+        let mut read = [0u8; 1];
+        spi.transfer_segments(&mut [Segment::with_write(&[0x81]), Segment::with_read(&mut read)])
+            .unwrap();
+        assert_eq!(read, [0x00]);
+        assert_eq!(
+            events.lock().unwrap().drain(..).collect::<Vec<_>>(),
+            transaction(bits(&[0x81, 0x00], 8, BitOrder::MsbFirst))
+        );
+
+        // ss_change briefly sets Slave Select inactive before the next segment
+        let mut first = Segment::with_write(&[0x81]);
+        first.set_ss_change(true);
+        spi.transfer_segments(&mut [first, Segment::with_write(&[0x42])])
+            .unwrap();
+        let mut expected = transaction(bits(&[0x81], 8, BitOrder::MsbFirst));
+        expected.extend(transaction(bits(&[0x42], 8, BitOrder::MsbFirst)));
+        assert_eq!(
+            events.lock().unwrap().drain(..).collect::<Vec<_>>(),
+            expected
+        );
+
+        // ss_change on the final segment keeps Slave Select active afterwards,
+        // until the end of the next transfer
+        let mut last = Segment::with_write(&[0x18]);
+        last.set_ss_change(true);
+        spi.transfer_segments(&mut [last]).unwrap();
+        let mut expected = vec![Event::Select];
+        expected.extend(bits(&[0x18], 8, BitOrder::MsbFirst));
+        assert_eq!(
+            events.lock().unwrap().drain(..).collect::<Vec<_>>(),
+            expected
+        );
+
+        spi.write(&[0x24]).unwrap();
+        let mut expected = bits(&[0x24], 8, BitOrder::MsbFirst);
+        expected.push(Event::Deselect);
+        assert_eq!(
+            events.lock().unwrap().drain(..).collect::<Vec<_>>(),
+            expected
+        );
+    }
+}
@@ -27,12 +27,11 @@
 use std::error;
 use std::fmt;
 use std::fs;
-use std::fs::File;
-use std::io::{BufRead, BufReader};
 use std::result;
 
 const PERIPHERAL_BASE_RPI: u32 = 0x2000_0000;
 const PERIPHERAL_BASE_RPI2: u32 = 0x3f00_0000;
+const PERIPHERAL_BASE_RPI4: u32 = 0xfe00_0000;
 const GPIO_OFFSET: u32 = 0x20_0000;
 
 /// Errors that can occur when trying to identify the Raspberry Pi hardware.
@@ -84,11 +83,14 @@ pub enum Model {
     RaspberryPi3B,
     RaspberryPi3BPlus,
     RaspberryPi4B,
+    RaspberryPi400,
     RaspberryPiComputeModule,
     RaspberryPiComputeModule3,
     RaspberryPiComputeModule3Plus,
+    RaspberryPiComputeModule4,
     RaspberryPiZero,
     RaspberryPiZeroW,
+    RaspberryPiZero2W,
     /// `Model` might be extended with additional variants in a minor or
     /// patch revision, and must not be exhaustively matched against.
     /// Instead, add a `_` catch-all arm to match future variants.
@@ -109,11 +111,14 @@ impl fmt::Display for Model {
             Model::RaspberryPi3BPlus => write!(f, "Raspberry Pi 3 B+"),
             Model::RaspberryPi3APlus => write!(f, "Raspberry Pi 3 A+"),
             Model::RaspberryPi4B => write!(f, "Raspberry Pi 4 B"),
+            Model::RaspberryPi400 => write!(f, "Raspberry Pi 400"),
             Model::RaspberryPiComputeModule => write!(f, "Raspberry Pi Compute Module"),
             Model::RaspberryPiComputeModule3 => write!(f, "Raspberry Pi Compute Module 3"),
             Model::RaspberryPiComputeModule3Plus => write!(f, "Raspberry Pi Compute Module 3+"),
+            Model::RaspberryPiComputeModule4 => write!(f, "Raspberry Pi Compute Module 4"),
             Model::RaspberryPiZero => write!(f, "Raspberry Pi Zero"),
             Model::RaspberryPiZeroW => write!(f, "Raspberry Pi Zero W"),
+            Model::RaspberryPiZero2W => write!(f, "Raspberry Pi Zero 2 W"),
             Model::__Nonexhaustive => write!(f, "__Nonexhaustive"),
         }
     }
@@ -153,61 +158,82 @@ impl fmt::Display for SoC {
 
 // Identify Pi model based on /proc/cpuinfo
 fn parse_proc_cpuinfo() -> Result<Model> {
-    let proc_cpuinfo = BufReader::new(match File::open("/proc/cpuinfo") {
-        Ok(file) => file,
-        Err(_) => return Err(Error::UnknownModel),
-    });
-
-    let mut hardware: String = String::new();
-    let mut revision: String = String::new();
-    for line_result in proc_cpuinfo.lines() {
-        if let Ok(line) = line_result {
-            if line.starts_with("Hardware\t: ") {
-                hardware = String::from(&line[11..]);
-            } else if line.starts_with("Revision\t: ") {
-                revision = String::from(&line[11..]).to_lowercase();
-            }
+    match fs::read_to_string("/proc/cpuinfo") {
+        Ok(buffer) => parse_cpuinfo(&buffer),
+        Err(_) => Err(Error::UnknownModel),
+    }
+}
+
+// Identify Pi model based on the contents of /proc/cpuinfo
+fn parse_cpuinfo(cpuinfo: &str) -> Result<Model> {
+    let mut hardware: &str = "";
+    let mut revision: &str = "";
+    for line in cpuinfo.lines() {
+        if let Some(value) = line.strip_prefix("Hardware\t: ") {
+            hardware = value;
+        } else if let Some(value) = line.strip_prefix("Revision\t: ") {
+            revision = value;
         }
     }
 
     // Return an error if we don't recognize the SoC. This check is
     // done to prevent accidentally identifying a non-Pi SBC as a Pi
     // solely based on the revision field.
-    match &hardware[..] {
+    match hardware.trim() {
         "BCM2708" | "BCM2835" | "BCM2709" | "BCM2836" | "BCM2710" | "BCM2837" | "BCM2837A1"
         | "BCM2837B0" | "BCM2711" => {}
         _ => return Err(Error::UnknownModel),
     }
 
-    let model = if (revision.len() == 4) || (revision.len() == 8) {
-        // Older revisions are 4 characters long, or 8 if they've been over-volted
-        match &revision[revision.len() - 4..] {
-            "0007" | "0008" | "0009" | "0015" => Model::RaspberryPiA,
-            "beta" | "0002" | "0003" => Model::RaspberryPiBRev1,
-            "0004" | "0005" | "0006" | "000d" | "000e" | "000f" => Model::RaspberryPiBRev2,
-            "0012" => Model::RaspberryPiAPlus,
-            "0010" | "0013" => Model::RaspberryPiBPlus,
-            "0011" | "0014" => Model::RaspberryPiComputeModule,
+    parse_revision(revision.trim())
+}
+
+// Identify Pi model based on the revision code. Decoding is based on
+// https://www.raspberrypi.org/documentation/hardware/raspberrypi/revision-codes/
+fn parse_revision(revision: &str) -> Result<Model> {
+    if revision.eq_ignore_ascii_case("beta") {
+        return Ok(Model::RaspberryPiBRev1);
+    }
+
+    let code = match u32::from_str_radix(revision, 16) {
+        Ok(code) => code,
+        Err(_) => return Err(Error::UnknownModel),
+    };
+
+    let model = if code & (1 << 23) == 0 {
+        // Old-style revision codes. The warranty bit (bit 28, 0x1000_0000) is set if the
+        // Pi has been over-volted, and is masked off together with the other upper bits.
+        match code & 0x00ff_ffff {
+            0x07 | 0x08 | 0x09 | 0x15 => Model::RaspberryPiA,
+            0x02 | 0x03 => Model::RaspberryPiBRev1,
+            0x04 | 0x05 | 0x06 | 0x0d | 0x0e | 0x0f => Model::RaspberryPiBRev2,
+            0x12 => Model::RaspberryPiAPlus,
+            0x10 | 0x13 => Model::RaspberryPiBPlus,
+            0x11 | 0x14 => Model::RaspberryPiComputeModule,
             _ => return Err(Error::UnknownModel),
         }
-    } else if revision.len() >= 6 {
-        // Newer revisions consist of at least 6 characters
-        match &revision[..] {
-            "900021" => Model::RaspberryPiAPlus,
-            "900032" => Model::RaspberryPiBPlus,
-            "a01040" | "a01041" | "a21041" | "a22042" => Model::RaspberryPi2B,
-            "a02082" | "a22082" | "a32082" | "a52082" => Model::RaspberryPi3B,
-            "900092" | "900093" | "920092" | "920093" => Model::RaspberryPiZero,
-            "a020a0" | "a220a0" => Model::RaspberryPiComputeModule3,
-            "9000c1" => Model::RaspberryPiZeroW,
-            "a020d3" => Model::RaspberryPi3BPlus,
-            "9020e0" => Model::RaspberryPi3APlus,
-            "a02100" => Model::RaspberryPiComputeModule3Plus,
-            "a03111" | "b03111" | "c03111" | "a03112" | "b03112" | "c03112" => Model::RaspberryPi4B,
+    } else {
+        // New-style revision codes store the board type in bits 4-11
+        match (code >> 4) & 0xff {
+            0x00 => Model::RaspberryPiA,
+            0x01 => Model::RaspberryPiBRev2,
+            0x02 => Model::RaspberryPiAPlus,
+            0x03 => Model::RaspberryPiBPlus,
+            0x04 => Model::RaspberryPi2B,
+            0x06 => Model::RaspberryPiComputeModule,
+            0x08 => Model::RaspberryPi3B,
+            0x09 => Model::RaspberryPiZero,
+            0x0a => Model::RaspberryPiComputeModule3,
+            0x0c => Model::RaspberryPiZeroW,
+            0x0d => Model::RaspberryPi3BPlus,
+            0x0e => Model::RaspberryPi3APlus,
+            0x10 => Model::RaspberryPiComputeModule3Plus,
+            0x11 => Model::RaspberryPi4B,
+            0x12 => Model::RaspberryPiZero2W,
+            0x13 => Model::RaspberryPi400,
+            0x14 => Model::RaspberryPiComputeModule4,
             _ => return Err(Error::UnknownModel),
         }
-    } else {
-        return Err(Error::UnknownModel);
     };
 
     Ok(model)
@@ -215,11 +241,14 @@ fn parse_proc_cpuinfo() -> Result<Model> {
 
 // Identify Pi model based on /sys/firmware/devicetree/base/compatible
 fn parse_base_compatible() -> Result<Model> {
-    let base_compatible = match fs::read_to_string("/sys/firmware/devicetree/base/compatible") {
-        Ok(buffer) => buffer,
-        Err(_) => return Err(Error::UnknownModel),
-    };
+    match fs::read_to_string("/sys/firmware/devicetree/base/compatible") {
+        Ok(buffer) => parse_compatible(&buffer),
+        Err(_) => Err(Error::UnknownModel),
+    }
+}
 
+// Identify Pi model based on the NUL-separated list of compatible strings
+fn parse_compatible(base_compatible: &str) -> Result<Model> {
     // Based on /arch/arm/boot/dts/ and /Documentation/devicetree/bindings/arm/bcm/
     for comp_id in base_compatible.split('\0') {
         let model = match comp_id {
@@ -239,6 +268,9 @@ fn parse_base_compatible() -> Result<Model> {
             "raspberrypi,3-model-b-plus" => Model::RaspberryPi3BPlus,
             "raspberrypi,3-model-a-plus" => Model::RaspberryPi3APlus,
             "raspberrypi,4-model-b" => Model::RaspberryPi4B,
+            "raspberrypi,400" => Model::RaspberryPi400,
+            "raspberrypi,4-compute-module" => Model::RaspberryPiComputeModule4,
+            "raspberrypi,model-zero-2-w" => Model::RaspberryPiZero2W,
             _ => continue,
         };
 
@@ -250,33 +282,34 @@ fn parse_base_compatible() -> Result<Model> {
 
 // Identify Pi model based on /sys/firmware/devicetree/base/model
 fn parse_base_model() -> Result<Model> {
-    let mut base_model = match fs::read_to_string("/sys/firmware/devicetree/base/model") {
-        Ok(mut buffer) => {
-            if let Some(idx) = buffer.find('\0') {
-                buffer.truncate(idx);
-                buffer
-            } else {
-                buffer
-            }
-        }
-        Err(_) => return Err(Error::UnknownModel),
+    match fs::read_to_string("/sys/firmware/devicetree/base/model") {
+        Ok(buffer) => parse_model(&buffer),
+        Err(_) => Err(Error::UnknownModel),
+    }
+}
+
+// Identify Pi model based on the model name
+fn parse_model(base_model: &str) -> Result<Model> {
+    let mut base_model = match base_model.find('\0') {
+        Some(idx) => &base_model[..idx],
+        None => base_model,
     };
 
     // Check if this is a Pi B rev 2 before we remove the revision part, assuming the
     // PCB Revision numbers on https://elinux.org/RPi_HardwareHistory are correct, and
     // the installed distro appends the revision to the model name.
-    match &base_model[..] {
+    match base_model {
         "Raspberry Pi Model B Rev 2.0" => return Ok(Model::RaspberryPiBRev2),
         "Raspberry Pi Model B rev2 Rev 2.0" => return Ok(Model::RaspberryPiBRev2),
         _ => (),
     }
 
     if let Some(idx) = base_model.find(" Rev ") {
-        base_model.truncate(idx);
+        base_model = &base_model[..idx];
     }
 
     // Based on /arch/arm/boot/dts/ and /Documentation/devicetree/bindings/arm/bcm/
-    let model = match &base_model[..] {
+    let model = match base_model {
         "Raspberry Pi Model B (no P5)" => Model::RaspberryPiBRev1,
         "Raspberry Pi Model B" => Model::RaspberryPiBRev1,
         "Raspberry Pi Model A" => Model::RaspberryPiA,
@@ -296,6 +329,9 @@ fn parse_base_model() -> Result<Model> {
         "Raspberry Pi 3 Model B Plus" => Model::RaspberryPi3BPlus,
         "Raspberry Pi 3 Model A Plus" => Model::RaspberryPi3APlus,
         "Raspberry Pi 4 Model B" => Model::RaspberryPi4B,
+        "Raspberry Pi 400" => Model::RaspberryPi400,
+        "Raspberry Pi Compute Module 4" => Model::RaspberryPiComputeModule4,
+        "Raspberry Pi Zero 2 W" => Model::RaspberryPiZero2W,
         _ => return Err(Error::UnknownModel),
     };
 
@@ -322,7 +358,11 @@ impl DeviceInfo {
         let model = parse_proc_cpuinfo()
             .or_else(|_| parse_base_compatible().or_else(|_| parse_base_model()))?;
 
-        // Set SoC and memory offsets based on model
+        Ok(DeviceInfo::from_model(model))
+    }
+
+    // Set SoC and memory offsets based on model
+    pub(crate) fn from_model(model: Model) -> DeviceInfo {
         match model {
             Model::RaspberryPiA
             | Model::RaspberryPiAPlus
@@ -331,38 +371,42 @@ impl DeviceInfo {
             | Model::RaspberryPiBPlus
             | Model::RaspberryPiComputeModule
             | Model::RaspberryPiZero
-            | Model::RaspberryPiZeroW => Ok(DeviceInfo {
+            | Model::RaspberryPiZeroW => DeviceInfo {
                 model,
                 soc: SoC::Bcm2835,
                 peripheral_base: PERIPHERAL_BASE_RPI,
                 gpio_offset: GPIO_OFFSET,
-            }),
-            Model::RaspberryPi2B => Ok(DeviceInfo {
+            },
+            Model::RaspberryPi2B => DeviceInfo {
                 model,
                 soc: SoC::Bcm2836,
                 peripheral_base: PERIPHERAL_BASE_RPI2,
                 gpio_offset: GPIO_OFFSET,
-            }),
-            Model::RaspberryPi3B | Model::RaspberryPiComputeModule3 => Ok(DeviceInfo {
-                model,
-                soc: SoC::Bcm2837A1,
-                peripheral_base: PERIPHERAL_BASE_RPI2,
-                gpio_offset: GPIO_OFFSET,
-            }),
+            },
+            Model::RaspberryPi3B | Model::RaspberryPiComputeModule3 | Model::RaspberryPiZero2W => {
+                DeviceInfo {
+                    model,
+                    soc: SoC::Bcm2837A1,
+                    peripheral_base: PERIPHERAL_BASE_RPI2,
+                    gpio_offset: GPIO_OFFSET,
+                }
+            }
             Model::RaspberryPi3BPlus
             | Model::RaspberryPi3APlus
-            | Model::RaspberryPiComputeModule3Plus => Ok(DeviceInfo {
+            | Model::RaspberryPiComputeModule3Plus => DeviceInfo {
                 model,
                 soc: SoC::Bcm2837B0,
                 peripheral_base: PERIPHERAL_BASE_RPI2,
                 gpio_offset: GPIO_OFFSET,
-            }),
-            Model::RaspberryPi4B => Ok(DeviceInfo {
-                model,
-                soc: SoC::Bcm2711,
-                peripheral_base: PERIPHERAL_BASE_RPI2,
-                gpio_offset: GPIO_OFFSET,
-            }),
+            },
+            Model::RaspberryPi4B | Model::RaspberryPi400 | Model::RaspberryPiComputeModule4 => {
+                DeviceInfo {
+                    model,
+                    soc: SoC::Bcm2711,
+                    peripheral_base: PERIPHERAL_BASE_RPI4,
+                    gpio_offset: GPIO_OFFSET,
+                }
+            }
             Model::__Nonexhaustive => unreachable!(),
         }
     }
@@ -387,3 +431,129 @@ impl DeviceInfo {
         self.gpio_offset
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    macro_rules! fixture {
+        ($name:expr) => {
+            include_str!(concat!("../tests/fixtures/system/", $name))
+        };
+    }
+
+    #[test]
+    fn cpuinfo() {
+        let fixtures = [
+            (fixture!("pi4b_cpuinfo"), Model::RaspberryPi4B),
+            (fixture!("pi400_cpuinfo"), Model::RaspberryPi400),
+            (fixture!("cm4_cpuinfo"), Model::RaspberryPiComputeModule4),
+            (fixture!("zero2w_cpuinfo"), Model::RaspberryPiZero2W),
+            (fixture!("pi3b_cpuinfo"), Model::RaspberryPi3B),
+            (fixture!("pib_rev2_cpuinfo"), Model::RaspberryPiBRev2),
+        ];
+
+        for &(cpuinfo, model) in fixtures.iter() {
+            assert_eq!(parse_cpuinfo(cpuinfo).unwrap(), model);
+        }
+    }
+
+    #[test]
+    fn cpuinfo_unknown_hardware() {
+        assert!(parse_cpuinfo(fixture!("generic_arm64_cpuinfo")).is_err());
+        assert!(parse_cpuinfo("").is_err());
+    }
+
+    #[test]
+    fn revision() {
+        let revisions = [
+            ("beta", Model::RaspberryPiBRev1),
+            ("0002", Model::RaspberryPiBRev1),
+            ("000e", Model::RaspberryPiBRev2),
+            ("1000000e", Model::RaspberryPiBRev2),
+            ("10000012", Model::RaspberryPiAPlus),
+            ("0012", Model::RaspberryPiAPlus),
+            ("900032", Model::RaspberryPiBPlus),
+            ("a22042", Model::RaspberryPi2B),
+            ("a32082", Model::RaspberryPi3B),
+            ("9000c1", Model::RaspberryPiZeroW),
+            ("a020d3", Model::RaspberryPi3BPlus),
+            ("9020e0", Model::RaspberryPi3APlus),
+            ("a02100", Model::RaspberryPiComputeModule3Plus),
+            ("A03111", Model::RaspberryPi4B),
+            ("d03115", Model::RaspberryPi4B),
+            ("902120", Model::RaspberryPiZero2W),
+            ("c03130", Model::RaspberryPi400),
+            ("a03140", Model::RaspberryPiComputeModule4),
+            ("d03140", Model::RaspberryPiComputeModule4),
+        ];
+
+        for &(revision, model) in revisions.iter() {
+            assert_eq!(parse_revision(revision).unwrap(), model, "{}", revision);
+        }
+
+        assert!(parse_revision("0001").is_err());
+        assert!(parse_revision("9020f0").is_err());
+        assert!(parse_revision("").is_err());
+    }
+
+    #[test]
+    fn compatible() {
+        let fixtures = [
+            (fixture!("pi4b_compatible"), Model::RaspberryPi4B),
+            (fixture!("pi400_compatible"), Model::RaspberryPi400),
+            (fixture!("cm4_compatible"), Model::RaspberryPiComputeModule4),
+            (fixture!("zero2w_compatible"), Model::RaspberryPiZero2W),
+        ];
+
+        for &(compatible, model) in fixtures.iter() {
+            assert_eq!(parse_compatible(compatible).unwrap(), model);
+        }
+
+        assert!(parse_compatible("brcm,bcm2711\0").is_err());
+    }
+
+    #[test]
+    fn model() {
+        let fixtures = [
+            (fixture!("pi4b_model"), Model::RaspberryPi4B),
+            (fixture!("pi400_model"), Model::RaspberryPi400),
+            (fixture!("cm4_model"), Model::RaspberryPiComputeModule4),
+            (fixture!("zero2w_model"), Model::RaspberryPiZero2W),
+        ];
+
+        for &(base_model, model) in fixtures.iter() {
+            assert_eq!(parse_model(base_model).unwrap(), model);
+        }
+
+        assert_eq!(
+            parse_model("Raspberry Pi Model B Rev 2.0\0").unwrap(),
+            Model::RaspberryPiBRev2
+        );
+    }
+
+    #[test]
+    fn soc() {
+        let models = [
+            (Model::RaspberryPi4B, SoC::Bcm2711, PERIPHERAL_BASE_RPI4),
+            (Model::RaspberryPi400, SoC::Bcm2711, PERIPHERAL_BASE_RPI4),
+            (
+                Model::RaspberryPiComputeModule4,
+                SoC::Bcm2711,
+                PERIPHERAL_BASE_RPI4,
+            ),
+            (
+                Model::RaspberryPiZero2W,
+                SoC::Bcm2837A1,
+                PERIPHERAL_BASE_RPI2,
+            ),
+            (Model::RaspberryPiZeroW, SoC::Bcm2835, PERIPHERAL_BASE_RPI),
+        ];
+
+        for &(model, soc, peripheral_base) in models.iter() {
+            let device_info = DeviceInfo::from_model(model);
+            assert_eq!(device_info.soc(), soc);
+            assert_eq!(device_info.peripheral_base(), peripheral_base);
+        }
+    }
+}
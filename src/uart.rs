@@ -168,10 +168,15 @@ use libc::{TIOCM_CAR, TIOCM_CTS, TIOCM_DSR, TIOCM_DTR, TIOCM_RNG, TIOCM_RTS};
 use crate::gpio::{self, Gpio, IoPin, Mode};
 use crate::system::{self, DeviceInfo, Model};
 
+#[cfg(feature = "async")]
+mod aio;
 #[cfg(feature = "hal")]
 mod hal;
 mod termios;
 
+#[cfg(feature = "async")]
+pub use self::aio::AsyncUart;
+
 const GPIO_RTS: u8 = 17;
 const GPIO_CTS: u8 = 16;
 
@@ -993,6 +998,12 @@ impl Uart {
     }
 }
 
+impl AsRawFd for Uart {
+    fn as_raw_fd(&self) -> RawFd {
+        self.inner.fd
+    }
+}
+
 impl Uart {
     pub fn set_bt()->Result<Uart>{
         Self::with_path("/dev/rfcomm0", 115200, Parity::None, 8, 1)
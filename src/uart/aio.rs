@@ -0,0 +1,125 @@
+use std::io::{self, Read, Write};
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use tokio::io::unix::AsyncFd;
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+use super::{Result, Uart};
+
+/// Asynchronous wrapper for [`Uart`].
+///
+/// `AsyncUart` implements tokio's [`AsyncRead`] and [`AsyncWrite`] traits, and
+/// requires the optional `async` feature. Reads and writes are driven by the
+/// tokio reactor, so a pending read doesn't block the executor thread.
+///
+/// The wrapped [`Uart`] is switched to non-blocking reads and writes, which means
+/// any settings configured through [`Uart::set_read_mode`] and [`Uart::set_write_mode`]
+/// are ignored. The remaining settings can still be changed through [`get_mut`].
+///
+/// [`Uart`]: struct.Uart.html
+/// [`Uart::set_read_mode`]: struct.Uart.html#method.set_read_mode
+/// [`Uart::set_write_mode`]: struct.Uart.html#method.set_write_mode
+/// [`get_mut`]: #method.get_mut
+/// [`AsyncRead`]: https://docs.rs/tokio/1/tokio/io/trait.AsyncRead.html
+/// [`AsyncWrite`]: https://docs.rs/tokio/1/tokio/io/trait.AsyncWrite.html
+#[derive(Debug)]
+pub struct AsyncUart {
+    inner: AsyncFd<Uart>,
+}
+
+impl AsyncUart {
+    /// Constructs a new `AsyncUart`.
+    ///
+    /// `new` must be called from within a tokio runtime.
+    pub fn new(mut uart: Uart) -> Result<AsyncUart> {
+        uart.set_read_mode(0, Duration::default())?;
+        uart.set_write_mode(false)?;
+
+        Ok(AsyncUart {
+            inner: AsyncFd::new(uart)?,
+        })
+    }
+
+    /// Returns a reference to the wrapped `Uart`.
+    pub fn get_ref(&self) -> &Uart {
+        self.inner.get_ref()
+    }
+
+    /// Returns a mutable reference to the wrapped `Uart`.
+    ///
+    /// Changing the read or write mode through the returned reference will
+    /// cause reads or writes to block the executor.
+    pub fn get_mut(&mut self) -> &mut Uart {
+        self.inner.get_mut()
+    }
+
+    /// Consumes the `AsyncUart`, and returns the wrapped `Uart`.
+    ///
+    /// The `Uart` is left configured for non-blocking reads and writes.
+    pub fn into_inner(self) -> Uart {
+        self.inner.into_inner()
+    }
+}
+
+impl AsyncRead for AsyncUart {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+
+        loop {
+            let mut guard = match this.inner.poll_read_ready_mut(cx) {
+                Poll::Ready(Ok(guard)) => guard,
+                Poll::Ready(Err(e)) => return Poll::Ready(Err(e)),
+                Poll::Pending => return Poll::Pending,
+            };
+
+            let unfilled = buf.initialize_unfilled();
+            match guard.try_io(|inner| inner.get_mut().inner.device.read(unfilled)) {
+                Ok(Ok(len)) => {
+                    buf.advance(len);
+                    return Poll::Ready(Ok(()));
+                }
+                Ok(Err(e)) => return Poll::Ready(Err(e)),
+                // The readiness flag was cleared, so wait for the next notification
+                Err(_) => (),
+            }
+        }
+    }
+}
+
+impl AsyncWrite for AsyncUart {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+
+        loop {
+            let mut guard = match this.inner.poll_write_ready_mut(cx) {
+                Poll::Ready(Ok(guard)) => guard,
+                Poll::Ready(Err(e)) => return Poll::Ready(Err(e)),
+                Poll::Pending => return Poll::Pending,
+            };
+
+            if let Ok(result) = guard.try_io(|inner| inner.get_mut().inner.device.write(buf)) {
+                return Poll::Ready(result);
+            }
+        }
+    }
+
+    // Data is handed to the output queue without any additional buffering. Use
+    // Uart::drain() to wait until the output queue has been transmitted.
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+}